@@ -0,0 +1,389 @@
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tauri::State;
+
+use crate::database::ConcordanceHit;
+use crate::pregloss::ecdict_word_forms;
+use crate::text_encoding::decode_text;
+use crate::{epub_text, pdf_text};
+use crate::AppState;
+
+/// Sentences outside this length range (in chars) are too fragmentary or too long to be
+/// useful examples and are not indexed.
+const MIN_SENTENCE_CHARS: usize = 12;
+const MAX_SENTENCE_CHARS: usize = 400;
+/// Ranked candidates fetched per requested example, so results can be spread across documents.
+const CANDIDATES_PER_EXAMPLE: usize = 8;
+/// Examples taken from one document before others get a turn.
+const MAX_PER_DOCUMENT: usize = 2;
+
+/// Words ending in a period that do not end a sentence.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "vs", "e.g", "i.e", "fig", "vol", "cf",
+];
+
+/// Text of one part of a document, extracted by the reader (PDF pages, EPUB chapters).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcordanceSegment {
+    /// Handed back with each example so the reader can jump to it (`page:12`, a chapter href).
+    pub location: String,
+    pub text: String,
+}
+
+fn is_terminator(c: char) -> bool {
+    matches!(c, '.' | '!' | '?' | '…' | '。' | '！' | '？')
+}
+
+fn is_closer(c: char) -> bool {
+    matches!(c, '"' | '\'' | '”' | '’' | ')' | ']' | '」' | '』')
+}
+
+/// Whether the `.` at `i` ends an abbreviation (`Mr.`, `e.g.`) or an initial (`J. R. R.`).
+fn ends_abbreviation(chars: &[char], i: usize) -> bool {
+    let mut start = i;
+    while start > 0 && (chars[start - 1].is_alphabetic() || chars[start - 1] == '.') {
+        start -= 1;
+    }
+    let word: String = chars[start..i].iter().collect::<String>().to_lowercase();
+    if word.chars().count() == 1 && chars[start].is_uppercase() {
+        return true;
+    }
+    ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// Splits text into sentences, returning each with the char offset it starts at. Single
+/// newlines are treated as spaces (hard-wrapped text); blank lines always end a sentence.
+pub(crate) fn split_sentences(text: &str) -> Vec<(usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = vec![];
+    let mut cur = String::new();
+    let mut start = 0;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if cur.trim().is_empty() {
+            cur.clear();
+            start = i;
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+        }
+
+        let blank_line_follows = || chars[i + 1..].iter().take_while(|c| **c != '\n').all(|c| c.is_whitespace());
+        if c == '\n' && i + 1 < chars.len() && blank_line_follows() {
+            // Blank line: paragraph break.
+            flush(&mut cur, start, &mut out);
+            i += 1;
+            continue;
+        }
+
+        cur.push(c);
+        if is_terminator(c) {
+            let mut j = i + 1;
+            while j < chars.len() && (is_terminator(chars[j]) || is_closer(chars[j])) {
+                cur.push(chars[j]);
+                j += 1;
+            }
+            let at_break = j >= chars.len() || chars[j].is_whitespace() || !c.is_ascii();
+            if at_break && !(c == '.' && ends_abbreviation(&chars, i)) {
+                flush(&mut cur, start, &mut out);
+            }
+            i = j;
+            continue;
+        }
+        i += 1;
+    }
+    flush(&mut cur, start, &mut out);
+    out
+}
+
+fn flush(cur: &mut String, start: usize, out: &mut Vec<(usize, String)>) {
+    let s = cur.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !s.is_empty() {
+        out.push((start, s));
+    }
+    cur.clear();
+}
+
+fn keep_sentence(s: &str) -> bool {
+    let n = s.chars().count();
+    (MIN_SENTENCE_CHARS..=MAX_SENTENCE_CHARS).contains(&n) && s.chars().any(|c| c.is_alphabetic())
+}
+
+/// Drops Markdown syntax line by line, keeping the line count so `line:N` locations still
+/// point into the original file. Code blocks become empty lines.
+fn strip_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_code = !in_code;
+            out.push('\n');
+            continue;
+        }
+        if in_code {
+            out.push('\n');
+            continue;
+        }
+        let body = trimmed.trim_start_matches(['#', '>']).trim_start();
+        let body = body
+            .strip_prefix("- ")
+            .or_else(|| body.strip_prefix("* "))
+            .or_else(|| body.strip_prefix("+ "))
+            .unwrap_or(body);
+        out.push_str(&strip_inline_markdown(body));
+        out.push('\n');
+    }
+    out
+}
+
+/// `![alt](src)` is dropped, `[text](href)` becomes `text`, and emphasis/code markers go.
+fn strip_inline_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let image = chars[i] == '!' && chars.get(i + 1) == Some(&'[');
+        if chars[i] == '[' || image {
+            let open = if image { i + 1 } else { i };
+            let close = chars[open..].iter().position(|c| *c == ']').map(|p| open + p);
+            if let Some(close) = close.filter(|c| chars.get(c + 1) == Some(&'(')) {
+                if let Some(end) = chars[close..].iter().position(|c| *c == ')').map(|p| close + p) {
+                    if !image {
+                        out.extend(&chars[open + 1..close]);
+                    }
+                    i = end + 1;
+                    continue;
+                }
+            }
+        }
+        if !matches!(chars[i], '*' | '_' | '`') {
+            out.push(chars[i]);
+        }
+        i += 1;
+    }
+    out
+}
+
+/// (location, sentence) pairs of a text file, located by 1-based line number.
+fn text_sentences(text: &str) -> Vec<(String, String)> {
+    let mut line_starts = vec![0];
+    for (i, c) in text.chars().enumerate() {
+        if c == '\n' {
+            line_starts.push(i + 1);
+        }
+    }
+    split_sentences(text)
+        .into_iter()
+        .filter(|(_, s)| keep_sentence(s))
+        .map(|(offset, s)| {
+            let line = line_starts.partition_point(|start| *start <= offset);
+            (format!("line:{}", line), s)
+        })
+        .collect()
+}
+
+fn segment_sentences(segments: &[ConcordanceSegment]) -> Vec<(String, String)> {
+    segments
+        .iter()
+        .flat_map(|seg| {
+            split_sentences(&seg.text)
+                .into_iter()
+                .filter(|(_, s)| keep_sentence(s))
+                .map(|(_, s)| (seg.location.clone(), s))
+        })
+        .collect()
+}
+
+/// Sentences of a document read from disk. EPUB chapters are located by href and PDF pages
+/// as `page:N`, the same locations the readers hand in as segments.
+fn file_sentences(path: &Path, doc_type: &str) -> Result<Vec<(String, String)>, String> {
+    let segments: Vec<ConcordanceSegment> = match doc_type {
+        "epub" => epub_text::book_text(path, None)?
+            .chapters
+            .into_iter()
+            .map(|c| ConcordanceSegment {
+                location: c.href.unwrap_or(c.idref),
+                text: c.text,
+            })
+            .collect(),
+        "pdf" => pdf_text::page_texts(path)?
+            .into_iter()
+            .map(|(index, text)| ConcordanceSegment {
+                location: format!("page:{}", index + 1),
+                text,
+            })
+            .collect(),
+        _ => {
+            let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+            let text = decode_text(&bytes);
+            return match doc_type {
+                "txt" => Ok(text_sentences(&text)),
+                "md" => Ok(text_sentences(&strip_markdown(&text))),
+                other => Err(format!("{} text must be supplied as segments", other)),
+            };
+        }
+    };
+    Ok(segment_sentences(&segments))
+}
+
+/// FTS5 query matching any of `forms` as a whole token.
+fn forms_query(forms: &[String]) -> Option<String> {
+    let terms: Vec<String> = forms
+        .iter()
+        .filter(|f| !f.is_empty() && f.chars().all(|c| c.is_alphanumeric() || c == '\'' || c == '-' || c == ' '))
+        .map(|f| format!("\"{}\"", f))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" OR "))
+}
+
+/// Picks `limit` examples from ranked hits: duplicates are dropped and each document gets
+/// at most [`MAX_PER_DOCUMENT`] until every document has had its turn.
+fn pick_examples(hits: Vec<ConcordanceHit>, limit: usize) -> Vec<ConcordanceHit> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut per_doc: HashMap<String, usize> = HashMap::new();
+    let mut picked = vec![];
+    let mut rest = vec![];
+    for hit in hits {
+        if !seen.insert(hit.sentence.to_lowercase()) {
+            continue;
+        }
+        let n = per_doc.entry(hit.document_id.clone()).or_insert(0);
+        if *n < MAX_PER_DOCUMENT && picked.len() < limit {
+            *n += 1;
+            picked.push(hit);
+        } else {
+            rest.push(hit);
+        }
+    }
+    let missing = limit.saturating_sub(picked.len());
+    picked.extend(rest.into_iter().take(missing));
+    picked
+}
+
+/// Indexes a document's sentences for `concordance_examples`, replacing any earlier index of
+/// it. The text comes from `segments` when given, otherwise it is read and extracted from `path`.
+#[tauri::command]
+pub async fn concordance_index_document(
+    state: State<'_, AppState>,
+    document_id: String,
+    title: String,
+    doc_type: String,
+    path: Option<String>,
+    segments: Option<Vec<ConcordanceSegment>>,
+) -> Result<usize, String> {
+    let db = state.db.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let sentences = match segments {
+            Some(segments) => segment_sentences(&segments),
+            None => {
+                let path = path.ok_or_else(|| "either path or segments is required".to_string())?;
+                file_sentences(Path::new(&path), &doc_type)?
+            }
+        };
+        db.replace_concordance_document(&document_id, &title, &doc_type, &sentences)
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn concordance_is_indexed(state: State<AppState>, document_id: String) -> Result<bool, String> {
+    state
+        .db
+        .is_concordance_document_indexed(&document_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn concordance_remove_document(state: State<AppState>, document_id: String) -> Result<(), String> {
+    state
+        .db
+        .delete_concordance_document(&document_id)
+        .map_err(|e| e.to_string())
+}
+
+/// Example sentences for `word` from the library, matching its lemma and inflections.
+#[tauri::command]
+pub fn concordance_examples(
+    state: State<AppState>,
+    word: String,
+    limit: Option<usize>,
+) -> Result<Vec<ConcordanceHit>, String> {
+    let word = word.trim();
+    let limit = limit.unwrap_or(5).clamp(1, 50);
+    let Some(query) = forms_query(&ecdict_word_forms(&state, word)) else {
+        return Ok(vec![]);
+    };
+    let hits = state
+        .db
+        .search_concordance(&query, limit * CANDIDATES_PER_EXAMPLE)
+        .map_err(|e| e.to_string())?;
+    Ok(pick_examples(hits, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sentences(text: &str) -> Vec<String> {
+        split_sentences(text).into_iter().map(|(_, s)| s).collect()
+    }
+
+    #[test]
+    fn test_split_sentences() {
+        assert_eq!(
+            sentences("Mr. Smith went home. He was tired!\nReally? \"Yes.\" Then J. R. R. Tolkien wrote."),
+            vec!["Mr. Smith went home.", "He was tired!", "Really?", "\"Yes.\"", "Then J. R. R. Tolkien wrote."]
+        );
+        assert_eq!(sentences("A line\nwrapped here.\n\nNew paragraph"), vec!["A line wrapped here.", "New paragraph"]);
+        assert_eq!(sentences("他走了。她也走了！"), vec!["他走了。", "她也走了！"]);
+        assert_eq!(split_sentences("One. Two three.")[1].0, 5);
+    }
+
+    #[test]
+    fn test_text_sentences_locate_lines() {
+        let text = "# Title\n\nThe quick brown fox jumps.\nIt was a long, long day.\n\n```\nlet x = 1;\n```\nShe [read](http://x) the *whole* book.\n";
+        let got = text_sentences(&strip_markdown(text));
+        assert_eq!(
+            got,
+            vec![
+                ("line:3".to_string(), "The quick brown fox jumps.".to_string()),
+                ("line:4".to_string(), "It was a long, long day.".to_string()),
+                ("line:9".to_string(), "She read the whole book.".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forms_query() {
+        let forms = vec!["go".to_string(), "went".to_string(), "a\"b".to_string()];
+        assert_eq!(forms_query(&forms).as_deref(), Some("\"go\" OR \"went\""));
+        assert_eq!(forms_query(&["".to_string()]), None);
+    }
+
+    #[test]
+    fn test_pick_examples_spreads_documents() {
+        let hit = |doc: &str, s: &str| ConcordanceHit {
+            sentence: s.to_string(),
+            document_id: doc.to_string(),
+            title: doc.to_string(),
+            location: "line:1".to_string(),
+        };
+        let hits = vec![
+            hit("a", "One."),
+            hit("a", "Two."),
+            hit("a", "Three."),
+            hit("b", "one."),
+            hit("b", "Four."),
+        ];
+        let got: Vec<String> = pick_examples(hits, 4).into_iter().map(|h| h.sentence).collect();
+        assert_eq!(got, vec!["One.", "Two.", "Four.", "Three."]);
+    }
+}
@@ -0,0 +1,336 @@
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+use sha1::{Digest, Sha1};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use tauri::ipc::{InvokeBody, Request};
+use tauri::State;
+use zip::ZipArchive;
+
+use crate::epub::hash_key;
+use crate::epub_meta::{percent_decode, read_epub_metadata};
+use crate::AppState;
+
+const THUMB_WIDTH: u32 = 240;
+const THUMB_HEIGHT: u32 = 360;
+/// Embedded images smaller than this are logos or ornaments, not a scanned page.
+const MIN_PAGE_IMAGE: u32 = 200;
+/// Only the start of a PDF is scanned for the first page image.
+const PDF_SCAN_BYTES: u64 = 16 * 1024 * 1024;
+
+const PALETTE: [[u8; 3]; 8] = [
+    [0x3b, 0x5b, 0x8c],
+    [0x8c, 0x3b, 0x4a],
+    [0x2f, 0x6f, 0x5e],
+    [0x6b, 0x4c, 0x8a],
+    [0x9a, 0x6a, 0x2e],
+    [0x35, 0x6e, 0x7f],
+    [0x5e, 0x5e, 0x5e],
+    [0x7a, 0x44, 0x30],
+];
+
+pub(crate) fn thumbnails_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("thumbnails")
+}
+
+/// Identifies a document file by path, size and modification time, so an edited file gets a new cover.
+pub(crate) fn document_fingerprint(path: &Path) -> Result<String, String> {
+    let canon = std::fs::canonicalize(path).map_err(|e| e.to_string())?;
+    let meta = std::fs::metadata(&canon).map_err(|e| e.to_string())?;
+    let modified_ms = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    Ok(hash_key(&canon.to_string_lossy(), meta.len(), modified_ms))
+}
+
+fn doc_type_of(path: &Path, doc_type: Option<&str>) -> String {
+    doc_type
+        .map(str::to_string)
+        .or_else(|| path.extension().map(|e| e.to_string_lossy().to_lowercase()))
+        .unwrap_or_default()
+}
+
+/// Scales down to fit the thumbnail box and writes a PNG via a temp file.
+fn save_thumbnail(img: &DynamicImage, target: &Path) -> Result<(), String> {
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let thumb = if img.width() > THUMB_WIDTH || img.height() > THUMB_HEIGHT {
+        img.thumbnail(THUMB_WIDTH, THUMB_HEIGHT)
+    } else {
+        img.clone()
+    };
+    let tmp = target.with_extension("png.tmp");
+    thumb
+        .save_with_format(&tmp, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, target).map_err(|e| e.to_string())
+}
+
+/// The image named by the OPF `cover-image` property or `<meta name="cover">`.
+fn epub_cover(path: &Path) -> Result<Option<DynamicImage>, String> {
+    let meta = read_epub_metadata(&path.to_path_buf())?;
+    let Some(href) = meta.cover_href else {
+        return Ok(None);
+    };
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+    let mut entry = zip.by_name(&href).map_err(|_| format!("cover {href} missing"))?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    image::load_from_memory(&bytes).map(Some).map_err(|e| e.to_string())
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+/// Returns the first page-sized JPEG image stream in `pdf`, which for scanned books is the cover.
+fn first_pdf_jpeg(pdf: &[u8]) -> Option<DynamicImage> {
+    let mut pos = 0;
+    while let Some(at) = find(pdf, b"stream", pos) {
+        pos = at + 6;
+        if at >= 3 && &pdf[at - 3..at] == b"end" {
+            continue;
+        }
+        // The stream dictionary sits between the object header and the `stream` keyword.
+        let window = &pdf[at.saturating_sub(1024)..at];
+        let dict = window.windows(3).rposition(|w| w == b"obj").map_or(window, |p| &window[p..]);
+        if find(dict, b"/Image", 0).is_none() || find(dict, b"/DCTDecode", 0).is_none() {
+            continue;
+        }
+        let mut start = pos;
+        if pdf.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if pdf.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let Some(end) = find(pdf, b"endstream", start) else { break };
+        let data = &pdf[start..end];
+        if !data.starts_with(&[0xff, 0xd8]) {
+            continue;
+        }
+        if let Ok(img) = image::load_from_memory_with_format(data, ImageFormat::Jpeg) {
+            if img.width() >= MIN_PAGE_IMAGE && img.height() >= MIN_PAGE_IMAGE {
+                return Some(img);
+            }
+        }
+        pos = end;
+    }
+    None
+}
+
+fn pdf_cover(path: &Path) -> Result<Option<DynamicImage>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    file.take(PDF_SCAN_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    Ok(first_pdf_jpeg(&bytes))
+}
+
+/// A plain cover for text documents: a title-derived colour with a frame and a type stripe.
+/// The colour comes from a stable hash so a title keeps its colour across builds.
+fn placeholder_cover(title: &str, doc_type: &str) -> DynamicImage {
+    let digest = Sha1::digest(title.as_bytes());
+    let [r, g, b] = PALETTE[digest[0] as usize % PALETTE.len()];
+    let base = Rgb([r, g, b]);
+    let light = Rgb([r.saturating_add(70), g.saturating_add(70), b.saturating_add(70)]);
+    let stripe = if doc_type == "md" { Rgb([0xe8, 0xe2, 0xd0]) } else { Rgb([0xf4, 0xf1, 0xea]) };
+
+    let mut img = RgbImage::from_pixel(THUMB_WIDTH, THUMB_HEIGHT, base);
+    let (w, h) = (THUMB_WIDTH, THUMB_HEIGHT);
+    for (x, y, px) in img.enumerate_pixels_mut() {
+        let frame = (12..16).contains(&x) || (w - 16..w - 12).contains(&x) || (12..16).contains(&y) || (h - 16..h - 12).contains(&y);
+        let inside = (12..w - 12).contains(&x) && (12..h - 12).contains(&y);
+        if frame && inside {
+            *px = light;
+        } else if (h * 2 / 3..h * 2 / 3 + 36).contains(&y) && (28..w - 28).contains(&x) {
+            *px = stripe;
+        }
+    }
+    DynamicImage::ImageRgb8(img)
+}
+
+/// Builds the cover for `path`, or `None` when only the frontend can render it (PDF without an embedded page image).
+fn generate_cover(path: &Path, doc_type: &str, title: &str) -> Option<DynamicImage> {
+    match doc_type {
+        "epub" => match epub_cover(path) {
+            Ok(Some(img)) => Some(img),
+            Ok(None) => Some(placeholder_cover(title, doc_type)),
+            Err(e) => {
+                log::warn!("[covers] epub cover {}: {e}", path.display());
+                Some(placeholder_cover(title, doc_type))
+            }
+        },
+        "pdf" => pdf_cover(path).unwrap_or_else(|e| {
+            log::warn!("[covers] pdf cover {}: {e}", path.display());
+            None
+        }),
+        _ => Some(placeholder_cover(title, doc_type)),
+    }
+}
+
+pub(crate) fn thumbnail_impl(
+    app_data_dir: &Path,
+    path: &Path,
+    doc_type: Option<&str>,
+    title: Option<&str>,
+) -> Result<Option<PathBuf>, String> {
+    let target = thumbnails_dir(app_data_dir).join(format!("{}.png", document_fingerprint(path)?));
+    if target.exists() {
+        return Ok(Some(target));
+    }
+    let doc_type = doc_type_of(path, doc_type);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let Some(img) = generate_cover(path, &doc_type, title.unwrap_or(&stem)) else {
+        return Ok(None);
+    };
+    save_thumbnail(&img, &target)?;
+    Ok(Some(target))
+}
+
+/// Returns the cached cover thumbnail for a document, generating it on first use.
+/// `None` means the frontend should render the first PDF page and pass it to `library_save_thumbnail`.
+#[tauri::command]
+pub async fn library_thumbnail(
+    state: State<'_, AppState>,
+    path: String,
+    doc_type: Option<String>,
+    title: Option<String>,
+) -> Result<Option<String>, String> {
+    let app_data_dir = state.app_data_dir.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        thumbnail_impl(&app_data_dir, Path::new(&path), doc_type.as_deref(), title.as_deref())
+            .map(|p| p.map(|p| p.to_string_lossy().to_string()))
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Stores an image rendered by the frontend (the first PDF page) as the document's thumbnail.
+/// The image is the raw request body and the document path a percent-encoded `path` header,
+/// so the bytes do not go through JSON.
+#[tauri::command]
+pub async fn library_save_thumbnail(state: State<'_, AppState>, request: Request<'_>) -> Result<String, String> {
+    let InvokeBody::Raw(data) = request.body() else {
+        return Err("thumbnail data must be sent as raw bytes".to_string());
+    };
+    let data = data.clone();
+    let path = request
+        .headers()
+        .get("path")
+        .and_then(|v| v.to_str().ok())
+        .map(percent_decode)
+        .ok_or_else(|| "missing path header".to_string())?;
+    let app_data_dir = state.app_data_dir.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let target = thumbnails_dir(&app_data_dir).join(format!("{}.png", document_fingerprint(Path::new(&path))?));
+        let img = image::load_from_memory(&data).map_err(|e| e.to_string())?;
+        save_thumbnail(&img, &target)?;
+        Ok(target.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use std::io::{Cursor, Write};
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("covers_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn encoded(img: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        img.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn placeholder_is_stable_per_title() {
+        let a = placeholder_cover("Walden", "txt");
+        let b = placeholder_cover("Walden", "txt");
+        assert_eq!(a.as_bytes(), b.as_bytes());
+        assert_eq!(a.dimensions(), (THUMB_WIDTH, THUMB_HEIGHT));
+    }
+
+    #[test]
+    fn finds_first_page_jpeg_in_pdf() {
+        let small = encoded(&DynamicImage::new_rgb8(16, 16), ImageFormat::Jpeg);
+        let page = encoded(&DynamicImage::new_rgb8(600, 800), ImageFormat::Jpeg);
+        let mut pdf = b"%PDF-1.4\n1 0 obj\n<< /Type /XObject /Subtype /Image /Filter /DCTDecode >>\nstream\n".to_vec();
+        pdf.extend_from_slice(&small);
+        pdf.extend_from_slice(b"\nendstream\nendobj\n2 0 obj\n<< /Length 10 >>\nstream\nBT ET\nendstream\nendobj\n");
+        pdf.extend_from_slice(b"3 0 obj\n<< /Subtype /Image /Width 600 /Filter /DCTDecode >>\nstream\r\n");
+        pdf.extend_from_slice(&page);
+        pdf.extend_from_slice(b"\nendstream\nendobj\n%%EOF");
+
+        let img = first_pdf_jpeg(&pdf).unwrap();
+        assert_eq!(img.dimensions(), (600, 800));
+        assert!(first_pdf_jpeg(b"%PDF-1.4\n%%EOF").is_none());
+    }
+
+    #[test]
+    fn caches_thumbnails_by_fingerprint() {
+        let dir = temp_dir();
+        let doc = dir.join("notes.md");
+        std::fs::write(&doc, "# Notes").unwrap();
+
+        let first = thumbnail_impl(&dir, &doc, None, None).unwrap().unwrap();
+        assert!(first.starts_with(thumbnails_dir(&dir)));
+        let img = image::open(&first).unwrap();
+        assert_eq!(img.dimensions(), (THUMB_WIDTH, THUMB_HEIGHT));
+        assert_eq!(thumbnail_impl(&dir, &doc, Some("md"), None).unwrap().unwrap(), first);
+
+        std::fs::write(&doc, "# Notes, revised").unwrap();
+        assert_ne!(thumbnail_impl(&dir, &doc, None, None).unwrap().unwrap(), first);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn extracts_and_resizes_epub_cover() {
+        use zip::write::SimpleFileOptions;
+
+        let dir = temp_dir();
+        let path = dir.join("book.epub");
+        {
+            let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+            let opts = SimpleFileOptions::default();
+            let cover = encoded(&DynamicImage::new_rgb8(800, 1200), ImageFormat::Png);
+            let files: [(&str, &[u8]); 3] = [
+                (
+                    "META-INF/container.xml",
+                    br#"<container><rootfiles><rootfile full-path="OPS/book.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+                ),
+                (
+                    "OPS/book.opf",
+                    br#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>T</dc:title></metadata><manifest><item id="c" href="img/c.png" media-type="image/png" properties="cover-image"/></manifest><spine/></package>"#,
+                ),
+                ("OPS/img/c.png", &cover),
+            ];
+            for (name, body) in files {
+                zip.start_file(name, opts).unwrap();
+                zip.write_all(body).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let thumb = thumbnail_impl(&dir, &path, None, None).unwrap().unwrap();
+        assert_eq!(image::open(&thumb).unwrap().dimensions(), (THUMB_WIDTH, THUMB_HEIGHT));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
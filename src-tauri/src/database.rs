@@ -51,6 +51,46 @@ impl Database {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS glossary (
+                id TEXT PRIMARY KEY,
+                document_id TEXT,
+                term TEXT NOT NULL,
+                translation TEXT NOT NULL,
+                note TEXT,
+                case_sensitive INTEGER DEFAULT 0,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_glossary_document ON glossary(document_id)",
+            [],
+        )?;
+
+        // Sentence index of the library for example lookups. The porter tokenizer lets a
+        // lemma match its regular inflections.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS concordance_documents (
+                document_id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                doc_type TEXT NOT NULL,
+                sentence_count INTEGER DEFAULT 0,
+                indexed_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS concordance USING fts5(
+                sentence,
+                document_id UNINDEXED,
+                location UNINDEXED,
+                tokenize = 'porter unicode61'
+            )",
+            [],
+        )?;
+
         Ok(Self { conn: Mutex::new(conn) })
     }
 
@@ -58,6 +98,9 @@ impl Database {
         let conn = self.conn.lock().unwrap();
         conn.execute("DELETE FROM notes", [])?;
         conn.execute("DELETE FROM documents", [])?;
+        conn.execute("DELETE FROM glossary", [])?;
+        conn.execute("DELETE FROM concordance_documents", [])?;
+        conn.execute("DELETE FROM concordance", [])?;
         conn.execute_batch("VACUUM;")?;
         Ok(())
     }
@@ -157,6 +200,163 @@ impl Database {
             params![new_document_id, old_document_id],
         )
     }
+
+    pub fn save_glossary_entry(&self, entry: &GlossaryEntry) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO glossary
+            (id, document_id, term, translation, note, case_sensitive, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                entry.id,
+                entry.document_id,
+                entry.term,
+                entry.translation,
+                entry.note,
+                entry.case_sensitive as i32,
+                entry.created_at,
+                entry.updated_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Entries in exactly one scope: the global glossary (`None`) or one document's.
+    pub fn get_glossary_entries(&self, document_id: Option<&str>) -> Result<Vec<GlossaryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, document_id, term, translation, note, case_sensitive, created_at, updated_at
+             FROM glossary WHERE document_id IS ?1 ORDER BY term COLLATE NOCASE"
+        )?;
+
+        let entries = stmt.query_map([document_id], glossary_from_row)?;
+        entries.collect()
+    }
+
+    /// Entries that apply to a document: its own plus the global ones, document entries first.
+    pub fn get_applicable_glossary_entries(&self, document_id: Option<&str>) -> Result<Vec<GlossaryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, document_id, term, translation, note, case_sensitive, created_at, updated_at
+             FROM glossary WHERE document_id IS NULL OR document_id = ?1
+             ORDER BY document_id IS NULL, term COLLATE NOCASE"
+        )?;
+
+        let entries = stmt.query_map([document_id], glossary_from_row)?;
+        entries.collect()
+    }
+
+    pub fn delete_glossary_entry(&self, entry_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM glossary WHERE id = ?1", [entry_id])?;
+        Ok(())
+    }
+
+    /// Removes what is stored for a document deleted from the library.
+    pub fn delete_document_data(&self, document_id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM glossary WHERE document_id = ?1", [document_id])?;
+        tx.execute("DELETE FROM concordance WHERE document_id = ?1", [document_id])?;
+        tx.execute("DELETE FROM concordance_documents WHERE document_id = ?1", [document_id])?;
+        tx.commit()
+    }
+
+    pub fn reassign_glossary_document(&self, old_document_id: &str, new_document_id: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE glossary SET document_id = ?1 WHERE document_id = ?2",
+            params![new_document_id, old_document_id],
+        )
+    }
+
+    /// Replaces a document's sentences in the concordance index. `sentences` are
+    /// (location, sentence) pairs.
+    pub fn replace_concordance_document(
+        &self,
+        document_id: &str,
+        title: &str,
+        doc_type: &str,
+        sentences: &[(String, String)],
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM concordance WHERE document_id = ?1", [document_id])?;
+        {
+            let mut stmt = tx.prepare("INSERT INTO concordance(sentence, document_id, location) VALUES (?1, ?2, ?3)")?;
+            for (location, sentence) in sentences {
+                stmt.execute(params![sentence, document_id, location])?;
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO concordance_documents (document_id, title, doc_type, sentence_count, indexed_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![document_id, title, doc_type, sentences.len() as i64, chrono::Utc::now().to_rfc3339()],
+        )?;
+        tx.commit()?;
+        Ok(sentences.len())
+    }
+
+    pub fn is_concordance_document_indexed(&self, document_id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM concordance_documents WHERE document_id = ?1)",
+            [document_id],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn delete_concordance_document(&self, document_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM concordance WHERE document_id = ?1", [document_id])?;
+        conn.execute("DELETE FROM concordance_documents WHERE document_id = ?1", [document_id])?;
+        Ok(())
+    }
+
+    pub fn reassign_concordance_document(&self, old_document_id: &str, new_document_id: &str) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE concordance_documents SET document_id = ?1 WHERE document_id = ?2",
+            params![new_document_id, old_document_id],
+        )?;
+        conn.execute(
+            "UPDATE concordance SET document_id = ?1 WHERE document_id = ?2",
+            params![new_document_id, old_document_id],
+        )
+    }
+
+    /// Best-ranked sentences for an FTS5 `MATCH` expression, with their document titles.
+    pub fn search_concordance(&self, match_query: &str, limit: usize) -> Result<Vec<ConcordanceHit>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT c.sentence, c.document_id, d.title, c.location
+             FROM concordance c JOIN concordance_documents d ON d.document_id = c.document_id
+             WHERE concordance MATCH ?1 ORDER BY rank LIMIT ?2"
+        )?;
+
+        let hits = stmt.query_map(params![match_query, limit as i64], |row| {
+            Ok(ConcordanceHit {
+                sentence: row.get(0)?,
+                document_id: row.get(1)?,
+                title: row.get(2)?,
+                location: row.get(3)?,
+            })
+        })?;
+        hits.collect()
+    }
+}
+
+fn glossary_from_row(row: &rusqlite::Row) -> Result<GlossaryEntry> {
+    Ok(GlossaryEntry {
+        id: row.get(0)?,
+        document_id: row.get(1)?,
+        term: row.get(2)?,
+        translation: row.get(3)?,
+        note: row.get(4)?,
+        case_sensitive: row.get::<_, i32>(5)? != 0,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
 }
 
 #[cfg(test)]
@@ -231,6 +431,34 @@ mod tests {
         assert!(notes.is_empty());
     }
 
+    #[test]
+    fn test_concordance_replace_search_and_reassign() {
+        let db = make_db();
+        let sentences = vec![
+            ("line:1".to_string(), "They were running late.".to_string()),
+            ("line:2".to_string(), "Nothing to see here.".to_string()),
+        ];
+        assert_eq!(db.replace_concordance_document("doc1", "Book", "txt", &sentences).unwrap(), 2);
+        // Re-indexing replaces rather than appends.
+        db.replace_concordance_document("doc1", "Book", "txt", &sentences).unwrap();
+
+        let hits = db.search_concordance("\"run\"", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].title, "Book");
+        assert_eq!(hits[0].location, "line:1");
+
+        db.reassign_concordance_document("doc1", "doc2").unwrap();
+        assert_eq!(db.search_concordance("\"see\"", 10).unwrap()[0].document_id, "doc2");
+        db.delete_concordance_document("doc2").unwrap();
+        assert!(db.search_concordance("\"see\"", 10).unwrap().is_empty());
+
+        db.replace_concordance_document("doc3", "Other", "txt", &sentences).unwrap();
+        assert!(db.is_concordance_document_indexed("doc3").unwrap());
+        db.delete_document_data("doc3").unwrap();
+        assert!(db.search_concordance("\"see\"", 10).unwrap().is_empty());
+        assert!(!db.is_concordance_document_indexed("doc3").unwrap());
+    }
+
     #[test]
     fn test_delete_nonexistent_note() {
         let db = make_db();
@@ -286,6 +514,49 @@ mod tests {
         assert_eq!(other.len(), 1);
     }
 
+    fn sample_glossary(id: &str, doc_id: Option<&str>, term: &str) -> GlossaryEntry {
+        GlossaryEntry {
+            id: id.to_string(),
+            document_id: doc_id.map(|s| s.to_string()),
+            term: term.to_string(),
+            translation: "译".to_string(),
+            note: None,
+            case_sensitive: false,
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+            updated_at: "2025-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_glossary_scopes() {
+        let db = make_db();
+        db.save_glossary_entry(&sample_glossary("g1", None, "kernel")).unwrap();
+        db.save_glossary_entry(&sample_glossary("g2", Some("doc1"), "thread")).unwrap();
+        db.save_glossary_entry(&sample_glossary("g3", Some("doc2"), "actor")).unwrap();
+
+        let global = db.get_glossary_entries(None).unwrap();
+        assert_eq!(global.len(), 1);
+        assert_eq!(global[0].id, "g1");
+
+        let doc1 = db.get_glossary_entries(Some("doc1")).unwrap();
+        assert_eq!(doc1.len(), 1);
+        assert_eq!(doc1[0].term, "thread");
+
+        let applicable = db.get_applicable_glossary_entries(Some("doc1")).unwrap();
+        let ids: Vec<&str> = applicable.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["g2", "g1"]);
+
+        db.reassign_glossary_document("doc1", "doc3").unwrap();
+        assert!(db.get_glossary_entries(Some("doc1")).unwrap().is_empty());
+
+        db.delete_glossary_entry("g1").unwrap();
+        assert!(db.get_glossary_entries(None).unwrap().is_empty());
+
+        db.delete_document_data("doc2").unwrap();
+        assert!(db.get_glossary_entries(Some("doc2")).unwrap().is_empty());
+        assert_eq!(db.get_glossary_entries(Some("doc3")).unwrap().len(), 1);
+    }
+
     #[test]
     fn test_clear_all() {
         let db = make_db();
@@ -311,3 +582,26 @@ pub struct NoteData {
     pub created_at: String,
     pub updated_at: String,
 }
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GlossaryEntry {
+    pub id: String,
+    /// `None` for the global glossary.
+    pub document_id: Option<String>,
+    pub term: String,
+    pub translation: String,
+    pub note: Option<String>,
+    pub case_sensitive: bool,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ConcordanceHit {
+    pub sentence: String,
+    pub document_id: String,
+    pub title: String,
+    /// Where the sentence is in the document: `line:N` for text files, otherwise whatever
+    /// the reader supplied when indexing (`page:N` for PDF, a chapter href or CFI for EPUB).
+    pub location: String,
+}
@@ -1,23 +1,83 @@
 use serde::Serialize;
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use tauri::{AppHandle, Manager, State};
 use stardict::StarDict;
 use rusqlite::{Connection, OptionalExtension, OpenFlags};
 use zip::ZipArchive;
 
+use crate::dictionary_registry::{lookup_first, read_ifo_field, DictionaryDirection, DictionaryFormat, CEDICT_ID, ECDICT_ID};
+use crate::ecdict_reverse::{build_reverse_index, index_is_current, merge_reverse_matches, MERGED_MATCHES, reverse_db_path, reverse_lookup_impl, ReverseIndex};
+use crate::glossary::glossary_lookup_impl;
+use crate::lookup_cache::{LookupCache, DEFAULT_CAPACITY};
+use crate::meanings::{definition_lines, group_definition_lines, phonetic_line};
+use crate::pinyin::{numbered_to_marked, syllables, PinyinSyllable};
+use crate::zh_convert::ScriptConverter;
+use crate::zh_segment::Segmenter;
 use crate::AppState;
 
 #[derive(Debug, Serialize)]
 pub struct DictionaryStatus {
     pub installed: bool,
-    pub ifo_path: Option<String>,
+    /// The `.ifo` file of a StarDict install, or the SQLite database.
+    pub path: Option<String>,
+    pub format: Option<DictionaryFormat>,
+    pub entry_count: Option<u64>,
+    /// Source version (`#! version=` in CC-CEDICT, `version=` in a StarDict `.ifo`).
+    pub version: Option<String>,
+    /// Source publication date, falling back to when the source file was last modified.
+    pub date: Option<String>,
+    /// Total size of the dictionary folder on disk.
+    pub size_bytes: u64,
+}
+
+impl DictionaryStatus {
+    fn missing() -> Self {
+        Self {
+            installed: false,
+            path: None,
+            format: None,
+            entry_count: None,
+            version: None,
+            date: None,
+            size_bytes: 0,
+        }
+    }
+
+    fn stardict(ifo: &Path, root: &Path) -> Self {
+        Self {
+            installed: true,
+            path: Some(ifo.to_string_lossy().to_string()),
+            format: Some(DictionaryFormat::Stardict),
+            entry_count: read_ifo_field(ifo, "wordcount").and_then(|v| v.parse().ok()),
+            version: read_ifo_field(ifo, "version"),
+            date: read_ifo_field(ifo, "date").or_else(|| file_date(ifo)),
+            size_bytes: dir_size(root),
+        }
+    }
+
+    fn sqlite(db: &Path, root: &Path) -> Self {
+        let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY).ok();
+        let meta = |key: &str| conn.as_ref().and_then(|c| read_meta(c, key));
+        Self {
+            installed: true,
+            path: Some(db.to_string_lossy().to_string()),
+            format: Some(DictionaryFormat::Sqlite),
+            entry_count: conn.as_ref().and_then(sqlite_entry_count),
+            version: meta("version"),
+            date: meta("date").or_else(|| file_date(db)),
+            size_bytes: dir_size(root),
+        }
+    }
 }
 
 pub struct CedictManager {
     db_path: Mutex<Option<PathBuf>>,
     db: Mutex<Option<Connection>>,
+    segmenter: Mutex<Option<Arc<Segmenter>>>,
+    converter: Mutex<Option<Arc<ScriptConverter>>>,
+    pub(crate) cache: LookupCache,
 }
 
 impl CedictManager {
@@ -25,17 +85,26 @@ impl CedictManager {
         Self {
             db_path: Mutex::new(None),
             db: Mutex::new(None),
+            segmenter: Mutex::new(None),
+            converter: Mutex::new(None),
+            cache: LookupCache::new(DEFAULT_CAPACITY),
         }
     }
 
     pub fn reset(&self) {
         *self.db_path.lock().unwrap() = None;
         *self.db.lock().unwrap() = None;
+        *self.segmenter.lock().unwrap() = None;
+        *self.converter.lock().unwrap() = None;
+        self.cache.clear();
     }
 
     fn set_db_path(&self, path: PathBuf) {
         *self.db_path.lock().unwrap() = Some(path);
         *self.db.lock().unwrap() = None;
+        *self.segmenter.lock().unwrap() = None;
+        *self.converter.lock().unwrap() = None;
+        self.cache.clear();
     }
 
     fn get_db_path(&self) -> Option<PathBuf> {
@@ -53,77 +122,108 @@ impl CedictManager {
         Ok(())
     }
 
-    fn lookup(&self, word: &str) -> Result<Option<DictionaryResult>, String> {
+    pub(crate) fn with_conn<T>(&self, f: impl FnOnce(&Connection) -> Result<T, String>) -> Result<T, String> {
         let db_path = self
             .get_db_path()
             .ok_or_else(|| "cedict not installed".to_string())?;
         self.load_db_if_needed(&db_path)?;
 
-        let mut guard = self.db.lock().unwrap();
-        let conn = guard.as_mut().ok_or_else(|| "cedict db not loaded".to_string())?;
+        let guard = self.db.lock().unwrap();
+        let conn = guard.as_ref().ok_or_else(|| "cedict db not loaded".to_string())?;
+        f(conn)
+    }
 
-        let mut stmt = conn
-            .prepare_cached(
-                "SELECT simplified, traditional, pinyin, defs FROM entries WHERE simplified = ?1 OR traditional = ?1 LIMIT 20",
-            )
-            .map_err(|e| e.to_string())?;
+    fn lookup(&self, word: &str) -> Result<Option<DictionaryResult>, String> {
+        self.cache
+            .get_or_lookup(word, || self.with_conn(|conn| lookup_cedict_conn(conn, word)))
+    }
 
-        let mut rows = stmt.query([word]).map_err(|e| e.to_string())?;
+    /// The word segmenter, built from the entries table on first use.
+    pub(crate) fn segmenter(&self) -> Result<Arc<Segmenter>, String> {
+        if let Some(seg) = self.segmenter.lock().unwrap().as_ref() {
+            return Ok(seg.clone());
+        }
+        let seg = Arc::new(self.with_conn(Segmenter::from_cedict)?);
+        *self.segmenter.lock().unwrap() = Some(seg.clone());
+        Ok(seg)
+    }
 
-        let mut pinyin: Option<String> = None;
-        let mut defs: Vec<String> = vec![];
+    /// The simplified/traditional converter, built from the entries table on first use.
+    pub(crate) fn script_converter(&self) -> Result<Arc<ScriptConverter>, String> {
+        if let Some(conv) = self.converter.lock().unwrap().as_ref() {
+            return Ok(conv.clone());
+        }
+        let conv = Arc::new(self.with_conn(ScriptConverter::from_cedict)?);
+        *self.converter.lock().unwrap() = Some(conv.clone());
+        Ok(conv)
+    }
+}
 
-        while let Some(r) = rows.next().map_err(|e| e.to_string())? {
-            let row_pinyin: Option<String> = r.get(2).ok();
-            if pinyin.is_none() {
-                if let Some(p) = row_pinyin {
-                    let p = p.trim().to_string();
-                    if !p.is_empty() {
-                        pinyin = Some(p);
-                    }
+/// Looks up a word in a CC-CEDICT style `entries(simplified, traditional, pinyin, defs)` table.
+pub(crate) fn lookup_cedict_conn(conn: &Connection, word: &str) -> Result<Option<DictionaryResult>, String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT simplified, traditional, pinyin, defs FROM entries WHERE simplified = ?1 OR traditional = ?1 LIMIT 20",
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut rows = stmt.query([word]).map_err(|e| e.to_string())?;
+
+    let mut pinyin: Option<String> = None;
+    let mut defs: Vec<String> = vec![];
+
+    while let Some(r) = rows.next().map_err(|e| e.to_string())? {
+        let row_pinyin: Option<String> = r.get(2).ok();
+        if pinyin.is_none() {
+            if let Some(p) = row_pinyin {
+                let p = p.trim().to_string();
+                if !p.is_empty() {
+                    pinyin = Some(p);
                 }
             }
+        }
 
-            let defs_raw: String = r.get(3).unwrap_or_default();
-            for d in defs_raw
-                .split('\n')
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty())
-            {
-                if !defs.contains(&d.to_string()) {
-                    defs.push(d.to_string());
-                }
+        let defs_raw: String = r.get(3).unwrap_or_default();
+        for d in defs_raw
+            .split('\n')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+        {
+            if !defs.contains(&d.to_string()) {
+                defs.push(d.to_string());
             }
         }
+    }
 
-        if defs.is_empty() {
-            return Ok(None);
-        }
+    if defs.is_empty() {
+        return Ok(None);
+    }
 
-        let translation = defs.get(0).cloned();
-        let rest = if defs.len() > 1 { defs[1..].to_vec() } else { vec![] };
+    let translation = defs.first().cloned();
+    let rest = if defs.len() > 1 { defs[1..].to_vec() } else { vec![] };
 
-        let meanings = if rest.is_empty() {
-            vec![]
-        } else {
-            vec![DictionaryMeaning {
-                part_of_speech: "".to_string(),
-                definitions: rest,
-                examples: vec![],
-            }]
-        };
+    let meanings = if rest.is_empty() {
+        vec![]
+    } else {
+        vec![DictionaryMeaning {
+            part_of_speech: "".to_string(),
+            definitions: rest,
+            examples: vec![],
+        }]
+    };
 
-        Ok(Some(DictionaryResult {
-            word: word.to_string(),
-            phonetic: pinyin,
-            audio_url: None,
-            translation,
-            meanings,
-        }))
-    }
+    Ok(Some(DictionaryResult {
+        word: word.to_string(),
+        phonetic: pinyin.as_deref().map(numbered_to_marked),
+        audio_url: None,
+        translation,
+        meanings,
+        html: None,
+        pinyin: pinyin.as_deref().map(syllables),
+    }))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DictionaryMeaning {
     #[serde(rename = "partOfSpeech")]
     pub part_of_speech: String,
@@ -131,7 +231,7 @@ pub struct DictionaryMeaning {
     pub examples: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DictionaryResult {
     pub word: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -141,6 +241,12 @@ pub struct DictionaryResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub translation: Option<String>,
     pub meanings: Vec<DictionaryMeaning>,
+    /// Sanitized HTML definition for sources that ship rich markup (MDict).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub html: Option<String>,
+    /// Per-syllable pinyin (tone marks, zhuyin, tone numbers) for CEDICT results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinyin: Option<Vec<PinyinSyllable>>,
 }
 
 pub struct DictionaryManager {
@@ -148,6 +254,8 @@ pub struct DictionaryManager {
     ifo_path: Mutex<Option<PathBuf>>,
     db_path: Mutex<Option<PathBuf>>,
     db: Mutex<Option<Connection>>,
+    pub(crate) reverse: ReverseIndex,
+    pub(crate) cache: LookupCache,
 }
 
 impl DictionaryManager {
@@ -157,6 +265,8 @@ impl DictionaryManager {
             ifo_path: Mutex::new(None),
             db_path: Mutex::new(None),
             db: Mutex::new(None),
+            reverse: ReverseIndex::new(),
+            cache: LookupCache::new(DEFAULT_CAPACITY),
         }
     }
 
@@ -165,6 +275,8 @@ impl DictionaryManager {
         *self.ifo_path.lock().unwrap() = None;
         *self.db_path.lock().unwrap() = None;
         *self.db.lock().unwrap() = None;
+        self.reverse.reset();
+        self.cache.clear();
     }
 
     fn set_ifo_path(&self, path: PathBuf) {
@@ -172,6 +284,7 @@ impl DictionaryManager {
         *self.dict.lock().unwrap() = None;
         *self.db_path.lock().unwrap() = None;
         *self.db.lock().unwrap() = None;
+        self.cache.clear();
     }
 
     fn set_db_path(&self, path: PathBuf) {
@@ -179,13 +292,15 @@ impl DictionaryManager {
         *self.db.lock().unwrap() = None;
         *self.ifo_path.lock().unwrap() = None;
         *self.dict.lock().unwrap() = None;
+        self.reverse.reset();
+        self.cache.clear();
     }
 
     fn get_ifo_path(&self) -> Option<PathBuf> {
         self.ifo_path.lock().unwrap().clone()
     }
 
-    fn get_db_path(&self) -> Option<PathBuf> {
+    pub(crate) fn get_db_path(&self) -> Option<PathBuf> {
         self.db_path.lock().unwrap().clone()
     }
 
@@ -199,7 +314,11 @@ impl DictionaryManager {
         Ok(())
     }
 
-    fn lookup(&self, word: &str) -> Result<Option<Vec<stardict::WordDefinition>>, String> {
+    /// Runs `f` with the loaded StarDict, holding the lock for the whole call.
+    pub(crate) fn with_stardict<T>(
+        &self,
+        f: impl FnOnce(&mut stardict::StarDictStd) -> Result<T, String>,
+    ) -> Result<T, String> {
         let ifo = self
             .get_ifo_path()
             .ok_or_else(|| "dictionary not installed".to_string())?;
@@ -207,8 +326,14 @@ impl DictionaryManager {
 
         let mut guard = self.dict.lock().unwrap();
         let dict = guard.as_mut().ok_or_else(|| "dictionary not loaded".to_string())?;
-        dict.lookup(word)
-            .map_err(|e| format!("stardict lookup failed: {e:?}"))
+        f(dict)
+    }
+
+    fn lookup(&self, word: &str) -> Result<Option<Vec<stardict::WordDefinition>>, String> {
+        self.with_stardict(|dict| {
+            dict.lookup(word)
+                .map_err(|e| format!("stardict lookup failed: {e:?}"))
+        })
     }
 
     fn load_db_if_needed(&self, db_path: &Path) -> Result<(), String> {
@@ -222,21 +347,61 @@ impl DictionaryManager {
         Ok(())
     }
 
-    fn lookup_db(&self, word: &str) -> Result<Option<DictionaryResult>, String> {
+    /// Runs `f` with the ECDICT SQLite connection, holding the lock for the whole call.
+    pub(crate) fn with_db<T>(&self, f: impl FnOnce(&Connection) -> Result<T, String>) -> Result<T, String> {
         let db_path = self
             .get_db_path()
             .ok_or_else(|| "dictionary not installed".to_string())?;
         self.load_db_if_needed(&db_path)?;
 
-        let mut guard = self.db.lock().unwrap();
-        let conn = guard.as_mut().ok_or_else(|| "dictionary db not loaded".to_string())?;
-        let mut row: Option<(String, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>)>;
-
-        let try_entries = || -> Result<Option<(String, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>)>, rusqlite::Error> {
-            let mut stmt = conn.prepare_cached(
-                "SELECT word, phonetic, definition, translation, pos, audio FROM entries WHERE word = ?1 LIMIT 1",
-            )?;
-            stmt.query_row([word], |r| {
+        let guard = self.db.lock().unwrap();
+        let conn = guard.as_ref().ok_or_else(|| "dictionary db not loaded".to_string())?;
+        f(conn)
+    }
+
+    pub(crate) fn uses_db(&self) -> bool {
+        self.get_db_path().is_some()
+    }
+
+    fn lookup_db(&self, word: &str) -> Result<Option<DictionaryResult>, String> {
+        self.with_db(|conn| lookup_sqlite_conn(conn, word))
+    }
+}
+
+/// Looks up a word in an ECDICT style SQLite database (`entries` or `stardict` table).
+pub(crate) fn lookup_sqlite_conn(conn: &Connection, word: &str) -> Result<Option<DictionaryResult>, String> {
+    let mut row: Option<(String, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>)>;
+
+    let try_entries = || -> Result<Option<(String, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>)>, rusqlite::Error> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT word, phonetic, definition, translation, pos, audio FROM entries WHERE word = ?1 LIMIT 1",
+        )?;
+        stmt.query_row([word], |r| {
+            Ok((
+                r.get(0)?,
+                r.get(1)?,
+                r.get(2)?,
+                r.get(3)?,
+                r.get(4)?,
+                r.get(5)?,
+            ))
+        })
+        .optional()
+    };
+
+    row = match try_entries() {
+        Ok(r) => r,
+        Err(_) => None,
+    };
+
+    if row.is_none() {
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT word, phonetic, definition, translation, pos, audio FROM stardict WHERE word = ?1 COLLATE NOCASE LIMIT 1",
+            )
+            .map_err(|e| e.to_string())?;
+        row = stmt
+            .query_row([word], |r| {
                 Ok((
                     r.get(0)?,
                     r.get(1)?,
@@ -247,76 +412,36 @@ impl DictionaryManager {
                 ))
             })
             .optional()
-        };
-
-        row = match try_entries() {
-            Ok(r) => r,
-            Err(_) => None,
-        };
-
-        if row.is_none() {
-            let mut stmt = conn
-                .prepare_cached(
-                    "SELECT word, phonetic, definition, translation, pos, audio FROM stardict WHERE word = ?1 COLLATE NOCASE LIMIT 1",
-                )
-                .map_err(|e| e.to_string())?;
-            row = stmt
-                .query_row([word], |r| {
-                    Ok((
-                        r.get(0)?,
-                        r.get(1)?,
-                        r.get(2)?,
-                        r.get(3)?,
-                        r.get(4)?,
-                        r.get(5)?,
-                    ))
-                })
-                .optional()
-                .map_err(|e| e.to_string())?;
-        }
-
-        let Some((w, phonetic, definition, translation, pos, audio)) = row else {
-            return Ok(None);
-        };
-
-        let t = clean_definition_text(&translation.unwrap_or_default());
-        let d = clean_definition_text(&definition.unwrap_or_default());
+            .map_err(|e| e.to_string())?;
+    }
 
-        let mut lines: Vec<String> = vec![];
-        for s in t.split('\n').map(|x| x.trim()).filter(|x| !x.is_empty()) {
-            lines.push(s.to_string());
-        }
-        if lines.is_empty() {
-            for s in d.split('\n').map(|x| x.trim()).filter(|x| !x.is_empty()) {
-                lines.push(s.to_string());
-            }
-        }
+    let Some((w, phonetic, definition, translation, _pos, audio)) = row else {
+        return Ok(None);
+    };
 
-        let translation_first = lines.get(0).cloned();
-        let rest = if lines.len() > 1 {
-            lines[1..].to_vec()
-        } else {
-            vec![]
-        };
+    let mut lines = definition_lines(&translation.unwrap_or_default());
+    if lines.is_empty() {
+        lines = definition_lines(&definition.unwrap_or_default());
+    }
 
-        let meanings = if rest.is_empty() {
-            vec![]
-        } else {
-            vec![DictionaryMeaning {
-                part_of_speech: pos.unwrap_or_default(),
-                definitions: rest,
-                examples: vec![],
-            }]
-        };
+    // The first line is shown as the summary translation; the rest are grouped by the
+    // part of speech written on each line (the `pos` column only holds usage ratios).
+    let translation_first = lines.first().cloned();
+    let meanings = if lines.len() > 1 {
+        group_definition_lines(&lines[1..])
+    } else {
+        vec![]
+    };
 
-        Ok(Some(DictionaryResult {
-            word: w,
-            phonetic,
-            audio_url: audio,
-            translation: translation_first,
-            meanings,
-        }))
-    }
+    Ok(Some(DictionaryResult {
+        word: w,
+        phonetic,
+        audio_url: audio,
+        translation: translation_first,
+        meanings,
+        html: None,
+        pinyin: None,
+    }))
 }
 
 fn ecdict_root(dictionaries_dir: &Path) -> PathBuf {
@@ -335,6 +460,76 @@ fn cedict_db_path(root: &Path) -> PathBuf {
     root.join("cedict.sqlite")
 }
 
+/// Left in the dictionaries dir when a built-in dictionary is uninstalled, so it is not
+/// reinstalled from the bundled archive on the next start.
+fn uninstalled_marker(dictionaries_dir: &Path, id: &str) -> PathBuf {
+    dictionaries_dir.join(format!(".{}-uninstalled", id))
+}
+
+pub(crate) fn dir_size(root: &Path) -> u64 {
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn file_date(path: &Path) -> Option<String> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(chrono::DateTime::<chrono::Utc>::from(modified).format("%Y-%m-%d").to_string())
+}
+
+/// Creates the `meta(key, value)` table the built-in databases use for source information.
+pub(crate) fn write_meta(conn: &Connection, values: &[(&str, Option<String>)]) -> Result<(), String> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT);")
+        .map_err(|e| e.to_string())?;
+    for (key, value) in values {
+        if let Some(value) = value {
+            conn.execute("INSERT OR REPLACE INTO meta(key, value) VALUES (?1, ?2)", rusqlite::params![key, value])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+fn read_meta(conn: &Connection, key: &str) -> Option<String> {
+    conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |r| r.get(0))
+        .optional()
+        .ok()
+        .flatten()
+}
+
+fn sqlite_entry_count(conn: &Connection) -> Option<u64> {
+    ["entries", "stardict"].iter().find_map(|table| {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get::<_, i64>(0))
+            .ok()
+            .map(|n| n as u64)
+    })
+}
+
+/// Builds a database at `<target>.tmp` so the installed one keeps serving lookups meanwhile.
+fn build_to_temp(target: &Path, build: impl FnOnce(&Path) -> Result<(), String>) -> Result<PathBuf, String> {
+    let tmp = target.with_extension("sqlite.tmp");
+    let _ = std::fs::remove_file(&tmp);
+    if let Err(e) = build(&tmp) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(tmp)
+}
+
+/// Replaces `target` with a freshly built `tmp`. `release` drops cached connections to the old
+/// file (Windows refuses to replace open files) and runs again afterwards so nothing built
+/// from the old database survives the swap.
+fn swap_database(tmp: &Path, target: &Path, release: impl Fn()) -> Result<(), String> {
+    release();
+    std::fs::rename(tmp, target).map_err(|e| e.to_string())?;
+    release();
+    Ok(())
+}
+
 fn find_first_u8(root: &Path) -> Option<PathBuf> {
     for entry in walkdir::WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
         if !entry.file_type().is_file() {
@@ -351,7 +546,7 @@ fn find_first_u8(root: &Path) -> Option<PathBuf> {
     None
 }
 
-fn extract_zip_to(zip_path: &Path, dest: &Path) -> Result<(), String> {
+pub(crate) fn extract_zip_to(zip_path: &Path, dest: &Path) -> Result<(), String> {
     let f = std::fs::File::open(zip_path).map_err(|e| e.to_string())?;
     let mut archive = ZipArchive::new(f).map_err(|e| e.to_string())?;
     for i in 0..archive.len() {
@@ -402,6 +597,8 @@ fn build_cedict_sqlite_from_u8(u8_path: &Path, db_path: &Path) -> Result<(), Str
 
     let f = std::fs::File::open(u8_path).map_err(|e| e.to_string())?;
     let reader = BufReader::new(f);
+    let mut version: Option<String> = None;
+    let mut date: Option<String> = None;
 
     let tx = conn.transaction().map_err(|e| e.to_string())?;
     {
@@ -412,6 +609,17 @@ fn build_cedict_sqlite_from_u8(u8_path: &Path, db_path: &Path) -> Result<(), Str
         for line in reader.lines() {
             let line = line.map_err(|e| e.to_string())?;
             let line = line.trim();
+            // Header lines: `#! version=1`, `#! date=2024-05-20T05:05:16Z`.
+            if let Some(header) = line.strip_prefix("#!") {
+                if let Some((key, value)) = header.split_once('=') {
+                    match key.trim() {
+                        "version" => version = Some(value.trim().to_string()),
+                        "date" => date = Some(value.trim().to_string()),
+                        _ => {}
+                    }
+                }
+                continue;
+            }
             if line.is_empty() || line.starts_with('#') {
                 continue;
             }
@@ -468,6 +676,7 @@ fn build_cedict_sqlite_from_u8(u8_path: &Path, db_path: &Path) -> Result<(), Str
             .map_err(|e| e.to_string())?;
         }
     }
+    write_meta(&tx, &[("version", version), ("date", date.or_else(|| file_date(u8_path)))])?;
     tx.commit().map_err(|e| e.to_string())?;
 
     Ok(())
@@ -486,7 +695,7 @@ fn find_first_ifo(root: &Path) -> Option<PathBuf> {
     None
 }
 
-fn clean_definition_text(input: &str) -> String {
+pub(crate) fn clean_definition_text(input: &str) -> String {
     // ECDICT StarDict often contains HTML. Make a best-effort cleanup.
     let input = input
         .replace("<br />", "\n")
@@ -606,16 +815,10 @@ pub fn cedict_status(state: State<AppState>) -> Result<DictionaryStatus, String>
     let db_path = cedict_db_path(&root);
     if db_path.exists() {
         state.cedict.set_db_path(db_path.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(db_path.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::sqlite(&db_path, &root));
     }
 
-    Ok(DictionaryStatus {
-        installed: false,
-        ifo_path: None,
-    })
+    Ok(DictionaryStatus::missing())
 }
 
 #[tauri::command]
@@ -623,16 +826,15 @@ pub async fn cedict_install(
     app: AppHandle,
     state: State<'_, AppState>,
 ) -> Result<DictionaryStatus, String> {
-    let root = cedict_root(&state.dictionaries_dir.read().unwrap());
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let _ = std::fs::remove_file(uninstalled_marker(&dict_dir, CEDICT_ID));
+    let root = cedict_root(&dict_dir);
     std::fs::create_dir_all(&root).map_err(|e| e.to_string())?;
 
     let db_path = cedict_db_path(&root);
     if db_path.exists() {
         state.cedict.set_db_path(db_path.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(db_path.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::sqlite(&db_path, &root));
     }
 
     let mut bundled: Option<PathBuf> = None;
@@ -702,28 +904,116 @@ pub async fn cedict_install(
         .map_err(|e| e.to_string())??;
 
     state.cedict.set_db_path(db_path.clone());
-    Ok(DictionaryStatus {
-        installed: true,
-        ifo_path: Some(db_path.to_string_lossy().to_string()),
+    Ok(DictionaryStatus::sqlite(&db_path, &root))
+}
+
+/// Removes the built-in CC-CEDICT. It is not reinstalled on startup until `cedict_install`.
+#[tauri::command]
+pub fn cedict_uninstall(state: State<AppState>) -> Result<DictionaryStatus, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    state.cedict.reset();
+    remove_builtin(&dict_dir, CEDICT_ID, &cedict_root(&dict_dir))?;
+    Ok(DictionaryStatus::missing())
+}
+
+/// Rebuilds the CC-CEDICT SQLite from the installed `cedict_ts.u8`.
+#[tauri::command]
+pub async fn cedict_rebuild(state: State<'_, AppState>) -> Result<DictionaryStatus, String> {
+    let root = cedict_root(&state.dictionaries_dir.read().unwrap());
+    let u8_path = find_first_u8(&root).ok_or_else(|| "cedict source .u8 not found; reinstall instead".to_string())?;
+    replace_cedict_db(&state, &root, u8_path).await
+}
+
+/// Replaces CC-CEDICT with a newer `cedict_ts.u8` chosen by the user. The current database
+/// keeps serving lookups until the new one is built and swapped in.
+#[tauri::command]
+pub async fn cedict_update_from_file(state: State<'_, AppState>, path: String) -> Result<DictionaryStatus, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let root = cedict_root(&dict_dir);
+    std::fs::create_dir_all(&root).map_err(|e| e.to_string())?;
+    let status = replace_cedict_db(&state, &root, PathBuf::from(&path)).await?;
+
+    // Keep the new source so later rebuilds use it. It is staged under a name `find_first_u8`
+    // skips, since the user may have picked a file inside the CEDICT folder.
+    let staged = root.join("cedict_ts.u8.new");
+    std::fs::copy(&path, &staged).map_err(|e| e.to_string())?;
+    while let Some(old) = find_first_u8(&root) {
+        std::fs::remove_file(&old).map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&staged, root.join("cedict_ts.u8")).map_err(|e| e.to_string())?;
+    let _ = std::fs::remove_file(uninstalled_marker(&dict_dir, CEDICT_ID));
+    Ok(status)
+}
+
+async fn replace_cedict_db(state: &AppState, root: &Path, u8_path: PathBuf) -> Result<DictionaryStatus, String> {
+    let db_path = cedict_db_path(root);
+    let target = db_path.clone();
+    let tmp = tauri::async_runtime::spawn_blocking(move || {
+        build_to_temp(&target, |tmp| build_cedict_sqlite_from_u8(&u8_path, tmp))
     })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    let count = Connection::open_with_flags(&tmp, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .ok()
+        .and_then(|c| sqlite_entry_count(&c))
+        .unwrap_or(0);
+    if count == 0 {
+        let _ = std::fs::remove_file(&tmp);
+        return Err("no CC-CEDICT entries found in source file".to_string());
+    }
+
+    swap_database(&tmp, &db_path, || state.cedict.set_db_path(db_path.clone()))?;
+    Ok(DictionaryStatus::sqlite(&db_path, root))
+}
+
+fn remove_builtin(dictionaries_dir: &Path, id: &str, root: &Path) -> Result<(), String> {
+    if root.exists() {
+        std::fs::remove_dir_all(root).map_err(|e| e.to_string())?;
+    }
+    std::fs::create_dir_all(dictionaries_dir).map_err(|e| e.to_string())?;
+    std::fs::write(uninstalled_marker(dictionaries_dir, id), b"").map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub fn cedict_lookup(state: State<AppState>, word: String) -> Result<Option<DictionaryResult>, String> {
+pub fn cedict_lookup(
+    state: State<AppState>,
+    word: String,
+    document_id: Option<String>,
+) -> Result<Option<DictionaryResult>, String> {
     let clean = word.trim();
     if clean.is_empty() {
         return Ok(None);
     }
+    if let Some(hit) = glossary_lookup_impl(&state, clean, document_id.as_deref()) {
+        return Ok(Some(hit));
+    }
+    let hit = lookup_first(&state, clean, &[DictionaryDirection::ZhEn, DictionaryDirection::ZhZh]);
+    let reverse = reverse_lookup_impl(&state, clean, MERGED_MATCHES);
+    match hit {
+        Ok(hit) => Ok(merge_reverse_matches(clean, hit, &reverse)),
+        Err(e) if reverse.is_empty() => Err(e),
+        Err(_) => Ok(merge_reverse_matches(clean, None, &reverse)),
+    }
+}
 
+/// Looks up `word` in the built-in CC-CEDICT, resolving the installed SQLite lazily.
+pub(crate) fn cedict_lookup_impl(state: &AppState, word: &str) -> Result<Option<DictionaryResult>, String> {
+    cedict_manager_ready(state)?;
+    state.cedict.lookup(word)
+}
+
+/// Points the CEDICT manager at the installed SQLite if it has not been resolved yet.
+pub(crate) fn cedict_manager_ready(state: &AppState) -> Result<(), String> {
     if state.cedict.get_db_path().is_none() {
         let root = cedict_root(&state.dictionaries_dir.read().unwrap());
         let db_path = cedict_db_path(&root);
-        if db_path.exists() {
-            state.cedict.set_db_path(db_path);
+        if !db_path.exists() {
+            return Err("cedict not installed".to_string());
         }
+        state.cedict.set_db_path(db_path);
     }
-
-    state.cedict.lookup(clean)
+    Ok(())
 }
 
 #[tauri::command]
@@ -733,33 +1023,21 @@ pub fn dictionary_status(state: State<AppState>) -> Result<DictionaryStatus, Str
 
     if let Some(ifo_path) = ifo {
         state.dictionary.set_ifo_path(ifo_path.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(ifo_path.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::stardict(&ifo_path, &root));
     }
 
     let db_path = ecdict_db_path(&root);
     if db_path.exists() {
         state.dictionary.set_db_path(db_path.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(db_path.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::sqlite(&db_path, &root));
     }
 
     if let Ok(Some(db)) = normalize_ecdict_sqlite(&root) {
         state.dictionary.set_db_path(db.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(db.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::sqlite(&db, &root));
     }
 
-    Ok(DictionaryStatus {
-        installed: false,
-        ifo_path: None,
-    })
+    Ok(DictionaryStatus::missing())
 }
 
 #[tauri::command]
@@ -767,25 +1045,21 @@ pub async fn dictionary_install_ecdict(
     app: AppHandle,
     state: State<'_, AppState>,
 ) -> Result<DictionaryStatus, String> {
-    let root = ecdict_root(&state.dictionaries_dir.read().unwrap());
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let _ = std::fs::remove_file(uninstalled_marker(&dict_dir, ECDICT_ID));
+    let root = ecdict_root(&dict_dir);
     std::fs::create_dir_all(&root).map_err(|e| e.to_string())?;
 
     // If already installed, return status
     if let Some(ifo) = find_first_ifo(&root) {
         state.dictionary.set_ifo_path(ifo.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(ifo.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::stardict(&ifo, &root));
     }
 
     let db_path = ecdict_db_path(&root);
     if db_path.exists() {
         state.dictionary.set_db_path(db_path.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(db_path.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::sqlite(&db_path, &root));
     }
 
     let mut archive_found: Option<PathBuf> = None;
@@ -811,10 +1085,7 @@ pub async fn dictionary_install_ecdict(
 
     if let Some(ifo) = find_first_ifo(&root) {
         state.dictionary.set_ifo_path(ifo.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(ifo.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::stardict(&ifo, &root));
     }
 
     let csv_path = root.join("stardict.csv");
@@ -828,10 +1099,7 @@ pub async fn dictionary_install_ecdict(
                 .map_err(|e| e.to_string())??;
         }
         state.dictionary.set_db_path(db_path2.clone());
-        return Ok(DictionaryStatus {
-            installed: true,
-            ifo_path: Some(db_path2.to_string_lossy().to_string()),
-        });
+        return Ok(DictionaryStatus::sqlite(&db_path2, &root));
     }
 
     let mut files: Vec<String> = vec![];
@@ -861,6 +1129,44 @@ pub async fn dictionary_install_ecdict(
     ))
 }
 
+/// Removes the built-in ECDICT (and its reverse index). It is not reinstalled on startup
+/// until `dictionary_install_ecdict`.
+#[tauri::command]
+pub fn dictionary_uninstall_ecdict(state: State<AppState>) -> Result<DictionaryStatus, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    state.dictionary.reset();
+    remove_builtin(&dict_dir, ECDICT_ID, &ecdict_root(&dict_dir))?;
+    Ok(DictionaryStatus::missing())
+}
+
+/// Rebuilds the ECDICT SQLite (and its reverse index, if built) from `stardict.csv`.
+#[tauri::command]
+pub async fn dictionary_rebuild_ecdict(state: State<'_, AppState>) -> Result<DictionaryStatus, String> {
+    let root = ecdict_root(&state.dictionaries_dir.read().unwrap());
+    let csv_path = root.join("stardict.csv");
+    if !csv_path.exists() {
+        return Err("ECDICT source stardict.csv not found; reinstall instead".to_string());
+    }
+    let db_path = ecdict_db_path(&root);
+    let target = db_path.clone();
+    let tmp = tauri::async_runtime::spawn_blocking(move || {
+        build_to_temp(&target, |tmp| build_sqlite_from_csv(&csv_path, tmp))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    swap_database(&tmp, &db_path, || state.dictionary.set_db_path(db_path.clone()))?;
+
+    let reverse = reverse_db_path(&db_path);
+    if reverse.exists() {
+        let source = db_path.clone();
+        tauri::async_runtime::spawn_blocking(move || build_reverse_index(&source, &reverse))
+            .await
+            .map_err(|e| e.to_string())??;
+        state.dictionary.reverse.reset();
+    }
+    Ok(DictionaryStatus::sqlite(&db_path, &root))
+}
+
 fn build_sqlite_from_csv(csv_path: &Path, db_path: &Path) -> Result<(), String> {
     if let Some(parent) = db_path.parent() {
         std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
@@ -881,7 +1187,13 @@ fn build_sqlite_from_csv(csv_path: &Path, db_path: &Path) -> Result<(), String>
             definition TEXT,\
             translation TEXT,\
             pos TEXT,\
-            audio TEXT\
+            audio TEXT,\
+            collins INTEGER,\
+            oxford INTEGER,\
+            tag TEXT,\
+            bnc INTEGER,\
+            frq INTEGER,\
+            exchange TEXT\
         );",
     )
     .map_err(|e| e.to_string())?;
@@ -896,7 +1208,8 @@ fn build_sqlite_from_csv(csv_path: &Path, db_path: &Path) -> Result<(), String>
     {
         let mut stmt = tx
             .prepare(
-                "INSERT OR REPLACE INTO entries(word, phonetic, definition, translation, pos, audio) VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
+                "INSERT OR REPLACE INTO entries(word, phonetic, definition, translation, pos, audio, collins, oxford, tag, bnc, frq, exchange) \
+                 VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
             )
             .map_err(|e| e.to_string())?;
 
@@ -911,6 +1224,10 @@ fn build_sqlite_from_csv(csv_path: &Path, db_path: &Path) -> Result<(), String>
             let translation = rec.get(3).unwrap_or("").trim();
             let pos = rec.get(4).unwrap_or("").trim();
             let audio = rec.get(12).unwrap_or("").trim();
+            // Columns 5-10: collins, oxford, tag, bnc, frq, exchange.
+            let num = |i: usize| rec.get(i).and_then(|v| v.trim().parse::<i64>().ok()).filter(|n| *n > 0);
+            let tag = rec.get(7).unwrap_or("").trim();
+            let exchange = rec.get(10).unwrap_or("").trim();
 
             stmt.execute(rusqlite::params![
                 word,
@@ -919,23 +1236,58 @@ fn build_sqlite_from_csv(csv_path: &Path, db_path: &Path) -> Result<(), String>
                 if translation.is_empty() { None } else { Some(translation) },
                 if pos.is_empty() { None } else { Some(pos) },
                 if audio.is_empty() { None } else { Some(audio) },
+                num(5),
+                num(6),
+                if tag.is_empty() { None } else { Some(tag) },
+                num(8),
+                num(9),
+                if exchange.is_empty() { None } else { Some(exchange) },
             ])
             .map_err(|e| e.to_string())?;
         }
     }
 
+    write_meta(&tx, &[("date", file_date(csv_path))])?;
     tx.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn dictionary_lookup(state: State<AppState>, word: String) -> Result<Option<DictionaryResult>, String> {
+pub fn dictionary_lookup(
+    state: State<AppState>,
+    word: String,
+    document_id: Option<String>,
+) -> Result<Option<DictionaryResult>, String> {
     let clean = word.trim();
     if clean.is_empty() {
         return Ok(None);
     }
+    if let Some(hit) = glossary_lookup_impl(&state, clean, document_id.as_deref()) {
+        return Ok(Some(hit));
+    }
+    lookup_first(&state, clean, &[DictionaryDirection::EnZh, DictionaryDirection::EnEn])
+}
+
+/// Looks up `word` in the built-in ECDICT, resolving the installed .ifo/SQLite lazily.
+pub(crate) fn ecdict_lookup_impl(state: &AppState, word: &str) -> Result<Option<DictionaryResult>, String> {
+    ecdict_manager_ready(state);
+
+    state.dictionary.cache.get_or_lookup(word, || {
+        if state.dictionary.get_db_path().is_some() {
+            return state.dictionary.lookup_db(word);
+        }
+
+        let defs = match state.dictionary.lookup(word)? {
+            Some(d) => d,
+            None => return Ok(None),
+        };
 
-    // Ensure ifo/db path is populated if already installed
+        Ok(stardict_definitions_to_result(word, defs))
+    })
+}
+
+/// Points the ECDICT manager at the installed .ifo or SQLite if it has not been resolved yet.
+pub(crate) fn ecdict_manager_ready(state: &AppState) {
     if state.dictionary.get_ifo_path().is_none() && state.dictionary.get_db_path().is_none() {
         let root = ecdict_root(&state.dictionaries_dir.read().unwrap());
         if let Some(ifo_path) = find_first_ifo(&root) {
@@ -949,59 +1301,49 @@ pub fn dictionary_lookup(state: State<AppState>, word: String) -> Result<Option<
             }
         }
     }
+}
 
-    if state.dictionary.get_db_path().is_some() {
-        return state.dictionary.lookup_db(clean);
-    }
-
-    let defs = match state.dictionary.lookup(clean)? {
-        Some(d) => d,
-        None => return Ok(None),
-    };
-
+/// Flattens StarDict definition segments into a `DictionaryResult`.
+pub(crate) fn stardict_definitions_to_result(
+    word: &str,
+    defs: Vec<stardict::WordDefinition>,
+) -> Option<DictionaryResult> {
     if defs.is_empty() {
-        return Ok(None);
+        return None;
     }
 
-    let mut definition_lines: Vec<String> = vec![];
-
+    let mut lines: Vec<String> = vec![];
     for d in defs {
         for seg in d.segments {
-            let t = clean_definition_text(&seg.text);
-            if !t.is_empty() {
-                definition_lines.extend(t.split('\n').map(|s| s.trim()).filter(|s| !s.is_empty()).map(|s| s.to_string()));
-            }
+            lines.extend(definition_lines(&seg.text));
         }
     }
 
-    if definition_lines.is_empty() {
-        return Ok(None);
+    // ECDICT's StarDict export starts each entry with a `*[phonetic]` line.
+    let phonetic = lines.first().and_then(|l| phonetic_line(l));
+    if phonetic.is_some() {
+        lines.remove(0);
+    }
+    if lines.is_empty() {
+        return None;
     }
 
-    let translation = definition_lines.get(0).cloned();
-    let rest = if definition_lines.len() > 1 {
-        definition_lines[1..].to_vec()
+    let translation = lines.first().cloned();
+    let meanings = if lines.len() > 1 {
+        group_definition_lines(&lines[1..])
     } else {
         vec![]
     };
 
-    let meanings = if rest.is_empty() {
-        vec![]
-    } else {
-        vec![DictionaryMeaning {
-            part_of_speech: "".to_string(),
-            definitions: rest,
-            examples: vec![],
-        }]
-    };
-
-    Ok(Some(DictionaryResult {
-        word: clean.to_string(),
-        phonetic: None,
+    Some(DictionaryResult {
+        word: word.to_string(),
+        phonetic,
         audio_url: None,
         translation,
         meanings,
-    }))
+        html: None,
+        pinyin: None,
+    })
 }
 
 /// Auto-prepare dictionaries on first launch (background, non-blocking).
@@ -1016,7 +1358,8 @@ pub fn auto_prepare_dictionaries(app_handle: &AppHandle, dictionaries_dir: &Path
         let _ = std::fs::create_dir_all(&ecdict_r);
         let ecdict_db = ecdict_db_path(&ecdict_r);
         let ecdict_ifo = find_first_ifo(&ecdict_r);
-        if !ecdict_db.exists() && ecdict_ifo.is_none() {
+        let ecdict_removed = uninstalled_marker(&dict_dir, ECDICT_ID).exists();
+        if !ecdict_removed && !ecdict_db.exists() && ecdict_ifo.is_none() {
             if let Ok(resource_dir) = app.path().resource_dir() {
                 let candidates = [
                     resource_dir.join("dictionaries").join("ecdict").join("stardict.7z"),
@@ -1036,12 +1379,23 @@ pub fn auto_prepare_dictionaries(app_handle: &AppHandle, dictionaries_dir: &Path
                 }
             }
         }
+        if ecdict_db.exists() && !index_is_current(&reverse_db_path(&ecdict_db), &ecdict_db) {
+            match build_reverse_index(&ecdict_db, &reverse_db_path(&ecdict_db)) {
+                // A lookup may already hold the replaced index open.
+                Ok(_) => {
+                    if let Some(state) = app.try_state::<AppState>() {
+                        state.dictionary.reverse.reset();
+                    }
+                }
+                Err(e) => log::warn!("[dictionary] ECDICT reverse index build failed: {}", e),
+            }
+        }
 
         // --- CC-CEDICT (ZH→EN) ---
         let cedict_r = cedict_root(&dict_dir);
         let _ = std::fs::create_dir_all(&cedict_r);
         let cedict_db = cedict_db_path(&cedict_r);
-        if !cedict_db.exists() {
+        if !uninstalled_marker(&dict_dir, CEDICT_ID).exists() && !cedict_db.exists() {
             if let Ok(resource_dir) = app.path().resource_dir() {
                 let candidates = [
                     resource_dir.join("dictionaries").join("cedict").join("cedict.zip"),
@@ -1074,3 +1428,38 @@ pub fn auto_prepare_dictionaries(app_handle: &AppHandle, dictionaries_dir: &Path
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cedict_update_swaps_in_new_build_with_meta() {
+        let root = std::env::temp_dir().join(format!("aireader_cedict_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&root).unwrap();
+        let db_path = cedict_db_path(&root);
+        let old = root.join("old.u8");
+        std::fs::write(&old, "中國 中国 [Zhong1 guo2] /China/\n").unwrap();
+        build_cedict_sqlite_from_u8(&old, &db_path).unwrap();
+
+        let new = root.join("new.u8");
+        std::fs::write(
+            &new,
+            "#! version=1\n#! date=2024-05-20T05:05:16Z\n中國 中国 [Zhong1 guo2] /China/\n你好 你好 [ni3 hao3] /hello/\n",
+        )
+        .unwrap();
+        let tmp = build_to_temp(&db_path, |tmp| build_cedict_sqlite_from_u8(&new, tmp)).unwrap();
+        let released = std::cell::Cell::new(0);
+        swap_database(&tmp, &db_path, || released.set(released.get() + 1)).unwrap();
+        assert_eq!(released.get(), 2);
+        assert!(!tmp.exists());
+
+        let status = DictionaryStatus::sqlite(&db_path, &root);
+        assert_eq!(status.entry_count, Some(2));
+        assert_eq!(status.version.as_deref(), Some("1"));
+        assert_eq!(status.date.as_deref(), Some("2024-05-20T05:05:16Z"));
+        assert!(status.size_bytes > 0);
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,1092 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use rusqlite::{Connection, OpenFlags};
+use stardict::StarDict;
+use tauri::State;
+
+use crate::dictionary::{
+    cedict_lookup_impl,
+    clean_definition_text,
+    ecdict_lookup_impl,
+    extract_zip_to,
+    lookup_cedict_conn,
+    lookup_sqlite_conn,
+    stardict_definitions_to_result,
+    DictionaryMeaning,
+    DictionaryResult,
+};
+use crate::glossary::glossary_lookup_impl;
+use crate::meanings::{definition_lines, group_definition_lines};
+use crate::mdict::{companion_mdd_paths, sanitize_html, MdictDictionary, MdictPasscode};
+use crate::wiktionary::{lookup_wiktionary_conn, WIKTIONARY_TABLE};
+use crate::AppState;
+
+const REGISTRY_FILE: &str = "registry.json";
+const USER_DIR: &str = "user";
+/// Pseudo dictionary id for user glossary hits in merged lookups.
+const GLOSSARY_ID: &str = "glossary";
+
+pub const ECDICT_ID: &str = "ecdict";
+pub const CEDICT_ID: &str = "cedict";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DictionaryFormat {
+    Stardict,
+    Sqlite,
+    Glossary,
+    Mdict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DictionaryDirection {
+    #[serde(rename = "en-zh")]
+    EnZh,
+    #[serde(rename = "zh-en")]
+    ZhEn,
+    #[serde(rename = "en-en")]
+    EnEn,
+    #[serde(rename = "zh-zh")]
+    ZhZh,
+    #[serde(rename = "other")]
+    Other,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub id: String,
+    pub name: String,
+    pub format: DictionaryFormat,
+    pub direction: DictionaryDirection,
+    /// Higher priority dictionaries are consulted (and listed) first.
+    pub priority: i32,
+    pub enabled: bool,
+    /// Main file of the dictionary, relative to the dictionaries dir.
+    /// For built-in dictionaries this is their root folder.
+    pub path: String,
+    #[serde(default)]
+    pub builtin: bool,
+    /// Registration code for encrypted MDict files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passcode: Option<MdictPasscode>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistryEntryInfo {
+    #[serde(flatten)]
+    pub entry: RegistryEntry,
+    pub installed: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DictionaryLookupGroup {
+    #[serde(rename = "dictionaryId")]
+    pub dictionary_id: String,
+    #[serde(rename = "dictionaryName")]
+    pub dictionary_name: String,
+    pub direction: DictionaryDirection,
+    pub result: DictionaryResult,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MergedLookupResult {
+    pub word: String,
+    pub groups: Vec<DictionaryLookupGroup>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DictionaryInstallOptions {
+    pub name: Option<String>,
+    pub direction: Option<DictionaryDirection>,
+    pub priority: Option<i32>,
+    pub passcode: Option<MdictPasscode>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DictionaryUpdateOptions {
+    pub name: Option<String>,
+    pub direction: Option<DictionaryDirection>,
+    pub priority: Option<i32>,
+    pub enabled: Option<bool>,
+}
+
+/// A loaded, user-installed dictionary that can answer lookups.
+trait DictionarySource: Send {
+    fn lookup(&mut self, word: &str) -> Result<Option<DictionaryResult>, String>;
+
+    /// Bundled resources (images, audio, CSS) referenced from definitions.
+    fn resource(&mut self, _path: &str) -> Result<Option<Vec<u8>>, String> {
+        Ok(None)
+    }
+}
+
+struct StardictSource {
+    dict: stardict::StarDictStd,
+}
+
+impl DictionarySource for StardictSource {
+    fn lookup(&mut self, word: &str) -> Result<Option<DictionaryResult>, String> {
+        let defs = self
+            .dict
+            .lookup(word)
+            .map_err(|e| format!("stardict lookup failed: {e:?}"))?;
+        Ok(defs.and_then(|d| stardict_definitions_to_result(word, d)))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SqliteSchema {
+    Ecdict,
+    Cedict,
+    Wiktionary,
+}
+
+struct SqliteSource {
+    conn: Connection,
+    schema: SqliteSchema,
+}
+
+impl DictionarySource for SqliteSource {
+    fn lookup(&mut self, word: &str) -> Result<Option<DictionaryResult>, String> {
+        match self.schema {
+            SqliteSchema::Ecdict => lookup_sqlite_conn(&self.conn, word),
+            SqliteSchema::Cedict => lookup_cedict_conn(&self.conn, word),
+            SqliteSchema::Wiktionary => lookup_wiktionary_conn(&self.conn, word),
+        }
+    }
+}
+
+/// Tab-separated `term<TAB>definition` file. Repeated terms accumulate definitions.
+struct GlossarySource {
+    terms: HashMap<String, (String, Vec<String>)>,
+}
+
+impl GlossarySource {
+    fn open(path: &Path) -> Result<Self, String> {
+        let f = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        let mut terms: HashMap<String, (String, Vec<String>)> = HashMap::new();
+        for line in BufReader::new(f).lines() {
+            let line = line.map_err(|e| e.to_string())?;
+            let line = line.trim_start_matches('\u{feff}');
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((term, def)) = line.split_once('\t') else {
+                continue;
+            };
+            let term = term.trim();
+            let def = clean_definition_text(def);
+            if term.is_empty() || def.is_empty() {
+                continue;
+            }
+            let slot = terms
+                .entry(term.to_lowercase())
+                .or_insert_with(|| (term.to_string(), vec![]));
+            for d in def.split('\n').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+                if !slot.1.iter().any(|x| x == d) {
+                    slot.1.push(d.to_string());
+                }
+            }
+        }
+        Ok(Self { terms })
+    }
+}
+
+impl DictionarySource for GlossarySource {
+    fn lookup(&mut self, word: &str) -> Result<Option<DictionaryResult>, String> {
+        let Some((term, defs)) = self.terms.get(&word.to_lowercase()) else {
+            return Ok(None);
+        };
+        let translation = defs.first().cloned();
+        let rest = defs.iter().skip(1).cloned().collect::<Vec<_>>();
+        let meanings = if rest.is_empty() {
+            vec![]
+        } else {
+            vec![DictionaryMeaning {
+                part_of_speech: "".to_string(),
+                definitions: rest,
+                examples: vec![],
+            }]
+        };
+        Ok(Some(DictionaryResult {
+            word: term.clone(),
+            phonetic: None,
+            audio_url: None,
+            translation,
+            meanings,
+            html: None,
+            pinyin: None,
+        }))
+    }
+}
+
+struct MdictSource {
+    dict: MdictDictionary,
+}
+
+impl DictionarySource for MdictSource {
+    fn lookup(&mut self, word: &str) -> Result<Option<DictionaryResult>, String> {
+        let records = self.dict.mdx.lookup(word)?;
+        if records.is_empty() {
+            return Ok(None);
+        }
+
+        let html = records
+            .iter()
+            .map(|r| sanitize_html(r))
+            .collect::<Vec<_>>()
+            .join("<hr/>");
+        let lines: Vec<String> = records.iter().flat_map(|r| definition_lines(r)).collect();
+
+        let translation = lines.first().cloned();
+        let meanings = if lines.len() > 1 {
+            group_definition_lines(&lines[1..])
+        } else {
+            vec![]
+        };
+        Ok(Some(DictionaryResult {
+            word: word.to_string(),
+            phonetic: None,
+            audio_url: None,
+            translation,
+            meanings,
+            html: Some(html),
+            pinyin: None,
+        }))
+    }
+
+    fn resource(&mut self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        self.dict.resource(path)
+    }
+}
+
+fn detect_sqlite_schema(conn: &Connection) -> Option<SqliteSchema> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name IN ('entries', 'stardict', ?1)")
+        .ok()?;
+    let tables: Vec<String> = stmt
+        .query_map([WIKTIONARY_TABLE], |r| r.get(0))
+        .ok()?
+        .filter_map(|r| r.ok())
+        .collect();
+    if tables.iter().any(|t| t == WIKTIONARY_TABLE) {
+        return Some(SqliteSchema::Wiktionary);
+    }
+    if tables.iter().any(|t| t == "stardict") {
+        return Some(SqliteSchema::Ecdict);
+    }
+    if !tables.iter().any(|t| t == "entries") {
+        return None;
+    }
+    let mut cols = conn.prepare("PRAGMA table_info(entries)").ok()?;
+    let names: Vec<String> = cols
+        .query_map([], |r| r.get(1))
+        .ok()?
+        .filter_map(|r| r.ok())
+        .collect();
+    if names.iter().any(|c| c == "word") {
+        Some(SqliteSchema::Ecdict)
+    } else if names.iter().any(|c| c == "simplified") {
+        Some(SqliteSchema::Cedict)
+    } else {
+        None
+    }
+}
+
+fn open_source(
+    format: DictionaryFormat,
+    path: &Path,
+    passcode: Option<&MdictPasscode>,
+) -> Result<Box<dyn DictionarySource>, String> {
+    match format {
+        DictionaryFormat::Stardict => {
+            let dict = stardict::no_cache(path).map_err(|e| format!("stardict load failed: {e:?}"))?;
+            Ok(Box::new(StardictSource { dict }))
+        }
+        DictionaryFormat::Sqlite => {
+            let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(|e| e.to_string())?;
+            let schema = detect_sqlite_schema(&conn)
+                .ok_or_else(|| "unsupported SQLite dictionary schema".to_string())?;
+            Ok(Box::new(SqliteSource { conn, schema }))
+        }
+        DictionaryFormat::Glossary => Ok(Box::new(GlossarySource::open(path)?)),
+        DictionaryFormat::Mdict => Ok(Box::new(MdictSource {
+            dict: MdictDictionary::open(path, passcode)?,
+        })),
+    }
+}
+
+fn builtin_entries() -> Vec<RegistryEntry> {
+    vec![
+        RegistryEntry {
+            id: ECDICT_ID.to_string(),
+            name: "ECDICT".to_string(),
+            format: DictionaryFormat::Sqlite,
+            direction: DictionaryDirection::EnZh,
+            priority: 100,
+            enabled: true,
+            path: ECDICT_ID.to_string(),
+            builtin: true,
+            passcode: None,
+        },
+        RegistryEntry {
+            id: CEDICT_ID.to_string(),
+            name: "CC-CEDICT".to_string(),
+            format: DictionaryFormat::Sqlite,
+            direction: DictionaryDirection::ZhEn,
+            priority: 100,
+            enabled: true,
+            path: CEDICT_ID.to_string(),
+            builtin: true,
+            passcode: None,
+        },
+    ]
+}
+
+/// Reads a `key=value` field (`bookname`, `wordcount`, `version`, ...) from a StarDict `.ifo`.
+pub(crate) fn read_ifo_field(ifo: &Path, key: &str) -> Option<String> {
+    let s = std::fs::read_to_string(ifo).ok()?;
+    s.lines()
+        .filter_map(|l| l.trim().strip_prefix(key)?.strip_prefix('='))
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+}
+
+fn read_mdx_title(mdx: &Path, passcode: Option<&MdictPasscode>) -> Option<String> {
+    let d = MdictDictionary::open(mdx, passcode).ok()?;
+    let title = d.mdx.header.title.trim().to_string();
+    // MdxBuilder writes this placeholder when no title was set.
+    if title.is_empty() || title == "Title (No HTML code allowed)" {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+fn find_all_with_ext(root: &Path, exts: &[&str]) -> Vec<PathBuf> {
+    let mut out = vec![];
+    for entry in walkdir::WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let ext = entry.path().extension().and_then(|s| s.to_str()).unwrap_or("");
+        if exts.iter().any(|x| ext.eq_ignore_ascii_case(x)) {
+            out.push(entry.path().to_path_buf());
+        }
+    }
+    out.sort();
+    out
+}
+
+/// Copies a StarDict `.ifo` together with its `.idx`/`.dict`/`.syn` siblings.
+fn copy_stardict_bundle(ifo: &Path, dest: &Path) -> Result<PathBuf, String> {
+    let dir = ifo.parent().ok_or_else(|| "invalid .ifo path".to_string())?;
+    let stem = ifo
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| "invalid .ifo path".to_string())?;
+    let mut copied_ifo: Option<PathBuf> = None;
+    for entry in std::fs::read_dir(dir).map_err(|e| e.to_string())?.flatten() {
+        let p = entry.path();
+        let Some(name) = p.file_name().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        if !p.is_file() || !name.starts_with(stem) || !name[stem.len()..].starts_with('.') {
+            continue;
+        }
+        let target = dest.join(name);
+        std::fs::copy(&p, &target).map_err(|e| e.to_string())?;
+        if p == ifo {
+            copied_ifo = Some(target);
+        }
+    }
+    copied_ifo.ok_or_else(|| "failed to copy .ifo".to_string())
+}
+
+/// Copies an `.mdx` together with its `.mdd` resource files and stylesheet.
+fn copy_mdict_bundle(mdx: &Path, dest: &Path) -> Result<PathBuf, String> {
+    let name = mdx.file_name().ok_or_else(|| "invalid .mdx path".to_string())?;
+    let target = dest.join(name);
+    std::fs::copy(mdx, &target).map_err(|e| e.to_string())?;
+    let mut extras = companion_mdd_paths(mdx);
+    extras.push(mdx.with_extension("css"));
+    for p in extras {
+        if let Some(n) = p.file_name() {
+            if p.is_file() {
+                std::fs::copy(&p, dest.join(n)).map_err(|e| e.to_string())?;
+            }
+        }
+    }
+    Ok(target)
+}
+
+fn rel_to(root: &Path, p: &Path) -> String {
+    p.strip_prefix(root)
+        .unwrap_or(p)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Stages `source` under `dest` and returns the main file of every dictionary found in it.
+fn stage_dictionary_files(source: &Path, dest: &Path) -> Result<Vec<(DictionaryFormat, PathBuf)>, String> {
+    let name = source
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let ext = source
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match ext.as_str() {
+        "zip" | "7z" => {
+            if ext == "zip" {
+                extract_zip_to(source, dest)?;
+            } else {
+                sevenz_rust2::decompress_file(source, dest).map_err(|e| format!("extract failed: {e:?}"))?;
+            }
+            let mut found: Vec<(DictionaryFormat, PathBuf)> = find_all_with_ext(dest, &["ifo"])
+                .into_iter()
+                .map(|p| (DictionaryFormat::Stardict, p))
+                .collect();
+            found.extend(
+                find_all_with_ext(dest, &["mdx"])
+                    .into_iter()
+                    .map(|p| (DictionaryFormat::Mdict, p)),
+            );
+            found.extend(
+                find_all_with_ext(dest, &["sqlite", "sqlite3", "db"])
+                    .into_iter()
+                    .map(|p| (DictionaryFormat::Sqlite, p)),
+            );
+            found.extend(
+                find_all_with_ext(dest, &["tsv", "tab"])
+                    .into_iter()
+                    .map(|p| (DictionaryFormat::Glossary, p)),
+            );
+            Ok(found)
+        }
+        "ifo" => Ok(vec![(DictionaryFormat::Stardict, copy_stardict_bundle(source, dest)?)]),
+        "mdx" => Ok(vec![(DictionaryFormat::Mdict, copy_mdict_bundle(source, dest)?)]),
+        "sqlite" | "sqlite3" | "db" => {
+            let target = dest.join(&name);
+            std::fs::copy(source, &target).map_err(|e| e.to_string())?;
+            Ok(vec![(DictionaryFormat::Sqlite, target)])
+        }
+        "tsv" | "tab" | "txt" => {
+            let target = dest.join(&name);
+            std::fs::copy(source, &target).map_err(|e| e.to_string())?;
+            Ok(vec![(DictionaryFormat::Glossary, target)])
+        }
+        _ => Err(format!("unsupported dictionary file: {}", name)),
+    }
+}
+
+/// Creates a fresh folder under `user/` for one install.
+pub(crate) fn new_install_dir(dictionaries_dir: &Path) -> Result<PathBuf, String> {
+    let dest = dictionaries_dir
+        .join(USER_DIR)
+        .join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dest).map_err(|e| e.to_string())?;
+    Ok(dest)
+}
+
+/// Registry entry for a user dictionary whose main file is `main`.
+pub(crate) fn user_registry_entry(
+    dictionaries_dir: &Path,
+    format: DictionaryFormat,
+    main: &Path,
+    name: String,
+    options: &DictionaryInstallOptions,
+    default_direction: DictionaryDirection,
+) -> RegistryEntry {
+    RegistryEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        name,
+        format,
+        direction: options.direction.unwrap_or(default_direction),
+        priority: options.priority.unwrap_or(0),
+        enabled: true,
+        path: rel_to(dictionaries_dir, main),
+        builtin: false,
+        passcode: if format == DictionaryFormat::Mdict { options.passcode.clone() } else { None },
+    }
+}
+
+/// Copies/extracts `source` into a fresh folder under `user/` and builds registry entries
+/// for every usable dictionary in it. Does not touch the registry itself.
+fn stage_install(
+    dictionaries_dir: &Path,
+    source: &Path,
+    options: DictionaryInstallOptions,
+) -> Result<Vec<RegistryEntry>, String> {
+    if !source.is_file() {
+        return Err("dictionary file not found".to_string());
+    }
+
+    let dest = new_install_dir(dictionaries_dir)?;
+
+    let staged = match stage_dictionary_files(source, &dest) {
+        Ok(found) if !found.is_empty() => found,
+        Ok(_) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            return Err("no .ifo, .mdx, SQLite or .tsv dictionary found in file".to_string());
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            return Err(e);
+        }
+    };
+
+    let mut new_entries: Vec<RegistryEntry> = vec![];
+    for (format, main) in staged {
+        // Validate by opening once; skip files that are not usable dictionaries.
+        if let Err(e) = open_source(format, &main, options.passcode.as_ref()) {
+            log::warn!("[dictionary] skipping {}: {}", main.to_string_lossy(), e);
+            continue;
+        }
+        let fallback_name = main
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("dictionary")
+            .to_string();
+        let detected_name = match format {
+            DictionaryFormat::Stardict => read_ifo_field(&main, "bookname"),
+            DictionaryFormat::Mdict => read_mdx_title(&main, options.passcode.as_ref()),
+            _ => None,
+        };
+        let name = match (&options.name, new_entries.is_empty()) {
+            (Some(n), true) if !n.trim().is_empty() => n.trim().to_string(),
+            _ => detected_name.unwrap_or(fallback_name),
+        };
+        new_entries.push(user_registry_entry(
+            dictionaries_dir,
+            format,
+            &main,
+            name,
+            &options,
+            DictionaryDirection::EnZh,
+        ));
+    }
+
+    if new_entries.is_empty() {
+        let _ = std::fs::remove_dir_all(&dest);
+        return Err("no usable dictionary found in file".to_string());
+    }
+
+    Ok(new_entries)
+}
+
+pub struct DictionaryRegistry {
+    entries: Mutex<Option<Vec<RegistryEntry>>>,
+    loaded: Mutex<HashMap<String, Box<dyn DictionarySource>>>,
+}
+
+impl DictionaryRegistry {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(None),
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn reset(&self) {
+        *self.entries.lock().unwrap() = None;
+        self.loaded.lock().unwrap().clear();
+    }
+
+    fn load_entries(dictionaries_dir: &Path) -> Vec<RegistryEntry> {
+        let mut entries: Vec<RegistryEntry> = std::fs::read_to_string(dictionaries_dir.join(REGISTRY_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        for b in builtin_entries() {
+            if !entries.iter().any(|e| e.id == b.id) {
+                entries.push(b);
+            }
+        }
+        entries
+    }
+
+    fn save_entries(dictionaries_dir: &Path, entries: &[RegistryEntry]) -> Result<(), String> {
+        std::fs::create_dir_all(dictionaries_dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_string_pretty(entries).map_err(|e| e.to_string())?;
+        let tmp = dictionaries_dir.join(format!("{}.tmp", REGISTRY_FILE));
+        std::fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, dictionaries_dir.join(REGISTRY_FILE)).map_err(|e| e.to_string())
+    }
+
+    /// All registered dictionaries, highest priority first.
+    pub fn entries(&self, dictionaries_dir: &Path) -> Vec<RegistryEntry> {
+        let mut guard = self.entries.lock().unwrap();
+        let entries = guard.get_or_insert_with(|| Self::load_entries(dictionaries_dir));
+        let mut out = entries.clone();
+        out.sort_by(|a, b| b.priority.cmp(&a.priority).then_with(|| a.name.cmp(&b.name)));
+        out
+    }
+
+    fn modify<R>(
+        &self,
+        dictionaries_dir: &Path,
+        f: impl FnOnce(&mut Vec<RegistryEntry>) -> Result<R, String>,
+    ) -> Result<R, String> {
+        let mut guard = self.entries.lock().unwrap();
+        let entries = guard.get_or_insert_with(|| Self::load_entries(dictionaries_dir));
+        let mut next = entries.clone();
+        let r = f(&mut next)?;
+        Self::save_entries(dictionaries_dir, &next)?;
+        *entries = next;
+        Ok(r)
+    }
+
+    fn with_source<R>(
+        &self,
+        dictionaries_dir: &Path,
+        entry: &RegistryEntry,
+        f: impl FnOnce(&mut dyn DictionarySource) -> Result<R, String>,
+    ) -> Result<R, String> {
+        let mut loaded = self.loaded.lock().unwrap();
+        if !loaded.contains_key(&entry.id) {
+            let source = open_source(entry.format, &dictionaries_dir.join(&entry.path), entry.passcode.as_ref())?;
+            loaded.insert(entry.id.clone(), source);
+        }
+        let source = loaded
+            .get_mut(&entry.id)
+            .ok_or_else(|| "dictionary not loaded".to_string())?;
+        f(source.as_mut())
+    }
+
+    fn lookup_user(&self, dictionaries_dir: &Path, entry: &RegistryEntry, word: &str) -> Result<Option<DictionaryResult>, String> {
+        self.with_source(dictionaries_dir, entry, |s| s.lookup(word))
+    }
+
+    fn unload(&self, id: &str) {
+        self.loaded.lock().unwrap().remove(id);
+    }
+
+    pub fn register(&self, dictionaries_dir: &Path, new_entries: Vec<RegistryEntry>) -> Result<(), String> {
+        self.modify(dictionaries_dir, move |entries| {
+            entries.extend(new_entries);
+            Ok(())
+        })
+    }
+
+    pub fn uninstall(&self, dictionaries_dir: &Path, id: &str) -> Result<(), String> {
+        let removed = self.modify(dictionaries_dir, |entries| {
+            let idx = entries
+                .iter()
+                .position(|e| e.id == id)
+                .ok_or_else(|| "dictionary not found".to_string())?;
+            if entries[idx].builtin {
+                return Err("built-in dictionaries cannot be uninstalled".to_string());
+            }
+            Ok(entries.remove(idx))
+        })?;
+        self.unload(id);
+
+        // Several dictionaries may share one install folder (e.g. a zip with many .ifo files).
+        let main = dictionaries_dir.join(&removed.path);
+        let user_root = dictionaries_dir.join(USER_DIR);
+        let folder = main
+            .strip_prefix(&user_root)
+            .ok()
+            .and_then(|rel| rel.components().next())
+            .map(|c| user_root.join(c));
+        if let Some(folder) = folder {
+            let prefix = rel_to(dictionaries_dir, &folder);
+            let still_used = self
+                .entries(dictionaries_dir)
+                .iter()
+                .any(|e| e.path.starts_with(&format!("{}/", prefix)));
+            if !still_used {
+                let _ = std::fs::remove_dir_all(&folder);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn update(&self, dictionaries_dir: &Path, id: &str, options: DictionaryUpdateOptions) -> Result<RegistryEntry, String> {
+        self.modify(dictionaries_dir, |entries| {
+            let e = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| "dictionary not found".to_string())?;
+            if let Some(n) = options.name.filter(|n| !n.trim().is_empty()) {
+                e.name = n.trim().to_string();
+            }
+            if let Some(d) = options.direction {
+                e.direction = d;
+            }
+            if let Some(p) = options.priority {
+                e.priority = p;
+            }
+            if let Some(en) = options.enabled {
+                e.enabled = en;
+            }
+            Ok(e.clone())
+        })
+    }
+}
+
+fn is_installed(dictionaries_dir: &Path, entry: &RegistryEntry) -> bool {
+    let p = dictionaries_dir.join(&entry.path);
+    if entry.builtin {
+        p.is_dir() && std::fs::read_dir(&p).map(|mut d| d.next().is_some()).unwrap_or(false)
+    } else {
+        p.is_file()
+    }
+}
+
+/// Looks `word` up in enabled dictionaries whose direction passes `accept`, in priority
+/// order, stopping after `limit` hits. Returns one group per dictionary with an entry, or
+/// the first lookup error when no dictionary produced one.
+fn lookup_groups(
+    state: &AppState,
+    word: &str,
+    accept: &dyn Fn(DictionaryDirection) -> bool,
+    limit: usize,
+) -> Result<Vec<DictionaryLookupGroup>, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let mut groups: Vec<DictionaryLookupGroup> = vec![];
+    let mut first_error: Option<String> = None;
+
+    for entry in state.dictionary_registry.entries(&dict_dir) {
+        if groups.len() >= limit {
+            break;
+        }
+        if !entry.enabled || !accept(entry.direction) {
+            continue;
+        }
+
+        let res = if entry.builtin {
+            match entry.id.as_str() {
+                ECDICT_ID => ecdict_lookup_impl(state, word),
+                CEDICT_ID => cedict_lookup_impl(state, word),
+                _ => Ok(None),
+            }
+        } else {
+            state.dictionary_registry.lookup_user(&dict_dir, &entry, word)
+        };
+
+        match res {
+            Ok(Some(result)) => groups.push(DictionaryLookupGroup {
+                dictionary_id: entry.id.clone(),
+                dictionary_name: entry.name.clone(),
+                direction: entry.direction,
+                result,
+            }),
+            Ok(None) => {}
+            // Not-installed built-ins and broken user files should not hide other results.
+            Err(e) => {
+                log::debug!("[dictionary] {} lookup failed: {}", entry.id, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    match first_error {
+        Some(e) if groups.is_empty() => Err(e),
+        _ => Ok(groups),
+    }
+}
+
+/// All hits for `word`, optionally restricted to one direction.
+pub(crate) fn lookup_all(
+    state: &AppState,
+    word: &str,
+    direction: Option<DictionaryDirection>,
+) -> Result<Vec<DictionaryLookupGroup>, String> {
+    lookup_groups(state, word, &|d| direction.is_none_or(|x| x == d), usize::MAX)
+}
+
+/// The highest-priority hit among dictionaries with one of the given directions.
+pub(crate) fn lookup_first(
+    state: &AppState,
+    word: &str,
+    directions: &[DictionaryDirection],
+) -> Result<Option<DictionaryResult>, String> {
+    Ok(lookup_groups(state, word, &|d| directions.contains(&d), 1)?
+        .into_iter()
+        .next()
+        .map(|g| g.result))
+}
+
+/// Maps a resource reference from a definition (`img/a.png`, `sound://a.mp3`) to a safe
+/// relative path inside the resource cache.
+fn resource_cache_rel(path: &str) -> Option<PathBuf> {
+    let p = path
+        .trim()
+        .trim_start_matches("sound://")
+        .trim_start_matches("file://");
+    let mut out = PathBuf::new();
+    for part in p.split(['/', '\\']) {
+        if part.contains(':') {
+            return None;
+        }
+        for c in Path::new(part).components() {
+            match c {
+                Component::Normal(s) => out.push(s),
+                Component::CurDir => {}
+                _ => return None,
+            }
+        }
+    }
+    if out.as_os_str().is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+#[tauri::command]
+pub fn dictionary_registry_list(state: State<AppState>) -> Result<Vec<RegistryEntryInfo>, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    Ok(state
+        .dictionary_registry
+        .entries(&dict_dir)
+        .into_iter()
+        .map(|entry| RegistryEntryInfo {
+            installed: is_installed(&dict_dir, &entry),
+            entry,
+        })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn dictionary_registry_install(
+    state: State<'_, AppState>,
+    path: String,
+    options: Option<DictionaryInstallOptions>,
+) -> Result<Vec<RegistryEntry>, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let options = options.unwrap_or_default();
+    let source = PathBuf::from(path);
+    // Extraction and validation can take a while for large bundles.
+    let dict_dir2 = dict_dir.clone();
+    let added = tauri::async_runtime::spawn_blocking(move || stage_install(&dict_dir2, &source, options))
+        .await
+        .map_err(|e| e.to_string())??;
+    state.dictionary_registry.register(&dict_dir, added.clone())?;
+    Ok(added)
+}
+
+#[tauri::command]
+pub fn dictionary_registry_uninstall(state: State<AppState>, id: String) -> Result<(), String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    state.dictionary_registry.uninstall(&dict_dir, &id)
+}
+
+#[tauri::command]
+pub fn dictionary_registry_update(
+    state: State<AppState>,
+    id: String,
+    options: DictionaryUpdateOptions,
+) -> Result<RegistryEntry, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    state.dictionary_registry.update(&dict_dir, &id, options)
+}
+
+#[tauri::command]
+pub fn dictionary_lookup_merged(
+    state: State<AppState>,
+    word: String,
+    direction: Option<DictionaryDirection>,
+    document_id: Option<String>,
+) -> Result<MergedLookupResult, String> {
+    let clean = word.trim();
+    if clean.is_empty() {
+        return Ok(MergedLookupResult {
+            word: String::new(),
+            groups: vec![],
+        });
+    }
+    let mut groups = vec![];
+    // User glossary entries come first regardless of direction.
+    if let Some(result) = glossary_lookup_impl(&state, clean, document_id.as_deref()) {
+        groups.push(DictionaryLookupGroup {
+            dictionary_id: GLOSSARY_ID.to_string(),
+            dictionary_name: "Glossary".to_string(),
+            direction: DictionaryDirection::Other,
+            result,
+        });
+    }
+    match lookup_all(&state, clean, direction) {
+        Ok(found) => groups.extend(found),
+        Err(e) if groups.is_empty() => return Err(e),
+        Err(_) => {}
+    }
+    Ok(MergedLookupResult {
+        word: clean.to_string(),
+        groups,
+    })
+}
+
+/// Extracts a resource referenced by an MDict definition (from its `.mdd` files) into the
+/// dictionary's `_resources` folder and returns the file path, or `None` if it does not exist.
+#[tauri::command]
+pub fn dictionary_resource(state: State<AppState>, id: String, path: String) -> Result<Option<String>, String> {
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let entry = state
+        .dictionary_registry
+        .entries(&dict_dir)
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or_else(|| "dictionary not found".to_string())?;
+    let rel = resource_cache_rel(&path).ok_or_else(|| "invalid resource path".to_string())?;
+
+    let main = dict_dir.join(&entry.path);
+    let target = main
+        .parent()
+        .ok_or_else(|| "invalid dictionary path".to_string())?
+        .join("_resources")
+        .join(&rel);
+    if target.exists() {
+        return Ok(Some(target.to_string_lossy().to_string()));
+    }
+
+    let key = rel.to_string_lossy().replace('\\', "/");
+    let bytes = state
+        .dictionary_registry
+        .with_source(&dict_dir, &entry, |s| s.resource(&key))?;
+    let Some(bytes) = bytes else {
+        return Ok(None);
+    };
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(&target, bytes).map_err(|e| e.to_string())?;
+    Ok(Some(target.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicU32, Ordering};
+    static TEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+    fn temp_dir() -> PathBuf {
+        let n = TEST_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "aireader_registry_test_{}_{}", std::process::id(), n
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn install(reg: &DictionaryRegistry, dir: &Path, src: &Path, options: DictionaryInstallOptions) -> Vec<RegistryEntry> {
+        let added = stage_install(dir, src, options).unwrap();
+        reg.register(dir, added.clone()).unwrap();
+        added
+    }
+
+    #[test]
+    fn test_builtins_present_by_default() {
+        let dir = temp_dir();
+        let reg = DictionaryRegistry::new();
+        let entries = reg.entries(&dir);
+        assert!(entries.iter().any(|e| e.id == ECDICT_ID && e.builtin));
+        assert!(entries.iter().any(|e| e.id == CEDICT_ID && e.builtin));
+    }
+
+    #[test]
+    fn test_install_glossary_and_lookup() {
+        let dir = temp_dir();
+        let src = dir.join("terms.tsv");
+        std::fs::write(&src, "# comment\nTransformer\t变换器模型\ntransformer\t一种神经网络结构\n").unwrap();
+
+        let reg = DictionaryRegistry::new();
+        let added = install(&reg, &dir.join("dicts"), &src, DictionaryInstallOptions {
+            name: Some("ML terms".to_string()),
+            direction: None,
+            priority: Some(5),
+            passcode: None,
+        });
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].name, "ML terms");
+        assert_eq!(added[0].format, DictionaryFormat::Glossary);
+
+        let res = reg
+            .lookup_user(&dir.join("dicts"), &added[0], "TRANSFORMER")
+            .unwrap()
+            .unwrap();
+        assert_eq!(res.translation.as_deref(), Some("变换器模型"));
+        assert_eq!(res.meanings[0].definitions, vec!["一种神经网络结构".to_string()]);
+    }
+
+    #[test]
+    fn test_registry_persists_and_sorts_by_priority() {
+        let dir = temp_dir();
+        let src = dir.join("a.tsv");
+        std::fs::write(&src, "a\tb\n").unwrap();
+
+        let reg = DictionaryRegistry::new();
+        let added = install(&reg, &dir, &src, DictionaryInstallOptions { name: None, direction: None, priority: Some(500), passcode: None });
+
+        let reloaded = DictionaryRegistry::new();
+        let entries = reloaded.entries(&dir);
+        assert_eq!(entries[0].id, added[0].id);
+        assert_eq!(entries.len(), 3);
+    }
+
+    #[test]
+    fn test_uninstall_removes_files_and_rejects_builtin() {
+        let dir = temp_dir();
+        let src = dir.join("g.tsv");
+        std::fs::write(&src, "x\ty\n").unwrap();
+
+        let reg = DictionaryRegistry::new();
+        let added = install(&reg, &dir, &src, DictionaryInstallOptions { name: None, direction: None, priority: None, passcode: None });
+        let main = dir.join(&added[0].path);
+        assert!(main.exists());
+
+        reg.uninstall(&dir, &added[0].id).unwrap();
+        assert!(!main.exists());
+        assert!(reg.uninstall(&dir, ECDICT_ID).is_err());
+    }
+
+    #[test]
+    fn test_update_entry() {
+        let dir = temp_dir();
+        let reg = DictionaryRegistry::new();
+        let e = reg
+            .update(&dir, CEDICT_ID, DictionaryUpdateOptions {
+                name: None,
+                direction: None,
+                priority: Some(1),
+                enabled: Some(false),
+            })
+            .unwrap();
+        assert!(!e.enabled);
+        assert_eq!(e.priority, 1);
+    }
+
+    #[test]
+    fn test_resource_cache_rel() {
+        assert_eq!(resource_cache_rel("sound://us/a.mp3"), Some(PathBuf::from("us").join("a.mp3")));
+        assert_eq!(resource_cache_rel("\\img\\b.png"), Some(PathBuf::from("img").join("b.png")));
+        assert_eq!(resource_cache_rel("../x"), None);
+        assert_eq!(resource_cache_rel("C:/Windows/x"), None);
+        assert_eq!(resource_cache_rel("img/a:b.png"), None);
+        assert_eq!(resource_cache_rel(""), None);
+    }
+
+    #[test]
+    fn test_sqlite_schema_detection() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE entries (simplified TEXT, traditional TEXT, pinyin TEXT, defs TEXT);")
+            .unwrap();
+        assert!(matches!(detect_sqlite_schema(&conn), Some(SqliteSchema::Cedict)));
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE other (x TEXT);").unwrap();
+        assert!(detect_sqlite_schema(&conn).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+use crate::covers::document_fingerprint;
+use crate::epub_writer::write_epub;
+use crate::fb2::convert_fb2;
+use crate::mobi::convert_mobi;
+use crate::AppState;
+
+/// Formats that are read by converting them to EPUB first.
+const CONVERTIBLE_EXTENSIONS: [&str; 4] = ["fb2", "mobi", "azw", "azw3"];
+
+pub(crate) fn is_convertible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| CONVERTIBLE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Converts an FB2, MOBI, AZW or AZW3 file into an EPUB at `dest`, keeping metadata and table of contents.
+pub(crate) fn convert_to_epub(src: &Path, dest: &Path) -> Result<(), String> {
+    let data = std::fs::read(src).map_err(|e| e.to_string())?;
+    let ext = src
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let book = match ext.as_str() {
+        "fb2" => convert_fb2(&data)?,
+        "mobi" | "azw" | "azw3" => convert_mobi(&data)?,
+        _ => return Err(format!("unsupported format: {}", ext)),
+    };
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    write_epub(&book, dest)
+}
+
+/// Cache of documents converted for in-place reading.
+pub(crate) fn converted_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("converted")
+}
+
+fn converted_path(app_data_dir: &Path, src: &Path) -> Result<PathBuf, String> {
+    Ok(converted_dir(app_data_dir).join(format!("{}.epub", document_fingerprint(src)?)))
+}
+
+/// Converted EPUB for a book opened in place. Conversions are cached by the source fingerprint.
+#[tauri::command]
+pub async fn convert_ebook(state: State<'_, AppState>, path: String) -> Result<String, String> {
+    let app_data_dir = state.app_data_dir.clone();
+    tokio::task::spawn_blocking(move || {
+        let src = PathBuf::from(&path);
+        let dest = converted_path(&app_data_dir, &src)?;
+        if !dest.exists() {
+            convert_to_epub(&src, &dest)?;
+        }
+        Ok(dest.to_string_lossy().to_string())
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
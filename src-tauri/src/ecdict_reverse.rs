@@ -0,0 +1,516 @@
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::dictionary::{cedict_lookup_impl, clean_definition_text, ecdict_manager_ready, DictionaryMeaning, DictionaryResult};
+use crate::meanings::{is_cjk, split_pos};
+use crate::AppState;
+
+const REVERSE_DB_FILE: &str = "ecdict_reverse.sqlite";
+/// Bumped whenever the index layout or sense splitting changes, forcing a rebuild.
+const REVERSE_INDEX_VERSION: i64 = 1;
+/// FTS candidates scored per query before ranking.
+const CANDIDATE_POOL: usize = 2000;
+/// English words merged into a CEDICT result.
+pub(crate) const MERGED_MATCHES: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReverseMatch {
+    pub word: String,
+    /// First translation line of the English word.
+    pub translation: String,
+    /// The query equals one of the word's senses, not just part of one.
+    pub exact: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frq: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collins: Option<u8>,
+    pub oxford: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ZhReverseResult {
+    pub word: String,
+    pub cedict: Option<DictionaryResult>,
+    pub matches: Vec<ReverseMatch>,
+}
+
+/// Reverse (Chinese → English) index over ECDICT's `translation` column, kept in its own
+/// SQLite file next to the ECDICT database.
+///
+/// `rev` is an FTS5 table whose text is the CJK characters of each translation separated by
+/// spaces, so an FTS phrase query `"放 弃"` is a substring match of any length. `senses`
+/// maps every individual sense (`放弃`) to its words for exact-match boosting.
+pub struct ReverseIndex {
+    conn: Mutex<Option<Connection>>,
+}
+
+impl ReverseIndex {
+    pub fn new() -> Self {
+        Self {
+            conn: Mutex::new(None),
+        }
+    }
+
+    pub fn reset(&self) {
+        *self.conn.lock().unwrap() = None;
+    }
+
+    fn with_conn<T>(&self, path: &Path, f: impl FnOnce(&Connection) -> Result<T, String>) -> Result<T, String> {
+        let mut guard = self.conn.lock().unwrap();
+        if guard.is_none() {
+            let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(|e| e.to_string())?;
+            *guard = Some(conn);
+        }
+        f(guard.as_ref().unwrap())
+    }
+}
+
+pub(crate) fn reverse_db_path(ecdict_db: &Path) -> PathBuf {
+    ecdict_db.with_file_name(REVERSE_DB_FILE)
+}
+
+/// Token standing in for punctuation and non-CJK runs in the indexed text. It must survive the
+/// `unicode61` tokenizer (which drops punctuation) so phrases cannot match across senses.
+const SENSE_BREAK: &str = "x";
+
+/// CJK characters of `text` separated by spaces, with a [`SENSE_BREAK`] token for every gap.
+fn spaced_cjk(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut gap = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            if gap && !out.is_empty() {
+                out.push(' ');
+                out.push_str(SENSE_BREAK);
+                out.push(' ');
+            } else if !out.is_empty() {
+                out.push(' ');
+            }
+            out.push(c);
+            gap = false;
+        } else {
+            gap = true;
+        }
+    }
+    out
+}
+
+/// Individual Chinese senses of an ECDICT translation: POS markers and parenthesised notes
+/// are dropped, and lines are split on commas and semicolons.
+fn translation_senses(translation: &str) -> Vec<String> {
+    let mut out: Vec<String> = vec![];
+    for line in clean_definition_text(translation).split('\n') {
+        let line = line.trim();
+        let body = split_pos(line).map(|(_, rest)| rest).unwrap_or(line);
+        let mut stripped = String::with_capacity(body.len());
+        let mut depth = 0;
+        for c in body.chars() {
+            match c {
+                '(' | '（' | '[' | '【' => depth += 1,
+                ')' | '）' | ']' | '】' => depth = (depth - 1).max(0),
+                _ if depth == 0 => stripped.push(c),
+                _ => {}
+            }
+        }
+        for sense in stripped.split([',', '，', ';', '；', '、']) {
+            let s = sense.trim();
+            if s.chars().any(is_cjk) && !out.iter().any(|x| x == s) {
+                out.push(s.to_string());
+            }
+        }
+    }
+    out
+}
+
+/// Size and modification time of the ECDICT database an index was built from, so a replaced
+/// or updated database forces a rebuild.
+fn source_stamp(source: &Path) -> Result<String, String> {
+    let meta = std::fs::metadata(source).map_err(|e| e.to_string())?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    Ok(format!("{}:{}", meta.len(), modified))
+}
+
+/// Builds the reverse index from the ECDICT SQLite at `source` into `target`, writing to a
+/// temporary file first so a half-built index is never picked up.
+pub(crate) fn build_reverse_index(source: &Path, target: &Path) -> Result<usize, String> {
+    let stamp = source_stamp(source)?;
+    let src = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| e.to_string())?;
+    let (table, cols) = ["entries", "stardict"]
+        .iter()
+        .find_map(|t| {
+            let mut stmt = src.prepare(&format!("PRAGMA table_info({})", t)).ok()?;
+            let cols: Vec<String> = stmt.query_map([], |r| r.get(1)).ok()?.filter_map(|r| r.ok()).collect();
+            (cols.iter().any(|c| c == "word") && cols.iter().any(|c| c == "translation")).then_some((*t, cols))
+        })
+        .ok_or_else(|| "ECDICT database has no translation column".to_string())?;
+    let col = |c: &str| if cols.iter().any(|x| x == c) { c.to_string() } else { "NULL".to_string() };
+
+    let tmp = target.with_extension("sqlite.tmp");
+    let _ = std::fs::remove_file(&tmp);
+    let mut dst = Connection::open(&tmp).map_err(|e| e.to_string())?;
+    dst.execute_batch(&format!(
+        "PRAGMA journal_mode=OFF;
+         PRAGMA synchronous=OFF;
+         CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT);
+         INSERT INTO meta VALUES ('version', '{}'), ('source', '{}');
+         CREATE VIRTUAL TABLE rev USING fts5(chars, word UNINDEXED, tokenize = 'unicode61');
+         CREATE TABLE words (word TEXT PRIMARY KEY, translation TEXT, frq INTEGER, collins INTEGER, oxford INTEGER);
+         CREATE TABLE senses (term TEXT NOT NULL, word TEXT NOT NULL);",
+        REVERSE_INDEX_VERSION, stamp
+    ))
+    .map_err(|e| e.to_string())?;
+
+    let sql = format!(
+        "SELECT word, translation, {}, {}, {}, {}, {} FROM {}",
+        col("frq"),
+        col("bnc"),
+        col("collins"),
+        col("oxford"),
+        col("exchange"),
+        table
+    );
+    let mut count = 0;
+    let tx = dst.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut read = src.prepare(&sql).map_err(|e| e.to_string())?;
+        let mut rows = read.query([]).map_err(|e| e.to_string())?;
+        let mut ins_rev = tx.prepare("INSERT INTO rev(chars, word) VALUES (?1, ?2)").map_err(|e| e.to_string())?;
+        let mut ins_word = tx
+            .prepare("INSERT OR IGNORE INTO words(word, translation, frq, collins, oxford) VALUES (?1, ?2, ?3, ?4, ?5)")
+            .map_err(|e| e.to_string())?;
+        let mut ins_sense = tx.prepare("INSERT INTO senses(term, word) VALUES (?1, ?2)").map_err(|e| e.to_string())?;
+
+        while let Some(r) = rows.next().map_err(|e| e.to_string())? {
+            let word: String = r.get(0).map_err(|e| e.to_string())?;
+            let translation: Option<String> = r.get(1).map_err(|e| e.to_string())?;
+            let exchange: Option<String> = r.get(6).ok().flatten();
+            // Inflected forms (`went`) point at their lemma, which carries the same glosses.
+            if exchange.as_deref().is_some_and(|x| x.split('/').any(|p| p.starts_with("0:"))) {
+                continue;
+            }
+            let Some(translation) = translation.filter(|t| t.chars().any(is_cjk)) else {
+                continue;
+            };
+            let frq: Option<i64> = r.get(2).ok().flatten();
+            let bnc: Option<i64> = r.get(3).ok().flatten();
+            let rank = [frq, bnc].into_iter().flatten().filter(|n| *n > 0).min();
+            let collins: Option<i64> = r.get(4).ok().flatten();
+            let oxford: Option<i64> = r.get(5).ok().flatten();
+
+            let first_line = clean_definition_text(&translation)
+                .split('\n')
+                .map(|s| s.trim().to_string())
+                .find(|s| !s.is_empty())
+                .unwrap_or_default();
+            ins_word
+                .execute(params![word, first_line, rank, collins, oxford])
+                .map_err(|e| e.to_string())?;
+            ins_rev
+                .execute(params![spaced_cjk(&clean_definition_text(&translation)), word])
+                .map_err(|e| e.to_string())?;
+            for sense in translation_senses(&translation) {
+                ins_sense.execute(params![sense, word]).map_err(|e| e.to_string())?;
+            }
+            count += 1;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    dst.execute_batch("CREATE INDEX idx_senses_term ON senses(term); INSERT INTO rev(rev) VALUES('optimize');")
+        .map_err(|e| e.to_string())?;
+    drop(dst);
+
+    std::fs::rename(&tmp, target).map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// Whether the index at `path` has the current layout and was built from `source` as it is now.
+pub(crate) fn index_is_current(path: &Path, source: &Path) -> bool {
+    let Ok(conn) = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) else {
+        return false;
+    };
+    let meta = |key: &str| {
+        conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |r| r.get::<_, String>(0))
+            .optional()
+            .ok()
+            .flatten()
+    };
+    meta("version").and_then(|v| v.parse::<i64>().ok()) == Some(REVERSE_INDEX_VERSION)
+        && meta("source").is_some_and(|v| source_stamp(source).is_ok_and(|stamp| stamp == v))
+}
+
+/// Higher is better: exact sense matches first, then common (frequent, Collins-starred,
+/// Oxford 3000) single words before rare words and phrases.
+fn score(m: &ReverseMatch) -> i64 {
+    let mut s = 0;
+    if m.exact {
+        s += 1000;
+    }
+    if let Some(rank) = m.frq {
+        s += 300 - (rank as i64 / 100).min(300);
+    }
+    s += m.collins.unwrap_or(0) as i64 * 40;
+    if m.oxford {
+        s += 60;
+    }
+    if m.word.contains(' ') {
+        s -= 80;
+    }
+    s
+}
+
+pub(crate) fn reverse_lookup_conn(conn: &Connection, term: &str, limit: usize) -> Result<Vec<ReverseMatch>, String> {
+    let phrase = spaced_cjk(term);
+    if phrase.is_empty() || phrase.split(' ').any(|t| t == SENSE_BREAK) {
+        return Ok(vec![]);
+    }
+
+    let mut exact: HashSet<String> = HashSet::new();
+    {
+        let mut stmt = conn
+            .prepare_cached("SELECT word FROM senses WHERE term = ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([term], |r| r.get::<_, String>(0)).map_err(|e| e.to_string())?;
+        for w in rows {
+            exact.insert(w.map_err(|e| e.to_string())?);
+        }
+    }
+
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT w.word, w.translation, w.frq, w.collins, w.oxford FROM rev \
+             JOIN words w ON w.word = rev.word WHERE rev MATCH ?1 ORDER BY rank LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let query = format!("\"{}\"", phrase);
+    let rows = stmt
+        .query_map(params![query, CANDIDATE_POOL as i64], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, Option<String>>(1)?,
+                r.get::<_, Option<i64>>(2)?,
+                r.get::<_, Option<i64>>(3)?,
+                r.get::<_, Option<i64>>(4)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut out: Vec<ReverseMatch> = vec![];
+    for row in rows {
+        let (word, translation, frq, collins, oxford) = row.map_err(|e| e.to_string())?;
+        if !seen.insert(word.to_lowercase()) {
+            continue;
+        }
+        out.push(ReverseMatch {
+            exact: exact.contains(&word),
+            word,
+            translation: translation.unwrap_or_default(),
+            frq: frq.filter(|n| *n > 0).and_then(|n| u32::try_from(n).ok()),
+            collins: collins.filter(|n| (1..=5).contains(n)).map(|n| n as u8),
+            oxford: oxford.unwrap_or(0) > 0,
+        });
+    }
+
+    out.sort_by(|a, b| score(b).cmp(&score(a)).then(a.word.len().cmp(&b.word.len())).then(a.word.cmp(&b.word)));
+    out.truncate(limit);
+    Ok(out)
+}
+
+/// Path of the reverse index if ECDICT is installed as SQLite and the index is built.
+fn ready_index_path(state: &AppState) -> Option<PathBuf> {
+    ecdict_manager_ready(state);
+    let path = reverse_db_path(&state.dictionary.get_db_path()?);
+    path.exists().then_some(path)
+}
+
+/// Reverse matches for `term`, or nothing when the index has not been built yet.
+pub(crate) fn reverse_lookup_impl(state: &AppState, term: &str, limit: usize) -> Vec<ReverseMatch> {
+    let Some(path) = ready_index_path(state) else {
+        return vec![];
+    };
+    match state
+        .dictionary
+        .reverse
+        .with_conn(&path, |conn| reverse_lookup_conn(conn, term, limit))
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::debug!("[dictionary] reverse lookup failed: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Adds the best English words from the reverse index to a CEDICT result, or builds a result
+/// from them alone when CEDICT has no entry.
+pub(crate) fn merge_reverse_matches(word: &str, cedict: Option<DictionaryResult>, matches: &[ReverseMatch]) -> Option<DictionaryResult> {
+    if matches.is_empty() {
+        return cedict;
+    }
+    let lines: Vec<String> = matches
+        .iter()
+        .take(MERGED_MATCHES)
+        .map(|m| format!("{} — {}", m.word, m.translation))
+        .collect();
+    let group = DictionaryMeaning {
+        part_of_speech: "ECDICT".to_string(),
+        definitions: lines,
+        examples: vec![],
+    };
+    match cedict {
+        Some(mut r) => {
+            r.meanings.push(group);
+            Some(r)
+        }
+        None => Some(DictionaryResult {
+            word: word.to_string(),
+            phonetic: None,
+            audio_url: None,
+            translation: Some(
+                matches
+                    .iter()
+                    .take(MERGED_MATCHES)
+                    .map(|m| m.word.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            meanings: vec![group],
+            html: None,
+            pinyin: None,
+        }),
+    }
+}
+
+/// Builds (or rebuilds when outdated) the reverse index from the installed ECDICT SQLite.
+#[tauri::command]
+pub async fn ecdict_reverse_index_build(state: State<'_, AppState>, force: Option<bool>) -> Result<usize, String> {
+    ecdict_manager_ready(&state);
+    let source = state
+        .dictionary
+        .get_db_path()
+        .ok_or_else(|| "reverse lookup needs the ECDICT SQLite database".to_string())?;
+    let target = reverse_db_path(&source);
+    if !force.unwrap_or(false) && target.exists() && index_is_current(&target, &source) {
+        return Ok(0);
+    }
+
+    state.dictionary.reverse.reset();
+    let count = tauri::async_runtime::spawn_blocking(move || build_reverse_index(&source, &target))
+        .await
+        .map_err(|e| e.to_string())??;
+    Ok(count)
+}
+
+/// Chinese → English lookup: the CEDICT entry plus ranked English words from ECDICT whose
+/// translation contains `word`.
+#[tauri::command]
+pub fn zh_reverse_lookup(state: State<AppState>, word: String, limit: Option<usize>) -> Result<ZhReverseResult, String> {
+    let clean = word.trim().to_string();
+    let matches = reverse_lookup_impl(&state, &clean, limit.unwrap_or(20).min(200));
+    let cedict = match cedict_lookup_impl(&state, &clean) {
+        Ok(hit) => hit,
+        Err(e) if matches.is_empty() => return Err(e),
+        Err(_) => None,
+    };
+    Ok(ZhReverseResult {
+        word: clean,
+        cedict,
+        matches,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aireader_rev_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn build_sample() -> (PathBuf, Connection) {
+        let dir = temp_dir();
+        let src = dir.join("ecdict.sqlite");
+        let conn = Connection::open(&src).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (word TEXT PRIMARY KEY COLLATE NOCASE, phonetic TEXT, definition TEXT, \
+             translation TEXT, pos TEXT, audio TEXT, collins INTEGER, oxford INTEGER, tag TEXT, bnc INTEGER, \
+             frq INTEGER, exchange TEXT);
+             INSERT INTO entries(word, translation, collins, oxford, bnc, frq, exchange) VALUES
+               ('abandon', 'vt. 放弃, 抛弃, 遗弃\\nn. 放任, 狂热', 4, 1, 2000, 1800, 'd:abandoned/p:abandoned'),
+               ('abandoned', 'a. 被抛弃的, 放荡的', 3, 0, 5000, 4000, '0:abandon/1:p'),
+               ('give up', 'phr. 放弃; 投降', NULL, NULL, NULL, NULL, NULL),
+               ('renounce', 'vt. 宣布放弃, 断绝关系（正式）', 2, 0, 9000, 9500, NULL),
+               ('relinquish', 'vt. 放弃, 让给', 1, 0, 15000, 14000, NULL),
+               ('apple', 'n. 苹果', 5, 1, 900, 800, NULL);",
+        )
+        .unwrap();
+        drop(conn);
+        let target = reverse_db_path(&src);
+        let n = build_reverse_index(&src, &target).unwrap();
+        assert_eq!(n, 5);
+        assert!(index_is_current(&target, &src));
+        // A replaced or updated ECDICT database makes the index stale.
+        let file = std::fs::File::options().write(true).open(&src).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1)).unwrap();
+        assert!(!index_is_current(&target, &src));
+        (dir, Connection::open(&target).unwrap())
+    }
+
+    #[test]
+    fn test_spaced_cjk_and_senses() {
+        assert_eq!(spaced_cjk("vt. 放弃, 抛弃"), "放 弃 x 抛 弃");
+        assert_eq!(
+            translation_senses("vt. 宣布放弃, 断绝关系（正式）\\n[网络] 放弃；摒弃"),
+            vec!["宣布放弃", "断绝关系", "放弃", "摒弃"]
+        );
+    }
+
+    #[test]
+    fn test_reverse_lookup_ranking() {
+        let (dir, conn) = build_sample();
+        let m = reverse_lookup_conn(&conn, "放弃", 10).unwrap();
+        let words: Vec<&str> = m.iter().map(|x| x.word.as_str()).collect();
+        // Exact-sense, frequent words first; `abandoned` is an inflection and not indexed.
+        assert_eq!(words, vec!["abandon", "relinquish", "give up", "renounce"]);
+        assert!(m[0].exact);
+        assert!(!m[3].exact);
+        assert_eq!(m[0].translation, "vt. 放弃, 抛弃, 遗弃");
+
+        // Phrase queries do not match across senses (弃 x 遗 in "抛弃, 遗弃").
+        assert!(reverse_lookup_conn(&conn, "弃遗", 10).unwrap().is_empty());
+        assert_eq!(reverse_lookup_conn(&conn, "苹果", 10).unwrap()[0].word, "apple");
+        assert!(reverse_lookup_conn(&conn, "abc", 10).unwrap().is_empty());
+        drop(conn);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_merge_reverse_matches() {
+        let matches = vec![ReverseMatch {
+            word: "abandon".to_string(),
+            translation: "vt. 放弃".to_string(),
+            exact: true,
+            frq: None,
+            collins: None,
+            oxford: false,
+        }];
+        let r = merge_reverse_matches("放弃", None, &matches).unwrap();
+        assert_eq!(r.translation.as_deref(), Some("abandon"));
+        assert_eq!(r.meanings[0].definitions, vec!["abandon — vt. 放弃"]);
+        assert!(merge_reverse_matches("放弃", None, &[]).is_none());
+    }
+}
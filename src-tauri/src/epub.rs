@@ -5,17 +5,89 @@ use std::fs::File;
 use std::hash::{Hash, Hasher};
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tauri::State;
 use zip::ZipArchive;
 
+use crate::covers::document_fingerprint;
+use crate::dictionary::dir_size;
+use crate::epub_crypto::{check_encryption, EpubOpenError};
+use crate::epub_repair::{locate_opf, repair_extracted, EpubRepairReport};
+use crate::epub_meta::parse_container;
+use crate::epub_protocol::ArchiveIndex;
 use crate::AppState;
 
+const CACHE_DIR: &str = "epub_extracted";
+const CACHE_CONFIG_FILE: &str = "epub_cache.json";
+const META_FILE: &str = "_meta.json";
+const DEFAULT_CACHE_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+/// Folders without `_meta.json` younger than this may still be extracting.
+const ORPHAN_GRACE_MS: u128 = 60 * 60 * 1000;
+/// Bumped when extraction output changes (2: obfuscated fonts are restored, 3: repair pass), so
+/// older folders are redone.
+const EXTRACT_FORMAT: u32 = 3;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct EpubExtractMeta {
     source_path: String,
     source_size: u64,
     source_modified_ms: u128,
     opf_rel: String,
+    #[serde(default)]
+    size_bytes: u64,
+    #[serde(default = "legacy_format")]
+    format: u32,
+    #[serde(default)]
+    repair: EpubRepairReport,
+    #[serde(default)]
+    last_access_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EpubCacheConfig {
+    /// Total size the extracted books may take before the least recently opened are evicted.
+    pub max_bytes: u64,
+}
+
+impl Default for EpubCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: DEFAULT_CACHE_MAX_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EpubCacheStatus {
+    pub path: String,
+    pub entries: usize,
+    pub size_bytes: u64,
+    pub max_bytes: u64,
+}
+
+fn legacy_format() -> u32 {
+    1
+}
+
+fn now_ms() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+fn modified_ms(meta: &std::fs::Metadata) -> u128 {
+    meta.modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+fn write_extract_meta(meta_path: &Path, m: &EpubExtractMeta) -> Result<(), String> {
+    let json = serde_json::to_string(m).map_err(|e| e.to_string())?;
+    let mut mf = File::create(meta_path).map_err(|e| e.to_string())?;
+    mf.write_all(json.as_bytes()).map_err(|e| e.to_string())
 }
 
 fn clean_rel_path(raw: &str) -> Option<PathBuf> {
@@ -36,7 +108,12 @@ fn clean_rel_path(raw: &str) -> Option<PathBuf> {
     }
 }
 
-fn parse_container_for_opf(xml: &str) -> Option<String> {
+pub(crate) fn parse_container_for_opf(xml: &str) -> Option<String> {
+    parse_container(xml).or_else(|| scan_container_for_opf(xml))
+}
+
+/// Fallback for container.xml files that are not well-formed XML.
+fn scan_container_for_opf(xml: &str) -> Option<String> {
     let mut i = 0usize;
     while let Some(pos) = xml[i..].find("full-path") {
         let start = i + pos;
@@ -67,7 +144,7 @@ fn parse_container_for_opf(xml: &str) -> Option<String> {
     None
 }
 
-fn hash_key(path: &str, size: u64, modified_ms: u128) -> String {
+pub(crate) fn hash_key(path: &str, size: u64, modified_ms: u128) -> String {
     let mut h = DefaultHasher::new();
     path.hash(&mut h);
     size.hash(&mut h);
@@ -76,34 +153,60 @@ fn hash_key(path: &str, size: u64, modified_ms: u128) -> String {
 }
 
 /// 内部同步解压逻辑
-fn epub_extract_sync(documents_dir: PathBuf, path: String) -> Result<String, String> {
+/// Extracts `path` into the cache (or reuses an earlier extraction) and returns the absolute OPF
+/// path along with what the repair pass changed.
+/// Books in `open` (the one being opened is added, and dropped again if extraction fails) are
+/// never evicted to make room.
+fn epub_extract_sync(
+    documents_dir: PathBuf,
+    path: String,
+    max_bytes: u64,
+    open: &Mutex<HashSet<String>>,
+) -> Result<(String, EpubRepairReport), EpubOpenError> {
     let src = PathBuf::from(&path);
     let canon = std::fs::canonicalize(&src).map_err(|e| e.to_string())?;
     let meta = std::fs::metadata(&canon).map_err(|e| e.to_string())?;
-    let modified_ms = meta
-        .modified()
-        .ok()
-        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-        .map(|d| d.as_millis())
-        .unwrap_or(0);
+    let key = hash_key(&canon.to_string_lossy(), meta.len(), modified_ms(&meta));
+    let added = open.lock().unwrap().insert(key.clone());
+    let result = extract_into_cache(documents_dir, &path, &canon, &meta, &key, max_bytes, open);
+    if result.is_err() && added {
+        open.lock().unwrap().remove(&key);
+    }
+    result
+}
 
-    let key = hash_key(&canon.to_string_lossy(), meta.len(), modified_ms);
-    let base_dir = documents_dir.join("epub_extracted");
+fn extract_into_cache(
+    documents_dir: PathBuf,
+    path: &str,
+    canon: &Path,
+    meta: &std::fs::Metadata,
+    key: &str,
+    max_bytes: u64,
+    open: &Mutex<HashSet<String>>,
+) -> Result<(String, EpubRepairReport), EpubOpenError> {
+    let modified_ms = modified_ms(meta);
+    let base_dir = documents_dir.join(CACHE_DIR);
     let _ = std::fs::create_dir_all(&base_dir);
-    let target_dir = base_dir.join(&key);
-    let meta_path = target_dir.join("_meta.json");
+    let target_dir = base_dir.join(key);
+    let meta_path = target_dir.join(META_FILE);
 
     // 快速路径：如果已经解压过且元数据匹配，直接返回
     if meta_path.exists() {
         if let Ok(s) = std::fs::read_to_string(&meta_path) {
-            if let Ok(m) = serde_json::from_str::<EpubExtractMeta>(&s) {
+            if let Ok(mut m) = serde_json::from_str::<EpubExtractMeta>(&s) {
                 if m.source_path == canon.to_string_lossy()
                     && m.source_size == meta.len()
                     && m.source_modified_ms == modified_ms
+                    && m.format == EXTRACT_FORMAT
                 {
                     let opf_abs = target_dir.join(&m.opf_rel);
                     if opf_abs.exists() {
-                        return Ok(opf_abs.to_string_lossy().to_string());
+                        m.last_access_ms = now_ms();
+                        if m.size_bytes == 0 {
+                            m.size_bytes = dir_size(&target_dir);
+                        }
+                        let _ = write_extract_meta(&meta_path, &m);
+                        return Ok((opf_abs.to_string_lossy().to_string(), m.repair));
                     }
                 }
             }
@@ -115,21 +218,47 @@ fn epub_extract_sync(documents_dir: PathBuf, path: String) -> Result<String, Str
     }
     std::fs::create_dir_all(&target_dir).map_err(|e| e.to_string())?;
 
-    let file = File::open(&canon).map_err(|e| e.to_string())?;
+    let file = File::open(canon).map_err(|e| e.to_string())?;
     let reader = BufReader::new(file);
     let mut zip = ZipArchive::new(reader).map_err(|e| e.to_string())?;
 
-    let container_xml = {
-        let mut f = zip
-            .by_name("META-INF/container.xml")
-            .map_err(|_| "container.xml missing (META-INF/container.xml)".to_string())?;
-        let mut s = String::new();
-        f.read_to_string(&mut s).map_err(|e| e.to_string())?;
-        s
+    let mut repair = EpubRepairReport::default();
+    let names: Vec<String> = zip.file_names().map(str::to_string).collect();
+    let opf_rel = locate_opf(
+        &names,
+        |name| {
+            let mut f = zip.by_name(name).ok()?;
+            let mut data = Vec::new();
+            f.read_to_end(&mut data).ok()?;
+            Some(String::from_utf8_lossy(&data).to_string())
+        },
+        parse_container_for_opf,
+        &mut repair,
+    );
+    let opf_rel = match opf_rel {
+        Ok(opf_rel) => opf_rel,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&target_dir);
+            return Err(e.into());
+        }
     };
 
-    let opf_rel = parse_container_for_opf(&container_xml)
-        .ok_or_else(|| "container.xml invalid (OPF not found)".to_string())?;
+    let fonts = check_encryption(
+        |name| {
+            let mut f = zip.by_name(name).ok()?;
+            let mut data = Vec::new();
+            f.read_to_end(&mut data).ok()?;
+            Some(data)
+        },
+        &opf_rel,
+    );
+    let fonts = match fonts {
+        Ok(fonts) => fonts,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&target_dir);
+            return Err(e);
+        }
+    };
 
     let mut created_dirs: HashSet<PathBuf> = HashSet::new();
     created_dirs.insert(target_dir.clone());
@@ -158,6 +287,14 @@ fn epub_extract_sync(documents_dir: PathBuf, path: String) -> Result<String, Str
             }
         }
 
+        if let Some(fonts) = fonts.as_ref().filter(|d| d.is_obfuscated(&name)) {
+            let mut data = Vec::new();
+            f.read_to_end(&mut data).map_err(|e| e.to_string())?;
+            fonts.apply(&name, &mut data);
+            std::fs::write(&out_path, data).map_err(|e| e.to_string())?;
+            continue;
+        }
+
         let out = File::create(&out_path).map_err(|e| e.to_string())?;
         let mut out = BufWriter::with_capacity(512 * 1024, out);
         loop {
@@ -174,7 +311,20 @@ fn epub_extract_sync(documents_dir: PathBuf, path: String) -> Result<String, Str
     let opf_abs = target_dir.join(&opf_rel_clean);
     if !opf_abs.exists() {
         let _ = std::fs::remove_dir_all(&target_dir);
-        return Err("OPF extracted but not found".to_string());
+        return Err("OPF extracted but not found".to_string().into());
+    }
+
+    let opf_archive_path = opf_rel_clean.to_string_lossy().replace('\\', "/");
+    if let Err(e) = repair_extracted(&target_dir, &opf_archive_path, &mut repair) {
+        log::warn!("[epub] repair pass failed for {}: {}", path, e);
+    }
+    if !repair.is_empty() {
+        log::info!(
+            "[epub] repaired {}: {} fixed, {} unresolved",
+            path,
+            repair.fixed.len(),
+            repair.unresolved.len()
+        );
     }
 
     let m = EpubExtractMeta {
@@ -182,12 +332,106 @@ fn epub_extract_sync(documents_dir: PathBuf, path: String) -> Result<String, Str
         source_size: meta.len(),
         source_modified_ms: modified_ms,
         opf_rel: opf_rel_clean.to_string_lossy().to_string(),
+        size_bytes: dir_size(&target_dir),
+        format: EXTRACT_FORMAT,
+        repair: repair.clone(),
+        last_access_ms: now_ms(),
     };
-    let json = serde_json::to_string(&m).map_err(|e| e.to_string())?;
-    let mut mf = File::create(&meta_path).map_err(|e| e.to_string())?;
-    mf.write_all(json.as_bytes()).map_err(|e| e.to_string())?;
+    write_extract_meta(&meta_path, &m)?;
+
+    let keep = open.lock().unwrap().clone();
+    let freed = prune_cache(&base_dir, max_bytes, &keep);
+    if freed > 0 {
+        log::info!("[epub] evicted {} bytes from {}", freed, base_dir.display());
+    }
+
+    Ok((opf_abs.to_string_lossy().to_string(), repair))
+}
+
+struct CacheEntry {
+    dir: PathBuf,
+    key: String,
+    size_bytes: u64,
+    last_access_ms: u128,
+    stale: bool,
+}
 
-    Ok(opf_abs.to_string_lossy().to_string())
+/// Lists extracted books. An entry is stale when its source was moved, deleted or
+/// modified (the key no longer matches), or it is an abandoned partial extraction.
+fn scan_cache(base_dir: &Path) -> Vec<CacheEntry> {
+    let Ok(rd) = std::fs::read_dir(base_dir) else {
+        return Vec::new();
+    };
+    let now = now_ms();
+    rd.filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .map(|e| {
+            let dir = e.path();
+            let key = e.file_name().to_string_lossy().to_string();
+            let meta = std::fs::read_to_string(dir.join(META_FILE))
+                .ok()
+                .and_then(|s| serde_json::from_str::<EpubExtractMeta>(&s).ok());
+            match meta {
+                Some(m) => {
+                    let stale = match std::fs::metadata(&m.source_path) {
+                        Ok(src) => src.len() != m.source_size || modified_ms(&src) != m.source_modified_ms,
+                        Err(_) => true,
+                    };
+                    let size_bytes = if m.size_bytes > 0 { m.size_bytes } else { dir_size(&dir) };
+                    CacheEntry { dir, key, size_bytes, last_access_ms: m.last_access_ms, stale }
+                }
+                None => {
+                    let touched = e.metadata().map(|m| modified_ms(&m)).unwrap_or(0);
+                    CacheEntry {
+                        size_bytes: dir_size(&dir),
+                        dir,
+                        key,
+                        last_access_ms: touched,
+                        stale: now.saturating_sub(touched) > ORPHAN_GRACE_MS,
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// Removes stale entries, then the least recently opened until the total fits `max_bytes`.
+/// Books in `keep` (open in a reader) are never evicted. Returns the bytes freed.
+fn prune_cache(base_dir: &Path, max_bytes: u64, keep: &HashSet<String>) -> u64 {
+    let (kept, mut entries): (Vec<CacheEntry>, Vec<CacheEntry>) =
+        scan_cache(base_dir).into_iter().partition(|e| keep.contains(&e.key));
+    let kept_size: u64 = kept.iter().map(|e| e.size_bytes).sum();
+    entries.sort_by_key(|e| (!e.stale, e.last_access_ms));
+
+    let mut total: u64 = kept_size + entries.iter().map(|e| e.size_bytes).sum::<u64>();
+    let mut freed = 0;
+    for e in entries {
+        if !e.stale && total <= max_bytes {
+            break;
+        }
+        if std::fs::remove_dir_all(&e.dir).is_ok() {
+            total = total.saturating_sub(e.size_bytes);
+            freed += e.size_bytes;
+        }
+    }
+    freed
+}
+
+fn load_cache_config(app_data_dir: &Path) -> EpubCacheConfig {
+    std::fs::read_to_string(app_data_dir.join(CACHE_CONFIG_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn cache_status(base_dir: &Path, max_bytes: u64) -> EpubCacheStatus {
+    let entries = scan_cache(base_dir);
+    EpubCacheStatus {
+        path: base_dir.to_string_lossy().to_string(),
+        entries: entries.len(),
+        size_bytes: entries.iter().map(|e| e.size_bytes).sum(),
+        max_bytes,
+    }
 }
 
 #[cfg(test)]
@@ -258,6 +502,63 @@ mod tests {
         assert_eq!(parse_container_for_opf(xml), None);
     }
 
+    #[test]
+    fn test_parse_container_for_opf_malformed_xml() {
+        let xml = r#"<container><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"></container>"#;
+        assert_eq!(parse_container_for_opf(xml), Some("OEBPS/content.opf".to_string()));
+    }
+
+    fn cache_entry(base: &Path, key: &str, source: &Path, size: usize, last_access_ms: u128) {
+        let dir = base.join(key);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("book.xhtml"), vec![b'x'; size]).unwrap();
+        let src = std::fs::metadata(source).ok();
+        let m = EpubExtractMeta {
+            source_path: source.to_string_lossy().to_string(),
+            source_size: src.as_ref().map(|m| m.len()).unwrap_or(0),
+            source_modified_ms: src.as_ref().map(modified_ms).unwrap_or(0),
+            opf_rel: "content.opf".to_string(),
+            size_bytes: size as u64,
+            format: EXTRACT_FORMAT,
+            repair: EpubRepairReport::default(),
+            last_access_ms,
+        };
+        write_extract_meta(&dir.join(META_FILE), &m).unwrap();
+    }
+
+    #[test]
+    fn test_prune_cache_evicts_stale_then_least_recent() {
+        let root = std::env::temp_dir().join(format!("epub_cache_{}", uuid::Uuid::new_v4()));
+        let base = root.join(CACHE_DIR);
+        std::fs::create_dir_all(&base).unwrap();
+        let source = root.join("a.epub");
+        std::fs::write(&source, b"epub").unwrap();
+
+        cache_entry(&base, "old", &source, 100, 1);
+        cache_entry(&base, "recent", &source, 100, 3);
+        cache_entry(&base, "current", &source, 100, 2);
+        cache_entry(&base, "moved", &root.join("gone.epub"), 10, 5);
+
+        // The missing source goes first, then "old"; "current" is protected although older than "recent".
+        assert_eq!(prune_cache(&base, 200, &HashSet::from(["current".to_string()])), 110);
+        let mut left: Vec<String> = scan_cache(&base).into_iter().map(|e| e.key).collect();
+        left.sort();
+        assert_eq!(left, vec!["current", "recent"]);
+
+        let status = cache_status(&base, 200);
+        assert_eq!((status.entries, status.size_bytes), (2, 200));
+        assert_eq!(prune_cache(&base, 0, &HashSet::new()), 200);
+        assert_eq!(cache_status(&base, 0).entries, 0);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_extract_meta_without_access_fields() {
+        let json = r#"{"source_path":"/a.epub","source_size":1,"source_modified_ms":2,"opf_rel":"content.opf"}"#;
+        let m: EpubExtractMeta = serde_json::from_str(json).unwrap();
+        assert_eq!((m.size_bytes, m.last_access_ms, m.format), (0, 0, 1));
+    }
+
     #[test]
     fn test_hash_key_deterministic() {
         let k1 = hash_key("/path/to/book.epub", 12345, 67890);
@@ -278,13 +579,78 @@ mod tests {
 
 /// 异步EPUB解压命令 - 在后台线程执行，不阻塞主线程
 #[tauri::command]
-pub async fn epub_extract(state: State<'_, AppState>, path: String) -> Result<String, String> {
+pub async fn epub_extract(state: State<'_, AppState>, path: String) -> Result<String, EpubOpenError> {
     let documents_dir = state.documents_dir.read().unwrap().clone();
+    let max_bytes = load_cache_config(&state.app_data_dir).max_bytes;
+    let open = state.open_extracts.clone();
     
     // 在后台线程执行IO密集型操作
     tokio::task::spawn_blocking(move || {
-        epub_extract_sync(documents_dir, path)
+        epub_extract_sync(documents_dir, path, max_bytes, &open).map(|(opf, _)| opf)
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Validation report of a book: what is repaired when it is read and what could not be. Reads the
+/// archive in place (reusing it if it is open) rather than extracting it.
+#[tauri::command]
+pub async fn epub_repair_report(state: State<'_, AppState>, path: String) -> Result<EpubRepairReport, EpubOpenError> {
+    let path = PathBuf::from(path);
+    let open = document_fingerprint(&path).ok().and_then(|doc| state.epub_archives.get(&doc));
+    tokio::task::spawn_blocking(move || match open {
+        Some(index) => Ok(index.repair_report()),
+        None => ArchiveIndex::build(&path).map(|index| index.repair_report()),
     })
     .await
     .map_err(|e| format!("spawn_blocking failed: {}", e))?
 }
+
+/// Size and entry count of the extracted EPUB cache.
+#[tauri::command]
+pub async fn epub_cache_status(state: State<'_, AppState>) -> Result<EpubCacheStatus, String> {
+    let base_dir = state.documents_dir.read().unwrap().join(CACHE_DIR);
+    let max_bytes = load_cache_config(&state.app_data_dir).max_bytes;
+    tokio::task::spawn_blocking(move || cache_status(&base_dir, max_bytes))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))
+}
+
+/// Sets the cache size cap and evicts down to it.
+#[tauri::command]
+pub async fn epub_cache_set_limit(state: State<'_, AppState>, max_bytes: u64) -> Result<EpubCacheStatus, String> {
+    let config = EpubCacheConfig { max_bytes };
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&state.app_data_dir).map_err(|e| e.to_string())?;
+    std::fs::write(state.app_data_dir.join(CACHE_CONFIG_FILE), json).map_err(|e| e.to_string())?;
+
+    let base_dir = state.documents_dir.read().unwrap().join(CACHE_DIR);
+    let keep = state.open_extracts.lock().unwrap().clone();
+    tokio::task::spawn_blocking(move || {
+        prune_cache(&base_dir, max_bytes, &keep);
+        cache_status(&base_dir, max_bytes)
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))
+}
+
+/// Removes every extracted book except those open in a reader; they are extracted again when next
+/// opened. Returns the bytes freed.
+#[tauri::command]
+pub async fn epub_cache_clear(state: State<'_, AppState>) -> Result<u64, String> {
+    let base_dir = state.documents_dir.read().unwrap().join(CACHE_DIR);
+    let keep = state.open_extracts.lock().unwrap().clone();
+    tokio::task::spawn_blocking(move || prune_cache(&base_dir, 0, &keep))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))
+}
+
+/// Marks a book opened with [`epub_extract`] as closed, so its extraction may be evicted again.
+#[tauri::command]
+pub fn epub_release_extract(state: State<AppState>, path: String) -> Result<(), String> {
+    let canon = std::fs::canonicalize(&path).map_err(|e| e.to_string())?;
+    let meta = std::fs::metadata(&canon).map_err(|e| e.to_string())?;
+    let key = hash_key(&canon.to_string_lossy(), meta.len(), modified_ms(&meta));
+    state.open_extracts.lock().unwrap().remove(&key);
+    Ok(())
+}
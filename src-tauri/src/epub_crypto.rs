@@ -0,0 +1,320 @@
+use roxmltree::Node;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::epub_meta::{parse_opf, parse_xml, percent_decode, EpubMetadata};
+
+pub(crate) const ENCRYPTION_XML: &str = "META-INF/encryption.xml";
+const IDPF_OBFUSCATION: &str = "http://www.idpf.org/2008/embedding";
+const ADOBE_OBFUSCATION: &str = "http://ns.adobe.com/pdf/enc#RC";
+const ADEPT_NS: &str = "http://ns.adobe.com/adept";
+/// Bytes of an obfuscated resource that are XORed with the key.
+const IDPF_HEADER_LEN: usize = 1040;
+const ADOBE_HEADER_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DrmScheme {
+    AdobeAdept,
+    AppleFairplay,
+    ReadiumLcp,
+    Unknown,
+}
+
+/// Error of the commands that open an EPUB for reading. Serialized as
+/// `{ "kind": "drm", "scheme": "adobe_adept", "message": ... }` or `{ "kind": "other", "message": ... }`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EpubOpenError {
+    Drm { scheme: DrmScheme, message: String },
+    Other { message: String },
+}
+
+impl EpubOpenError {
+    fn drm(scheme: DrmScheme, encrypted: usize) -> Self {
+        let message = match scheme {
+            DrmScheme::AdobeAdept => {
+                "This book is protected by Adobe DRM (ADEPT) and can only be read in Adobe Digital Editions or another authorized reader.".to_string()
+            }
+            DrmScheme::AppleFairplay => {
+                "This book is protected by Apple FairPlay DRM and can only be read in Apple Books.".to_string()
+            }
+            DrmScheme::ReadiumLcp => {
+                "This book is protected by Readium LCP and needs a reader that holds its license.".to_string()
+            }
+            DrmScheme::Unknown => format!("{encrypted} files in this book are encrypted with an unrecognized scheme, so it cannot be opened."),
+        };
+        EpubOpenError::Drm { scheme, message }
+    }
+}
+
+impl From<String> for EpubOpenError {
+    fn from(message: String) -> Self {
+        EpubOpenError::Other { message }
+    }
+}
+
+impl fmt::Display for EpubOpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpubOpenError::Drm { message, .. } | EpubOpenError::Other { message } => f.write_str(message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Obfuscation {
+    Idpf,
+    Adobe,
+}
+
+/// What `encryption.xml` declares, by archive path.
+#[derive(Debug, Default)]
+struct Encryption {
+    obfuscated: HashMap<String, Obfuscation>,
+    /// Entries encrypted with anything other than font obfuscation.
+    encrypted: Vec<String>,
+    adept_key: bool,
+    lcp_key: bool,
+}
+
+fn parse_encryption(xml: &str) -> Result<Encryption, String> {
+    let doc = parse_xml(xml)?;
+    let mut enc = Encryption::default();
+    let named = |n: &Node, name: &str| n.is_element() && n.tag_name().name() == name;
+    for data in doc.descendants().filter(|n| named(n, "EncryptedData")) {
+        let algorithm = data
+            .descendants()
+            .find(|n| named(n, "EncryptionMethod"))
+            .and_then(|n| n.attribute("Algorithm"))
+            .unwrap_or_default();
+        let Some(uri) = data
+            .descendants()
+            .find(|n| named(n, "CipherReference"))
+            .and_then(|n| n.attribute("URI"))
+        else {
+            continue;
+        };
+        // CipherReference URIs are relative to the container root.
+        let name = percent_decode(uri.trim()).trim_start_matches('/').to_string();
+        match algorithm {
+            IDPF_OBFUSCATION => {
+                enc.obfuscated.insert(name, Obfuscation::Idpf);
+            }
+            ADOBE_OBFUSCATION => {
+                enc.obfuscated.insert(name, Obfuscation::Adobe);
+            }
+            _ => enc.encrypted.push(name),
+        }
+        for key in data.descendants().filter(|n| n.is_element()) {
+            if key.tag_name().namespace() == Some(ADEPT_NS) {
+                enc.adept_key = true;
+            }
+            if named(&key, "RetrievalMethod") && key.attribute("URI").is_some_and(|u| u.contains("license.lcpl")) {
+                enc.lcp_key = true;
+            }
+        }
+    }
+    Ok(enc)
+}
+
+fn uuid_bytes(value: &str) -> Option<[u8; 16]> {
+    let v = value.trim();
+    let v = v.strip_prefix("urn:uuid:").unwrap_or(v);
+    let hex: String = v.chars().filter(|c| *c != '-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut out = [0u8; 16];
+    for (i, b) in out.iter_mut().enumerate() {
+        *b = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+/// Restores fonts obfuscated with the IDPF or Adobe algorithm. Both XOR the start of the
+/// uncompressed resource with a key derived from the package identifier, so applying it twice
+/// is the identity.
+#[derive(Debug)]
+pub(crate) struct FontDeobfuscator {
+    fonts: HashMap<String, Obfuscation>,
+    /// SHA-1 of the unique identifier with whitespace removed.
+    idpf_key: Option<[u8; 20]>,
+    /// The bytes of the book's `urn:uuid:` identifier.
+    adobe_key: Option<[u8; 16]>,
+}
+
+impl FontDeobfuscator {
+    fn new(fonts: HashMap<String, Obfuscation>, meta: &EpubMetadata) -> Self {
+        let idpf_key = meta.unique_identifier.as_ref().map(|id| {
+            let stripped: String = id.chars().filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n')).collect();
+            Sha1::digest(stripped.as_bytes()).into()
+        });
+        let adobe_key = meta
+            .unique_identifier
+            .iter()
+            .chain(meta.identifiers.iter().map(|i| &i.value))
+            .find_map(|v| uuid_bytes(v));
+        Self {
+            fonts,
+            idpf_key,
+            adobe_key,
+        }
+    }
+
+    pub(crate) fn is_obfuscated(&self, name: &str) -> bool {
+        self.fonts.contains_key(name)
+    }
+
+    /// Deobfuscates `data` in place if `name` is an obfuscated entry.
+    pub(crate) fn apply(&self, name: &str, data: &mut [u8]) {
+        let (key, len): (&[u8], usize) = match (self.fonts.get(name), &self.idpf_key, &self.adobe_key) {
+            (Some(Obfuscation::Idpf), Some(k), _) => (k, IDPF_HEADER_LEN),
+            (Some(Obfuscation::Adobe), _, Some(k)) => (k, ADOBE_HEADER_LEN),
+            _ => return,
+        };
+        for (i, b) in data.iter_mut().take(len).enumerate() {
+            *b ^= key[i % key.len()];
+        }
+    }
+}
+
+/// Checks `META-INF/encryption.xml` through `read` (archive path -> entry bytes). Books with
+/// encrypted content are refused with the DRM scheme that protects them, and so are books whose
+/// encryption.xml cannot be parsed; books that only obfuscate fonts get a deobfuscator for those entries.
+pub(crate) fn check_encryption(
+    mut read: impl FnMut(&str) -> Option<Vec<u8>>,
+    opf_path: &str,
+) -> Result<Option<FontDeobfuscator>, EpubOpenError> {
+    let Some(xml) = read(ENCRYPTION_XML) else {
+        return Ok(None);
+    };
+    let enc = match parse_encryption(&String::from_utf8_lossy(&xml)) {
+        Ok(enc) => enc,
+        Err(e) => {
+            // Whether content is encrypted cannot be told; opening it could show ciphertext as text.
+            log::warn!("[epub_crypto] unreadable {ENCRYPTION_XML}: {e}");
+            return Err(EpubOpenError::Drm {
+                scheme: DrmScheme::Unknown,
+                message: format!("This book's {ENCRYPTION_XML} cannot be read ({e}), so it may be encrypted and cannot be opened."),
+            });
+        }
+    };
+    if !enc.encrypted.is_empty() {
+        let scheme = if enc.lcp_key || read("META-INF/license.lcpl").is_some() {
+            DrmScheme::ReadiumLcp
+        } else if read("META-INF/sinf.xml").is_some() {
+            DrmScheme::AppleFairplay
+        } else if enc.adept_key || read("META-INF/rights.xml").is_some() {
+            DrmScheme::AdobeAdept
+        } else {
+            DrmScheme::Unknown
+        };
+        return Err(EpubOpenError::drm(scheme, enc.encrypted.len()));
+    }
+    if enc.obfuscated.is_empty() {
+        return Ok(None);
+    }
+    let opf = read(opf_path).ok_or_else(|| format!("{opf_path} not found"))?;
+    let meta = parse_opf(&String::from_utf8_lossy(&opf), opf_path)?;
+    Ok(Some(FontDeobfuscator::new(enc.obfuscated, &meta)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPF_PATH: &str = "OEBPS/content.opf";
+    const OPF: &str = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <dc:identifier id="uid"> urn:uuid:0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0 </dc:identifier>
+</metadata>
+<manifest/><spine/></package>"#;
+
+    fn obfuscation_xml(algorithm: &str, uri: &str) -> String {
+        format!(
+            r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="{algorithm}"/>
+    <enc:CipherData><enc:CipherReference URI="{uri}"/></enc:CipherData>
+  </enc:EncryptedData>
+</encryption>"#
+        )
+    }
+
+    fn reader(files: Vec<(&'static str, String)>) -> impl FnMut(&str) -> Option<Vec<u8>> {
+        move |name| files.iter().find(|(n, _)| *n == name).map(|(_, s)| s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn restores_idpf_and_adobe_obfuscated_fonts() {
+        let font: Vec<u8> = (0..2000u32).map(|i| (i * 7 % 251) as u8).collect();
+
+        let xml = obfuscation_xml(IDPF_OBFUSCATION, "OEBPS/fonts/My%20Font.otf");
+        let de = check_encryption(reader(vec![(ENCRYPTION_XML, xml), (OPF_PATH, OPF.to_string())]), OPF_PATH)
+            .unwrap()
+            .unwrap();
+        assert!(de.is_obfuscated("OEBPS/fonts/My Font.otf"));
+        // Whitespace around the identifier is not part of the key.
+        let key: [u8; 20] = Sha1::digest(b"urn:uuid:0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0").into();
+        let mut data = font.clone();
+        for (i, b) in data.iter_mut().take(IDPF_HEADER_LEN).enumerate() {
+            *b ^= key[i % 20];
+        }
+        de.apply("OEBPS/fonts/My Font.otf", &mut data);
+        assert_eq!(data, font);
+
+        let xml = obfuscation_xml(ADOBE_OBFUSCATION, "OEBPS/fonts/a.ttf");
+        let de = check_encryption(reader(vec![(ENCRYPTION_XML, xml), (OPF_PATH, OPF.to_string())]), OPF_PATH)
+            .unwrap()
+            .unwrap();
+        let key = uuid_bytes("0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0").unwrap();
+        assert_eq!(key[0], 0x0f);
+        let mut data = font.clone();
+        for (i, b) in data.iter_mut().take(ADOBE_HEADER_LEN).enumerate() {
+            *b ^= key[i % 16];
+        }
+        assert_ne!(data, font);
+        de.apply("OEBPS/fonts/a.ttf", &mut data);
+        assert_eq!(data, font);
+        // Other entries are left alone.
+        let mut other = font.clone();
+        de.apply("OEBPS/text.xhtml", &mut other);
+        assert_eq!(other, font);
+    }
+
+    #[test]
+    fn refuses_drm_with_its_scheme() {
+        let aes = "http://www.w3.org/2001/04/xmlenc#aes128-cbc";
+        let scheme = |files: Vec<(&'static str, String)>| match check_encryption(reader(files), OPF_PATH) {
+            Err(EpubOpenError::Drm { scheme, .. }) => Some(scheme),
+            _ => None,
+        };
+        let xml = obfuscation_xml(aes, "OEBPS/ch1.xhtml");
+        assert_eq!(
+            scheme(vec![(ENCRYPTION_XML, xml.clone()), ("META-INF/rights.xml", String::new())]),
+            Some(DrmScheme::AdobeAdept)
+        );
+        assert_eq!(
+            scheme(vec![(ENCRYPTION_XML, xml.clone()), ("META-INF/sinf.xml", String::new())]),
+            Some(DrmScheme::AppleFairplay)
+        );
+        assert_eq!(
+            scheme(vec![(ENCRYPTION_XML, xml.clone()), ("META-INF/license.lcpl", String::new())]),
+            Some(DrmScheme::ReadiumLcp)
+        );
+        assert_eq!(scheme(vec![(ENCRYPTION_XML, xml)]), Some(DrmScheme::Unknown));
+        assert_eq!(
+            scheme(vec![(ENCRYPTION_XML, "<encryption><EncryptedData>".to_string())]),
+            Some(DrmScheme::Unknown)
+        );
+        // No encryption.xml at all.
+        assert!(matches!(check_encryption(reader(vec![]), OPF_PATH), Ok(None)));
+
+        let err = serde_json::to_value(EpubOpenError::drm(DrmScheme::ReadiumLcp, 1)).unwrap();
+        assert_eq!(err["kind"], "drm");
+        assert_eq!(err["scheme"], "readium_lcp");
+    }
+}
@@ -0,0 +1,722 @@
+use roxmltree::{Document, Node, ParsingOptions};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::PathBuf;
+use zip::ZipArchive;
+
+const OPF_MEDIA_TYPE: &str = "application/oebps-package+xml";
+const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+const OPS_NS: &str = "http://www.idpf.org/2007/ops";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EpubIdentifier {
+    pub value: String,
+    /// `opf:scheme` (EPUB 2) or the refining `identifier-type` (EPUB 3), e.g. "ISBN".
+    pub scheme: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ManifestItem {
+    pub id: String,
+    /// Path inside the archive, resolved against the OPF folder.
+    pub href: String,
+    pub media_type: String,
+    pub properties: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpineItem {
+    pub idref: String,
+    pub href: Option<String>,
+    pub linear: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub label: String,
+    /// Archive path with the fragment kept (`OEBPS/ch1.xhtml#s2`); `None` for heading-only entries.
+    pub href: Option<String>,
+    pub children: Vec<TocEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EpubMetadata {
+    /// Package version, "2.0" or "3.0".
+    pub version: String,
+    pub opf_path: String,
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub language: Option<String>,
+    pub publisher: Option<String>,
+    pub identifiers: Vec<EpubIdentifier>,
+    /// The identifier named by `<package unique-identifier>`.
+    pub unique_identifier: Option<String>,
+    pub cover_id: Option<String>,
+    pub cover_href: Option<String>,
+    pub manifest: Vec<ManifestItem>,
+    pub spine: Vec<SpineItem>,
+    pub toc: Vec<TocEntry>,
+}
+
+pub(crate) fn parse_xml(text: &str) -> Result<Document<'_>, String> {
+    let opt = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    Document::parse_with_options(text.trim_start_matches('\u{feff}'), opt).map_err(|e| e.to_string())
+}
+
+/// XHTML navigation documents may use HTML entities that an XML parser does not know.
+/// Rewrites every HTML named reference other than XML's own five as numeric references.
+pub(crate) fn replace_html_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let name = rest[1..]
+            .find(';')
+            .filter(|&end| end > 0 && end <= 32)
+            .map(|end| &rest[1..end + 2])
+            .filter(|name| !matches!(*name, "amp;" | "lt;" | "gt;" | "quot;" | "apos;"));
+        match name.and_then(|name| Some((name, named_entity(name)?))) {
+            Some((name, decoded)) => {
+                for c in decoded.chars() {
+                    out.push_str(&format!("&#{};", c as u32));
+                }
+                rest = &rest[name.len() + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Character for a named HTML reference, `name` including its trailing `;`.
+pub(crate) fn named_entity(name: &str) -> Option<String> {
+    let &(first, second) = markup5ever::data::NAMED_ENTITIES.get(name)?;
+    let mut out: String = char::from_u32(first)?.into();
+    if second != 0 {
+        out.push(char::from_u32(second)?);
+    }
+    Some(out)
+}
+
+/// Decodes named and numeric character references the way a browser reads text and attribute values.
+/// Numeric references may omit the `;`.
+pub(crate) fn decode_html_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(num) = rest.strip_prefix('#') {
+            let (digits, radix) = match num.strip_prefix(['x', 'X']) {
+                Some(hex) => (hex, 16),
+                None => (num, 10),
+            };
+            let len = digits.find(|c: char| !c.is_digit(radix)).unwrap_or(digits.len());
+            if len > 0 {
+                let significant = digits[..len].trim_start_matches('0');
+                let code = if significant.len() > 8 {
+                    0xFFFD
+                } else {
+                    u32::from_str_radix(significant, radix).unwrap_or(0)
+                };
+                out.push(char::from_u32(code).filter(|c| *c != '\0').unwrap_or('\u{fffd}'));
+                rest = &digits[len..];
+                rest = rest.strip_prefix(';').unwrap_or(rest);
+                continue;
+            }
+        } else if let Some(end) = rest.find(';').filter(|e| *e <= 32) {
+            if let Some(decoded) = named_entity(&rest[..=end]) {
+                out.push_str(&decoded);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        out.push('&');
+    }
+    out.push_str(rest);
+    out
+}
+
+pub(crate) fn percent_decode(raw: &str) -> String {
+    let bytes = raw.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |c: u8| (c as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(out).unwrap_or_else(|_| raw.to_string())
+}
+
+/// Resolves `href` against the folder of `base` (an archive path), keeping any fragment.
+pub(crate) fn resolve_href(base: &str, href: &str) -> String {
+    let (path, fragment) = match href.split_once('#') {
+        Some((p, f)) => (p, Some(f)),
+        None => (href, None),
+    };
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    let decoded = percent_decode(path);
+    if decoded.is_empty() {
+        parts = base.split('/').collect();
+    } else {
+        for seg in decoded.split('/') {
+            match seg {
+                "" | "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                s => parts.push(s),
+            }
+        }
+    }
+    let mut out = parts.into_iter().filter(|s| !s.is_empty()).collect::<Vec<_>>().join("/");
+    if let Some(f) = fragment {
+        out.push('#');
+        out.push_str(f);
+    }
+    out
+}
+
+pub(crate) fn element_text(node: Node) -> String {
+    let raw: String = node
+        .descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect();
+    raw.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub(crate) fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+pub(crate) fn children<'a, 'i>(node: Node<'a, 'i>, name: &'static str) -> impl Iterator<Item = Node<'a, 'i>> {
+    node.children().filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// Returns the OPF path from `META-INF/container.xml`, preferring the OPF media type.
+pub(crate) fn parse_container(xml: &str) -> Option<String> {
+    let doc = parse_xml(xml).ok()?;
+    let rootfiles: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "rootfile")
+        .collect();
+    rootfiles
+        .iter()
+        .find(|n| n.attribute("media-type") == Some(OPF_MEDIA_TYPE))
+        .or_else(|| rootfiles.first())
+        .and_then(|n| n.attribute("full-path"))
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+}
+
+/// Reads the attribute `name` whether or not it carries the `opf:` namespace (EPUB 2 writes both).
+fn opf_attribute<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes()
+        .find(|a| a.name() == name)
+        .map(|a| a.value())
+}
+
+/// EPUB 3 `<meta refines="#id" property="...">` values, keyed by (id, property).
+fn refinements(metadata: Node) -> HashMap<(String, String), String> {
+    let mut out = HashMap::new();
+    for meta in children(metadata, "meta") {
+        let (Some(target), Some(property)) = (meta.attribute("refines"), meta.attribute("property")) else {
+            continue;
+        };
+        let value = element_text(meta);
+        if !value.is_empty() {
+            out.entry((target.trim_start_matches('#').to_string(), property.to_string()))
+                .or_insert(value);
+        }
+    }
+    out
+}
+
+/// Parses an OPF package document. `opf_path` is its archive path, used to resolve hrefs.
+/// The TOC is left empty; see [`parse_nav`] and [`parse_ncx`].
+pub(crate) fn parse_opf(xml: &str, opf_path: &str) -> Result<EpubMetadata, String> {
+    let doc = parse_xml(xml)?;
+    let package = doc.root_element();
+    if package.tag_name().name() != "package" {
+        return Err("OPF root is not <package>".to_string());
+    }
+    let version = package.attribute("version").unwrap_or("2.0").to_string();
+    let metadata = child(package, "metadata").ok_or_else(|| "OPF has no <metadata>".to_string())?;
+    let refines = refinements(metadata);
+    let refined = |node: Node, property: &str| {
+        node.attribute("id")
+            .and_then(|id| refines.get(&(id.to_string(), property.to_string())))
+            .cloned()
+    };
+
+    let titles: Vec<Node> = children(metadata, "title").collect();
+    let title = titles
+        .iter()
+        .find(|n| refined(**n, "title-type").as_deref() == Some("main"))
+        .or_else(|| titles.first())
+        .map(|n| element_text(*n))
+        .filter(|t| !t.is_empty());
+
+    let mut authors = Vec::new();
+    let mut contributors = Vec::new();
+    for creator in children(metadata, "creator") {
+        let name = element_text(creator);
+        if name.is_empty() {
+            continue;
+        }
+        let role = opf_attribute(creator, "role").map(str::to_string).or_else(|| refined(creator, "role"));
+        match role.as_deref() {
+            None | Some("aut") => authors.push(name),
+            Some(_) => contributors.push(name),
+        }
+    }
+    if authors.is_empty() {
+        authors = contributors;
+    }
+
+    let first_text = |name: &'static str| {
+        children(metadata, name)
+            .map(element_text)
+            .find(|t| !t.is_empty())
+    };
+    let language = first_text("language");
+    let publisher = first_text("publisher");
+
+    let unique_id = package.attribute("unique-identifier");
+    let mut identifiers = Vec::new();
+    let mut unique_identifier = None;
+    for node in children(metadata, "identifier") {
+        let value = element_text(node);
+        if value.is_empty() {
+            continue;
+        }
+        if unique_id.is_some() && node.attribute("id") == unique_id {
+            unique_identifier = Some(value.clone());
+        }
+        let scheme = opf_attribute(node, "scheme")
+            .map(str::to_string)
+            .or_else(|| refined(node, "identifier-type"));
+        identifiers.push(EpubIdentifier { value, scheme });
+    }
+
+    let manifest: Vec<ManifestItem> = child(package, "manifest")
+        .map(|m| {
+            children(m, "item")
+                .filter_map(|item| {
+                    Some(ManifestItem {
+                        id: item.attribute("id")?.to_string(),
+                        href: resolve_href(opf_path, item.attribute("href")?),
+                        media_type: item.attribute("media-type").unwrap_or_default().to_string(),
+                        properties: item
+                            .attribute("properties")
+                            .map(|p| p.split_whitespace().map(str::to_string).collect())
+                            .unwrap_or_default(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let by_id: HashMap<&str, &ManifestItem> = manifest.iter().map(|i| (i.id.as_str(), i)).collect();
+
+    let spine: Vec<SpineItem> = child(package, "spine")
+        .map(|s| {
+            children(s, "itemref")
+                .filter_map(|r| {
+                    let idref = r.attribute("idref")?.to_string();
+                    Some(SpineItem {
+                        href: by_id.get(idref.as_str()).map(|i| i.href.clone()),
+                        linear: r.attribute("linear") != Some("no"),
+                        idref,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let cover_id = manifest
+        .iter()
+        .find(|i| i.properties.iter().any(|p| p == "cover-image"))
+        .map(|i| i.id.clone())
+        .or_else(|| {
+            children(metadata, "meta")
+                .find(|m| m.attribute("name") == Some("cover"))
+                .and_then(|m| m.attribute("content"))
+                .filter(|id| by_id.contains_key(id))
+                .map(str::to_string)
+        })
+        .or_else(|| {
+            manifest
+                .iter()
+                .find(|i| i.media_type.starts_with("image/") && i.id.to_lowercase().contains("cover"))
+                .map(|i| i.id.clone())
+        });
+    let cover_href = cover_id
+        .as_deref()
+        .and_then(|id| by_id.get(id))
+        .map(|i| i.href.clone());
+
+    Ok(EpubMetadata {
+        version,
+        opf_path: opf_path.to_string(),
+        title,
+        authors,
+        language,
+        publisher,
+        identifiers,
+        unique_identifier,
+        cover_id,
+        cover_href,
+        manifest,
+        spine,
+        toc: Vec::new(),
+    })
+}
+
+/// The EPUB 3 navigation document item, then the EPUB 2 NCX named by `<spine toc>`.
+fn toc_sources(opf_xml: &str, meta: &EpubMetadata) -> (Option<String>, Option<String>) {
+    let nav = meta
+        .manifest
+        .iter()
+        .find(|i| i.properties.iter().any(|p| p == "nav"))
+        .map(|i| i.href.clone());
+    let spine_toc = parse_xml(opf_xml).ok().and_then(|doc| {
+        child(doc.root_element(), "spine")
+            .and_then(|s| s.attribute("toc"))
+            .map(str::to_string)
+    });
+    let ncx = meta
+        .manifest
+        .iter()
+        .find(|i| Some(&i.id) == spine_toc.as_ref())
+        .or_else(|| meta.manifest.iter().find(|i| i.media_type == NCX_MEDIA_TYPE))
+        .map(|i| i.href.clone());
+    (nav, ncx)
+}
+
+fn nav_list(ol: Node, base: &str) -> Vec<TocEntry> {
+    children(ol, "li")
+        .filter_map(|li| {
+            let head = li
+                .children()
+                .find(|n| n.is_element() && matches!(n.tag_name().name(), "a" | "span"));
+            let label = head.map(element_text).unwrap_or_default();
+            let href = head
+                .and_then(|a| a.attribute("href"))
+                .map(|h| resolve_href(base, h));
+            let children = child(li, "ol").map(|o| nav_list(o, base)).unwrap_or_default();
+            if label.is_empty() && children.is_empty() {
+                return None;
+            }
+            Some(TocEntry { label, href, children })
+        })
+        .collect()
+}
+
+/// Parses the `epub:type="toc"` list of an EPUB 3 navigation document.
+pub(crate) fn parse_nav(xhtml: &str, nav_path: &str) -> Result<Vec<TocEntry>, String> {
+    let text = replace_html_entities(xhtml);
+    let doc = parse_xml(&text)?;
+    let navs: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "nav")
+        .collect();
+    let nav = navs
+        .iter()
+        .find(|n| {
+            n.attribute((OPS_NS, "type"))
+                .is_some_and(|t| t.split_whitespace().any(|t| t == "toc"))
+        })
+        .or_else(|| navs.first())
+        .ok_or_else(|| "navigation document has no <nav>".to_string())?;
+    let ol = nav
+        .descendants()
+        .find(|n| n.is_element() && n.tag_name().name() == "ol")
+        .ok_or_else(|| "navigation document has no <ol>".to_string())?;
+    Ok(nav_list(ol, nav_path))
+}
+
+fn ncx_points(parent: Node, base: &str) -> Vec<TocEntry> {
+    children(parent, "navPoint")
+        .map(|point| TocEntry {
+            label: child(point, "navLabel").map(element_text).unwrap_or_default(),
+            href: child(point, "content")
+                .and_then(|c| c.attribute("src"))
+                .map(|s| resolve_href(base, s)),
+            children: ncx_points(point, base),
+        })
+        .collect()
+}
+
+/// Parses the `navMap` of an EPUB 2 NCX.
+pub(crate) fn parse_ncx(xml: &str, ncx_path: &str) -> Result<Vec<TocEntry>, String> {
+    let doc = parse_xml(xml)?;
+    let nav_map = doc
+        .descendants()
+        .find(|n| n.is_element() && n.tag_name().name() == "navMap")
+        .ok_or_else(|| "NCX has no <navMap>".to_string())?;
+    Ok(ncx_points(nav_map, ncx_path))
+}
+
+fn read_entry<R: Read + std::io::Seek>(zip: &mut ZipArchive<R>, name: &str) -> Result<String, String> {
+    let mut f = zip.by_name(name).map_err(|_| format!("{name} missing"))?;
+    let mut s = String::new();
+    f.read_to_string(&mut s).map_err(|e| e.to_string())?;
+    Ok(s)
+}
+
+/// Reads container, package and table of contents straight from the archive.
+pub(crate) fn read_epub_metadata(path: &PathBuf) -> Result<EpubMetadata, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    let container = read_entry(&mut zip, "META-INF/container.xml")?;
+    let opf_path = parse_container(&container).ok_or_else(|| "container.xml invalid (OPF not found)".to_string())?;
+    let opf_xml = read_entry(&mut zip, &opf_path)?;
+    let mut meta = parse_opf(&opf_xml, &opf_path)?;
+
+    let (nav, ncx) = toc_sources(&opf_xml, &meta);
+    if let Some(nav) = nav {
+        match read_entry(&mut zip, &nav).and_then(|x| parse_nav(&x, &nav)) {
+            Ok(toc) => meta.toc = toc,
+            Err(e) => log::warn!("[epub_meta] nav {nav}: {e}"),
+        }
+    }
+    if meta.toc.is_empty() {
+        if let Some(ncx) = ncx {
+            match read_entry(&mut zip, &ncx).and_then(|x| parse_ncx(&x, &ncx)) {
+                Ok(toc) => meta.toc = toc,
+                Err(e) => log::warn!("[epub_meta] ncx {ncx}: {e}"),
+            }
+        }
+    }
+    Ok(meta)
+}
+
+/// Metadata, spine and table of contents of an EPUB file.
+#[tauri::command]
+pub async fn epub_metadata(path: String) -> Result<EpubMetadata, String> {
+    tokio::task::spawn_blocking(move || read_epub_metadata(&PathBuf::from(path)))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPF2: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="2.0" unique-identifier="BookId">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:title>Pride and Prejudice</dc:title>
+    <dc:creator opf:role="aut" opf:file-as="Austen, Jane">Jane Austen</dc:creator>
+    <dc:creator opf:role="ill">Hugh Thomson</dc:creator>
+    <dc:language>en</dc:language>
+    <dc:publisher>Project Gutenberg</dc:publisher>
+    <dc:identifier id="BookId" opf:scheme="URI">http://www.gutenberg.org/1342</dc:identifier>
+    <dc:identifier opf:scheme="ISBN">9780141439518</dc:identifier>
+    <meta name="cover" content="cover-img"/>
+  </metadata>
+  <manifest>
+    <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="cover-img" href="images/cover.jpg" media-type="image/jpeg"/>
+    <item id="ch1" href="text/chapter%201.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ch2" href="text/chapter2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="notes" href="../notes.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine toc="ncx">
+    <itemref idref="ch1"/>
+    <itemref idref="ch2"/>
+    <itemref idref="notes" linear="no"/>
+  </spine>
+</package>"##;
+
+    const OPF3: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title id="sub">A Novel</dc:title>
+    <dc:title id="main">三体</dc:title>
+    <meta refines="#main" property="title-type">main</meta>
+    <meta refines="#sub" property="title-type">subtitle</meta>
+    <dc:creator id="c1">刘慈欣</dc:creator>
+    <meta refines="#c1" property="role" scheme="marc:relators">aut</meta>
+    <dc:identifier id="uid">urn:uuid:1234</dc:identifier>
+    <dc:identifier id="isbn">9787536692930</dc:identifier>
+    <meta refines="#isbn" property="identifier-type" scheme="onix:codelist5">ISBN</meta>
+    <dc:language>zh-CN</dc:language>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="cover" href="cover.png" media-type="image/png" properties="cover-image"/>
+    <item id="c1" href="c1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="c1"/></spine>
+</package>"##;
+
+    #[test]
+    fn container_prefers_opf_rootfile() {
+        let xml = r#"<?xml version="1.0"?>
+<container xmlns="urn:oasis:names:tc:opendocument:xmlns:container" version="1.0">
+  <rootfiles>
+    <rootfile full-path="OEBPS/book.pdf" media-type="application/pdf"/>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#;
+        assert_eq!(parse_container(xml).as_deref(), Some("OEBPS/content.opf"));
+        assert_eq!(parse_container("<container/>"), None);
+    }
+
+    #[test]
+    fn resolves_relative_hrefs() {
+        assert_eq!(resolve_href("OEBPS/content.opf", "text/a.xhtml"), "OEBPS/text/a.xhtml");
+        assert_eq!(resolve_href("OEBPS/text/nav.xhtml", "../a%20b.xhtml#p1"), "OEBPS/a b.xhtml#p1");
+        assert_eq!(resolve_href("content.opf", "./a.xhtml"), "a.xhtml");
+        assert_eq!(resolve_href("OEBPS/c1.xhtml", "#note"), "OEBPS/c1.xhtml#note");
+    }
+
+    #[test]
+    fn parses_epub2_package() {
+        let meta = parse_opf(OPF2, "OEBPS/content.opf").unwrap();
+        assert_eq!(meta.version, "2.0");
+        assert_eq!(meta.title.as_deref(), Some("Pride and Prejudice"));
+        assert_eq!(meta.authors, vec!["Jane Austen"]);
+        assert_eq!(meta.language.as_deref(), Some("en"));
+        assert_eq!(meta.publisher.as_deref(), Some("Project Gutenberg"));
+        assert_eq!(meta.unique_identifier.as_deref(), Some("http://www.gutenberg.org/1342"));
+        assert_eq!(meta.identifiers[1].scheme.as_deref(), Some("ISBN"));
+        assert_eq!(meta.cover_id.as_deref(), Some("cover-img"));
+        assert_eq!(meta.cover_href.as_deref(), Some("OEBPS/images/cover.jpg"));
+        assert_eq!(meta.manifest[2].href, "OEBPS/text/chapter 1.xhtml");
+        assert_eq!(meta.manifest[4].href, "notes.xhtml");
+        let spine: Vec<_> = meta.spine.iter().map(|s| (s.idref.as_str(), s.linear)).collect();
+        assert_eq!(spine, vec![("ch1", true), ("ch2", true), ("notes", false)]);
+        assert_eq!(meta.spine[1].href.as_deref(), Some("OEBPS/text/chapter2.xhtml"));
+
+        let (nav, ncx) = toc_sources(OPF2, &meta);
+        assert_eq!((nav, ncx.as_deref()), (None, Some("OEBPS/toc.ncx")));
+    }
+
+    #[test]
+    fn parses_epub3_package_refinements() {
+        let meta = parse_opf(OPF3, "content.opf").unwrap();
+        assert_eq!(meta.version, "3.0");
+        assert_eq!(meta.title.as_deref(), Some("三体"));
+        assert_eq!(meta.authors, vec!["刘慈欣"]);
+        assert_eq!(meta.unique_identifier.as_deref(), Some("urn:uuid:1234"));
+        assert_eq!(meta.identifiers[1].scheme.as_deref(), Some("ISBN"));
+        assert_eq!(meta.cover_href.as_deref(), Some("cover.png"));
+        assert_eq!(toc_sources(OPF3, &meta).0.as_deref(), Some("nav.xhtml"));
+    }
+
+    #[test]
+    fn parses_nested_nav_toc() {
+        let xhtml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<body>
+  <nav epub:type="landmarks"><ol><li><a href="c1.xhtml">Start</a></li></ol></nav>
+  <nav epub:type="toc" id="toc">
+    <h1>Contents</h1>
+    <ol>
+      <li><a href="text/c1.xhtml">Part&nbsp;One</a>
+        <ol>
+          <li><a href="text/c1.xhtml#s1">Chapter <em>1</em></a></li>
+          <li><a href="text/c2.xhtml">Chapter 2</a></li>
+        </ol>
+      </li>
+      <li><span>Appendix</span><ol><li><a href="../notes.xhtml">Notes</a></li></ol></li>
+    </ol>
+  </nav>
+</body>
+</html>"#;
+        let toc = parse_nav(xhtml, "OEBPS/nav.xhtml").unwrap();
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].label, "Part One");
+        assert_eq!(toc[0].children[0].label, "Chapter 1");
+        assert_eq!(toc[0].children[0].href.as_deref(), Some("OEBPS/text/c1.xhtml#s1"));
+        assert_eq!(toc[1].href, None);
+        assert_eq!(toc[1].children[0].href.as_deref(), Some("notes.xhtml"));
+    }
+
+    #[test]
+    fn parses_nested_ncx_toc() {
+        let ncx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE ncx PUBLIC "-//NISO//DTD ncx 2005-1//EN" "http://www.daisy.org/z3986/2005/ncx-2005-1.dtd">
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1">
+  <navMap>
+    <navPoint id="p1" playOrder="1">
+      <navLabel><text>Volume I</text></navLabel>
+      <content src="text/v1.xhtml"/>
+      <navPoint id="p2" playOrder="2">
+        <navLabel><text>Chapter 1</text></navLabel>
+        <content src="text/v1.xhtml#c1"/>
+      </navPoint>
+    </navPoint>
+    <navPoint id="p3" playOrder="3">
+      <navLabel><text>Volume II</text></navLabel>
+      <content src="text/v2.xhtml"/>
+    </navPoint>
+  </navMap>
+</ncx>"#;
+        let toc = parse_ncx(ncx, "OEBPS/toc.ncx").unwrap();
+        assert_eq!(toc.len(), 2);
+        assert_eq!(toc[0].children[0].label, "Chapter 1");
+        assert_eq!(toc[0].children[0].href.as_deref(), Some("OEBPS/text/v1.xhtml#c1"));
+        assert!(toc[1].children.is_empty());
+    }
+
+    #[test]
+    fn reads_metadata_from_archive() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let dir = std::env::temp_dir().join(format!("epub_meta_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("book.epub");
+        {
+            let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+            let opts = SimpleFileOptions::default();
+            let files = [
+                (
+                    "META-INF/container.xml",
+                    r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+                ),
+                ("OEBPS/content.opf", OPF3),
+                (
+                    "OEBPS/nav.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body><nav epub:type="toc"><ol><li><a href="c1.xhtml">One</a></li></ol></nav></body></html>"#,
+                ),
+            ];
+            for (name, body) in files {
+                zip.start_file(name, opts).unwrap();
+                zip.write_all(body.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let meta = read_epub_metadata(&path).unwrap();
+        assert_eq!(meta.opf_path, "OEBPS/content.opf");
+        assert_eq!(meta.title.as_deref(), Some("三体"));
+        assert_eq!(meta.toc[0].href.as_deref(), Some("OEBPS/c1.xhtml"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,678 @@
+use flate2::read::DeflateDecoder;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::http::{header, Method, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, State, UriSchemeResponder};
+use zip::{CompressionMethod, ZipArchive};
+
+use crate::covers::document_fingerprint;
+use crate::epub::parse_container_for_opf;
+use crate::epub_crypto::{check_encryption, EpubOpenError, FontDeobfuscator};
+use crate::epub_meta::percent_decode;
+use crate::epub_repair::{
+    decode_markup, declare_utf8, is_markup, locate_opf, mimetype_problem, repair_manifest, EpubRepairReport, RepairKind,
+};
+use crate::AppState;
+
+pub(crate) const SCHEME: &str = "epub";
+/// Upper bound on decompressed entries kept in memory for range requests into deflated media.
+const INFLATED_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+struct EntryInfo {
+    data_start: u64,
+    compressed_size: u64,
+    size: u64,
+    deflated: bool,
+}
+
+/// Central-directory offsets of one EPUB, so entries can be read with a single seek.
+pub(crate) struct ArchiveIndex {
+    path: PathBuf,
+    entries: HashMap<String, EntryInfo>,
+    /// Lowercased name -> name, for books whose links disagree with the archive on case.
+    folded: HashMap<String, String>,
+    opf_path: String,
+    fonts: Option<FontDeobfuscator>,
+    /// The package document with its manifest repaired, served in place of the archived one.
+    opf_repaired: Option<Vec<u8>>,
+    /// Repairs found while indexing; encoding fixes are applied as entries are read.
+    repair: EpubRepairReport,
+}
+
+impl ArchiveIndex {
+    /// Fails with [`EpubOpenError::Drm`] if the book's content is encrypted.
+    pub(crate) fn build(path: &Path) -> Result<Self, EpubOpenError> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+        let mut entries = HashMap::new();
+        let mut folded = HashMap::new();
+        for i in 0..zip.len() {
+            let f = zip.by_index_raw(i).map_err(|e| e.to_string())?;
+            if f.is_dir() || f.encrypted() {
+                continue;
+            }
+            let deflated = match f.compression() {
+                CompressionMethod::Stored => false,
+                CompressionMethod::Deflated => true,
+                other => {
+                    log::warn!("[epub_protocol] {}: unsupported compression {other:?}", f.name());
+                    continue;
+                }
+            };
+            let name = f.name().trim_start_matches('/').to_string();
+            folded.entry(name.to_lowercase()).or_insert_with(|| name.clone());
+            entries.insert(
+                name,
+                EntryInfo {
+                    data_start: f.data_start(),
+                    compressed_size: f.compressed_size(),
+                    size: f.size(),
+                    deflated,
+                },
+            );
+        }
+
+        let mut index = Self {
+            path: path.to_path_buf(),
+            entries,
+            folded,
+            opf_path: String::new(),
+            fonts: None,
+            opf_repaired: None,
+            repair: EpubRepairReport::default(),
+        };
+        let mut names: Vec<String> = index.entries.keys().cloned().collect();
+        names.sort();
+        let mut repair = EpubRepairReport::default();
+        index.opf_path = locate_opf(
+            &names,
+            |name| index.read_entry(name).ok().map(|b| String::from_utf8_lossy(&b).into_owned()),
+            parse_container_for_opf,
+            &mut repair,
+        )?;
+        index.fonts = check_encryption(|name| index.read_entry(name).ok(), &index.opf_path)?;
+
+        let files = names.into_iter().collect();
+        let opf = index.read(&index.opf_path.clone())?;
+        match repair_manifest(&String::from_utf8_lossy(&opf), &index.opf_path, &files, &mut repair) {
+            Ok(fixed) => index.opf_repaired = fixed.map(String::into_bytes),
+            Err(e) => log::warn!("[epub_protocol] {}: {e}", index.opf_path),
+        }
+        index.repair = repair;
+        Ok(index)
+    }
+
+    /// Validation report without extracting: repairs made while indexing plus the markup that is
+    /// transcoded when read.
+    pub(crate) fn repair_report(&self) -> EpubRepairReport {
+        let mut report = self.repair.clone();
+        if let Some(problem) = mimetype_problem(self.read_entry("mimetype").ok().as_deref()) {
+            report.fix(RepairKind::Mimetype, "mimetype", format!("{problem}; ignored"));
+        }
+        let mut markup: Vec<&String> = self.entries.keys().filter(|n| is_markup(n)).collect();
+        markup.sort();
+        for name in markup {
+            if let Some((_, detail)) = self.read_entry(name).ok().and_then(|b| decode_markup(&b)) {
+                report.fix(RepairKind::Encoding, name, detail);
+            }
+        }
+        report
+    }
+
+    pub(crate) fn opf_path(&self) -> &str {
+        &self.opf_path
+    }
+
+    fn entry(&self, name: &str) -> Option<(&str, &EntryInfo)> {
+        if let Some((k, e)) = self.entries.get_key_value(name) {
+            return Some((k.as_str(), e));
+        }
+        let real = self.folded.get(&name.to_lowercase())?;
+        self.entries.get_key_value(real).map(|(k, e)| (k.as_str(), e))
+    }
+
+    fn read_raw(&self, info: &EntryInfo, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+        let mut file = File::open(&self.path).map_err(|e| e.to_string())?;
+        let start = info.data_start.saturating_add(offset);
+        // Sizes come from the central directory; never trust them past the end of the file.
+        let available = file.metadata().map_err(|e| e.to_string())?.len().saturating_sub(start);
+        let len = len.min(available);
+        file.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+        let mut buf = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut buf).map_err(|e| e.to_string())?;
+        Ok(buf)
+    }
+
+    fn is_obfuscated(&self, name: &str) -> bool {
+        self.fonts.as_ref().is_some_and(|f| f.is_obfuscated(name))
+    }
+
+    /// Reads a whole entry as the reader should see it: the repaired OPF, and markup transcoded to UTF-8.
+    pub(crate) fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        let (real, _) = self.entry(name).ok_or_else(|| format!("{name} not found"))?;
+        if real == self.opf_path {
+            if let Some(opf) = &self.opf_repaired {
+                return Ok(opf.clone());
+            }
+        }
+        let data = self.read_entry(real)?;
+        if !is_markup(real) {
+            return Ok(data);
+        }
+        Ok(match decode_markup(&data) {
+            Some((text, _)) => declare_utf8(&text).into_bytes(),
+            None => data,
+        })
+    }
+
+    /// Reads and, if needed, inflates and deobfuscates a whole entry exactly as archived.
+    fn read_entry(&self, name: &str) -> Result<Vec<u8>, String> {
+        let (real, info) = self.entry(name).ok_or_else(|| format!("{name} not found"))?;
+        let raw = self.read_raw(info, 0, info.compressed_size)?;
+        let mut out = if info.deflated {
+            let mut out = Vec::new();
+            DeflateDecoder::new(raw.as_slice())
+                .take(info.size)
+                .read_to_end(&mut out)
+                .map_err(|e| e.to_string())?;
+            out
+        } else {
+            raw
+        };
+        if let Some(fonts) = &self.fonts {
+            fonts.apply(real, &mut out);
+        }
+        Ok(out)
+    }
+}
+
+/// Open archives by document id, plus a small cache of inflated entries.
+pub struct EpubArchives {
+    archives: Mutex<HashMap<String, Arc<ArchiveIndex>>>,
+    inflated: Mutex<VecDeque<(String, Arc<Vec<u8>>)>>,
+}
+
+impl EpubArchives {
+    pub fn new() -> Self {
+        Self {
+            archives: Mutex::new(HashMap::new()),
+            inflated: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub(crate) fn get(&self, doc: &str) -> Option<Arc<ArchiveIndex>> {
+        self.archives.lock().unwrap().get(doc).cloned()
+    }
+
+    fn insert(&self, doc: String, index: ArchiveIndex) -> Arc<ArchiveIndex> {
+        let index = Arc::new(index);
+        self.archives.lock().unwrap().insert(doc, index.clone());
+        index
+    }
+
+    fn remove(&self, doc: &str) {
+        self.archives.lock().unwrap().remove(doc);
+        let prefix = format!("{doc}/");
+        self.inflated.lock().unwrap().retain(|(k, _)| !k.starts_with(&prefix));
+    }
+
+    fn inflated(&self, doc: &str, index: &ArchiveIndex, name: &str) -> Result<Arc<Vec<u8>>, String> {
+        let key = format!("{doc}/{name}");
+        {
+            let mut cache = self.inflated.lock().unwrap();
+            if let Some(pos) = cache.iter().position(|(k, _)| *k == key) {
+                let hit = cache.remove(pos).unwrap();
+                let data = hit.1.clone();
+                cache.push_back(hit);
+                return Ok(data);
+            }
+        }
+        let data = Arc::new(index.read(name)?);
+        let mut cache = self.inflated.lock().unwrap();
+        cache.push_back((key, data.clone()));
+        let mut total: usize = cache.iter().map(|(_, d)| d.len()).sum();
+        while total > INFLATED_CACHE_BYTES && cache.len() > 1 {
+            if let Some((_, d)) = cache.pop_front() {
+                total -= d.len();
+            }
+        }
+        Ok(data)
+    }
+
+    /// Bytes `start..=end` of an entry; stored entries are read straight from disk unless they
+    /// are obfuscated fonts.
+    fn read_range(&self, doc: &str, index: &ArchiveIndex, name: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        let (real, info) = index.entry(name).ok_or_else(|| format!("{name} not found"))?;
+        if !info.deflated && !index.is_obfuscated(real) {
+            return index.read_raw(info, start, end + 1 - start);
+        }
+        let data = self.inflated(doc, index, name)?;
+        let end = (end as usize).min(data.len().saturating_sub(1));
+        Ok(data.get(start as usize..=end).unwrap_or_default().to_vec())
+    }
+}
+
+pub(crate) fn mime_type(name: &str) -> &'static str {
+    let ext = name.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+    match ext.as_str() {
+        "xhtml" | "xht" => "application/xhtml+xml",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "mp3" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "ogg" | "oga" => "audio/ogg",
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "opf" => "application/oebps-package+xml",
+        "ncx" => "application/x-dtbncx+xml",
+        "smil" => "application/smil+xml",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Parses a single `bytes=` range against a body of `len` bytes into an inclusive `(start, end)`.
+/// `Ok(None)` means no usable range header; `Err(())` means the range is unsatisfiable.
+pub(crate) fn parse_range(value: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    // Multipart ranges are answered with the whole body.
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let (a, b) = spec.split_once('-').ok_or(())?;
+    let (a, b) = (a.trim(), b.trim());
+    let range = if a.is_empty() {
+        let suffix: u64 = b.parse().map_err(|_| ())?;
+        if suffix == 0 {
+            return Err(());
+        }
+        (len.saturating_sub(suffix), len.checked_sub(1).ok_or(())?)
+    } else {
+        let start: u64 = a.parse().map_err(|_| ())?;
+        let end = if b.is_empty() { len.saturating_sub(1) } else { b.parse::<u64>().map_err(|_| ())?.min(len.saturating_sub(1)) };
+        (start, end)
+    };
+    if len == 0 || range.0 > range.1 || range.0 >= len {
+        return Err(());
+    }
+    Ok(Some(range))
+}
+
+/// Splits `/<doc>/<entry path>` into the document id and the decoded archive path.
+fn split_request_path(path: &str) -> Option<(String, String)> {
+    let (doc, rest) = path.trim_start_matches('/').split_once('/')?;
+    let name = percent_decode(rest);
+    if doc.is_empty() || name.is_empty() || name.split('/').any(|s| s == "..") {
+        return None;
+    }
+    Some((doc.to_string(), name))
+}
+
+fn respond(status: StatusCode, body: Vec<u8>) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(body)
+        .unwrap()
+}
+
+pub(crate) fn serve(archives: &EpubArchives, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let Some((doc, name)) = split_request_path(request.uri().path()) else {
+        return respond(StatusCode::BAD_REQUEST, b"bad epub path".to_vec());
+    };
+    let Some(index) = archives.get(&doc) else {
+        return respond(StatusCode::NOT_FOUND, b"archive not open".to_vec());
+    };
+    let Some((real, info)) = index.entry(&name) else {
+        return respond(StatusCode::NOT_FOUND, format!("{name} not found").into_bytes());
+    };
+    let real = real.to_string();
+    // Markup may be transcoded on the way out, so its length is that of the repaired body.
+    let repaired = if is_markup(&real) {
+        match index.read(&real) {
+            Ok(body) => Some(body),
+            Err(e) => return respond(StatusCode::INTERNAL_SERVER_ERROR, e.into_bytes()),
+        }
+    } else {
+        None
+    };
+    let len = repaired.as_ref().map_or(info.size, |b| b.len() as u64);
+
+    let range = match request.headers().get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(v) => parse_range(v, len),
+        None => Ok(None),
+    };
+    let builder = Response::builder()
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::CONTENT_TYPE, mime_type(&real))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CACHE_CONTROL, "max-age=3600");
+    let head = request.method() == Method::HEAD;
+
+    let result = match range {
+        Err(()) => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                .body(Vec::new())
+                .unwrap();
+        }
+        Ok(Some((start, end))) => {
+            let body = match &repaired {
+                _ if head => Ok(Vec::new()),
+                Some(b) => Ok(b[start as usize..=end as usize].to_vec()),
+                None => archives.read_range(&doc, &index, &real, start, end),
+            };
+            body.map(|b| {
+                builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))
+                    .header(header::CONTENT_LENGTH, end + 1 - start)
+                    .body(b)
+                    .unwrap()
+            })
+        }
+        Ok(None) => {
+            let body = match repaired {
+                _ if head => Ok(Vec::new()),
+                Some(b) => Ok(b),
+                None => index.read(&real),
+            };
+            body.map(|b| builder.status(StatusCode::OK).header(header::CONTENT_LENGTH, len).body(b).unwrap())
+        }
+    };
+    result.unwrap_or_else(|e| {
+        log::warn!("[epub_protocol] {doc}/{real}: {e}");
+        respond(StatusCode::INTERNAL_SERVER_ERROR, e.into_bytes())
+    })
+}
+
+/// Handler for the `epub://` scheme; reads happen off the main thread.
+pub(crate) fn handle_request(app: &AppHandle, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        responder.respond(serve(&state.epub_archives, &request));
+    });
+}
+
+/// Origin of the `epub` scheme as the webview sees it.
+fn scheme_base() -> String {
+    if cfg!(any(windows, target_os = "android")) {
+        format!("http://{SCHEME}.localhost/")
+    } else {
+        format!("{SCHEME}://localhost/")
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EpubArchiveInfo {
+    pub doc: String,
+    /// `<scheme base><doc>/`; entry paths are appended to it.
+    pub base_url: String,
+    pub opf_path: String,
+    pub opf_url: String,
+}
+
+/// Indexes an EPUB so its entries can be served through `epub://` without extracting it.
+#[tauri::command]
+pub async fn epub_open_archive(state: State<'_, AppState>, path: String) -> Result<EpubArchiveInfo, EpubOpenError> {
+    let path = PathBuf::from(path);
+    let doc = document_fingerprint(&path)?;
+    let index = match state.epub_archives.get(&doc) {
+        Some(index) => index,
+        None => {
+            let p = path.clone();
+            let index = tauri::async_runtime::spawn_blocking(move || ArchiveIndex::build(&p))
+                .await
+                .map_err(|e| format!("spawn_blocking failed: {}", e))??;
+            state.epub_archives.insert(doc.clone(), index)
+        }
+    };
+    let base_url = format!("{}{}/", scheme_base(), doc);
+    let opf_url = format!(
+        "{}{}",
+        base_url,
+        index.opf_path.split('/').map(encode_segment).collect::<Vec<_>>().join("/")
+    );
+    Ok(EpubArchiveInfo {
+        doc,
+        base_url,
+        opf_path: index.opf_path.clone(),
+        opf_url,
+    })
+}
+
+#[tauri::command]
+pub fn epub_close_archive(state: State<AppState>, doc: String) {
+    state.epub_archives.remove(&doc);
+}
+
+fn encode_segment(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    fn sample_epub(dir: &Path) -> PathBuf {
+        let path = dir.join("book.epub");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file("mimetype", stored).unwrap();
+        zip.write_all(b"application/epub+zip").unwrap();
+        zip.start_file("META-INF/container.xml", deflated).unwrap();
+        zip.write_all(br#"<container><rootfiles><rootfile full-path="OPS/package.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#).unwrap();
+        zip.start_file("OPS/package.opf", deflated).unwrap();
+        zip.write_all(b"<package/>").unwrap();
+        zip.start_file("OPS/Text/Chapter 1.xhtml", deflated).unwrap();
+        zip.write_all(&b"0123456789".repeat(100)).unwrap();
+        zip.start_file("OPS/audio/clip.mp3", stored).unwrap();
+        zip.write_all(&(0u8..=255).collect::<Vec<_>>()).unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    fn get(archives: &EpubArchives, uri: &str, range: Option<&str>) -> Response<Vec<u8>> {
+        let mut req = Request::builder().uri(uri);
+        if let Some(r) = range {
+            req = req.header(header::RANGE, r);
+        }
+        serve(archives, &req.body(Vec::new()).unwrap())
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 99))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 999))));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Ok(Some((990, 999))));
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(parse_range("items=0-1", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=5-2", 1000), Err(()));
+    }
+
+    #[test]
+    fn serves_entries_with_ranges_and_mime_types() {
+        let dir = std::env::temp_dir().join(format!("epub_protocol_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let index = ArchiveIndex::build(&sample_epub(&dir)).unwrap();
+        assert_eq!(index.opf_path, "OPS/package.opf");
+        let archives = EpubArchives::new();
+        archives.insert("doc1".to_string(), index);
+
+        let res = get(&archives, "epub://localhost/doc1/OPS/Text/Chapter%201.xhtml", None);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/xhtml+xml");
+        assert_eq!(res.body().len(), 1000);
+
+        // Deflated entry: the range is cut from the inflated body.
+        let res = get(&archives, "http://epub.localhost/doc1/OPS/Text/Chapter%201.xhtml", Some("bytes=5-14"));
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 5-14/1000");
+        assert_eq!(res.body(), b"5678901234");
+
+        // Stored entry: read straight from the file offset.
+        let res = get(&archives, "epub://localhost/doc1/OPS/audio/clip.mp3", Some("bytes=-4"));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "audio/mpeg");
+        assert_eq!(res.body(), &[252, 253, 254, 255]);
+
+        // Case-insensitive fallback, unknown entries, unknown docs, bad ranges.
+        assert_eq!(get(&archives, "epub://localhost/doc1/ops/PACKAGE.OPF", None).body(), b"<package/>");
+        assert_eq!(get(&archives, "epub://localhost/doc1/OPS/missing.css", None).status(), StatusCode::NOT_FOUND);
+        assert_eq!(get(&archives, "epub://localhost/other/mimetype", None).status(), StatusCode::NOT_FOUND);
+        assert_eq!(get(&archives, "epub://localhost/doc1/../secret", None).status(), StatusCode::BAD_REQUEST);
+        let res = get(&archives, "epub://localhost/doc1/mimetype", Some("bytes=500-"));
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        archives.remove("doc1");
+        assert_eq!(get(&archives, "epub://localhost/doc1/mimetype", None).status(), StatusCode::NOT_FOUND);
+
+        // Sizes from a crafted central directory are bounded by the file, not allocated up front.
+        let mut index = ArchiveIndex::build(&sample_epub(&dir)).unwrap();
+        let file_len = std::fs::metadata(&index.path).unwrap().len();
+        for (name, deflated) in [("huge.bin", false), ("huge.z", true)] {
+            let info = EntryInfo {
+                data_start: 0,
+                compressed_size: u64::MAX / 2,
+                size: u64::MAX / 2,
+                deflated,
+            };
+            index.entries.insert(name.to_string(), info);
+        }
+        assert_eq!(index.read("huge.bin").unwrap().len() as u64, file_len);
+        assert!(index.read("huge.z").is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn deobfuscates_stored_fonts_and_refuses_drm() {
+        let dir = std::env::temp_dir().join(format!("epub_protocol_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, algorithm: &str, extra: Option<(&str, &[u8])>| {
+            let path = dir.join(name);
+            let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+            let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+            let mut files: Vec<(&str, Vec<u8>)> = vec![
+                ("META-INF/container.xml", br#"<container><rootfiles><rootfile full-path="content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#.to_vec()),
+                ("content.opf", br#"<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="id"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:identifier id="id">urn:uuid:00112233-4455-6677-8899-aabbccddeeff</dc:identifier></metadata><manifest/><spine/></package>"#.to_vec()),
+                ("META-INF/encryption.xml", format!(r#"<encryption xmlns:enc="http://www.w3.org/2001/04/xmlenc#"><enc:EncryptedData><enc:EncryptionMethod Algorithm="{algorithm}"/><enc:CipherData><enc:CipherReference URI="font.otf"/></enc:CipherData></enc:EncryptedData></encryption>"#).into_bytes()),
+                // "OTTO" XORed with the first key bytes 00 11 22 33.
+                ("font.otf", vec![b'O', b'T' ^ 0x11, b'T' ^ 0x22, b'O' ^ 0x33]),
+            ];
+            if let Some((n, b)) = extra {
+                files.push((n, b.to_vec()));
+            }
+            for (n, b) in files {
+                zip.start_file(n, stored).unwrap();
+                zip.write_all(&b).unwrap();
+            }
+            zip.finish().unwrap();
+            path
+        };
+
+        let index = ArchiveIndex::build(&write("fonts.epub", "http://ns.adobe.com/pdf/enc#RC", None)).unwrap();
+        let archives = EpubArchives::new();
+        archives.insert("doc1".to_string(), index);
+        assert_eq!(get(&archives, "epub://localhost/doc1/font.otf", None).body(), b"OTTO");
+        assert_eq!(get(&archives, "epub://localhost/doc1/font.otf", Some("bytes=2-3")).body(), b"TO");
+
+        let drm = write(
+            "drm.epub",
+            "http://www.w3.org/2001/04/xmlenc#aes128-cbc",
+            Some(("META-INF/rights.xml", b"<rights/>")),
+        );
+        match ArchiveIndex::build(&drm) {
+            Err(EpubOpenError::Drm { scheme, .. }) => assert_eq!(scheme, crate::epub_crypto::DrmScheme::AdobeAdept),
+            other => panic!("expected a DRM error, got {:?}", other.err()),
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repairs_books_read_from_the_archive() {
+        let dir = std::env::temp_dir().join(format!("epub_protocol_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("broken.epub");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let (chapter, _, _) =
+            encoding_rs::GBK.encode("<?xml version=\"1.0\" encoding=\"GBK\"?><html><body>中文</body></html>");
+        let files: [(&str, &[u8]); 2] = [
+            (
+                "OEBPS/content.opf",
+                br#"<package><manifest><item id="c" href="Text/CH1.xhtml"/><item id="x" href="gone.xhtml"/></manifest><spine><itemref idref="c"/><itemref idref="x"/></spine></package>"#,
+            ),
+            ("OEBPS/text/ch1.xhtml", &chapter),
+        ];
+        for (name, data) in files {
+            zip.start_file(name, deflated).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        // No container.xml and no mimetype: the OPF is found by scanning.
+        let index = ArchiveIndex::build(&path).unwrap();
+        assert_eq!(index.opf_path(), "OEBPS/content.opf");
+        let opf = String::from_utf8(index.read("OEBPS/content.opf").unwrap()).unwrap();
+        assert!(opf.contains(r#"href="text/ch1.xhtml""#) && !opf.contains("gone.xhtml"));
+        let kinds: Vec<_> = index.repair_report().fixed.iter().map(|a| a.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                RepairKind::OpfLocated,
+                RepairKind::HrefCase,
+                RepairKind::MissingItem,
+                RepairKind::Mimetype,
+                RepairKind::Encoding
+            ]
+        );
+
+        let archives = EpubArchives::new();
+        archives.insert("doc1".to_string(), index);
+        let res = get(&archives, "epub://localhost/doc1/OEBPS/text/ch1.xhtml", None);
+        let body = String::from_utf8(res.body().clone()).unwrap();
+        assert_eq!(body, "<?xml version=\"1.0\" encoding=\"utf-8\"?><html><body>中文</body></html>");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], body.len().to_string().as_str());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn encodes_url_segments() {
+        assert_eq!(encode_segment("Chapter 1.xhtml"), "Chapter%201.xhtml");
+        assert_eq!(encode_segment("封面.xhtml"), "%E5%B0%81%E9%9D%A2.xhtml");
+    }
+}
@@ -0,0 +1,421 @@
+use encoding_rs::Encoding;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::epub_meta::{parse_xml, resolve_href};
+
+const CONTAINER_XML: &str = "META-INF/container.xml";
+const MIMETYPE: &[u8] = b"application/epub+zip";
+const MARKUP_EXTENSIONS: [&str; 8] = ["xhtml", "html", "htm", "xml", "opf", "ncx", "smil", "svg"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepairKind {
+    /// container.xml only exists under a different case.
+    ContainerPath,
+    /// The OPF path in container.xml differs from the archive in case.
+    OpfPath,
+    /// container.xml is missing or unusable; the OPF was found by scanning the archive.
+    OpfLocated,
+    /// The `mimetype` file was missing or wrong.
+    Mimetype,
+    /// A markup file was transcoded to UTF-8.
+    Encoding,
+    /// A manifest href was rewritten to the case of the file in the archive.
+    HrefCase,
+    /// A manifest item has no file in the archive.
+    MissingItem,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepairAction {
+    pub kind: RepairKind,
+    /// Archive path the action applies to.
+    pub path: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EpubRepairReport {
+    pub fixed: Vec<RepairAction>,
+    /// Problems left in place; the book may still open with parts missing.
+    pub unresolved: Vec<RepairAction>,
+}
+
+impl EpubRepairReport {
+    pub(crate) fn fix(&mut self, kind: RepairKind, path: &str, detail: String) {
+        self.fixed.push(RepairAction {
+            kind,
+            path: path.to_string(),
+            detail,
+        });
+    }
+
+    fn report(&mut self, kind: RepairKind, path: &str, detail: String) {
+        self.unresolved.push(RepairAction {
+            kind,
+            path: path.to_string(),
+            detail,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.unresolved.is_empty()
+    }
+}
+
+/// Whether an archive path is markup that may need transcoding.
+pub(crate) fn is_markup(name: &str) -> bool {
+    let ext = name.rsplit('.').next().unwrap_or_default().to_ascii_lowercase();
+    MARKUP_EXTENSIONS.contains(&ext.as_str())
+}
+
+/// What is wrong with the contents of the `mimetype` entry, if anything.
+pub(crate) fn mimetype_problem(found: Option<&[u8]>) -> Option<String> {
+    match found {
+        Some(b) if b.trim_ascii() == MIMETYPE => None,
+        Some(b) => Some(format!("was \"{}\"", String::from_utf8_lossy(b).trim())),
+        None => Some("missing".to_string()),
+    }
+}
+
+fn find_name<'a>(names: &'a [String], wanted: &str) -> Option<&'a str> {
+    names
+        .iter()
+        .find(|n| *n == wanted)
+        .or_else(|| names.iter().find(|n| n.eq_ignore_ascii_case(wanted)))
+        .map(String::as_str)
+}
+
+/// Finds the package document of an archive with entry `names`. `read` returns an entry as text
+/// and `parse_container` pulls the OPF path out of container.xml. Case mismatches and a missing
+/// or broken container.xml are repaired and recorded in `report`.
+pub(crate) fn locate_opf(
+    names: &[String],
+    mut read: impl FnMut(&str) -> Option<String>,
+    parse_container: impl Fn(&str) -> Option<String>,
+    report: &mut EpubRepairReport,
+) -> Result<String, String> {
+    let container = find_name(names, CONTAINER_XML);
+    if let Some(c) = container.filter(|c| *c != CONTAINER_XML) {
+        report.fix(RepairKind::ContainerPath, c, format!("used {c} as {CONTAINER_XML}"));
+    }
+    let declared = container.and_then(&mut read).and_then(|xml| parse_container(&xml));
+    if let Some(declared) = &declared {
+        let wanted = declared.trim_start_matches('/');
+        if let Some(found) = find_name(names, wanted) {
+            if found != wanted {
+                report.fix(RepairKind::OpfPath, found, format!("container.xml names {declared}"));
+            }
+            return Ok(found.to_string());
+        }
+    }
+
+    let mut opfs: Vec<&String> = names.iter().filter(|n| n.to_ascii_lowercase().ends_with(".opf")).collect();
+    opfs.sort_by(|a, b| a.matches('/').count().cmp(&b.matches('/').count()).then(a.cmp(b)));
+    let Some(opf) = opfs.first() else {
+        return Err(match container {
+            Some(_) => "container.xml invalid (OPF not found)".to_string(),
+            None => "container.xml missing (META-INF/container.xml)".to_string(),
+        });
+    };
+    let why = match (&declared, container) {
+        (Some(d), _) => format!("container.xml names {d}, which is not in the archive"),
+        (None, Some(_)) => "container.xml has no rootfile".to_string(),
+        (None, None) => "container.xml is missing".to_string(),
+    };
+    report.fix(RepairKind::OpfLocated, opf, why);
+    Ok(opf.to_string())
+}
+
+/// Byte ranges of the charset labels in an XML declaration or `<meta>` tag near the top of `text`.
+fn charset_labels(text: &str) -> Vec<Range<usize>> {
+    let lower = text.to_ascii_lowercase();
+    let head_end = lower.find("<body").unwrap_or(lower.len());
+    let head = &lower.as_bytes()[..head_end];
+    let mut out = Vec::new();
+    for key in ["encoding", "charset"] {
+        let mut from = 0;
+        while let Some(pos) = lower[from..head_end].find(key) {
+            from += pos + key.len();
+            let mut i = from;
+            while head.get(i).is_some_and(u8::is_ascii_whitespace) {
+                i += 1;
+            }
+            if head.get(i) != Some(&b'=') {
+                continue;
+            }
+            i += 1;
+            while head.get(i).is_some_and(|b| b.is_ascii_whitespace() || *b == b'"' || *b == b'\'') {
+                i += 1;
+            }
+            let start = i;
+            while head.get(i).is_some_and(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(b)) {
+                i += 1;
+            }
+            if i > start {
+                out.push(start..i);
+            }
+        }
+    }
+    out.sort_by_key(|r| r.start);
+    out
+}
+
+/// Decodes a markup file that is not UTF-8, returning the text and what was done. Uses the BOM,
+/// then the declared charset, then GB18030 (the usual culprit in undeclared Chinese books), then
+/// windows-1252. Returns `None` when the file is already UTF-8 and says so.
+pub(crate) fn decode_markup(bytes: &[u8]) -> Option<(String, String)> {
+    if let Some((enc, bom)) = Encoding::for_bom(bytes) {
+        if enc == encoding_rs::UTF_8 {
+            return None;
+        }
+        let (text, _) = enc.decode_without_bom_handling(&bytes[bom..]);
+        return Some((text.into_owned(), format!("converted from {}", enc.name())));
+    }
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(4096)]);
+    let declared = charset_labels(&head)
+        .first()
+        .and_then(|r| Encoding::for_label(head[r.clone()].as_bytes()))
+        // A UTF-16 label in a file that reads as ASCII is wrong.
+        .filter(|e| *e != encoding_rs::UTF_8 && *e != encoding_rs::UTF_16LE && *e != encoding_rs::UTF_16BE);
+    let utf8 = std::str::from_utf8(bytes).ok();
+    match (declared, utf8) {
+        (Some(_), _) if bytes.is_ascii() => None,
+        (Some(enc), Some(text)) => Some((text.to_string(), format!("declared {} but is UTF-8", enc.name()))),
+        (Some(enc), None) => {
+            let (text, _) = enc.decode_without_bom_handling(bytes);
+            Some((text.into_owned(), format!("converted from {}", enc.name())))
+        }
+        (None, Some(_)) => None,
+        (None, None) => [encoding_rs::GB18030, encoding_rs::WINDOWS_1252].into_iter().find_map(|enc| {
+            enc.decode_without_bom_handling_and_without_replacement(bytes)
+                .map(|text| (text.into_owned(), format!("converted from undeclared {}", enc.name())))
+        }),
+    }
+}
+
+/// Points every declared charset at UTF-8 after transcoding.
+pub(crate) fn declare_utf8(text: &str) -> String {
+    let mut out = text.to_string();
+    for r in charset_labels(text).into_iter().rev() {
+        out.replace_range(r, "utf-8");
+    }
+    out
+}
+
+/// Percent-encodes the characters of an archive path that cannot appear raw in an href.
+fn encode_href(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            ' ' => out.push_str("%20"),
+            '%' => out.push_str("%25"),
+            '#' => out.push_str("%23"),
+            '?' => out.push_str("%3F"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `target` relative to the folder of `base` (both archive paths).
+fn relative_href(base: &str, target: &str) -> String {
+    let dir: Vec<&str> = base.split('/').collect();
+    let dir = &dir[..dir.len() - 1];
+    let target: Vec<&str> = target.split('/').collect();
+    let common = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![".."; dir.len() - common];
+    parts.extend(&target[common..]);
+    encode_href(&parts.join("/"))
+}
+
+/// Fixes manifest hrefs whose case disagrees with the archive and drops items that have no file,
+/// along with their spine entries. Returns the rewritten OPF if anything changed.
+pub(crate) fn repair_manifest(
+    opf: &str,
+    opf_path: &str,
+    files: &HashSet<String>,
+    report: &mut EpubRepairReport,
+) -> Result<Option<String>, String> {
+    let opf = opf.trim_start_matches('\u{feff}');
+    let doc = parse_xml(opf)?;
+    let folded: HashMap<String, &String> = files.iter().map(|f| (f.to_lowercase(), f)).collect();
+    let element = |name: &'static str| {
+        doc.descendants()
+            .filter(move |n| n.is_element() && n.tag_name().name() == name)
+    };
+
+    let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+    let mut missing: Vec<(roxmltree::Node, String, String)> = Vec::new();
+    for item in element("item") {
+        let Some(href) = item.attributes().find(|a| a.name() == "href") else {
+            continue;
+        };
+        let target = resolve_href(opf_path, href.value());
+        let target = target.split('#').next().unwrap_or_default().to_string();
+        if files.contains(&target) {
+            continue;
+        }
+        match folded.get(&target.to_lowercase()) {
+            Some(actual) => {
+                let new_href = relative_href(opf_path, actual);
+                report.fix(RepairKind::HrefCase, actual, format!("manifest href {} -> {new_href}", href.value()));
+                edits.push((href.range_value(), new_href));
+            }
+            None => missing.push((item, item.attribute("id").unwrap_or_default().to_string(), target)),
+        }
+    }
+
+    let itemrefs: Vec<_> = element("itemref").collect();
+    let missing_ids: HashSet<&str> = missing.iter().map(|(_, id, _)| id.as_str()).collect();
+    let readable = itemrefs
+        .iter()
+        .filter(|r| !missing_ids.contains(r.attribute("idref").unwrap_or_default()))
+        .count();
+    if readable == 0 && !itemrefs.is_empty() {
+        // Dropping every chapter would leave nothing to open; keep the OPF as it is.
+        for (_, id, target) in &missing {
+            report.report(RepairKind::MissingItem, target, format!("manifest item {id} has no file"));
+        }
+    } else {
+        for (item, id, target) in &missing {
+            edits.push((item.range(), String::new()));
+            let mut detail = format!("removed manifest item {id}");
+            for r in itemrefs.iter().filter(|r| r.attribute("idref") == Some(id.as_str())) {
+                edits.push((r.range(), String::new()));
+                detail.push_str(" and its spine entry");
+            }
+            if let Some(toc) = element("spine")
+                .flat_map(|s| s.attributes())
+                .find(|a| a.name() == "toc" && a.value() == id)
+            {
+                edits.push((toc.range(), String::new()));
+                detail.push_str(" and the spine toc reference");
+            }
+            report.fix(RepairKind::MissingItem, target, detail);
+        }
+    }
+
+    if edits.is_empty() {
+        return Ok(None);
+    }
+    edits.sort_by_key(|(r, _)| std::cmp::Reverse(r.start));
+    let mut out = opf.to_string();
+    for (range, with) in edits {
+        out.replace_range(range, &with);
+    }
+    Ok(Some(out))
+}
+
+/// Validation and repair pass over an extracted book rooted at `root`: restores `mimetype`,
+/// transcodes markup to UTF-8 and repairs the manifest of `opf_rel`.
+pub(crate) fn repair_extracted(root: &Path, opf_rel: &str, report: &mut EpubRepairReport) -> Result<(), String> {
+    let mimetype = root.join("mimetype");
+    if let Some(problem) = mimetype_problem(std::fs::read(&mimetype).ok().as_deref()) {
+        std::fs::write(&mimetype, MIMETYPE).map_err(|e| e.to_string())?;
+        report.fix(RepairKind::Mimetype, "mimetype", format!("{problem}; rewritten"));
+    }
+
+    let mut files = HashSet::new();
+    for entry in walkdir::WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(root) else { continue };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if is_markup(&rel) {
+            let bytes = std::fs::read(entry.path()).map_err(|e| e.to_string())?;
+            if let Some((text, detail)) = decode_markup(&bytes) {
+                std::fs::write(entry.path(), declare_utf8(&text)).map_err(|e| e.to_string())?;
+                report.fix(RepairKind::Encoding, &rel, detail);
+            }
+        }
+        files.insert(rel);
+    }
+
+    let opf_abs = root.join(opf_rel);
+    let opf = std::fs::read_to_string(&opf_abs).map_err(|e| e.to_string())?;
+    match repair_manifest(&opf, opf_rel, &files, report) {
+        Ok(Some(fixed)) => std::fs::write(&opf_abs, fixed).map_err(|e| e.to_string())?,
+        Ok(None) => {}
+        Err(e) => log::warn!("[epub_repair] {opf_rel}: {e}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_opf_despite_case_and_missing_container() {
+        let parse = |xml: &str| crate::epub_meta::parse_container(xml);
+        let container = r#"<container><rootfiles><rootfile full-path="oebps/Content.opf"/></rootfiles></container>"#;
+        let names: Vec<String> = ["Meta-Inf/Container.xml", "OEBPS/content.opf"].iter().map(|s| s.to_string()).collect();
+        let mut report = EpubRepairReport::default();
+        let opf = locate_opf(&names, |_| Some(container.to_string()), parse, &mut report).unwrap();
+        assert_eq!(opf, "OEBPS/content.opf");
+        let kinds: Vec<_> = report.fixed.iter().map(|a| a.kind).collect();
+        assert_eq!(kinds, vec![RepairKind::ContainerPath, RepairKind::OpfPath]);
+
+        let names: Vec<String> = ["OEBPS/sub/other.opf", "book.OPF"].iter().map(|s| s.to_string()).collect();
+        let mut report = EpubRepairReport::default();
+        assert_eq!(locate_opf(&names, |_| None, parse, &mut report).unwrap(), "book.OPF");
+        assert_eq!(report.fixed[0].kind, RepairKind::OpfLocated);
+
+        let mut report = EpubRepairReport::default();
+        assert!(locate_opf(&[], |_| None, parse, &mut report).is_err());
+    }
+
+    #[test]
+    fn transcodes_declared_and_undeclared_encodings() {
+        let (gbk, _, _) = encoding_rs::GBK.encode("<?xml version=\"1.0\" encoding=\"GBK\"?><html><body>中文</body></html>");
+        let (text, detail) = decode_markup(&gbk).unwrap();
+        assert_eq!(detail, "converted from GBK");
+        assert_eq!(declare_utf8(&text), "<?xml version=\"1.0\" encoding=\"utf-8\"?><html><body>中文</body></html>");
+
+        let (undeclared, _, _) =
+            encoding_rs::GB18030.encode("<html><head><meta charset='utf-8'/></head><body>汉字</body></html>");
+        let (text, _) = decode_markup(&undeclared).unwrap();
+        assert!(text.contains("汉字"));
+
+        assert!(decode_markup("<?xml version='1.0' encoding='utf-8'?><p>é</p>".as_bytes()).is_none());
+        let (text, detail) = decode_markup("<?xml version='1.0' encoding='gb2312'?><p>é</p>".as_bytes()).unwrap();
+        assert_eq!((text.as_str(), detail.as_str()), ("<?xml version='1.0' encoding='gb2312'?><p>é</p>", "declared GBK but is UTF-8"));
+        let latin1 = b"<?xml version='1.0' encoding='iso-8859-1'?><p>\xe9</p>";
+        assert!(decode_markup(latin1).unwrap().0.contains("<p>é</p>"));
+    }
+
+    #[test]
+    fn repairs_manifest_hrefs_and_missing_items() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf"><manifest>
+<item id="ch1" href="Text/Chapter%201.XHTML" media-type="application/xhtml+xml"/>
+<item id="ch2" href="text/gone.xhtml" media-type="application/xhtml+xml"/>
+<item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+<item id="img" href="../Images/Cover.jpg" media-type="image/jpeg"/>
+</manifest><spine toc="ncx"><itemref idref="ch1"/><itemref idref="ch2"/></spine></package>"#;
+        let files: HashSet<String> = ["OEBPS/text/chapter 1.xhtml", "images/cover.JPG"].iter().map(|s| s.to_string()).collect();
+        let mut report = EpubRepairReport::default();
+        let fixed = repair_manifest(opf, "OEBPS/content.opf", &files, &mut report).unwrap().unwrap();
+        assert!(fixed.contains(r#"href="text/chapter%201.xhtml""#));
+        assert!(fixed.contains(r#"href="../images/cover.JPG""#));
+        assert!(!fixed.contains("gone.xhtml") && !fixed.contains("idref=\"ch2\""));
+        assert!(!fixed.contains("toc.ncx") && !fixed.contains("toc=\"ncx\""));
+        assert!(parse_xml(&fixed).is_ok());
+        let kinds: Vec<_> = report.fixed.iter().map(|a| a.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![RepairKind::HrefCase, RepairKind::HrefCase, RepairKind::MissingItem, RepairKind::MissingItem]
+        );
+
+        // Nothing readable would remain: report instead of removing.
+        let files = HashSet::new();
+        let mut report = EpubRepairReport::default();
+        let opf = r#"<package><manifest><item id="a" href="a.xhtml"/></manifest><spine><itemref idref="a"/></spine></package>"#;
+        assert_eq!(repair_manifest(opf, "content.opf", &files, &mut report).unwrap(), None);
+        assert_eq!(report.unresolved.len(), 1);
+    }
+}
@@ -0,0 +1,598 @@
+use roxmltree::{Node, NodeId};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::epub_meta::{parse_opf, parse_xml, replace_html_entities, EpubMetadata};
+use crate::covers::document_fingerprint;
+use crate::epub_protocol::ArchiveIndex;
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Heading,
+    Paragraph,
+    ListItem,
+    Quote,
+    Preformatted,
+    TableCell,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextBlock {
+    pub kind: BlockKind,
+    /// 1–6 for headings, nesting depth for list items.
+    pub level: Option<u8>,
+    /// Char offsets into the chapter text.
+    pub start: usize,
+    pub end: usize,
+    /// CFI of the first character of the block.
+    pub cfi: String,
+}
+
+/// A run of output characters that maps one-to-one onto a text node of the XHTML.
+#[derive(Debug, Clone, Serialize)]
+pub struct TextSegment {
+    pub start: usize,
+    pub end: usize,
+    /// CFI steps from the document element down to the text node, e.g. `/4/2[p1]/1`.
+    pub path: String,
+    /// UTF-16 offset within the text node at `start`, as DOM ranges and CFIs count it.
+    pub offset: usize,
+    #[serde(skip)]
+    next_source: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChapterText {
+    pub index: usize,
+    pub idref: String,
+    pub href: Option<String>,
+    pub linear: bool,
+    pub text: String,
+    pub blocks: Vec<TextBlock>,
+    pub segments: Vec<TextSegment>,
+    /// `/6/N[idref]!`; a location is `epubcfi(<cfi_base><segment path>:<offset>)`.
+    pub cfi_base: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookText {
+    pub title: Option<String>,
+    pub chapters: Vec<ChapterText>,
+}
+
+fn block_kind(name: &str) -> Option<(BlockKind, Option<u8>)> {
+    Some(match name {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => (BlockKind::Heading, name[1..].parse().ok()),
+        "li" | "dt" | "dd" => (BlockKind::ListItem, None),
+        "blockquote" => (BlockKind::Quote, None),
+        "pre" => (BlockKind::Preformatted, None),
+        "td" | "th" => (BlockKind::TableCell, None),
+        "p" | "div" | "section" | "article" | "aside" | "header" | "footer" | "figure" | "figcaption" | "caption"
+        | "address" | "center" | "nav" | "tr" | "table" | "ul" | "ol" | "dl" | "hr" | "main" => {
+            (BlockKind::Paragraph, None)
+        }
+        _ => return None,
+    })
+}
+
+/// Elements whose content is never reading text. `rt`/`rp` drop ruby annotations so the base text reads through.
+fn skipped(name: &str) -> bool {
+    matches!(
+        name,
+        "head" | "script" | "style" | "template" | "noscript" | "rt" | "rp"
+    )
+}
+
+/// CFI step of `node` among its siblings: elements are even, text runs between them odd.
+fn cfi_step(node: Node) -> (usize, usize) {
+    let mut elements = 0;
+    let mut run_offset = 0;
+    for sib in node.prev_siblings().skip(1) {
+        if sib.is_element() {
+            elements += 1;
+        } else if elements == 0 && sib.is_text() {
+            run_offset += sib.text().map(|t| t.encode_utf16().count()).unwrap_or(0);
+        }
+    }
+    if node.is_element() {
+        (2 * (elements + 1), 0)
+    } else {
+        (2 * elements + 1, run_offset)
+    }
+}
+
+/// HTML collapses only ASCII whitespace; a no-break space is content.
+fn is_html_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0c')
+}
+
+fn element_path(node: Node, root: NodeId) -> String {
+    let mut steps = Vec::new();
+    let mut cur = Some(node);
+    while let Some(n) = cur {
+        if n.id() == root {
+            break;
+        }
+        let (step, _) = cfi_step(n);
+        steps.push(match n.attribute("id") {
+            Some(id) if n.is_element() => format!("/{step}[{id}]"),
+            _ => format!("/{step}"),
+        });
+        cur = n.parent();
+    }
+    steps.reverse();
+    steps.concat()
+}
+
+struct Extractor {
+    root: NodeId,
+    text: String,
+    len: usize,
+    blocks: Vec<TextBlock>,
+    segments: Vec<TextSegment>,
+    /// Enclosing block elements, innermost last.
+    block: Vec<(BlockKind, Option<u8>)>,
+    /// Whether the current block has produced text yet.
+    open: bool,
+    /// Inside a list item that has not opened a block yet; its first paragraph carries the marker.
+    item_pending: bool,
+    pending_break: bool,
+    list_depth: u8,
+    pre_depth: usize,
+    cfi_base: String,
+}
+
+impl Extractor {
+    fn push_unmapped(&mut self, s: &str) {
+        self.text.push_str(s);
+        self.len += s.chars().count();
+    }
+
+    fn close_block(&mut self) {
+        if !self.open {
+            return;
+        }
+        if self.text.ends_with(' ') {
+            self.text.pop();
+            self.len -= 1;
+            if let Some(seg) = self.segments.last_mut() {
+                if seg.end > self.len {
+                    seg.end = self.len;
+                }
+            }
+            self.segments.retain(|s| s.start < s.end);
+        }
+        if let Some(b) = self.blocks.last_mut() {
+            b.end = self.len;
+        }
+        self.open = false;
+        self.pending_break = true;
+    }
+
+    fn open_block(&mut self, path: &str, source: usize) {
+        let (kind, level) = self.block.last().copied().unwrap_or((BlockKind::Paragraph, None));
+        if std::mem::take(&mut self.pending_break) && self.len > 0 {
+            // Consecutive list items sit on adjacent lines; everything else is a paragraph break.
+            let tight = kind == BlockKind::ListItem && self.blocks.last().map(|b| b.kind) == Some(BlockKind::ListItem);
+            self.push_unmapped(if tight { "\n" } else { "\n\n" });
+        }
+        let level = if kind == BlockKind::ListItem {
+            Some(self.list_depth.max(1))
+        } else {
+            level
+        };
+        if kind == BlockKind::ListItem {
+            self.push_unmapped(&"  ".repeat(self.list_depth.saturating_sub(1) as usize));
+            self.push_unmapped("- ");
+        }
+        self.item_pending = false;
+        self.blocks.push(TextBlock {
+            kind,
+            level,
+            start: self.len,
+            end: self.len,
+            cfi: format!("epubcfi({}{}:{})", self.cfi_base, path, source),
+        });
+        self.open = true;
+    }
+
+    fn emit(&mut self, c: char, path: &str, source: usize) {
+        match self.segments.last_mut() {
+            Some(seg) if seg.end == self.len && seg.path == path && seg.next_source == source => {
+                seg.end += 1;
+                seg.next_source += c.len_utf16();
+            }
+            _ => self.segments.push(TextSegment {
+                start: self.len,
+                end: self.len + 1,
+                path: path.to_string(),
+                offset: source,
+                next_source: source + c.len_utf16(),
+            }),
+        }
+        self.text.push(c);
+        self.len += 1;
+    }
+
+    fn text_node(&mut self, node: Node) {
+        let Some(raw) = node.text() else { return };
+        let (step, run_offset) = cfi_step(node);
+        let path = format!(
+            "{}/{}",
+            node.parent().map(|p| element_path(p, self.root)).unwrap_or_default(),
+            step
+        );
+        let mut source = run_offset;
+        for c in raw.chars() {
+            let here = source;
+            source += c.len_utf16();
+            if self.pre_depth == 0 && is_html_space(c) {
+                if !self.open || self.text.ends_with(' ') || self.text.ends_with('\n') {
+                    continue;
+                }
+                self.emit(' ', &path, here);
+                continue;
+            }
+            if !self.open {
+                self.open_block(&path, here);
+            }
+            self.emit(c, &path, here);
+        }
+    }
+
+    fn walk(&mut self, node: Node) {
+        for child in node.children() {
+            if child.is_text() {
+                self.text_node(child);
+                continue;
+            }
+            if !child.is_element() {
+                continue;
+            }
+            let name = child.tag_name().name().to_ascii_lowercase();
+            if skipped(&name) {
+                continue;
+            }
+            if name == "br" {
+                if self.open {
+                    if self.text.ends_with(' ') {
+                        self.text.pop();
+                        self.len -= 1;
+                        if let Some(seg) = self.segments.last_mut() {
+                            seg.end = seg.end.min(self.len);
+                        }
+                    }
+                    self.push_unmapped("\n");
+                }
+                continue;
+            }
+            let block = block_kind(&name);
+            let list = matches!(name.as_str(), "ul" | "ol" | "dl");
+            if let Some(kind) = block {
+                self.close_block();
+                let kind = match kind {
+                    (BlockKind::Paragraph, _) if self.item_pending => (BlockKind::ListItem, None),
+                    (BlockKind::ListItem, _) => {
+                        self.item_pending = true;
+                        kind
+                    }
+                    _ => kind,
+                };
+                self.block.push(kind);
+            }
+            if list {
+                self.list_depth += 1;
+            }
+            if name == "pre" {
+                self.pre_depth += 1;
+            }
+            self.walk(child);
+            if name == "pre" {
+                self.pre_depth -= 1;
+            }
+            if list {
+                self.list_depth -= 1;
+            }
+            if block.is_some() {
+                self.close_block();
+                self.block.pop();
+                self.item_pending = false;
+            }
+        }
+    }
+}
+
+/// Extracts reading text from an XHTML content document, keeping paragraph, heading and
+/// list structure. `cfi_base` is the spine part of the CFI, e.g. `/6/4[ch1]!`.
+pub(crate) fn extract_xhtml_text(
+    xhtml: &str,
+    cfi_base: &str,
+) -> Result<(String, Vec<TextBlock>, Vec<TextSegment>), String> {
+    let source = replace_html_entities(xhtml);
+    let doc = parse_xml(&source)?;
+    let root = doc.root_element();
+    let body = root
+        .children()
+        .find(|n| n.is_element() && n.tag_name().name().eq_ignore_ascii_case("body"))
+        .unwrap_or(root);
+    let mut ex = Extractor {
+        root: root.id(),
+        text: String::new(),
+        len: 0,
+        blocks: Vec::new(),
+        segments: Vec::new(),
+        block: Vec::new(),
+        open: false,
+        item_pending: false,
+        pending_break: false,
+        list_depth: 0,
+        pre_depth: 0,
+        cfi_base: cfi_base.to_string(),
+    };
+    ex.walk(body);
+    ex.close_block();
+    Ok((ex.text, ex.blocks, ex.segments))
+}
+
+/// Step of `<spine>` among the package element's children (normally 6).
+fn spine_step(opf_xml: &str) -> usize {
+    parse_xml(opf_xml)
+        .ok()
+        .and_then(|doc| {
+            doc.root_element()
+                .children()
+                .filter(|n| n.is_element())
+                .position(|n| n.tag_name().name() == "spine")
+        })
+        .map(|i| 2 * (i + 1))
+        .unwrap_or(6)
+}
+
+struct Book {
+    index: Arc<ArchiveIndex>,
+    meta: EpubMetadata,
+    spine_step: usize,
+}
+
+/// Archive index of `path` if the reader has it open.
+fn open_archive(state: &AppState, path: &Path) -> Option<Arc<ArchiveIndex>> {
+    document_fingerprint(path).ok().and_then(|doc| state.epub_archives.get(&doc))
+}
+
+/// Reads the package of `path`, reusing `open` instead of indexing the archive again.
+fn open_book(path: &Path, open: Option<Arc<ArchiveIndex>>) -> Result<Book, String> {
+    let index = match open {
+        Some(index) => index,
+        None => Arc::new(ArchiveIndex::build(path).map_err(|e| e.to_string())?),
+    };
+    let opf_xml = String::from_utf8_lossy(&index.read(index.opf_path())?).to_string();
+    let meta = parse_opf(&opf_xml, index.opf_path())?;
+    Ok(Book {
+        spine_step: spine_step(&opf_xml),
+        index,
+        meta,
+    })
+}
+
+fn chapter(book: &Book, i: usize) -> Result<ChapterText, String> {
+    let item = book
+        .meta
+        .spine
+        .get(i)
+        .ok_or_else(|| format!("spine index {i} out of range"))?;
+    let cfi_base = format!("/{}/{}[{}]!", book.spine_step, 2 * (i + 1), item.idref);
+    let (text, blocks, segments) = match &item.href {
+        Some(href) => {
+            let xhtml = String::from_utf8_lossy(&book.index.read(href)?).to_string();
+            extract_xhtml_text(&xhtml, &cfi_base).map_err(|e| format!("{href}: {e}"))?
+        }
+        None => Default::default(),
+    };
+    Ok(ChapterText {
+        index: i,
+        idref: item.idref.clone(),
+        href: item.href.clone(),
+        linear: item.linear,
+        text,
+        blocks,
+        segments,
+        cfi_base,
+    })
+}
+
+/// Text and structure of one spine item.
+#[tauri::command]
+pub async fn epub_chapter_text(state: State<'_, AppState>, path: String, index: usize) -> Result<ChapterText, String> {
+    let path = PathBuf::from(path);
+    let open = open_archive(&state, &path);
+    tokio::task::spawn_blocking(move || chapter(&open_book(&path, open)?, index))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Text of every spine item in reading order. Chapters that fail to parse are logged and skipped.
+pub(crate) fn book_text(path: &Path, open: Option<Arc<ArchiveIndex>>) -> Result<BookText, String> {
+    let book = open_book(path, open)?;
+    let chapters = (0..book.meta.spine.len())
+        .filter_map(|i| match chapter(&book, i) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                log::warn!("[epub_text] {}: {e}", path.display());
+                None
+            }
+        })
+        .collect();
+    Ok(BookText {
+        title: book.meta.title.clone(),
+        chapters,
+    })
+}
+
+/// Text of every spine item in reading order. Chapters that fail to parse are logged and skipped.
+#[tauri::command]
+pub async fn epub_book_text(state: State<'_, AppState>, path: String) -> Result<BookText, String> {
+    let path = PathBuf::from(path);
+    let open = open_archive(&state, &path);
+    tokio::task::spawn_blocking(move || book_text(&path, open))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAPTER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><title>Ignored</title><style>p { color: red }</style></head>
+<body>
+  <h1 id="t">Chapter&nbsp;One</h1>
+  <p id="p1">It was   a <em>bright</em>
+     cold day.</p>
+  <script>alert("no")</script>
+  <ul>
+    <li>First</li>
+    <li>Second<ul><li>Nested</li></ul></li>
+    <li><p>Caf&eacute;</p><p>More</p></li>
+  </ul>
+  <p>汉<ruby>字<rt>zì</rt></ruby>😀x</p>
+  <p>Line<br/>break</p>
+  <pre>  keep
+  spaces</pre>
+</body>
+</html>"#;
+
+    /// CFI of the character at `offset` (a char offset into `text`), if it comes from the document.
+    fn cfi_at(c: &ChapterText, offset: usize) -> Option<String> {
+        let seg = c
+            .segments
+            .iter()
+            .find(|s| s.start <= offset && offset < s.end)
+            .or_else(|| c.segments.iter().find(|s| s.end == offset))?;
+        let within: usize = c
+            .text
+            .chars()
+            .skip(seg.start)
+            .take(offset - seg.start)
+            .map(char::len_utf16)
+            .sum();
+        Some(format!("epubcfi({}{}:{})", c.cfi_base, seg.path, seg.offset + within))
+    }
+
+    fn extract() -> ChapterText {
+        let (text, blocks, segments) = extract_xhtml_text(CHAPTER, "/6/4[ch1]!").unwrap();
+        ChapterText {
+            index: 1,
+            idref: "ch1".to_string(),
+            href: None,
+            linear: true,
+            text,
+            blocks,
+            segments,
+            cfi_base: "/6/4[ch1]!".to_string(),
+        }
+    }
+
+    #[test]
+    fn keeps_structure_and_drops_scripts() {
+        let c = extract();
+        assert_eq!(
+            c.text,
+            "Chapter\u{a0}One\n\nIt was a bright cold day.\n\n- First\n- Second\n  - Nested\n- Café\n\nMore\n\n汉字😀x\n\nLine\nbreak\n\n  keep\n  spaces"
+        );
+        let kinds: Vec<_> = c.blocks.iter().map(|b| (b.kind, b.level)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (BlockKind::Heading, Some(1)),
+                (BlockKind::Paragraph, None),
+                (BlockKind::ListItem, Some(1)),
+                (BlockKind::ListItem, Some(1)),
+                (BlockKind::ListItem, Some(2)),
+                (BlockKind::ListItem, Some(1)),
+                (BlockKind::Paragraph, None),
+                (BlockKind::Paragraph, None),
+                (BlockKind::Paragraph, None),
+                (BlockKind::Preformatted, None),
+            ]
+        );
+        let heading = &c.blocks[0];
+        assert_eq!(
+            c.text
+                .chars()
+                .skip(heading.start)
+                .take(heading.end - heading.start)
+                .collect::<String>(),
+            "Chapter\u{a0}One"
+        );
+        assert_eq!(heading.cfi, "epubcfi(/6/4[ch1]!/4/2[t]/1:0)");
+    }
+
+    #[test]
+    fn maps_offsets_to_cfis() {
+        let c = extract();
+        let at = |needle: &str| c.text.find(needle).map(|b| c.text[..b].chars().count()).unwrap();
+        // "It" is at the start of the paragraph's first text node.
+        assert_eq!(cfi_at(&c, at("It was")).unwrap(), "epubcfi(/6/4[ch1]!/4/4[p1]/1:0)");
+        // Collapsed whitespace: "a" sits after three spaces in the source.
+        assert_eq!(cfi_at(&c, at("a bright")).unwrap(), "epubcfi(/6/4[ch1]!/4/4[p1]/1:9)");
+        // Inside <em>.
+        assert_eq!(cfi_at(&c, at("bright")).unwrap(), "epubcfi(/6/4[ch1]!/4/4[p1]/2/1:0)");
+        // Text after the element is the next odd step, leading newline and indent skipped.
+        assert_eq!(cfi_at(&c, at("cold")).unwrap(), "epubcfi(/6/4[ch1]!/4/4[p1]/3:6)");
+        // Offsets after an astral character count UTF-16 units; ruby base text maps into <ruby>.
+        assert_eq!(cfi_at(&c, at("字")).unwrap(), "epubcfi(/6/4[ch1]!/4/10/2/1:0)");
+        assert_eq!(cfi_at(&c, at("x\n")).unwrap(), "epubcfi(/6/4[ch1]!/4/10/3:2)");
+        // List bullets are not in the document.
+        assert_eq!(cfi_at(&c, at("- First")), None);
+    }
+
+    #[test]
+    fn reads_chapters_from_archive() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+
+        let dir = std::env::temp_dir().join(format!("epub_text_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("book.epub");
+        {
+            let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+            let opts = SimpleFileOptions::default();
+            let files = [
+                (
+                    "META-INF/container.xml",
+                    r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+                ),
+                (
+                    "OEBPS/content.opf",
+                    r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Book</dc:title></metadata><manifest><item id="a" href="a.xhtml" media-type="application/xhtml+xml"/><item id="b" href="b.xhtml" media-type="application/xhtml+xml"/></manifest><spine><itemref idref="a"/><itemref idref="b"/></spine></package>"#,
+                ),
+                (
+                    "OEBPS/a.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><p>Alpha</p></body></html>"#,
+                ),
+                (
+                    "OEBPS/b.xhtml",
+                    r#"<html xmlns="http://www.w3.org/1999/xhtml"><body><p>Beta</p></body></html>"#,
+                ),
+            ];
+            for (name, body) in files {
+                zip.start_file(name, opts).unwrap();
+                zip.write_all(body.as_bytes()).unwrap();
+            }
+            zip.finish().unwrap();
+        }
+
+        let book = open_book(&path, None).unwrap();
+        let b = chapter(&book, 1).unwrap();
+        assert_eq!((b.text.as_str(), b.cfi_base.as_str()), ("Beta", "/6/4[b]!"));
+        assert_eq!(cfi_at(&b, 0).unwrap(), "epubcfi(/6/4[b]!/2/2/1:0)");
+        assert!(chapter(&book, 2).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
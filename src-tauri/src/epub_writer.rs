@@ -0,0 +1,285 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::epub_protocol::mime_type;
+
+/// Folder of the package inside generated books; every href below is relative to it.
+const OPF_DIR: &str = "OEBPS";
+
+/// A book converted from another format, ready to be written out as EPUB 3.
+#[derive(Debug, Default)]
+pub(crate) struct ConvertedBook {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub language: Option<String>,
+    pub identifier: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub chapters: Vec<Chapter>,
+    pub resources: Vec<Resource>,
+    /// Href of the cover image among `resources`.
+    pub cover: Option<String>,
+    /// Falls back to one entry per chapter when empty.
+    pub toc: Vec<TocNode>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Chapter {
+    /// e.g. `text/ch0001.xhtml`.
+    pub href: String,
+    pub title: String,
+    /// A complete XHTML document.
+    pub xhtml: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Resource {
+    pub href: String,
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Resource {
+    pub fn new(href: String, data: Vec<u8>) -> Self {
+        Self {
+            media_type: mime_type(&href).to_string(),
+            href,
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TocNode {
+    pub label: String,
+    /// Chapter href, optionally with a fragment.
+    pub href: String,
+    pub children: Vec<TocNode>,
+}
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Wraps body markup in an XHTML document. `stylesheets` are hrefs relative to the chapter.
+pub(crate) fn xhtml_document(title: &str, language: Option<&str>, stylesheets: &[&str], body: &str) -> String {
+    let lang = language
+        .map(|l| format!(" xml:lang=\"{0}\" lang=\"{0}\"", escape_xml(l)))
+        .unwrap_or_default();
+    let links: String = stylesheets
+        .iter()
+        .map(|s| {
+            format!(
+                "<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>\n",
+                escape_xml(s)
+            )
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\"{lang}>\n<head>\n<title>{}</title>\n{links}</head>\n<body>\n{body}\n</body>\n</html>\n",
+        escape_xml(title)
+    )
+}
+
+fn nav_list(nodes: &[TocNode], out: &mut String) {
+    out.push_str("<ol>\n");
+    for n in nodes {
+        out.push_str(&format!(
+            "<li><a href=\"{}\">{}</a>",
+            escape_xml(&n.href),
+            escape_xml(&n.label)
+        ));
+        if !n.children.is_empty() {
+            out.push('\n');
+            nav_list(&n.children, out);
+        }
+        out.push_str("</li>\n");
+    }
+    out.push_str("</ol>\n");
+}
+
+fn package_document(book: &ConvertedBook, identifier: &str) -> String {
+    let mut meta = format!(
+        "<dc:identifier id=\"book-id\">{}</dc:identifier>\n<dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n",
+        escape_xml(identifier),
+        escape_xml(&book.title),
+        escape_xml(book.language.as_deref().unwrap_or("und"))
+    );
+    for a in &book.authors {
+        meta.push_str(&format!("<dc:creator>{}</dc:creator>\n", escape_xml(a)));
+    }
+    if let Some(p) = &book.publisher {
+        meta.push_str(&format!("<dc:publisher>{}</dc:publisher>\n", escape_xml(p)));
+    }
+    if let Some(d) = &book.description {
+        meta.push_str(&format!("<dc:description>{}</dc:description>\n", escape_xml(d)));
+    }
+    meta.push_str(&format!(
+        "<meta property=\"dcterms:modified\">{}</meta>\n",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+    ));
+    if book.cover.is_some() {
+        meta.push_str("<meta name=\"cover\" content=\"cover-image\"/>\n");
+    }
+
+    let mut manifest =
+        String::from("<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n");
+    let mut spine = String::new();
+    for (i, c) in book.chapters.iter().enumerate() {
+        let svg = if c.xhtml.contains("<svg") {
+            " properties=\"svg\""
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            "<item id=\"c{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{svg}/>\n",
+            i + 1,
+            escape_xml(&c.href)
+        ));
+        spine.push_str(&format!("<itemref idref=\"c{}\"/>\n", i + 1));
+    }
+    for (i, r) in book.resources.iter().enumerate() {
+        let (id, props) = if book.cover.as_deref() == Some(r.href.as_str()) {
+            ("cover-image".to_string(), " properties=\"cover-image\"")
+        } else {
+            (format!("r{}", i + 1), "")
+        };
+        manifest.push_str(&format!(
+            "<item id=\"{id}\" href=\"{}\" media-type=\"{}\"{props}/>\n",
+            escape_xml(&r.href),
+            escape_xml(&r.media_type)
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\">\n<metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{meta}</metadata>\n<manifest>\n{manifest}</manifest>\n<spine>\n{spine}</spine>\n</package>\n"
+    )
+}
+
+/// Writes `book` to `dest` as an EPUB 3 with a navigation document. The file appears atomically.
+pub(crate) fn write_epub(book: &ConvertedBook, dest: &Path) -> Result<(), String> {
+    let identifier = book
+        .identifier
+        .clone()
+        .unwrap_or_else(|| format!("urn:uuid:{}", uuid::Uuid::new_v4()));
+    let toc = if book.toc.is_empty() {
+        book.chapters
+            .iter()
+            .map(|c| TocNode {
+                label: c.title.clone(),
+                href: c.href.clone(),
+                children: Vec::new(),
+            })
+            .collect()
+    } else {
+        book.toc.clone()
+    };
+    let mut nav = String::from("<nav epub:type=\"toc\" id=\"toc\">\n");
+    nav.push_str(&format!("<h1>{}</h1>\n", escape_xml(&book.title)));
+    nav_list(&toc, &mut nav);
+    nav.push_str("</nav>");
+
+    let tmp = dest.with_extension("epub.part");
+    let result = (|| -> Result<(), String> {
+        let mut zip = ZipWriter::new(BufWriter::new(File::create(&tmp).map_err(|e| e.to_string())?));
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        let mut add = |name: &str, data: &[u8], opts: SimpleFileOptions| -> Result<(), String> {
+            zip.start_file(name, opts).map_err(|e| e.to_string())?;
+            zip.write_all(data).map_err(|e| e.to_string())
+        };
+        add("mimetype", b"application/epub+zip", stored)?;
+        add(
+            "META-INF/container.xml",
+            format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\"><rootfiles><rootfile full-path=\"{OPF_DIR}/content.opf\" media-type=\"application/oebps-package+xml\"/></rootfiles></container>\n").as_bytes(),
+            deflated,
+        )?;
+        add(
+            &format!("{OPF_DIR}/content.opf"),
+            package_document(book, &identifier).as_bytes(),
+            deflated,
+        )?;
+        add(
+            &format!("{OPF_DIR}/nav.xhtml"),
+            xhtml_document(&book.title, book.language.as_deref(), &[], &nav).as_bytes(),
+            deflated,
+        )?;
+        for c in &book.chapters {
+            add(&format!("{OPF_DIR}/{}", c.href), c.xhtml.as_bytes(), deflated)?;
+        }
+        for r in &book.resources {
+            // Images and fonts are already compressed.
+            let opts = if r.media_type.starts_with("image/") && r.media_type != "image/svg+xml" {
+                stored
+            } else {
+                deflated
+            };
+            add(&format!("{OPF_DIR}/{}", r.href), &r.data, opts)?;
+        }
+        zip.finish()
+            .map_err(|e| e.to_string())?
+            .flush()
+            .map_err(|e| e.to_string())
+    })();
+    match result {
+        Ok(()) => std::fs::rename(&tmp, dest).map_err(|e| e.to_string()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub_meta::read_epub_metadata;
+
+    #[test]
+    fn writes_a_readable_epub() {
+        let dir = std::env::temp_dir().join(format!("epub_writer_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let book = ConvertedBook {
+            title: "Tom & Jerry".to_string(),
+            authors: vec!["A. Author".to_string()],
+            language: Some("en".to_string()),
+            chapters: vec![
+                Chapter {
+                    href: "text/ch0001.xhtml".to_string(),
+                    title: "One".to_string(),
+                    xhtml: xhtml_document("One", Some("en"), &[], "<h1 id=\"s1\">One</h1>"),
+                },
+                Chapter {
+                    href: "text/ch0002.xhtml".to_string(),
+                    title: "Two".to_string(),
+                    xhtml: xhtml_document("Two", Some("en"), &[], "<p>Two</p>"),
+                },
+            ],
+            resources: vec![Resource::new(
+                "images/cover.png".to_string(),
+                vec![0x89, b'P', b'N', b'G'],
+            )],
+            cover: Some("images/cover.png".to_string()),
+            ..Default::default()
+        };
+        let path = dir.join("book.epub");
+        write_epub(&book, &path).unwrap();
+        assert!(!dir.join("book.epub.part").exists());
+
+        let meta = read_epub_metadata(&path).unwrap();
+        assert_eq!(meta.title.as_deref(), Some("Tom & Jerry"));
+        assert_eq!(meta.authors, vec!["A. Author".to_string()]);
+        assert_eq!(meta.cover_href.as_deref(), Some("OEBPS/images/cover.png"));
+        let spine: Vec<_> = meta.spine.iter().filter_map(|s| s.href.as_deref()).collect();
+        assert_eq!(spine, vec!["OEBPS/text/ch0001.xhtml", "OEBPS/text/ch0002.xhtml"]);
+        let toc: Vec<_> = meta.toc.iter().map(|t| t.label.as_str()).collect();
+        assert_eq!(toc, vec!["One", "Two"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,535 @@
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use roxmltree::{Node, NodeId};
+use std::collections::HashMap;
+
+use crate::epub_meta::{child, children, element_text, parse_xml, replace_html_entities};
+use crate::epub_repair::{declare_utf8, decode_markup};
+use crate::epub_writer::{escape_xml, xhtml_document, Chapter, ConvertedBook, Resource, TocNode};
+
+const STYLESHEET: &str = "styles/fb2.css";
+const CSS: &str = "\
+h1, h2, h3, h4, h5, h6 { text-align: center; }
+p { margin: 0; text-indent: 1.5em; }
+.subtitle, .text-author, .date { text-indent: 0; text-align: center; }
+.text-author { font-style: italic; text-align: right; }
+.empty-line { text-indent: 0; }
+.epigraph, .cite { margin: 1em 2em; }
+.poem { margin: 1em 2em; }
+.stanza { margin-bottom: 1em; }
+.v { text-indent: 0; }
+.image { text-align: center; margin: 1em 0; }
+.image img { max-width: 100%; }
+a.note { vertical-align: super; font-size: 0.75em; }
+";
+
+/// FB2 files wrap base64 at arbitrary widths and often drop the padding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+fn image_extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/png" => "png",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        "image/webp" => "webp",
+        _ => "jpg",
+    }
+}
+
+fn link_target<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    node.attributes().find(|a| a.name() == "href").map(|a| a.value())
+}
+
+fn heading_text(title: Node) -> String {
+    children(title, "p")
+        .map(element_text)
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+struct Converter {
+    /// FB2 binary id -> resource href (relative to the package folder).
+    images: HashMap<String, String>,
+    /// Element id -> href of the chapter that contains it.
+    anchors: HashMap<String, String>,
+    /// Generated ids for titled sections that have none of their own.
+    section_ids: HashMap<NodeId, String>,
+    chapter: String,
+    language: Option<String>,
+}
+
+impl Converter {
+    fn section_id(&self, node: Node) -> Option<String> {
+        node.attribute("id")
+            .map(str::to_string)
+            .or_else(|| self.section_ids.get(&node.id()).cloned())
+    }
+
+    fn id_attr(&self, node: Node) -> String {
+        match self.section_id(node) {
+            Some(id) => format!(" id=\"{}\"", escape_xml(&id)),
+            None => String::new(),
+        }
+    }
+
+    /// Link target in the converted book. Only in-book fragments and http(s)/mailto links are
+    /// kept; anything else (`javascript:`, `file:`, ...) is dropped.
+    fn href(&self, target: &str) -> Option<String> {
+        if let Some(id) = target.strip_prefix('#') {
+            return Some(match self.anchors.get(id) {
+                Some(ch) if *ch != self.chapter => format!("{}#{}", file_name(ch), id),
+                _ => target.to_string(),
+            });
+        }
+        let lower = target.trim_start().to_ascii_lowercase();
+        ["http://", "https://", "mailto:"]
+            .iter()
+            .any(|scheme| lower.starts_with(scheme))
+            .then(|| target.trim_start().to_string())
+    }
+
+    fn image(&self, node: Node, out: &mut String) {
+        let Some(src) = link_target(node)
+            .and_then(|h| h.strip_prefix('#'))
+            .and_then(|id| self.images.get(id))
+        else {
+            return;
+        };
+        let alt = node.attribute("alt").unwrap_or_default();
+        out.push_str(&format!(
+            "<img src=\"../{}\" alt=\"{}\"/>",
+            escape_xml(src),
+            escape_xml(alt)
+        ));
+    }
+
+    fn inline(&self, node: Node, out: &mut String) {
+        for c in node.children() {
+            if let Some(t) = c.text().filter(|_| c.is_text()) {
+                out.push_str(&escape_xml(t));
+                continue;
+            }
+            if !c.is_element() {
+                continue;
+            }
+            let tag = match c.tag_name().name() {
+                "strong" => "strong",
+                "emphasis" => "em",
+                "strikethrough" => "del",
+                "sub" => "sub",
+                "sup" => "sup",
+                "code" => "code",
+                "style" => "span",
+                "image" => {
+                    self.image(c, out);
+                    continue;
+                }
+                "a" => {
+                    let href = match link_target(c).and_then(|h| self.href(h)) {
+                        Some(href) => format!(" href=\"{}\"", escape_xml(&href)),
+                        None => String::new(),
+                    };
+                    let class = if c.attribute("type") == Some("note") {
+                        " class=\"note\""
+                    } else {
+                        ""
+                    };
+                    out.push_str(&format!("<a{href}{class}>"));
+                    self.inline(c, out);
+                    out.push_str("</a>");
+                    continue;
+                }
+                _ => {
+                    self.inline(c, out);
+                    continue;
+                }
+            };
+            out.push_str(&format!("<{tag}>"));
+            self.inline(c, out);
+            out.push_str(&format!("</{tag}>"));
+        }
+    }
+
+    fn paragraph(&self, node: Node, class: Option<&str>, out: &mut String) {
+        let class = class.map(|c| format!(" class=\"{c}\"")).unwrap_or_default();
+        out.push_str(&format!("<p{}{class}>", self.id_attr(node)));
+        self.inline(node, out);
+        out.push_str("</p>\n");
+    }
+
+    fn wrapped(&self, node: Node, tag: &str, class: &str, depth: usize, out: &mut String) {
+        out.push_str(&format!("<{tag} class=\"{class}\"{}>\n", self.id_attr(node)));
+        self.blocks(node, depth, out);
+        out.push_str(&format!("</{tag}>\n"));
+    }
+
+    fn blocks(&self, node: Node, depth: usize, out: &mut String) {
+        for c in node.children().filter(|n| n.is_element()) {
+            self.block(c, depth, out);
+        }
+    }
+
+    fn block(&self, c: Node, depth: usize, out: &mut String) {
+        match c.tag_name().name() {
+            "title" => {
+                let level = depth.clamp(1, 6);
+                out.push_str(&format!("<h{level}{}>", self.id_attr(c)));
+                let mut first = true;
+                for p in children(c, "p") {
+                    if !first {
+                        out.push_str("<br/>");
+                    }
+                    first = false;
+                    self.inline(p, out);
+                }
+                out.push_str(&format!("</h{level}>\n"));
+            }
+            "section" => self.wrapped(c, "div", "section", depth + 1, out),
+            "p" => self.paragraph(c, None, out),
+            "subtitle" => self.paragraph(c, Some("subtitle"), out),
+            "text-author" => self.paragraph(c, Some("text-author"), out),
+            "date" => self.paragraph(c, Some("date"), out),
+            "v" => self.paragraph(c, Some("v"), out),
+            "empty-line" => out.push_str("<p class=\"empty-line\">&#160;</p>\n"),
+            "epigraph" => self.wrapped(c, "blockquote", "epigraph", depth, out),
+            "cite" => self.wrapped(c, "blockquote", "cite", depth, out),
+            "annotation" => self.wrapped(c, "div", "annotation", depth, out),
+            "poem" => self.wrapped(c, "div", "poem", depth, out),
+            "stanza" => self.wrapped(c, "div", "stanza", depth, out),
+            "image" => {
+                out.push_str("<div class=\"image\">");
+                self.image(c, out);
+                out.push_str("</div>\n");
+            }
+            "table" => {
+                out.push_str("<table>\n");
+                for tr in children(c, "tr") {
+                    out.push_str("<tr>");
+                    for cell in tr.children().filter(|n| n.is_element()) {
+                        let tag = if cell.tag_name().name() == "th" { "th" } else { "td" };
+                        let span: String = ["colspan", "rowspan"]
+                            .iter()
+                            .filter_map(|a| cell.attribute(*a).map(|v| format!(" {a}=\"{}\"", escape_xml(v))))
+                            .collect();
+                        out.push_str(&format!("<{tag}{span}>"));
+                        self.inline(cell, out);
+                        out.push_str(&format!("</{tag}>"));
+                    }
+                    out.push_str("</tr>\n");
+                }
+                out.push_str("</table>\n");
+            }
+            _ => self.blocks(c, depth, out),
+        }
+    }
+}
+
+fn file_name(href: &str) -> &str {
+    href.rsplit('/').next().unwrap_or(href)
+}
+
+/// One output file: the nodes it renders, its title and the section to list in the TOC.
+struct Part<'a, 'i> {
+    nodes: Vec<Node<'a, 'i>>,
+    title: String,
+    section: Option<Node<'a, 'i>>,
+}
+
+fn toc_node(section: Node, href: &str, conv: &Converter) -> Option<TocNode> {
+    let children: Vec<TocNode> = children(section, "section")
+        .filter_map(|s| toc_node(s, href, conv))
+        .collect();
+    let label = child(section, "title").map(heading_text).filter(|t| !t.is_empty());
+    match label {
+        Some(label) => Some(TocNode {
+            label,
+            // Top-level sections start their own file; nested ones link to their anchor.
+            href: match conv.section_id(section) {
+                Some(id) if section.parent().is_some_and(|p| p.tag_name().name() == "section") => {
+                    format!("{href}#{id}")
+                }
+                _ => href.to_string(),
+            },
+            children,
+        }),
+        // Untitled sections pass their titled subsections up.
+        None if children.len() == 1 => children.into_iter().next(),
+        None => None,
+    }
+}
+
+/// Converts a FictionBook 2 document. Non-UTF-8 files are decoded by their declared encoding.
+pub(crate) fn convert_fb2(data: &[u8]) -> Result<ConvertedBook, String> {
+    let text = match decode_markup(data) {
+        Some((text, _)) => declare_utf8(&text),
+        None => String::from_utf8_lossy(data).trim_start_matches('\u{feff}').to_string(),
+    };
+    let text = replace_html_entities(&text);
+    let doc = parse_xml(&text)?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "FictionBook" {
+        return Err("not a FictionBook document".to_string());
+    }
+
+    let description = child(root, "description");
+    let title_info = description.and_then(|d| child(d, "title-info"));
+    let info = |name: &'static str| {
+        title_info
+            .and_then(|t| child(t, name))
+            .map(element_text)
+            .filter(|s| !s.is_empty())
+    };
+    let mut book = ConvertedBook {
+        title: info("book-title").unwrap_or_else(|| "Untitled".to_string()),
+        language: info("lang"),
+        description: info("annotation"),
+        identifier: description
+            .and_then(|d| child(d, "document-info"))
+            .and_then(|d| child(d, "id"))
+            .map(element_text)
+            .filter(|s| !s.is_empty()),
+        publisher: description
+            .and_then(|d| child(d, "publish-info"))
+            .and_then(|p| child(p, "publisher"))
+            .map(element_text)
+            .filter(|s| !s.is_empty()),
+        ..Default::default()
+    };
+    for author in title_info.into_iter().flat_map(|t| children(t, "author")) {
+        let part = |name| child(author, name).map(element_text).filter(|s| !s.is_empty());
+        let full: Vec<String> = ["first-name", "middle-name", "last-name"]
+            .into_iter()
+            .filter_map(part)
+            .collect();
+        let name = if full.is_empty() {
+            part("nickname")
+        } else {
+            Some(full.join(" "))
+        };
+        book.authors.extend(name);
+    }
+
+    let mut images = HashMap::new();
+    for bin in children(root, "binary") {
+        let Some(id) = bin.attribute("id") else { continue };
+        let raw: String = bin
+            .text()
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let data = match BASE64.decode(raw.as_bytes()) {
+            Ok(d) => d,
+            Err(e) => {
+                log::warn!("[fb2] binary {id}: {e}");
+                continue;
+            }
+        };
+        let content_type = bin.attribute("content-type").unwrap_or("image/jpeg");
+        let stem: String = id
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let href = format!("images/{}-{}.{}", images.len() + 1, stem, image_extension(content_type));
+        images.insert(id.to_string(), href.clone());
+        book.resources.push(Resource {
+            href,
+            media_type: content_type.to_string(),
+            data,
+        });
+    }
+    book.cover = title_info
+        .and_then(|t| child(t, "coverpage"))
+        .and_then(|c| child(c, "image"))
+        .and_then(link_target)
+        .and_then(|h| images.get(h.trim_start_matches('#')).cloned());
+
+    // Split the main body into one file per top-level section; note bodies get one file each.
+    let mut parts: Vec<Part> = Vec::new();
+    for body in children(root, "body") {
+        let notes = body.attribute("name").is_some();
+        if notes {
+            let title = child(body, "title").map(heading_text).filter(|t| !t.is_empty());
+            parts.push(Part {
+                nodes: body.children().filter(|n| n.is_element()).collect(),
+                title: title.unwrap_or_else(|| "Notes".to_string()),
+                section: None,
+            });
+            continue;
+        }
+        let start = parts.len();
+        let mut front = Vec::new();
+        for c in body.children().filter(|n| n.is_element()) {
+            if c.tag_name().name() == "section" {
+                let title = child(c, "title").map(heading_text).filter(|t| !t.is_empty());
+                parts.push(Part {
+                    nodes: vec![c],
+                    title: title.unwrap_or_else(|| format!("{} {}", book.title, parts.len() + 1)),
+                    section: Some(c),
+                });
+            } else {
+                front.push(c);
+            }
+        }
+        if !front.is_empty() {
+            parts.insert(
+                start,
+                Part {
+                    nodes: front,
+                    title: book.title.clone(),
+                    section: None,
+                },
+            );
+        }
+    }
+    if parts.is_empty() {
+        return Err("FictionBook has no body".to_string());
+    }
+
+    let mut conv = Converter {
+        images,
+        anchors: HashMap::new(),
+        section_ids: HashMap::new(),
+        chapter: String::new(),
+        language: book.language.clone(),
+    };
+    let hrefs: Vec<String> = (0..parts.len()).map(|i| format!("text/ch{:04}.xhtml", i + 1)).collect();
+    let mut generated = 0;
+    for (part, href) in parts.iter().zip(&hrefs) {
+        for node in part.nodes.iter().flat_map(|n| n.descendants()) {
+            if let Some(id) = node.attribute("id") {
+                conv.anchors.entry(id.to_string()).or_insert_with(|| href.clone());
+            } else if node.tag_name().name() == "section" && child(node, "title").is_some() {
+                generated += 1;
+                let id = format!("section-{generated}");
+                conv.anchors.insert(id.clone(), href.clone());
+                conv.section_ids.insert(node.id(), id);
+            }
+        }
+    }
+
+    if let Some(cover) = &book.cover {
+        let body = format!(
+            "<div class=\"image\"><img src=\"../{}\" alt=\"{}\"/></div>",
+            escape_xml(cover),
+            escape_xml(&book.title)
+        );
+        book.chapters.push(Chapter {
+            href: "text/cover.xhtml".to_string(),
+            title: book.title.clone(),
+            xhtml: xhtml_document(&book.title, conv.language.as_deref(), &["../styles/fb2.css"], &body),
+        });
+    }
+    for (part, href) in parts.iter().zip(&hrefs) {
+        conv.chapter = href.clone();
+        let mut body = String::new();
+        for node in &part.nodes {
+            conv.block(*node, 0, &mut body);
+        }
+        book.chapters.push(Chapter {
+            href: href.clone(),
+            title: part.title.clone(),
+            xhtml: xhtml_document(&part.title, conv.language.as_deref(), &["../styles/fb2.css"], &body),
+        });
+        match part.section.and_then(|s| toc_node(s, href, &conv)) {
+            Some(node) => book.toc.push(node),
+            None => book.toc.push(TocNode {
+                label: part.title.clone(),
+                href: href.clone(),
+                children: Vec::new(),
+            }),
+        }
+    }
+    book.resources
+        .push(Resource::new(STYLESHEET.to_string(), CSS.as_bytes().to_vec()));
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r##"<?xml version="1.0" encoding="utf-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+<description><title-info>
+  <author><first-name>Lev</first-name><last-name>Tolstoy</last-name></author>
+  <book-title>Sample &amp; Book</book-title>
+  <coverpage><image l:href="#cover.png"/></coverpage>
+  <lang>ru</lang>
+</title-info></description>
+<body>
+  <title><p>Sample</p></title>
+  <epigraph><p>Words</p><text-author>Someone</text-author></epigraph>
+  <section id="one"><title><p>Part One</p></title>
+    <section><title><p>Chapter 1</p></title><p>Text<a l:href="#n1" type="note">1</a> &mdash; <emphasis>more</emphasis></p></section>
+    <section><title><p>Chapter 2</p></title><poem><stanza><v>Line</v></stanza></poem></section>
+  </section>
+  <section><title><p>Part Two</p></title><p>See <a l:href="#one">part one</a>, <a l:href="https://example.com">site</a> or <a l:href="javascript:alert(1)">this</a>.</p></section>
+</body>
+<body name="notes"><section id="n1"><title><p>1</p></title><p>A note.</p></section></body>
+<binary id="cover.png" content-type="image/png">iVBORw0K
+GgoAAAA</binary>
+</FictionBook>"##;
+
+    #[test]
+    fn converts_sections_notes_and_images() {
+        let book = convert_fb2(SAMPLE.as_bytes()).unwrap();
+        assert_eq!(book.title, "Sample & Book");
+        assert_eq!(book.authors, vec!["Lev Tolstoy".to_string()]);
+        assert_eq!(book.language.as_deref(), Some("ru"));
+        assert_eq!(book.cover.as_deref(), Some("images/1-cover_png.png"));
+        let cover = book
+            .resources
+            .iter()
+            .find(|r| r.href == "images/1-cover_png.png")
+            .unwrap();
+        assert!(cover.data.starts_with(b"\x89PNG"));
+
+        let hrefs: Vec<_> = book.chapters.iter().map(|c| c.href.as_str()).collect();
+        assert_eq!(
+            hrefs,
+            [
+                "text/cover.xhtml",
+                "text/ch0001.xhtml",
+                "text/ch0002.xhtml",
+                "text/ch0003.xhtml",
+                "text/ch0004.xhtml"
+            ]
+        );
+        for c in &book.chapters {
+            parse_xml(&c.xhtml).unwrap();
+        }
+        assert!(book.chapters[1].xhtml.contains("<blockquote class=\"epigraph\">"));
+        let part_one = &book.chapters[2].xhtml;
+        assert!(part_one.contains("<a href=\"ch0004.xhtml#n1\" class=\"note\">1</a> \u{2014} <em>more</em>"));
+        assert!(part_one.contains("<p class=\"v\">Line</p>"));
+        let part_two = &book.chapters[3].xhtml;
+        assert!(part_two.contains("<a href=\"ch0002.xhtml#one\">part one</a>"));
+        assert!(part_two.contains("<a href=\"https://example.com\">site</a>"));
+        assert!(part_two.contains("<a>this</a>"));
+
+        let labels: Vec<_> = book.toc.iter().map(|t| (t.label.as_str(), t.href.as_str())).collect();
+        assert_eq!(
+            labels,
+            [
+                ("Sample & Book", "text/ch0001.xhtml"),
+                ("Part One", "text/ch0002.xhtml"),
+                ("Part Two", "text/ch0003.xhtml"),
+                ("Notes", "text/ch0004.xhtml"),
+            ]
+        );
+        let chapters: Vec<_> = book.toc[1].children.iter().map(|t| t.href.as_str()).collect();
+        assert_eq!(chapters, ["text/ch0002.xhtml#section-1", "text/ch0002.xhtml#section-2"]);
+    }
+}
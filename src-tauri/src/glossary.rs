@@ -0,0 +1,557 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::State;
+
+use crate::database::GlossaryEntry;
+use crate::dictionary::{DictionaryMeaning, DictionaryResult};
+use crate::AppState;
+
+/// Same field names as [`GlossaryEntry`], so a listed entry can be sent back unchanged.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GlossaryEntryInput {
+    /// Existing entry to update; a new entry is created when absent.
+    pub id: Option<String>,
+    pub document_id: Option<String>,
+    pub term: String,
+    pub translation: String,
+    pub note: Option<String>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlossaryImportReport {
+    pub added: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delimiter {
+    Comma,
+    Tab,
+}
+
+impl Delimiter {
+    fn for_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("csv") => Some(Delimiter::Comma),
+            Some("tsv") | Some("tab") => Some(Delimiter::Tab),
+            _ => None,
+        }
+    }
+
+    fn sniff(text: &str) -> Self {
+        let first = text.lines().next().unwrap_or("");
+        if first.contains('\t') {
+            Delimiter::Tab
+        } else {
+            Delimiter::Comma
+        }
+    }
+
+    fn byte(self) -> u8 {
+        match self {
+            Delimiter::Comma => b',',
+            Delimiter::Tab => b'\t',
+        }
+    }
+}
+
+fn term_eq(entry: &GlossaryEntry, word: &str) -> bool {
+    if entry.case_sensitive {
+        entry.term == word
+    } else {
+        entry.term.to_lowercase() == word.to_lowercase()
+    }
+}
+
+/// Keeps the first entry per term, so document entries (listed first) shadow global ones.
+/// Case-sensitive terms only shadow the exact same spelling.
+fn dedup_by_term(entries: Vec<GlossaryEntry>) -> Vec<GlossaryEntry> {
+    let mut seen: Vec<(bool, String)> = vec![];
+    let mut out = vec![];
+    for e in entries {
+        let key = if e.case_sensitive {
+            (true, e.term.clone())
+        } else {
+            (false, e.term.to_lowercase())
+        };
+        if seen.contains(&key) {
+            continue;
+        }
+        seen.push(key);
+        out.push(e);
+    }
+    out
+}
+
+fn applicable_entries(state: &AppState, document_id: Option<&str>) -> Vec<GlossaryEntry> {
+    match state.db.get_applicable_glossary_entries(document_id) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("[glossary] failed to load entries: {}", e);
+            vec![]
+        }
+    }
+}
+
+fn entry_to_result(word: &str, entry: &GlossaryEntry) -> DictionaryResult {
+    let note = entry.note.as_deref().map(|n| n.trim()).filter(|n| !n.is_empty());
+    DictionaryResult {
+        word: word.to_string(),
+        phonetic: None,
+        audio_url: None,
+        translation: Some(entry.translation.clone()),
+        meanings: match note {
+            Some(n) => vec![DictionaryMeaning {
+                part_of_speech: "".to_string(),
+                definitions: vec![n.to_string()],
+                examples: vec![],
+            }],
+            None => vec![],
+        },
+        html: None,
+        pinyin: None,
+    }
+}
+
+fn find_entry<'a>(entries: &'a [GlossaryEntry], word: &str) -> Option<&'a GlossaryEntry> {
+    entries.iter().find(|e| term_eq(e, word))
+}
+
+/// Looks `word` up in the glossaries that apply to `document_id`. A document entry wins over
+/// a global entry for the same term.
+pub(crate) fn glossary_lookup_impl(
+    state: &AppState,
+    word: &str,
+    document_id: Option<&str>,
+) -> Option<DictionaryResult> {
+    let entries = applicable_entries(state, document_id);
+    find_entry(&entries, word).map(|e| entry_to_result(word, e))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `term` occurs in `text` as a whole word. Boundaries are only enforced on sides
+/// where the term itself starts/ends with an ASCII word character, so CJK terms match anywhere.
+fn contains_term(text: &str, term: &str, case_sensitive: bool) -> bool {
+    if term.is_empty() {
+        return false;
+    }
+    let (hay, needle) = if case_sensitive {
+        (text.to_string(), term.to_string())
+    } else {
+        (text.to_lowercase(), term.to_lowercase())
+    };
+    let check_start = needle.chars().next().is_some_and(|c| c.is_ascii_alphanumeric());
+    let check_end = needle.chars().last().is_some_and(|c| c.is_ascii_alphanumeric());
+
+    let mut from = 0;
+    while let Some(pos) = hay[from..].find(&needle) {
+        let start = from + pos;
+        let end = start + needle.len();
+        let before_ok = !check_start || !hay[..start].chars().next_back().is_some_and(is_word_char);
+        let after_ok = !check_end || !hay[end..].chars().next().is_some_and(is_word_char);
+        if before_ok && after_ok {
+            return true;
+        }
+        from = start + needle.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+    }
+    false
+}
+
+/// Glossary entries whose term occurs in `text`, document entries shadowing global ones.
+pub(crate) fn glossary_terms_in_text(entries: Vec<GlossaryEntry>, text: &str) -> Vec<GlossaryEntry> {
+    dedup_by_term(entries)
+        .into_iter()
+        .filter(|e| contains_term(text, &e.term, e.case_sensitive))
+        .collect()
+}
+
+/// Prompt fragment instructing the model to use the preferred translations.
+pub(crate) fn glossary_prompt(entries: &[GlossaryEntry]) -> Option<String> {
+    if entries.is_empty() {
+        return None;
+    }
+    let mut out = String::from("请严格使用以下术语译法：\n");
+    for e in entries {
+        match e.note.as_deref().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+            Some(note) => out.push_str(&format!("- {} → {}（{}）\n", e.term, e.translation, note)),
+            None => out.push_str(&format!("- {} → {}\n", e.term, e.translation)),
+        }
+    }
+    Some(out)
+}
+
+/// Glossary prompt fragment for the terms of `text` that have preferred translations.
+pub(crate) fn glossary_prompt_for_text(state: &AppState, text: &str, document_id: Option<&str>) -> Option<String> {
+    glossary_prompt(&glossary_terms_in_text(applicable_entries(state, document_id), text))
+}
+
+fn parse_delimited(text: &str, delim: Delimiter) -> Vec<Vec<String>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .delimiter(delim.byte())
+        .quoting(delim == Delimiter::Comma)
+        .from_reader(text.trim_start_matches('\u{feff}').as_bytes());
+    rdr.records()
+        .filter_map(|r| r.ok())
+        .map(|r| r.iter().map(|f| f.to_string()).collect::<Vec<_>>())
+        .filter(|r| r.iter().any(|f| !f.trim().is_empty()))
+        .collect()
+}
+
+fn parse_bool(s: &str) -> bool {
+    matches!(s.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "y" | "是")
+}
+
+/// Column positions for term, translation, note and case sensitivity.
+struct Columns {
+    term: usize,
+    translation: usize,
+    note: Option<usize>,
+    case_sensitive: Option<usize>,
+}
+
+fn header_columns(row: &[String]) -> Option<Columns> {
+    let find = |names: &[&str]| {
+        row.iter()
+            .position(|c| names.contains(&c.trim().to_lowercase().replace([' ', '-'], "_").as_str()))
+    };
+    let term = find(&["term", "source", "术语", "原文"])?;
+    let translation = find(&["translation", "preferred_translation", "target", "译名", "译文", "翻译"])?;
+    Some(Columns {
+        term,
+        translation,
+        note: find(&["note", "notes", "comment", "备注"]),
+        case_sensitive: find(&["case_sensitive", "case", "区分大小写"]),
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct GlossaryRow {
+    term: String,
+    translation: String,
+    note: Option<String>,
+    case_sensitive: bool,
+}
+
+/// Parses CSV/TSV rows, returning them with the number of rows skipped. A header row is
+/// recognised by its column names; without one, columns are term, translation, note, case.
+fn parse_glossary_rows(text: &str, delim: Delimiter) -> (Vec<GlossaryRow>, usize) {
+    let rows = parse_delimited(text, delim);
+    let (cols, body) = match rows.first().and_then(|r| header_columns(r)) {
+        Some(c) => (c, &rows[1..]),
+        None => (
+            Columns {
+                term: 0,
+                translation: 1,
+                note: Some(2),
+                case_sensitive: Some(3),
+            },
+            &rows[..],
+        ),
+    };
+
+    let mut out = vec![];
+    let mut skipped = 0;
+    for r in body {
+        let get = |i: Option<usize>| i.and_then(|i| r.get(i)).map(|s| s.trim().to_string());
+        let term = get(Some(cols.term)).unwrap_or_default();
+        let translation = get(Some(cols.translation)).unwrap_or_default();
+        if term.is_empty() || translation.is_empty() {
+            skipped += 1;
+            continue;
+        }
+        let note = get(cols.note).filter(|s| !s.is_empty());
+        let case_sensitive = get(cols.case_sensitive).map(|s| parse_bool(&s)).unwrap_or(false);
+        out.push(GlossaryRow {
+            term,
+            translation,
+            note,
+            case_sensitive,
+        });
+    }
+    (out, skipped)
+}
+
+fn format_glossary(entries: &[GlossaryEntry], delim: Delimiter) -> Result<Vec<u8>, String> {
+    let mut wtr = csv::WriterBuilder::new()
+        .delimiter(delim.byte())
+        .quote_style(match delim {
+            Delimiter::Comma => csv::QuoteStyle::Necessary,
+            Delimiter::Tab => csv::QuoteStyle::Never,
+        })
+        .from_writer(vec![]);
+    // TSV has no quoting, so tabs and line breaks inside fields become spaces.
+    let field = |s: &str| match delim {
+        Delimiter::Comma => s.to_string(),
+        Delimiter::Tab => s.replace(['\t', '\r', '\n'], " "),
+    };
+    wtr.write_record(["term", "translation", "note", "case_sensitive"])
+        .map_err(|e| e.to_string())?;
+    for e in entries {
+        wtr.write_record([
+            field(&e.term),
+            field(&e.translation),
+            field(e.note.as_deref().unwrap_or("")),
+            if e.case_sensitive { "1" } else { "0" }.to_string(),
+        ])
+        .map_err(|e| e.to_string())?;
+    }
+    wtr.into_inner().map_err(|e| e.to_string())
+}
+
+fn normalize_document_id(document_id: Option<String>) -> Option<String> {
+    document_id.filter(|s| !s.trim().is_empty())
+}
+
+#[tauri::command]
+pub fn glossary_list(
+    state: State<AppState>,
+    document_id: Option<String>,
+    include_global: Option<bool>,
+) -> Result<Vec<GlossaryEntry>, String> {
+    let document_id = normalize_document_id(document_id);
+    if include_global.unwrap_or(false) && document_id.is_some() {
+        state
+            .db
+            .get_applicable_glossary_entries(document_id.as_deref())
+            .map_err(|e| e.to_string())
+    } else {
+        state.db.get_glossary_entries(document_id.as_deref()).map_err(|e| e.to_string())
+    }
+}
+
+#[tauri::command]
+pub fn glossary_save(state: State<AppState>, entry: GlossaryEntryInput) -> Result<GlossaryEntry, String> {
+    let term = entry.term.trim().to_string();
+    let translation = entry.translation.trim().to_string();
+    if term.is_empty() || translation.is_empty() {
+        return Err("term and translation are required".to_string());
+    }
+    let document_id = normalize_document_id(entry.document_id);
+    let now = chrono::Utc::now().to_rfc3339();
+
+    let existing = match &entry.id {
+        Some(id) => state
+            .db
+            .get_glossary_entries(document_id.as_deref())
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .find(|e| &e.id == id),
+        None => None,
+    };
+    let saved = GlossaryEntry {
+        id: entry.id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        document_id,
+        term,
+        translation,
+        note: entry.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        case_sensitive: entry.case_sensitive,
+        created_at: existing.map(|e| e.created_at).unwrap_or_else(|| now.clone()),
+        updated_at: now,
+    };
+    state.db.save_glossary_entry(&saved).map_err(|e| e.to_string())?;
+    Ok(saved)
+}
+
+#[tauri::command]
+pub fn glossary_delete(state: State<AppState>, entry_id: String) -> Result<(), String> {
+    state.db.delete_glossary_entry(&entry_id).map_err(|e| e.to_string())
+}
+
+/// Imports a CSV/TSV file into one scope. Rows whose term already exists in that scope
+/// update the existing entry.
+#[tauri::command]
+pub fn glossary_import(
+    state: State<AppState>,
+    path: String,
+    document_id: Option<String>,
+) -> Result<GlossaryImportReport, String> {
+    let path = Path::new(&path);
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&bytes);
+    let delim = Delimiter::for_path(path).unwrap_or_else(|| Delimiter::sniff(&text));
+    let document_id = normalize_document_id(document_id);
+
+    let (rows, skipped) = parse_glossary_rows(&text, delim);
+    let mut existing = state
+        .db
+        .get_glossary_entries(document_id.as_deref())
+        .map_err(|e| e.to_string())?;
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut report = GlossaryImportReport {
+        skipped,
+        ..Default::default()
+    };
+
+    for GlossaryRow {
+        term,
+        translation,
+        note,
+        case_sensitive,
+    } in rows
+    {
+        let pos = existing
+            .iter()
+            .position(|e| e.term.to_lowercase() == term.to_lowercase() && e.case_sensitive == case_sensitive);
+        let entry = match pos {
+            Some(i) => {
+                let e = &mut existing[i];
+                e.translation = translation;
+                e.note = note;
+                e.updated_at = now.clone();
+                report.updated += 1;
+                e.clone()
+            }
+            None => {
+                let e = GlossaryEntry {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    document_id: document_id.clone(),
+                    term,
+                    translation,
+                    note,
+                    case_sensitive,
+                    created_at: now.clone(),
+                    updated_at: now.clone(),
+                };
+                existing.push(e.clone());
+                report.added += 1;
+                e
+            }
+        };
+        state.db.save_glossary_entry(&entry).map_err(|e| e.to_string())?;
+    }
+
+    Ok(report)
+}
+
+/// Exports one scope to CSV or TSV (chosen by the file extension, CSV by default).
+#[tauri::command]
+pub fn glossary_export(
+    state: State<AppState>,
+    path: String,
+    document_id: Option<String>,
+) -> Result<usize, String> {
+    let document_id = normalize_document_id(document_id);
+    let entries = state
+        .db
+        .get_glossary_entries(document_id.as_deref())
+        .map_err(|e| e.to_string())?;
+    let path = Path::new(&path);
+    let delim = Delimiter::for_path(path).unwrap_or(Delimiter::Comma);
+    std::fs::write(path, format_glossary(&entries, delim)?).map_err(|e| e.to_string())?;
+    Ok(entries.len())
+}
+
+/// Glossary entries used in `text`, for building translation prompts.
+#[tauri::command]
+pub fn glossary_match(
+    state: State<AppState>,
+    text: String,
+    document_id: Option<String>,
+) -> Result<Vec<GlossaryEntry>, String> {
+    let document_id = normalize_document_id(document_id);
+    Ok(glossary_terms_in_text(applicable_entries(&state, document_id.as_deref()), &text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(term: &str, translation: &str, doc: Option<&str>, case_sensitive: bool) -> GlossaryEntry {
+        GlossaryEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            document_id: doc.map(|s| s.to_string()),
+            term: term.to_string(),
+            translation: translation.to_string(),
+            note: None,
+            case_sensitive,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_csv_with_quotes_and_header() {
+        let text = "\u{feff}Term,Translation,Note,Case Sensitive\r\nkernel,内核,\"OS sense, not math\",0\r\n\"say \"\"hi\"\"\",打招呼,,\r\nGo,Go 语言,,yes\r\n,缺词,,\r\n";
+        let (rows, skipped) = parse_glossary_rows(text, Delimiter::Comma);
+        assert_eq!(skipped, 1);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].note.as_deref(), Some("OS sense, not math"));
+        assert_eq!(rows[1].term, "say \"hi\"");
+        assert!(rows[2].case_sensitive);
+    }
+
+    #[test]
+    fn test_parse_tsv_without_header() {
+        let (rows, skipped) = parse_glossary_rows("thread\t线程\nactor\t参与者\t模型术语\n", Delimiter::Tab);
+        assert_eq!(skipped, 0);
+        assert_eq!(
+            rows[0],
+            GlossaryRow {
+                term: "thread".to_string(),
+                translation: "线程".to_string(),
+                note: None,
+                case_sensitive: false,
+            }
+        );
+        assert_eq!(rows[1].note.as_deref(), Some("模型术语"));
+    }
+
+    #[test]
+    fn test_export_roundtrip() {
+        let mut e = entry("a, b", "甲\"乙\"", None, true);
+        e.note = Some("line1\nline2".to_string());
+        for delim in [Delimiter::Comma, Delimiter::Tab] {
+            let bytes = format_glossary(std::slice::from_ref(&e), delim).unwrap();
+            let (rows, _) = parse_glossary_rows(&String::from_utf8(bytes).unwrap(), delim);
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].term, "a, b");
+            assert_eq!(rows[0].translation, "甲\"乙\"");
+            assert!(rows[0].case_sensitive);
+        }
+    }
+
+    #[test]
+    fn test_contains_term_boundaries() {
+        assert!(contains_term("The Kernel panics.", "kernel", false));
+        assert!(!contains_term("The Kernel panics.", "kernel", true));
+        assert!(!contains_term("kernels", "kernel", false));
+        assert!(contains_term("C++ rocks", "C++", true));
+        assert!(contains_term("这是内核代码", "内核", false));
+    }
+
+    #[test]
+    fn test_document_entries_shadow_global() {
+        let entries = vec![
+            entry("actor", "演员", Some("doc"), false),
+            entry("Actor", "参与者", None, false),
+            entry("thread", "线程", None, false),
+        ];
+        let found = glossary_terms_in_text(entries.clone(), "An actor spawns a thread.");
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].translation, "演员");
+
+        assert_eq!(find_entry(&entries, "ACTOR").unwrap().translation, "演员");
+        assert!(find_entry(&[entry("Go", "Go 语言", None, true)], "go").is_none());
+
+        let prompt = glossary_prompt(&found).unwrap();
+        assert!(prompt.contains("actor → 演员"));
+        assert!(glossary_prompt(&[]).is_none());
+
+        let cased = vec![entry("US", "美国", Some("doc"), true), entry("us", "我们", None, true)];
+        assert_eq!(glossary_terms_in_text(cased, "The US told us.").len(), 2);
+
+        let input: GlossaryEntryInput =
+            serde_json::from_value(serde_json::to_value(entry("Go", "Go 语言", Some("doc"), true)).unwrap()).unwrap();
+        assert_eq!(input.document_id.as_deref(), Some("doc"));
+        assert!(input.case_sensitive);
+    }
+}
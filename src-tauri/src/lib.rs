@@ -2,21 +2,74 @@ mod ollama;
 mod ollama_proxy;
 mod database;
 mod dictionary;
+mod dictionary_registry;
+mod ecdict_reverse;
+mod mdict;
+mod meanings;
+mod mobi;
 mod builtin_llm;
+mod concordance;
+mod covers;
+mod ebook_import;
 mod epub;
+mod epub_crypto;
+mod epub_meta;
+mod epub_protocol;
+mod epub_repair;
+mod epub_text;
+mod epub_writer;
+mod fb2;
+mod glossary;
+mod markdown_import;
+mod pdf_text;
+mod lookup_cache;
+mod pinyin;
+mod pregloss;
+mod zh_convert;
+mod text_chapters;
+mod text_encoding;
+mod tts;
+mod wiktionary;
+mod zh_segment;
 
 use ollama::OllamaClient;
+use covers::{library_save_thumbnail, library_thumbnail};
+use concordance::{concordance_examples, concordance_index_document, concordance_is_indexed, concordance_remove_document};
+use pinyin::{pinyin_convert, pinyin_syllables};
+use lookup_cache::dictionary_lookup_stats;
+use pregloss::dictionary_pregloss;
+use ecdict_reverse::{ecdict_reverse_index_build, zh_reverse_lookup};
+use text_chapters::text_chapters;
+use text_encoding::{import_text, original_path, text_encoding_report, text_read, text_reimport};
+use tts::{tts_clear_cache, tts_get_config, tts_pick_executable, tts_pronounce, tts_read, tts_save_config};
+use wiktionary::dictionary_import_wiktionary;
+use zh_convert::cedict_convert_script;
+use zh_segment::{cedict_segment, cedict_segment_at};
 use database::{Database, NoteData};
 use dictionary::{
     cedict_install,
     cedict_lookup,
+    cedict_rebuild,
     cedict_status,
+    cedict_uninstall,
+    cedict_update_from_file,
     dictionary_install_ecdict,
     dictionary_lookup,
+    dictionary_rebuild_ecdict,
     dictionary_status,
+    dictionary_uninstall_ecdict,
     CedictManager,
     DictionaryManager,
 };
+use dictionary_registry::{
+    dictionary_lookup_merged,
+    dictionary_registry_install,
+    dictionary_registry_list,
+    dictionary_registry_uninstall,
+    dictionary_registry_update,
+    dictionary_resource,
+    DictionaryRegistry,
+};
 use builtin_llm::{
     builtin_llm_auto_start,
     builtin_llm_benchmark,
@@ -37,7 +90,24 @@ use builtin_llm::{
     builtin_llm_stop,
     BuiltinLlmManager,
 };
-use epub::epub_extract;
+use ebook_import::{convert_ebook, convert_to_epub, is_convertible};
+use epub::{
+    epub_cache_clear, epub_cache_set_limit, epub_cache_status, epub_extract, epub_release_extract, epub_repair_report,
+};
+use epub_meta::epub_metadata;
+use epub_protocol::{epub_close_archive, epub_open_archive, EpubArchives};
+use epub_text::{epub_book_text, epub_chapter_text};
+use markdown_import::{convert_to_markdown, is_markdown_convertible, markdown_convert, write_markdown_doc};
+use pdf_text::{pdf_close_document, pdf_document_text, pdf_metadata, pdf_page_text, PdfDocuments};
+use glossary::{
+    glossary_delete,
+    glossary_export,
+    glossary_import,
+    glossary_list,
+    glossary_match,
+    glossary_prompt_for_text,
+    glossary_save,
+};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::RwLock;
@@ -127,8 +197,21 @@ fn import_document_copy_impl(dest_dir: &Path, source_path: &str) -> Result<Strin
         .ok_or_else(|| "invalid source path".to_string())?;
 
     let file_name = sanitize_file_name(file_name);
+    if is_convertible(Path::new(source_path)) {
+        let stem = Path::new(&file_name).file_stem().and_then(|s| s.to_str()).unwrap_or("book");
+        let dest_path = unique_dest_path(dest_dir, &format!("{}.epub", stem));
+        convert_to_epub(Path::new(source_path), &dest_path)?;
+        return Ok(dest_path.to_string_lossy().to_string());
+    }
     let dest_path = unique_dest_path(dest_dir, &file_name);
-    std::fs::copy(source_path, &dest_path).map_err(|e| e.to_string())?;
+    let is_text = Path::new(&file_name)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("txt"));
+    if is_text {
+        import_text(Path::new(source_path), &dest_path)?;
+    } else {
+        std::fs::copy(source_path, &dest_path).map_err(|e| e.to_string())?;
+    }
 
     Ok(dest_path.to_string_lossy().to_string())
 }
@@ -165,10 +248,15 @@ fn import_markdown_copy_impl(dest_dir: &Path, source_path: &str) -> Result<Strin
     }
     std::fs::create_dir_all(&dest_root).map_err(|e| e.to_string())?;
 
-    let dest_md_path = dest_root.join(&file_name);
-    std::fs::copy(&src_path, &dest_md_path).map_err(|e| e.to_string())?;
-
-    let md = std::fs::read_to_string(&src_path).map_err(|e| e.to_string())?;
+    let (dest_md_path, md) = if is_markdown_convertible(&src_path) {
+        let doc = convert_to_markdown(&src_path)?;
+        let path = write_markdown_doc(&doc, &dest_root, &folder_stem)?;
+        (path, doc.markdown)
+    } else {
+        let path = dest_root.join(&file_name);
+        std::fs::copy(&src_path, &path).map_err(|e| e.to_string())?;
+        (path, std::fs::read_to_string(&src_path).map_err(|e| e.to_string())?)
+    };
 
     let mut idx = 0usize;
     while let Some(pos) = md[idx..].find("](") {
@@ -221,10 +309,11 @@ fn import_markdown_copy_impl(dest_dir: &Path, source_path: &str) -> Result<Strin
                 continue;
             }
 
-            let candidate = if Path::new(dest_first).is_absolute() {
-                PathBuf::from(dest_first)
+            let dest_first = epub_meta::percent_decode(dest_first);
+            let candidate = if Path::new(&dest_first).is_absolute() {
+                PathBuf::from(&dest_first)
             } else {
-                src_dir.join(dest_first)
+                src_dir.join(&dest_first)
             };
 
             let abs = match std::fs::canonicalize(&candidate) {
@@ -242,6 +331,9 @@ fn import_markdown_copy_impl(dest_dir: &Path, source_path: &str) -> Result<Strin
             };
 
             let dest_abs_path = dest_root.join(rel);
+            if dest_abs_path.exists() {
+                continue;
+            }
             if let Some(parent) = dest_abs_path.parent() {
                 let _ = std::fs::create_dir_all(parent);
             }
@@ -315,18 +407,29 @@ struct AppState {
     dictionaries_dir: RwLock<PathBuf>,
     dictionary: DictionaryManager,
     cedict: CedictManager,
+    dictionary_registry: DictionaryRegistry,
     llm_dir: PathBuf,           // fixed: app_data_dir/llm — runtime only
     models_dir: RwLock<PathBuf>, // user-configurable: model storage
     builtin_llm: BuiltinLlmManager,
+    epub_archives: EpubArchives,
+    pdf_documents: Arc<PdfDocuments>,
+    /// Cache keys of books a reader opened with `epub_extract`; pruning leaves them alone.
+    open_extracts: Arc<Mutex<std::collections::HashSet<String>>>,
     download_cancel: std::sync::atomic::AtomicBool,
     log_lock: Mutex<()>,
 }
 
 #[tauri::command]
-async fn ai_translate(text: String, mode: String) -> Result<String, String> {
+async fn ai_translate(
+    state: State<'_, AppState>,
+    text: String,
+    mode: String,
+    document_id: Option<String>,
+) -> Result<String, String> {
     let client = OllamaClient::new();
-    
-    let prompt = match mode.as_str() {
+    let glossary = glossary_prompt_for_text(&state, &text, document_id.as_deref());
+
+    let mut prompt = match mode.as_str() {
         "literal" => format!(
             "请将以下英文文本直译为中文，保持原文的句式结构，尽量逐字逐句翻译：\n\n{}\n\n直译结果：",
             text
@@ -344,6 +447,9 @@ async fn ai_translate(text: String, mode: String) -> Result<String, String> {
             text
         ),
     };
+    if let Some(g) = glossary {
+        prompt = format!("{}\n{}", g, prompt);
+    }
 
     client.generate(&prompt).await
 }
@@ -401,6 +507,14 @@ fn reassign_notes_document(
     old_document_id: String,
     new_document_id: String,
 ) -> Result<usize, String> {
+    state
+        .db
+        .reassign_glossary_document(&old_document_id, &new_document_id)
+        .map_err(|e| e.to_string())?;
+    state
+        .db
+        .reassign_concordance_document(&old_document_id, &new_document_id)
+        .map_err(|e| e.to_string())?;
     state
         .db
         .reassign_notes_document(&old_document_id, &new_document_id)
@@ -567,6 +681,7 @@ fn save_app_config(state: State<AppState>, config: AppConfigInput) -> Result<(),
         obj.insert("dictionariesDir".to_string(), serde_json::Value::String(d.clone()));
         state.dictionary.reset();
         state.cedict.reset();
+        state.dictionary_registry.reset();
         *state.dictionaries_dir.write().unwrap() = p;
     }
 
@@ -630,6 +745,7 @@ fn reset_app_data(state: State<AppState>) -> Result<(), String> {
     state.builtin_llm.stop();
     state.dictionary.reset();
     state.cedict.reset();
+    state.dictionary_registry.reset();
 
     state.db.clear_all().map_err(|e| e.to_string())?;
 
@@ -718,13 +834,13 @@ fn import_folder_copies(
 
         let p = entry.path();
         let ext = p.extension().and_then(|s| s.to_str()).unwrap_or("").to_ascii_lowercase();
-        let supported = matches!(ext.as_str(), "pdf" | "epub" | "txt" | "md");
+        let supported = matches!(ext.as_str(), "pdf" | "epub" | "txt" | "md") || is_convertible(p) || is_markdown_convertible(p);
         if !supported {
             continue;
         }
 
         let src = p.to_string_lossy().to_string();
-        let result = if ext == "md" {
+        let result = if ext == "md" || is_markdown_convertible(p) {
             import_markdown_copy_impl(&base, &src)
         } else {
             import_document_copy_impl(&base, &src)
@@ -749,7 +865,7 @@ fn scan_folder_documents(folder_path: String) -> Result<Vec<String>, String> {
         let entry = match entry { Ok(e) => e, Err(_) => continue };
         if !entry.file_type().is_file() { continue; }
         let ext = entry.path().extension().and_then(|s| s.to_str()).unwrap_or("").to_ascii_lowercase();
-        if matches!(ext.as_str(), "pdf" | "epub" | "txt" | "md") {
+        if matches!(ext.as_str(), "pdf" | "epub" | "txt" | "md") || is_convertible(entry.path()) || is_markdown_convertible(entry.path()) {
             out.push(entry.path().to_string_lossy().to_string());
         }
     }
@@ -761,7 +877,12 @@ fn delete_document_copy(
     state: State<AppState>,
     path: String,
     documents_dir: Option<String>,
+    document_id: Option<String>,
 ) -> Result<(), String> {
+    if let Some(id) = document_id.filter(|id| !id.is_empty()) {
+        state.db.delete_document_data(&id).map_err(|e| e.to_string())?;
+    }
+
     let mut roots: Vec<PathBuf> = vec![];
     let default_root = std::fs::canonicalize(&*state.documents_dir.read().unwrap()).map_err(|e| e.to_string())?;
     roots.push(default_root);
@@ -814,6 +935,7 @@ fn delete_document_copy(
         std::fs::remove_dir_all(&target).map_err(|e| e.to_string())?;
     } else {
         std::fs::remove_file(&target).map_err(|e| e.to_string())?;
+        let _ = std::fs::remove_file(original_path(&target));
     }
 
     Ok(())
@@ -829,7 +951,7 @@ fn migrate_documents(from_dir: String, to_dir: String) -> Result<u32, String> {
     std::fs::create_dir_all(&dst).map_err(|e| e.to_string())?;
 
     let doc_extensions: std::collections::HashSet<&str> = [
-        "pdf", "epub", "txt", "md", "markdown",
+        "pdf", "epub", "txt", "md", "markdown", "fb2", "mobi", "azw", "azw3",
     ].iter().copied().collect();
 
     fn dir_has_document(dir: &Path, exts: &std::collections::HashSet<&str>) -> bool {
@@ -952,6 +1074,9 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_http::init())
+        .register_asynchronous_uri_scheme_protocol(epub_protocol::SCHEME, |ctx, request, responder| {
+            epub_protocol::handle_request(ctx.app_handle(), request, responder)
+        })
         .setup(|app| {
             let app_data_dir = app.path().app_data_dir().expect("failed to get app data dir");
             let db = Database::new(app_data_dir.clone()).expect("failed to init database");
@@ -1013,9 +1138,13 @@ pub fn run() {
                 dictionaries_dir: RwLock::new(dictionaries_dir),
                 dictionary: DictionaryManager::new(),
                 cedict: CedictManager::new(),
+                dictionary_registry: DictionaryRegistry::new(),
                 llm_dir,
                 models_dir: RwLock::new(models_dir),
                 builtin_llm: BuiltinLlmManager::new(),
+                epub_archives: EpubArchives::new(),
+                pdf_documents: Arc::new(PdfDocuments::new()),
+                open_extracts: Arc::new(Mutex::new(std::collections::HashSet::new())),
                 download_cancel: std::sync::atomic::AtomicBool::new(false),
                 log_lock: Mutex::new(()),
             });
@@ -1046,10 +1175,47 @@ pub fn run() {
             delete_document_copy,
             cedict_status,
             cedict_install,
+            cedict_uninstall,
+            cedict_rebuild,
+            cedict_update_from_file,
             cedict_lookup,
+            cedict_segment,
+            cedict_segment_at,
+            cedict_convert_script,
+            ecdict_reverse_index_build,
+            zh_reverse_lookup,
+            dictionary_lookup_stats,
+            pinyin_convert,
+            pinyin_syllables,
+            tts_get_config,
+            tts_save_config,
+            tts_pick_executable,
+            tts_pronounce,
+            tts_read,
+            tts_clear_cache,
+            concordance_index_document,
+            concordance_is_indexed,
+            concordance_remove_document,
+            concordance_examples,
             dictionary_status,
             dictionary_install_ecdict,
+            dictionary_uninstall_ecdict,
+            dictionary_rebuild_ecdict,
             dictionary_lookup,
+            dictionary_lookup_merged,
+            dictionary_registry_list,
+            dictionary_import_wiktionary,
+            dictionary_registry_install,
+            dictionary_registry_uninstall,
+            dictionary_registry_update,
+            dictionary_resource,
+            dictionary_pregloss,
+            glossary_list,
+            glossary_save,
+            glossary_delete,
+            glossary_import,
+            glossary_export,
+            glossary_match,
             builtin_llm_status,
             builtin_llm_install,
             builtin_llm_ensure_running,
@@ -1068,6 +1234,28 @@ pub fn run() {
             builtin_llm_auto_start,
             builtin_llm_benchmark,
             epub_extract,
+            epub_cache_status,
+            epub_cache_set_limit,
+            epub_cache_clear,
+            epub_release_extract,
+            epub_repair_report,
+            convert_ebook,
+            markdown_convert,
+            epub_metadata,
+            epub_open_archive,
+            epub_close_archive,
+            epub_chapter_text,
+            epub_book_text,
+            pdf_metadata,
+            pdf_page_text,
+            pdf_document_text,
+            pdf_close_document,
+            text_encoding_report,
+            text_reimport,
+            text_read,
+            text_chapters,
+            library_thumbnail,
+            library_save_thumbnail,
             import_samples,
             migrate_documents,
             reset_app_data,
@@ -0,0 +1,259 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::State;
+
+use crate::dictionary::DictionaryResult;
+use crate::AppState;
+
+/// Entries kept per built-in dictionary before the least recently used word is evicted.
+pub(crate) const DEFAULT_CAPACITY: usize = 2048;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct LookupStats {
+    pub lookups: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Hits on a cached "not found" result.
+    pub negative_hits: u64,
+    pub errors: u64,
+    pub entries: usize,
+    pub capacity: usize,
+    pub hit_rate: f64,
+    pub avg_latency_us: u64,
+    pub max_latency_us: u64,
+    /// Average latency of lookups that had to go to the dictionary.
+    pub avg_miss_latency_us: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    lookups: u64,
+    hits: u64,
+    misses: u64,
+    negative_hits: u64,
+    errors: u64,
+    total_latency_us: u64,
+    max_latency_us: u64,
+    miss_latency_us: u64,
+}
+
+struct Inner {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, (u64, Option<DictionaryResult>)>,
+    /// Last-use tick -> key, oldest first.
+    order: BTreeMap<u64, String>,
+    counters: Counters,
+}
+
+impl Inner {
+    fn touch(&mut self, key: &str) -> Option<Option<DictionaryResult>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (used, value) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = tick;
+        self.order.insert(tick, key.to_string());
+        Some(value.clone())
+    }
+
+    fn insert(&mut self, key: String, value: Option<DictionaryResult>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((used, _)) = self.entries.remove(&key) {
+            self.order.remove(&used);
+        }
+        while self.entries.len() >= self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            self.entries.remove(&oldest);
+        }
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (self.tick, value));
+    }
+
+    fn record(&mut self, started: Instant) {
+        let us = started.elapsed().as_micros() as u64;
+        self.counters.lookups += 1;
+        self.counters.total_latency_us += us;
+        self.counters.max_latency_us = self.counters.max_latency_us.max(us);
+    }
+}
+
+/// Bounded LRU of lookup results, keyed by the exact query. Misses are cached
+/// too, so repeatedly hovering an unknown word does not hit the dictionary again.
+pub struct LookupCache {
+    inner: Mutex<Inner>,
+}
+
+impl LookupCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                capacity,
+                tick: 0,
+                entries: HashMap::new(),
+                order: BTreeMap::new(),
+                counters: Counters::default(),
+            }),
+        }
+    }
+
+    /// Returns the cached result for `key`, or runs `lookup` and caches what it
+    /// found. Errors are passed through uncached. The lock is not held while
+    /// `lookup` runs.
+    pub fn get_or_lookup(
+        &self,
+        key: &str,
+        lookup: impl FnOnce() -> Result<Option<DictionaryResult>, String>,
+    ) -> Result<Option<DictionaryResult>, String> {
+        let started = Instant::now();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(value) = inner.touch(key) {
+                inner.counters.hits += 1;
+                if value.is_none() {
+                    inner.counters.negative_hits += 1;
+                }
+                inner.record(started);
+                return Ok(value);
+            }
+        }
+
+        let result = lookup();
+        let mut inner = self.inner.lock().unwrap();
+        inner.record(started);
+        match &result {
+            Ok(value) => {
+                inner.counters.misses += 1;
+                inner.counters.miss_latency_us += started.elapsed().as_micros() as u64;
+                inner.insert(key.to_string(), value.clone());
+            }
+            Err(_) => inner.counters.errors += 1,
+        }
+        result
+    }
+
+    /// Drops every cached result, keeping the counters.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.order.clear();
+    }
+
+    pub fn stats(&self) -> LookupStats {
+        let inner = self.inner.lock().unwrap();
+        let c = &inner.counters;
+        LookupStats {
+            lookups: c.lookups,
+            hits: c.hits,
+            misses: c.misses,
+            negative_hits: c.negative_hits,
+            errors: c.errors,
+            entries: inner.entries.len(),
+            capacity: inner.capacity,
+            hit_rate: if c.lookups == 0 { 0.0 } else { c.hits as f64 / c.lookups as f64 },
+            avg_latency_us: c.total_latency_us.checked_div(c.lookups).unwrap_or(0),
+            max_latency_us: c.max_latency_us,
+            avg_miss_latency_us: c.miss_latency_us.checked_div(c.misses).unwrap_or(0),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.inner.lock().unwrap().counters = Counters::default();
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DictionaryLookupStats {
+    pub ecdict: LookupStats,
+    pub cedict: LookupStats,
+}
+
+/// Lookup cache counters for the built-in dictionaries; `reset` zeroes them afterwards.
+#[tauri::command]
+pub fn dictionary_lookup_stats(state: State<AppState>, reset: Option<bool>) -> DictionaryLookupStats {
+    let stats = DictionaryLookupStats {
+        ecdict: state.dictionary.cache.stats(),
+        cedict: state.cedict.cache.stats(),
+    };
+    if reset.unwrap_or(false) {
+        state.dictionary.cache.reset_stats();
+        state.cedict.cache.reset_stats();
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(word: &str) -> Option<DictionaryResult> {
+        Some(DictionaryResult {
+            word: word.to_string(),
+            phonetic: None,
+            audio_url: None,
+            translation: Some(format!("{word}!")),
+            meanings: vec![],
+            html: None,
+            pinyin: None,
+        })
+    }
+
+    #[test]
+    fn caches_hits_and_misses() {
+        let cache = LookupCache::new(4);
+        let mut calls = 0;
+        for _ in 0..3 {
+            let hit = cache
+                .get_or_lookup("apple", || {
+                    calls += 1;
+                    Ok(result("apple"))
+                })
+                .unwrap();
+            assert_eq!(hit.unwrap().word, "apple");
+        }
+        for _ in 0..2 {
+            assert!(cache.get_or_lookup("zzz", || { calls += 1; Ok(None) }).unwrap().is_none());
+        }
+        assert_eq!(calls, 2);
+
+        let stats = cache.stats();
+        assert_eq!((stats.lookups, stats.hits, stats.misses, stats.negative_hits), (5, 3, 2, 1));
+        assert_eq!(stats.entries, 2);
+        assert!((stats.hit_rate - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = LookupCache::new(2);
+        cache.get_or_lookup("a", || Ok(result("a"))).unwrap();
+        cache.get_or_lookup("b", || Ok(result("b"))).unwrap();
+        // Touch "a" so "b" becomes the oldest.
+        cache.get_or_lookup("a", || panic!("should be cached")).unwrap();
+        cache.get_or_lookup("c", || Ok(result("c"))).unwrap();
+
+        assert_eq!(cache.stats().entries, 2);
+        cache.get_or_lookup("a", || panic!("should be cached")).unwrap();
+        let mut refetched = false;
+        cache.get_or_lookup("b", || { refetched = true; Ok(result("b")) }).unwrap();
+        assert!(refetched);
+    }
+
+    #[test]
+    fn errors_are_not_cached_and_clear_keeps_counters() {
+        let cache = LookupCache::new(2);
+        assert!(cache.get_or_lookup("a", || Err("locked".to_string())).is_err());
+        assert!(cache.get_or_lookup("a", || Ok(result("a"))).unwrap().is_some());
+        assert_eq!(cache.stats().errors, 1);
+
+        cache.clear();
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.lookups), (0, 2));
+        cache.reset_stats();
+        assert_eq!(cache.stats().lookups, 0);
+    }
+}
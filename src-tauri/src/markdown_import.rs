@@ -0,0 +1,1660 @@
+use base64::Engine;
+use encoding_rs::Encoding;
+use roxmltree::Node;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use tauri::State;
+use zip::ZipArchive;
+
+use crate::covers::document_fingerprint;
+use crate::ebook_import::converted_dir;
+use crate::epub_meta::parse_xml;
+use crate::epub_repair::decode_markup;
+use crate::AppState;
+
+/// Formats imported by converting them to Markdown.
+const MARKDOWN_CONVERTIBLE_EXTENSIONS: [&str; 4] = ["docx", "html", "htm", "rtf"];
+
+/// Stands in for a hard line break until whitespace has been collapsed.
+const LINE_BREAK: char = '\u{2028}';
+
+/// A document converted to Markdown, with the images it references by paths relative to the Markdown file.
+#[derive(Debug, Default)]
+pub(crate) struct MarkdownDoc {
+    pub markdown: String,
+    pub images: Vec<(String, Vec<u8>)>,
+}
+
+pub(crate) fn is_markdown_convertible(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| MARKDOWN_CONVERTIBLE_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Converts a DOCX, HTML or RTF file to Markdown.
+pub(crate) fn convert_to_markdown(src: &Path) -> Result<MarkdownDoc, String> {
+    let data = std::fs::read(src).map_err(|e| e.to_string())?;
+    let ext = src
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "docx" => docx_to_markdown(&data),
+        "html" | "htm" => Ok(html_to_markdown(&data)),
+        "rtf" => rtf_to_markdown(&data),
+        _ => Err(format!("unsupported format: {}", ext)),
+    }
+}
+
+/// Markdown copy of a document opened in place. Conversions are cached by the source fingerprint.
+#[tauri::command]
+pub async fn markdown_convert(state: State<'_, AppState>, path: String) -> Result<String, String> {
+    let app_data_dir = state.app_data_dir.clone();
+    tokio::task::spawn_blocking(move || {
+        let dir = converted_dir(&app_data_dir).join(document_fingerprint(Path::new(&path))?);
+        let cached = walkdir::WalkDir::new(&dir)
+            .max_depth(2)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .find(|e| e.file_type().is_file() && e.path().extension().is_some_and(|x| x == "md"));
+        match cached {
+            Some(entry) => Ok(entry.path().to_string_lossy().to_string()),
+            None => crate::import_markdown_copy_impl(&dir, &path),
+        }
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+// ---------------------------------------------------------------------------------------------
+// Markdown output shared by the converters
+
+#[derive(Default)]
+struct Images {
+    files: Vec<(String, Vec<u8>)>,
+    by_key: HashMap<String, String>,
+}
+
+impl Images {
+    /// Stores an image once per `key` and returns its relative path.
+    fn add(&mut self, key: &str, ext: &str, data: Vec<u8>) -> String {
+        if let Some(path) = self.by_key.get(key) {
+            return path.clone();
+        }
+        let path = format!("images/image{}.{}", self.files.len() + 1, ext);
+        self.files.push((path.clone(), data));
+        self.by_key.insert(key.to_string(), path.clone());
+        path
+    }
+}
+
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if data.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if data.starts_with(b"GIF8") {
+        Some("gif")
+    } else if data.starts_with(b"BM") {
+        Some("bmp")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Blocks of the output; consecutive list items stay in one block so the list is tight.
+#[derive(Default)]
+struct Blocks {
+    out: Vec<String>,
+    in_list: bool,
+}
+
+impl Blocks {
+    fn push(&mut self, block: String) {
+        if !block.trim().is_empty() {
+            self.out.push(block);
+        }
+        self.in_list = false;
+    }
+
+    fn push_item(&mut self, item: String) {
+        match self.out.last_mut() {
+            Some(last) if self.in_list => {
+                last.push('\n');
+                last.push_str(&item);
+            }
+            _ => self.out.push(item),
+        }
+        self.in_list = true;
+    }
+
+    fn finish(self) -> String {
+        let mut md = self.out.join("\n\n");
+        md.push('\n');
+        md
+    }
+}
+
+fn escape_md(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Splits `content` into leading padding, the text itself and trailing padding.
+fn split_padding(content: &str) -> (&str, &str, &str) {
+    let trimmed = content.trim_matches(|c: char| c.is_ascii_whitespace() || c == LINE_BREAK);
+    let start = content.find(trimmed).unwrap_or(0);
+    (&content[..start], trimmed, &content[start + trimmed.len()..])
+}
+
+/// Wraps `content` in an emphasis marker, keeping surrounding whitespace outside it.
+fn wrap(marker: &str, content: &str) -> String {
+    let (lead, trimmed, trail) = split_padding(content);
+    if trimmed.is_empty() {
+        return content.to_string();
+    }
+    format!("{lead}{marker}{trimmed}{marker}{trail}")
+}
+
+fn link_dest(dest: &str) -> String {
+    if dest.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", dest.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        dest.to_string()
+    }
+}
+
+/// Collapses whitespace runs and turns line-break markers into `br`.
+fn normalize(text: &str, br: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            space = true;
+            continue;
+        }
+        if space && !out.is_empty() && c != LINE_BREAK && !out.ends_with(LINE_BREAK) {
+            out.push(' ');
+        }
+        space = false;
+        out.push(c);
+    }
+    out.trim_matches(LINE_BREAK).replace(LINE_BREAK, br)
+}
+
+/// A paragraph's text with leading characters that would start another block escaped.
+fn paragraph(text: &str) -> String {
+    let text = normalize(text, "  \n");
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    let rest = &text[digits..];
+    if digits > 0 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        return format!("{}\\{}", &text[..digits], rest);
+    }
+    match text.chars().next() {
+        Some('#' | '>' | '-' | '+' | '=' | '|') => format!("\\{}", text),
+        _ => text,
+    }
+}
+
+fn heading(level: usize, text: &str) -> String {
+    format!("{} {}", "#".repeat(level.clamp(1, 6)), normalize(text, " "))
+}
+
+/// Indents every line after the first so that it continues a list item.
+fn list_item(marker: &str, depth: usize, text: &str) -> String {
+    let indent = " ".repeat(depth * 3);
+    let cont = " ".repeat(depth * 3 + marker.len());
+    let mut out = format!("{}{}", indent, marker);
+    for (i, line) in text.lines().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            if !line.is_empty() {
+                out.push_str(&cont);
+            }
+        }
+        out.push_str(line);
+    }
+    out
+}
+
+fn table_cell(text: &str) -> String {
+    normalize(text, "<br>").replace('|', "\\|")
+}
+
+/// GitHub-style table; the first row becomes the header.
+fn gfm_table(rows: &[Vec<String>]) -> Option<String> {
+    let width = rows.iter().map(Vec::len).max().filter(|w| *w > 0)?;
+    let line = |cells: &[String]| {
+        let mut s = String::from("|");
+        for i in 0..width {
+            s.push(' ');
+            s.push_str(cells.get(i).map(String::as_str).unwrap_or(""));
+            s.push_str(" |");
+        }
+        s
+    };
+    let mut out = line(&rows[0]);
+    out.push_str("\n|");
+    out.push_str(&" --- |".repeat(width));
+    for row in &rows[1..] {
+        out.push('\n');
+        out.push_str(&line(row));
+    }
+    Some(out)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Fmt {
+    bold: bool,
+    italic: bool,
+    strike: bool,
+    link: Option<String>,
+}
+
+/// Renders runs of formatted Markdown text, merging neighbours that share a format.
+fn render_segments(segs: &[(String, Fmt)]) -> String {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < segs.len() {
+        let fmt = &segs[i].1;
+        let mut text = String::new();
+        while i < segs.len() && segs[i].1 == *fmt {
+            text.push_str(&segs[i].0);
+            i += 1;
+        }
+        if fmt.strike {
+            text = wrap("~~", &text);
+        }
+        if fmt.italic {
+            text = wrap("*", &text);
+        }
+        if fmt.bold {
+            text = wrap("**", &text);
+        }
+        match &fmt.link {
+            Some(href) => match split_padding(&text) {
+                (_, "", _) => out.push_str(&text),
+                (lead, label, trail) => out.push_str(&format!("{lead}[{label}]({}){trail}", link_dest(href))),
+            },
+            None => out.push_str(&text),
+        }
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------------------------
+// DOCX
+
+fn xml_attr<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attributes().find(|a| a.name() == name).map(|a| a.value())
+}
+
+fn xml_child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+/// `<w:b/>` and `<w:b w:val="1"/>` are on; `w:val="0"`/`"false"` turns the property off.
+fn toggle(props: Option<Node>, name: &str) -> bool {
+    props
+        .and_then(|p| xml_child(p, name))
+        .is_some_and(|n| !matches!(xml_attr(n, "val"), Some("0" | "false" | "off" | "none")))
+}
+
+struct Docx<'d> {
+    zip: ZipArchive<Cursor<&'d [u8]>>,
+    /// Relationship id -> (target, external).
+    rels: HashMap<String, (String, bool)>,
+    /// Paragraph style id -> heading level.
+    headings: HashMap<String, usize>,
+    /// (numbering id, level) -> ordered.
+    numbering: HashMap<(String, String), bool>,
+    images: Images,
+}
+
+impl Docx<'_> {
+    fn read(&mut self, name: &str) -> Option<Vec<u8>> {
+        let mut file = self.zip.by_name(name).ok()?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).ok()?;
+        Some(buf)
+    }
+
+    fn image(&mut self, rel: &str, alt: &str) -> Option<String> {
+        let (target, external) = self.rels.get(rel)?.clone();
+        if external {
+            return Some(format!("![{}]({})", escape_md(alt), link_dest(&target)));
+        }
+        let name = match target.strip_prefix('/') {
+            Some(abs) => abs.to_string(),
+            None => format!("word/{}", target),
+        };
+        let data = self.read(&name)?;
+        let ext = Path::new(&name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .or_else(|| image_extension(&data).map(str::to_string))?;
+        let path = self.images.add(&name, &ext, data);
+        Some(format!("![{}]({})", escape_md(alt), path))
+    }
+
+    fn inline(&mut self, node: Node, fmt: &Fmt, segs: &mut Vec<(String, Fmt)>) {
+        for c in node.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "r" => {
+                    let props = xml_child(c, "rPr");
+                    let fmt = Fmt {
+                        bold: fmt.bold || toggle(props, "b"),
+                        italic: fmt.italic || toggle(props, "i"),
+                        strike: fmt.strike || toggle(props, "strike") || toggle(props, "dstrike"),
+                        link: fmt.link.clone(),
+                    };
+                    for part in c.children().filter(|n| n.is_element()) {
+                        let text = match part.tag_name().name() {
+                            "t" => escape_md(part.text().unwrap_or_default()),
+                            "tab" | "ptab" => " ".to_string(),
+                            "br" if xml_attr(part, "type") == Some("page") => continue,
+                            "br" | "cr" => LINE_BREAK.to_string(),
+                            "noBreakHyphen" => "-".to_string(),
+                            "drawing" => {
+                                let alt = part
+                                    .descendants()
+                                    .find(|n| n.tag_name().name() == "docPr")
+                                    .and_then(|n| xml_attr(n, "descr"))
+                                    .unwrap_or_default()
+                                    .to_string();
+                                let rel = part
+                                    .descendants()
+                                    .find(|n| n.tag_name().name() == "blip")
+                                    .and_then(|n| xml_attr(n, "embed").or_else(|| xml_attr(n, "link")));
+                                match rel.and_then(|r| self.image(r, &alt)) {
+                                    Some(md) => {
+                                        segs.push((md, Fmt::default()));
+                                        continue;
+                                    }
+                                    None => continue,
+                                }
+                            }
+                            "pict" | "object" => {
+                                let rel = part
+                                    .descendants()
+                                    .find(|n| n.tag_name().name() == "imagedata")
+                                    .and_then(|n| xml_attr(n, "id"));
+                                if let Some(md) = rel.and_then(|r| self.image(r, "")) {
+                                    segs.push((md, Fmt::default()));
+                                }
+                                continue;
+                            }
+                            _ => continue,
+                        };
+                        segs.push((text, fmt.clone()));
+                    }
+                }
+                "hyperlink" => {
+                    let link = xml_attr(c, "id")
+                        .and_then(|id| self.rels.get(id))
+                        .filter(|(_, external)| *external)
+                        .map(|(target, _)| target.clone());
+                    let fmt = Fmt {
+                        link: link.or(fmt.link.clone()),
+                        ..fmt.clone()
+                    };
+                    self.inline(c, &fmt, segs);
+                }
+                "ins" | "smartTag" | "fldSimple" | "customXml" | "sdtContent" | "moveTo" | "dir" | "bdo" => {
+                    self.inline(c, fmt, segs)
+                }
+                "sdt" => {
+                    if let Some(content) = xml_child(c, "sdtContent") {
+                        self.inline(content, fmt, segs);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn paragraph_text(&mut self, p: Node) -> String {
+        let mut segs = Vec::new();
+        self.inline(p, &Fmt::default(), &mut segs);
+        render_segments(&segs)
+    }
+
+    fn paragraph(&mut self, p: Node, out: &mut Blocks) {
+        let props = xml_child(p, "pPr");
+        let text = self.paragraph_text(p);
+        if normalize(&text, "").is_empty() {
+            return;
+        }
+        let level = props
+            .and_then(|pr| xml_child(pr, "outlineLvl"))
+            .and_then(|n| xml_attr(n, "val"))
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|l| *l < 9)
+            .map(|l| l + 1)
+            .or_else(|| {
+                let style = props
+                    .and_then(|pr| xml_child(pr, "pStyle"))
+                    .and_then(|n| xml_attr(n, "val"))?;
+                self.headings.get(style).copied()
+            });
+        if let Some(level) = level {
+            out.push(heading(level, &text));
+            return;
+        }
+        let num = props.and_then(|pr| xml_child(pr, "numPr"));
+        let num_id = num.and_then(|n| xml_child(n, "numId")).and_then(|n| xml_attr(n, "val"));
+        if let Some(num_id) = num_id.filter(|id| *id != "0") {
+            let ilvl = num
+                .and_then(|n| xml_child(n, "ilvl"))
+                .and_then(|n| xml_attr(n, "val"))
+                .unwrap_or("0");
+            let ordered = self
+                .numbering
+                .get(&(num_id.to_string(), ilvl.to_string()))
+                .copied()
+                .unwrap_or(false);
+            let depth = ilvl.parse::<usize>().unwrap_or(0).min(8);
+            out.push_item(list_item(
+                if ordered { "1. " } else { "- " },
+                depth,
+                &normalize(&text, "  \n"),
+            ));
+            return;
+        }
+        out.push(paragraph(&text));
+    }
+
+    fn table(&mut self, tbl: Node) -> Option<String> {
+        let mut rows = Vec::new();
+        for tr in tbl.children().filter(|n| n.tag_name().name() == "tr") {
+            let mut row = Vec::new();
+            for tc in tr.children().filter(|n| n.tag_name().name() == "tc") {
+                let parts: Vec<String> = tc
+                    .descendants()
+                    .filter(|n| n.tag_name().name() == "p")
+                    .map(|p| self.paragraph_text(p))
+                    .filter(|t| !normalize(t, "").is_empty())
+                    .collect();
+                row.push(table_cell(&parts.join(&LINE_BREAK.to_string())));
+            }
+            rows.push(row);
+        }
+        gfm_table(&rows)
+    }
+
+    fn body(&mut self, node: Node, out: &mut Blocks) {
+        for c in node.children().filter(|c| c.is_element()) {
+            match c.tag_name().name() {
+                "p" => self.paragraph(c, out),
+                "tbl" => {
+                    if let Some(table) = self.table(c) {
+                        out.push(table);
+                    }
+                }
+                "sdt" => {
+                    if let Some(content) = xml_child(c, "sdtContent") {
+                        self.body(content, out);
+                    }
+                }
+                "customXml" | "ins" => self.body(c, out),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Converts the main story of a Word document: headings by outline level or heading style, lists,
+/// tables, hyperlinks, bold/italic/strike and embedded images.
+pub(crate) fn docx_to_markdown(data: &[u8]) -> Result<MarkdownDoc, String> {
+    let zip = ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+    let mut docx = Docx {
+        zip,
+        rels: HashMap::new(),
+        headings: HashMap::new(),
+        numbering: HashMap::new(),
+        images: Images::default(),
+    };
+    let document = docx
+        .read("word/document.xml")
+        .ok_or("not a Word document: word/document.xml is missing")?;
+
+    if let Some(raw) = docx.read("word/_rels/document.xml.rels") {
+        let text = String::from_utf8_lossy(&raw);
+        if let Ok(doc) = parse_xml(&text) {
+            for rel in doc.descendants().filter(|n| n.tag_name().name() == "Relationship") {
+                if let (Some(id), Some(target)) = (xml_attr(rel, "Id"), xml_attr(rel, "Target")) {
+                    let external = xml_attr(rel, "TargetMode") == Some("External");
+                    docx.rels.insert(id.to_string(), (target.to_string(), external));
+                }
+            }
+        }
+    }
+    if let Some(raw) = docx.read("word/styles.xml") {
+        let text = String::from_utf8_lossy(&raw);
+        if let Ok(doc) = parse_xml(&text) {
+            for style in doc.descendants().filter(|n| n.tag_name().name() == "style") {
+                let Some(id) = xml_attr(style, "styleId") else { continue };
+                let name = xml_child(style, "name")
+                    .and_then(|n| xml_attr(n, "val"))
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                let outline = xml_child(style, "pPr")
+                    .and_then(|pr| xml_child(pr, "outlineLvl"))
+                    .and_then(|n| xml_attr(n, "val"))
+                    .and_then(|v| v.parse::<usize>().ok())
+                    .filter(|l| *l < 9)
+                    .map(|l| l + 1);
+                let level = match name.strip_prefix("heading ") {
+                    Some(n) => n.trim().parse::<usize>().ok(),
+                    None if name == "title" => Some(1),
+                    None => outline,
+                };
+                if let Some(level) = level {
+                    docx.headings.insert(id.to_string(), level);
+                }
+            }
+        }
+    }
+    if let Some(raw) = docx.read("word/numbering.xml") {
+        let text = String::from_utf8_lossy(&raw);
+        if let Ok(doc) = parse_xml(&text) {
+            let mut abstract_levels: HashMap<&str, Vec<(String, bool)>> = HashMap::new();
+            for abs in doc.descendants().filter(|n| n.tag_name().name() == "abstractNum") {
+                let Some(id) = xml_attr(abs, "abstractNumId") else {
+                    continue;
+                };
+                let levels = abs
+                    .children()
+                    .filter(|n| n.tag_name().name() == "lvl")
+                    .map(|lvl| {
+                        let fmt = xml_child(lvl, "numFmt")
+                            .and_then(|n| xml_attr(n, "val"))
+                            .unwrap_or("bullet");
+                        let ilvl = xml_attr(lvl, "ilvl").unwrap_or("0").to_string();
+                        (ilvl, !matches!(fmt, "bullet" | "none"))
+                    })
+                    .collect();
+                abstract_levels.insert(id, levels);
+            }
+            for num in doc.descendants().filter(|n| n.tag_name().name() == "num") {
+                let Some(id) = xml_attr(num, "numId") else { continue };
+                let abs = xml_child(num, "abstractNumId").and_then(|n| xml_attr(n, "val"));
+                for (ilvl, ordered) in abs.and_then(|a| abstract_levels.get(a)).into_iter().flatten() {
+                    docx.numbering.insert((id.to_string(), ilvl.clone()), *ordered);
+                }
+            }
+        }
+    }
+
+    let text = String::from_utf8_lossy(&document);
+    let doc = parse_xml(&text)?;
+    let body = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "body")
+        .ok_or("Word document has no body")?;
+    let mut out = Blocks::default();
+    docx.body(body, &mut out);
+    Ok(MarkdownDoc {
+        markdown: out.finish(),
+        images: docx.images.files,
+    })
+}
+
+// ---------------------------------------------------------------------------------------------
+// HTML
+
+#[derive(Debug)]
+enum HtmlNode {
+    Text(String),
+    Element(HtmlElement),
+}
+
+#[derive(Debug)]
+struct HtmlElement {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<HtmlNode>,
+}
+
+impl HtmlElement {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn text(&self, out: &mut String) {
+        for c in &self.children {
+            match c {
+                HtmlNode::Text(t) => out.push_str(t),
+                HtmlNode::Element(e) if e.name == "br" => out.push('\n'),
+                HtmlNode::Element(e) => e.text(out),
+            }
+        }
+    }
+}
+
+const VOID_ELEMENTS: [&str; 14] = [
+    "br", "hr", "img", "input", "meta", "link", "col", "area", "base", "source", "wbr", "embed", "param", "track",
+];
+const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "textarea", "title"];
+const SKIPPED_ELEMENTS: [&str; 17] = [
+    "head", "script", "style", "noscript", "template", "iframe", "svg", "math", "object", "video", "audio", "canvas",
+    "select", "button", "input", "textarea", "title",
+];
+const BLOCK_ELEMENTS: [&str; 37] = [
+    "html",
+    "body",
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "footer",
+    "nav",
+    "aside",
+    "figure",
+    "figcaption",
+    "address",
+    "center",
+    "details",
+    "summary",
+    "form",
+    "fieldset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "ul",
+    "ol",
+    "li",
+    "dl",
+    "dt",
+    "dd",
+    "blockquote",
+    "pre",
+    "table",
+    "hr",
+    "hgroup",
+    "caption",
+];
+
+fn decode_entities(text: &str) -> String {
+    const NAMED: [(&str, &str); 24] = [
+        ("amp", "&"),
+        ("lt", "<"),
+        ("gt", ">"),
+        ("quot", "\""),
+        ("apos", "'"),
+        ("nbsp", "\u{a0}"),
+        ("shy", ""),
+        ("ndash", "\u{2013}"),
+        ("mdash", "\u{2014}"),
+        ("hellip", "\u{2026}"),
+        ("lsquo", "\u{2018}"),
+        ("rsquo", "\u{2019}"),
+        ("ldquo", "\u{201c}"),
+        ("rdquo", "\u{201d}"),
+        ("laquo", "\u{ab}"),
+        ("raquo", "\u{bb}"),
+        ("middot", "\u{b7}"),
+        ("bull", "\u{2022}"),
+        ("copy", "\u{a9}"),
+        ("reg", "\u{ae}"),
+        ("trade", "\u{2122}"),
+        ("deg", "\u{b0}"),
+        ("times", "\u{d7}"),
+        ("euro", "\u{20ac}"),
+    ];
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let end = rest[1..].find(';').map(|e| e + 1).filter(|e| *e <= 10);
+        let decoded = end.and_then(|e| {
+            let name = &rest[1..e];
+            let ch = match name.strip_prefix('#') {
+                Some(num) => match num.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => num.parse().ok(),
+                }
+                .and_then(char::from_u32)
+                .map(String::from),
+                None => NAMED.iter().find(|(n, _)| *n == name).map(|(_, v)| v.to_string()),
+            };
+            ch.map(|c| (c, e + 1))
+        });
+        match decoded {
+            Some((text, used)) => {
+                out.push_str(&text);
+                rest = &rest[used..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Builds an element tree from HTML, closing what browsers would close implicitly.
+fn parse_html(html: &str) -> Vec<HtmlNode> {
+    fn close(stack: &mut Vec<HtmlElement>, at: usize) {
+        while stack.len() > at.max(1) {
+            let done = stack.pop().unwrap();
+            stack.last_mut().unwrap().children.push(HtmlNode::Element(done));
+        }
+    }
+    let bytes = html.as_bytes();
+    let mut stack = vec![HtmlElement {
+        name: String::new(),
+        attrs: Vec::new(),
+        children: Vec::new(),
+    }];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'<' {
+            let end = html[i..].find('<').map_or(html.len(), |p| p + i);
+            stack
+                .last_mut()
+                .unwrap()
+                .children
+                .push(HtmlNode::Text(decode_entities(&html[i..end])));
+            i = end;
+            continue;
+        }
+        let rest = &html[i..];
+        if rest.starts_with("<!--") {
+            i = rest.find("-->").map_or(html.len(), |p| i + p + 3);
+            continue;
+        }
+        let next = bytes.get(i + 1).copied().unwrap_or(0);
+        if next == b'!' || next == b'?' {
+            i = rest.find('>').map_or(html.len(), |p| i + p + 1);
+            continue;
+        }
+        let closing = next == b'/';
+        let name_start = i + 1 + closing as usize;
+        let name_len = bytes
+            .get(name_start..)
+            .unwrap_or_default()
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == b':' || **c == b'-')
+            .count();
+        if name_len == 0 || !bytes[name_start].is_ascii_alphabetic() {
+            stack.last_mut().unwrap().children.push(HtmlNode::Text("<".to_string()));
+            i += 1;
+            continue;
+        }
+        let name = html[name_start..name_start + name_len].to_ascii_lowercase();
+
+        let mut attrs = Vec::new();
+        let mut j = name_start + name_len;
+        let mut self_closing = false;
+        loop {
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            match bytes.get(j) {
+                None => break,
+                Some(b'>') => {
+                    j += 1;
+                    break;
+                }
+                Some(b'/') => {
+                    self_closing = bytes.get(j + 1) == Some(&b'>');
+                    j += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let key_start = j;
+            while j < bytes.len() && !bytes[j].is_ascii_whitespace() && !matches!(bytes[j], b'=' | b'>') {
+                j += 1;
+            }
+            let key = html[key_start..j].to_ascii_lowercase();
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            let mut value = String::new();
+            if bytes.get(j) == Some(&b'=') {
+                j += 1;
+                while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                    j += 1;
+                }
+                match bytes.get(j) {
+                    Some(&q) if q == b'"' || q == b'\'' => {
+                        let end = html[j + 1..].find(q as char).map_or(html.len(), |p| j + 1 + p);
+                        value = decode_entities(&html[j + 1..end]);
+                        j = (end + 1).min(html.len());
+                    }
+                    _ => {
+                        let start = j;
+                        while j < bytes.len() && !bytes[j].is_ascii_whitespace() && bytes[j] != b'>' {
+                            j += 1;
+                        }
+                        value = decode_entities(&html[start..j]);
+                    }
+                }
+            }
+            if !key.is_empty() {
+                attrs.push((key, value));
+            }
+        }
+        i = j;
+
+        if closing {
+            if let Some(at) = stack.iter().rposition(|e| e.name == name).filter(|at| *at > 0) {
+                close(&mut stack, at);
+            }
+            continue;
+        }
+        // Implicit end tags.
+        let closes: &[&str] = match name.as_str() {
+            "li" => &["li"],
+            "dt" | "dd" => &["dt", "dd"],
+            "tr" => &["tr", "td", "th"],
+            "td" | "th" => &["td", "th"],
+            "option" => &["option"],
+            n if BLOCK_ELEMENTS.contains(&n) => &["p"],
+            _ => &[],
+        };
+        let scope = ["ul", "ol", "dl", "table", "div", "blockquote", "td", "th"];
+        if let Some(at) = stack.iter().rposition(|e| closes.contains(&e.name.as_str())) {
+            if at > 0 && !stack[at + 1..].iter().any(|e| scope.contains(&e.name.as_str())) {
+                close(&mut stack, at);
+            }
+        }
+        let element = HtmlElement {
+            name: name.clone(),
+            attrs,
+            children: Vec::new(),
+        };
+        if VOID_ELEMENTS.contains(&name.as_str()) || self_closing {
+            stack.last_mut().unwrap().children.push(HtmlNode::Element(element));
+            continue;
+        }
+        if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let lower = html[i..].to_ascii_lowercase();
+            let end = lower.find(&format!("</{}", name)).map_or(html.len(), |p| p + i);
+            let mut element = element;
+            element.children.push(HtmlNode::Text(html[i..end].to_string()));
+            stack.last_mut().unwrap().children.push(HtmlNode::Element(element));
+            i = html[end..].find('>').map_or(html.len(), |p| end + p + 1);
+            continue;
+        }
+        stack.push(element);
+    }
+    close(&mut stack, 1);
+    stack.pop().map(|root| root.children).unwrap_or_default()
+}
+
+struct HtmlConverter {
+    images: Images,
+}
+
+impl HtmlConverter {
+    fn image_src(&mut self, src: &str) -> Option<String> {
+        let src = src.trim();
+        if let Some(data) = src.strip_prefix("data:") {
+            let (meta, payload) = data.split_once(',')?;
+            if !meta.ends_with(";base64") {
+                return None;
+            }
+            let payload: String = payload.chars().filter(|c| !c.is_whitespace()).collect();
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(payload.as_bytes())
+                .ok()?;
+            let ext = image_extension(&bytes).or(if meta.starts_with("image/svg") {
+                Some("svg")
+            } else {
+                None
+            })?;
+            let key = format!("data:{}", self.images.files.len());
+            return Some(self.images.add(&key, ext, bytes));
+        }
+        let lower = src.to_ascii_lowercase();
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            return Some(src.to_string());
+        }
+        // Local references are copied next to the Markdown by the import.
+        Some(src.replace(' ', "%20"))
+    }
+
+    fn inline_children(&mut self, e: &HtmlElement, out: &mut String) {
+        for c in &e.children {
+            match c {
+                HtmlNode::Text(t) => out.push_str(&escape_md(t)),
+                HtmlNode::Element(child) => self.inline(child, out),
+            }
+        }
+    }
+
+    fn inline(&mut self, e: &HtmlElement, out: &mut String) {
+        if SKIPPED_ELEMENTS.contains(&e.name.as_str()) {
+            return;
+        }
+        let mut inner = String::new();
+        match e.name.as_str() {
+            "br" => out.push(LINE_BREAK),
+            "img" => {
+                let alt = escape_md(&normalize(e.attr("alt").unwrap_or_default(), " "));
+                if let Some(src) = e.attr("src").and_then(|s| self.image_src(s)) {
+                    out.push_str(&format!("![{}]({})", alt, link_dest(&src)));
+                }
+            }
+            "a" => {
+                self.inline_children(e, &mut inner);
+                let href = e.attr("href").unwrap_or_default().trim();
+                let lower = href.to_ascii_lowercase();
+                let (lead, label, trail) = split_padding(&inner);
+                if href.is_empty() || href.starts_with('#') || lower.starts_with("javascript:") || label.is_empty() {
+                    out.push_str(&inner);
+                } else {
+                    out.push_str(&format!("{lead}[{label}]({}){trail}", link_dest(href)));
+                }
+            }
+            "strong" | "b" => {
+                self.inline_children(e, &mut inner);
+                out.push_str(&wrap("**", &inner));
+            }
+            "em" | "i" | "cite" | "dfn" | "var" => {
+                self.inline_children(e, &mut inner);
+                out.push_str(&wrap("*", &inner));
+            }
+            "del" | "s" | "strike" => {
+                self.inline_children(e, &mut inner);
+                out.push_str(&wrap("~~", &inner));
+            }
+            "code" | "kbd" | "samp" | "tt" => {
+                e.text(&mut inner);
+                let code = normalize(&inner, " ");
+                if !code.is_empty() {
+                    let fence = if code.contains('`') { "``" } else { "`" };
+                    out.push_str(&format!("{fence}{code}{fence}"));
+                }
+            }
+            _ => self.inline_children(e, out),
+        }
+    }
+
+    fn blocks(&mut self, nodes: &[HtmlNode], out: &mut Blocks) {
+        let mut para = String::new();
+        for n in nodes {
+            match n {
+                HtmlNode::Text(t) => para.push_str(&escape_md(t)),
+                HtmlNode::Element(e) if BLOCK_ELEMENTS.contains(&e.name.as_str()) => {
+                    out.push(paragraph(&std::mem::take(&mut para)));
+                    self.block(e, out);
+                }
+                HtmlNode::Element(e) => self.inline(e, &mut para),
+            }
+        }
+        out.push(paragraph(&para));
+    }
+
+    fn block(&mut self, e: &HtmlElement, out: &mut Blocks) {
+        match e.name.as_str() {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let mut text = String::new();
+                self.inline_children(e, &mut text);
+                if !normalize(&text, "").is_empty() {
+                    out.push(heading((e.name.as_bytes()[1] - b'0') as usize, &text));
+                }
+            }
+            "ul" | "ol" => {
+                let marker = if e.name == "ol" { "1. " } else { "- " };
+                let mut items = Vec::new();
+                for c in &e.children {
+                    let HtmlNode::Element(li) = c else { continue };
+                    if li.name != "li" {
+                        continue;
+                    }
+                    let mut inner = Blocks::default();
+                    self.blocks(&li.children, &mut inner);
+                    let text = inner.out.join("\n");
+                    if !text.trim().is_empty() {
+                        items.push(list_item(marker, 0, &text));
+                    }
+                }
+                if !items.is_empty() {
+                    out.push(items.join("\n"));
+                }
+            }
+            "blockquote" => {
+                let mut inner = Blocks::default();
+                self.blocks(&e.children, &mut inner);
+                let quoted: Vec<String> = inner
+                    .finish()
+                    .trim_end()
+                    .lines()
+                    .map(|l| {
+                        if l.is_empty() {
+                            ">".to_string()
+                        } else {
+                            format!("> {}", l)
+                        }
+                    })
+                    .collect();
+                out.push(quoted.join("\n"));
+            }
+            "pre" => {
+                let mut text = String::new();
+                e.text(&mut text);
+                let text = text.trim_matches('\n');
+                if !text.trim().is_empty() {
+                    let fence = if text.contains("```") { "~~~" } else { "```" };
+                    out.push(format!("{fence}\n{text}\n{fence}"));
+                }
+            }
+            "table" => {
+                let mut rows = Vec::new();
+                self.table_rows(e, &mut rows);
+                if let Some(table) = gfm_table(&rows) {
+                    out.push(table);
+                }
+            }
+            "hr" => out.push("---".to_string()),
+            _ if SKIPPED_ELEMENTS.contains(&e.name.as_str()) => {}
+            _ => self.blocks(&e.children, out),
+        }
+    }
+
+    fn table_rows(&mut self, e: &HtmlElement, rows: &mut Vec<Vec<String>>) {
+        for c in &e.children {
+            let HtmlNode::Element(child) = c else { continue };
+            match child.name.as_str() {
+                "thead" | "tbody" | "tfoot" => self.table_rows(child, rows),
+                "tr" => {
+                    let mut row = Vec::new();
+                    for cell in &child.children {
+                        let HtmlNode::Element(cell) = cell else { continue };
+                        if cell.name == "td" || cell.name == "th" {
+                            let mut text = String::new();
+                            self.inline_children(cell, &mut text);
+                            row.push(table_cell(&text));
+                        }
+                    }
+                    rows.push(row);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Converts a standalone HTML page. Embedded `data:` images are extracted; local ones keep their
+/// relative paths so the import copies them.
+pub(crate) fn html_to_markdown(data: &[u8]) -> MarkdownDoc {
+    let html = match decode_markup(data) {
+        Some((text, _)) => text,
+        None => String::from_utf8_lossy(data).trim_start_matches('\u{feff}').to_string(),
+    };
+    let nodes = parse_html(&html);
+    let mut conv = HtmlConverter {
+        images: Images::default(),
+    };
+    let mut out = Blocks::default();
+    conv.blocks(&nodes, &mut out);
+    MarkdownDoc {
+        markdown: out.finish(),
+        images: conv.images.files,
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// RTF
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+enum RtfDest {
+    #[default]
+    Text,
+    Skip,
+    FieldInstruction,
+    ListText,
+    Picture,
+    StyleSheet,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RtfGroup {
+    dest: RtfDest,
+    fmt: Fmt,
+    /// Characters to skip after `\uN`.
+    uc: usize,
+    style: Option<i32>,
+}
+
+/// Destinations whose content is not part of the text.
+const RTF_SKIPPED: [&str; 27] = [
+    "fonttbl",
+    "colortbl",
+    "info",
+    "header",
+    "headerl",
+    "headerr",
+    "headerf",
+    "footer",
+    "footerl",
+    "footerr",
+    "footerf",
+    "footnote",
+    "annotation",
+    "xmlnstbl",
+    "listtable",
+    "listoverridetable",
+    "rsidtbl",
+    "generator",
+    "themedata",
+    "colorschememapping",
+    "latentstyles",
+    "datastore",
+    "nonshppict",
+    "revtbl",
+    "pgdsctbl",
+    "filetbl",
+    "mmathPr",
+];
+
+struct Rtf<'a> {
+    data: &'a [u8],
+    pos: usize,
+    stack: Vec<RtfGroup>,
+    state: RtfGroup,
+    codepage: &'static Encoding,
+    pending: Vec<u8>,
+    skip_fallback: usize,
+    ignorable: bool,
+    segs: Vec<(String, Fmt)>,
+    heading: Option<usize>,
+    in_table: bool,
+    list_level: usize,
+    list_marker: String,
+    field_instruction: String,
+    styles: HashMap<i32, usize>,
+    style_name: String,
+    picture: (String, Option<&'static str>),
+    cell: String,
+    row: Vec<String>,
+    rows: Vec<Vec<String>>,
+    images: Images,
+    out: Blocks,
+}
+
+fn rtf_codepage(cp: i32) -> &'static Encoding {
+    match cp {
+        65001 => encoding_rs::UTF_8,
+        932 => encoding_rs::SHIFT_JIS,
+        936 => encoding_rs::GBK,
+        949 => encoding_rs::EUC_KR,
+        950 => encoding_rs::BIG5,
+        cp => Encoding::for_label(format!("windows-{}", cp).as_bytes()).unwrap_or(encoding_rs::WINDOWS_1252),
+    }
+}
+
+impl Rtf<'_> {
+    fn emit(&mut self, text: &str) {
+        match self.state.dest {
+            RtfDest::Text => {
+                let text = escape_md(text);
+                match self.segs.last_mut() {
+                    Some((last, fmt)) if *fmt == self.state.fmt => last.push_str(&text),
+                    _ => self.segs.push((text, self.state.fmt.clone())),
+                }
+            }
+            RtfDest::FieldInstruction => self.field_instruction.push_str(text),
+            RtfDest::ListText => self.list_marker.push_str(text),
+            RtfDest::StyleSheet => self.style_name.push_str(text),
+            RtfDest::Skip | RtfDest::Picture => {}
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.pending.is_empty() {
+            let bytes = std::mem::take(&mut self.pending);
+            let (text, _, _) = self.codepage.decode(&bytes);
+            self.emit(&text);
+        }
+    }
+
+    fn emit_raw(&mut self, markdown: String) {
+        if self.state.dest == RtfDest::Text {
+            self.segs.push((markdown, Fmt::default()));
+        }
+    }
+
+    fn flush_table(&mut self) {
+        if !self.row.is_empty() {
+            let row = std::mem::take(&mut self.row);
+            self.rows.push(row);
+        }
+        if let Some(table) = gfm_table(&std::mem::take(&mut self.rows)) {
+            self.out.push(table);
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        self.flush();
+        let text = render_segments(&std::mem::take(&mut self.segs));
+        let marker = std::mem::take(&mut self.list_marker);
+        if self.in_table {
+            if !normalize(&text, "").is_empty() {
+                if !self.cell.is_empty() {
+                    self.cell.push(LINE_BREAK);
+                }
+                self.cell.push_str(&text);
+            }
+            return;
+        }
+        if !self.rows.is_empty() || !self.row.is_empty() {
+            self.flush_table();
+        }
+        if normalize(&text, "").is_empty() {
+            return;
+        }
+        if let Some(level) = self.heading {
+            self.out.push(heading(level, &text));
+            return;
+        }
+        let marker = marker.trim();
+        if !marker.is_empty() {
+            let ordered = marker
+                .trim_end_matches(['.', ')'])
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric())
+                && marker.ends_with(['.', ')']);
+            let item = list_item(
+                if ordered { "1. " } else { "- " },
+                self.list_level,
+                &normalize(&text, "  \n"),
+            );
+            self.out.push_item(item);
+            return;
+        }
+        self.out.push(paragraph(&text));
+    }
+
+    fn end_cell(&mut self) {
+        self.end_paragraph();
+        let cell = std::mem::take(&mut self.cell);
+        self.row.push(table_cell(&cell));
+    }
+
+    fn end_group(&mut self) {
+        self.flush();
+        let closed = std::mem::replace(&mut self.state, self.stack.pop().unwrap_or_default());
+        if closed.dest == RtfDest::Picture && self.state.dest != RtfDest::Picture {
+            let (hex, ext) = std::mem::take(&mut self.picture);
+            if let Some(ext) = ext {
+                let bytes: Vec<u8> = hex
+                    .as_bytes()
+                    .chunks_exact(2)
+                    .filter_map(|p| u8::from_str_radix(std::str::from_utf8(p).ok()?, 16).ok())
+                    .collect();
+                let key = format!("pict:{}", self.images.files.len());
+                let path = self.images.add(&key, ext, bytes);
+                self.emit_raw(format!("![]({})", path));
+            }
+        }
+        if closed.dest == RtfDest::StyleSheet {
+            let name = std::mem::take(&mut self.style_name)
+                .trim()
+                .trim_end_matches(';')
+                .to_ascii_lowercase();
+            let level = match name.strip_prefix("heading ") {
+                Some(n) => n.trim().parse::<usize>().ok(),
+                None if name == "title" => Some(1),
+                None => None,
+            };
+            if let (Some(style), Some(level)) = (closed.style, level) {
+                self.styles.insert(style, level);
+            }
+        }
+    }
+
+    fn control(&mut self, word: &str, param: Option<i32>) {
+        let ignorable = std::mem::take(&mut self.ignorable);
+        if self.state.dest == RtfDest::Skip {
+            return;
+        }
+        let on = param != Some(0);
+        let text_dest = self.state.dest == RtfDest::Text;
+        match word {
+            "par" | "sect" | "page" if text_dest => self.end_paragraph(),
+            "line" => self.emit_char(LINE_BREAK),
+            "tab" => self.emit_char(' '),
+            "emdash" => self.emit_char('\u{2014}'),
+            "endash" => self.emit_char('\u{2013}'),
+            "bullet" => self.emit_char('\u{2022}'),
+            "lquote" => self.emit_char('\u{2018}'),
+            "rquote" => self.emit_char('\u{2019}'),
+            "ldblquote" => self.emit_char('\u{201c}'),
+            "rdblquote" => self.emit_char('\u{201d}'),
+            "u" => {
+                let code = param.unwrap_or(0);
+                let code = if code < 0 { code + 65536 } else { code } as u32;
+                if let Some(c) = char::from_u32(code) {
+                    self.emit_char(c);
+                }
+                self.skip_fallback = self.state.uc;
+            }
+            "uc" => self.state.uc = param.unwrap_or(1).max(0) as usize,
+            "ansicpg" => self.codepage = rtf_codepage(param.unwrap_or(1252)),
+            "b" => self.state.fmt.bold = on,
+            "i" => self.state.fmt.italic = on,
+            "strike" | "striked" => self.state.fmt.strike = on,
+            "plain" => {
+                self.state.fmt.bold = false;
+                self.state.fmt.italic = false;
+                self.state.fmt.strike = false;
+            }
+            "pard" if text_dest => {
+                self.heading = None;
+                self.in_table = false;
+                self.list_level = 0;
+            }
+            "s" if self.state.dest == RtfDest::StyleSheet => self.state.style = param,
+            "s" if text_dest => self.heading = param.and_then(|s| self.styles.get(&s).copied()),
+            "outlinelevel" if text_dest => {
+                self.heading = param.filter(|l| (0..9).contains(l)).map(|l| l as usize + 1);
+            }
+            "intbl" if text_dest => self.in_table = true,
+            "cell" if text_dest => self.end_cell(),
+            "row" if text_dest => {
+                let row = std::mem::take(&mut self.row);
+                self.rows.push(row);
+            }
+            "ilvl" if text_dest => self.list_level = param.unwrap_or(0).clamp(0, 8) as usize,
+            "listtext" | "pntext" => self.state.dest = RtfDest::ListText,
+            "fldinst" => {
+                self.field_instruction.clear();
+                self.state.dest = RtfDest::FieldInstruction;
+            }
+            "fldrslt" => {
+                let inst = self.field_instruction.trim();
+                self.state.fmt.link = inst
+                    .strip_prefix("HYPERLINK")
+                    .map(str::trim)
+                    .filter(|rest| !rest.starts_with("\\l"))
+                    .and_then(|rest| rest.split('"').nth(1))
+                    .map(str::to_string);
+            }
+            "pict" => {
+                self.picture = (String::new(), None);
+                self.state.dest = RtfDest::Picture;
+            }
+            "pngblip" => self.picture.1 = Some("png"),
+            "jpegblip" => self.picture.1 = Some("jpg"),
+            "stylesheet" => self.state.dest = RtfDest::StyleSheet,
+            "bin" => {
+                let len = param.unwrap_or(0).max(0) as usize;
+                let end = (self.pos + len).min(self.data.len());
+                if self.state.dest == RtfDest::Picture {
+                    let hex: String = self.data[self.pos..end].iter().map(|b| format!("{:02x}", b)).collect();
+                    self.picture.0.push_str(&hex);
+                }
+                self.pos = end;
+            }
+            "shppict" | "field" => {}
+            w if RTF_SKIPPED.contains(&w) || ignorable => self.state.dest = RtfDest::Skip,
+            _ => {}
+        }
+    }
+
+    fn emit_char(&mut self, c: char) {
+        self.flush();
+        let mut buf = [0; 4];
+        self.emit(c.encode_utf8(&mut buf));
+    }
+
+    fn run(&mut self) {
+        while self.pos < self.data.len() {
+            let c = self.data[self.pos];
+            self.pos += 1;
+            match c {
+                b'{' => {
+                    self.flush();
+                    self.stack.push(self.state.clone());
+                }
+                b'}' => self.end_group(),
+                b'\\' => self.escape(),
+                b'\r' | b'\n' => {}
+                _ if self.state.dest == RtfDest::Picture && c.is_ascii_hexdigit() => self.picture.0.push(c as char),
+                _ if self.state.dest == RtfDest::Picture => {}
+                _ if self.skip_fallback > 0 => self.skip_fallback -= 1,
+                _ if self.state.dest != RtfDest::Skip => self.pending.push(c),
+                _ => {}
+            }
+        }
+        self.end_paragraph();
+        self.flush_table();
+    }
+
+    fn escape(&mut self) {
+        let Some(&c) = self.data.get(self.pos) else { return };
+        if c.is_ascii_alphabetic() {
+            let start = self.pos;
+            while self.data.get(self.pos).is_some_and(u8::is_ascii_alphabetic) {
+                self.pos += 1;
+            }
+            let word = String::from_utf8_lossy(&self.data[start..self.pos]).into_owned();
+            let num_start = self.pos;
+            if self.data.get(self.pos) == Some(&b'-') {
+                self.pos += 1;
+            }
+            while self.data.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                self.pos += 1;
+            }
+            let param = std::str::from_utf8(&self.data[num_start..self.pos])
+                .ok()
+                .and_then(|s| s.parse().ok());
+            if self.data.get(self.pos) == Some(&b' ') {
+                self.pos += 1;
+            }
+            self.flush();
+            self.control(&word, param);
+            return;
+        }
+        self.pos += 1;
+        match c {
+            b'\'' => {
+                let hex = self
+                    .data
+                    .get(self.pos..self.pos + 2)
+                    .and_then(|h| std::str::from_utf8(h).ok());
+                let byte = hex.and_then(|h| u8::from_str_radix(h, 16).ok());
+                self.pos += 2;
+                match byte {
+                    _ if self.skip_fallback > 0 => self.skip_fallback -= 1,
+                    Some(b) if self.state.dest != RtfDest::Skip => self.pending.push(b),
+                    _ => {}
+                }
+            }
+            b'*' => self.ignorable = true,
+            b'~' => self.emit_char('\u{a0}'),
+            b'_' => self.emit_char('-'),
+            b'-' => {}
+            b'\r' | b'\n' => self.control("par", None),
+            b'\\' | b'{' | b'}' if self.skip_fallback > 0 => self.skip_fallback -= 1,
+            b'\\' | b'{' | b'}' => self.pending.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Converts RTF: paragraphs, headings by outline level or heading style, lists, tables, hyperlink
+/// fields, bold/italic/strike and PNG/JPEG pictures.
+pub(crate) fn rtf_to_markdown(data: &[u8]) -> Result<MarkdownDoc, String> {
+    if !data.starts_with(b"{\\rtf") {
+        return Err("not an RTF document".to_string());
+    }
+    let mut rtf = Rtf {
+        data,
+        pos: 0,
+        stack: Vec::new(),
+        state: RtfGroup {
+            uc: 1,
+            ..Default::default()
+        },
+        codepage: encoding_rs::WINDOWS_1252,
+        pending: Vec::new(),
+        skip_fallback: 0,
+        ignorable: false,
+        segs: Vec::new(),
+        heading: None,
+        in_table: false,
+        list_level: 0,
+        list_marker: String::new(),
+        field_instruction: String::new(),
+        styles: HashMap::new(),
+        style_name: String::new(),
+        picture: (String::new(), None),
+        cell: String::new(),
+        row: Vec::new(),
+        rows: Vec::new(),
+        images: Images::default(),
+        out: Blocks::default(),
+    };
+    rtf.run();
+    Ok(MarkdownDoc {
+        markdown: rtf.out.finish(),
+        images: rtf.images.files,
+    })
+}
+
+/// Writes a converted document as `<dir>/<stem>.md` plus its images, returning the Markdown path.
+pub(crate) fn write_markdown_doc(doc: &MarkdownDoc, dir: &Path, stem: &str) -> Result<PathBuf, String> {
+    for (rel, data) in &doc.images {
+        let path = dir.join(rel);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(&path, data).map_err(|e| e.to_string())?;
+    }
+    let md_path = dir.join(format!("{}.md", stem));
+    std::fs::write(&md_path, &doc.markdown).map_err(|e| e.to_string())?;
+    Ok(md_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn converts_html_structure_and_inline_images() {
+        let html = br#"<html><head><title>T</title><style>p{}</style></head><body>
+<h1>Title &amp; more</h1>
+<p>Some <b>bold</b> and <i>italic </i>text with<a href="https://example.com/a b"> a link</a>.<br>Next line
+<p>1. not a list <img src="data:image/png;base64,iVBORw0KGgo=" alt="dot"> <img src="page_files/pic%201.png">
+<ul><li>one<li>two<ul><li>nested</ul></ul>
+<table><tr><th>A<th>B</tr><tr><td>1|2<td>x</table>
+<pre>let x = 1;
+  indented</pre>
+<blockquote><p>Quoted</blockquote>
+<script>ignored()</script>
+</body></html>"#;
+        let doc = html_to_markdown(html);
+        assert_eq!(
+            doc.markdown,
+            "# Title & more\n\n\
+Some **bold** and *italic* text with [a link](<https://example.com/a b>).  \nNext line\n\n\
+1\\. not a list ![dot](images/image1.png) ![](page_files/pic%201.png)\n\n\
+- one\n- two\n  - nested\n\n\
+| A | B |\n| --- | --- |\n| 1\\|2 | x |\n\n\
+```\nlet x = 1;\n  indented\n```\n\n\
+> Quoted\n"
+        );
+        assert_eq!(doc.images.len(), 1);
+        assert_eq!(doc.images[0].0, "images/image1.png");
+        assert!(doc.images[0].1.starts_with(b"\x89PNG"));
+    }
+
+    #[test]
+    fn converts_rtf_formatting_lists_tables_and_links() {
+        let rtf = br#"{\rtf1\ansi\ansicpg1251\deff0{\fonttbl{\f0 Times;}}{\stylesheet{\s0 Normal;}{\s1\outlinelevel0 heading 1;}}
+{\*\generator Test}
+\pard\s1 Heading\par
+\pard Plain {\b bold} and {\i it}\'e0\u8364?\par
+{\listtext\'95\tab}\pard\ilvl0 first\par
+{\listtext 2.\tab}\pard second\par
+\pard\intbl A\cell B\cell\row
+\pard\intbl 1\cell 2\cell\row
+\pard {\field{\*\fldinst{HYPERLINK "https://example.com"}}{\fldrslt{site}}} end\par
+{\*\shppict{\pict\pngblip 89504e47}}{\nonshppict{\pict\wmetafile8 0102}}\par
+}"#;
+        let doc = rtf_to_markdown(rtf).unwrap();
+        assert_eq!(
+            doc.markdown,
+            "# Heading\n\n\
+Plain **bold** and *it*\u{430}\u{20ac}\n\n\
+- first\n1. second\n\n\
+| A | B |\n| --- | --- |\n| 1 | 2 |\n\n\
+[site](https://example.com) end\n\n\
+![](images/image1.png)\n"
+        );
+        assert_eq!(doc.images[0].1, b"\x89PNG".to_vec());
+    }
+
+    #[test]
+    fn converts_docx_headings_lists_tables_and_images() {
+        const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships" xmlns:a="http://schemas.openxmlformats.org/drawingml/2006/main" xmlns:wp="http://schemas.openxmlformats.org/drawingml/2006/wordprocessingDrawing""#;
+        let document = format!(
+            r#"<w:document {W}><w:body>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Intro</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Hello </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>bold</w:t></w:r><w:r><w:rPr><w:b w:val="0"/></w:rPr><w:t xml:space="preserve"> and *plain*</w:t></w:r><w:hyperlink r:id="rLink"><w:r><w:t xml:space="preserve"> link</w:t></w:r></w:hyperlink></w:p>
+<w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>item</w:t></w:r></w:p>
+<w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>sub</w:t></w:r></w:p>
+<w:tbl><w:tr><w:tc><w:p><w:r><w:t>H</w:t></w:r></w:p></w:tc></w:tr><w:tr><w:tc><w:p><w:r><w:t>V</w:t></w:r></w:p></w:tc></w:tr></w:tbl>
+<w:p><w:r><w:drawing><wp:inline><wp:docPr id="1" name="P" descr="Chart"/><a:graphic><a:graphicData><a:blip r:embed="rImg"/></a:graphicData></a:graphic></wp:inline></w:drawing></w:r></w:p>
+<w:sectPr/></w:body></w:document>"#
+        );
+        let styles = format!(
+            r#"<w:styles {W}><w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style></w:styles>"#
+        );
+        let numbering = format!(
+            r#"<w:numbering {W}><w:abstractNum w:abstractNumId="0"><w:lvl w:ilvl="0"><w:numFmt w:val="decimal"/></w:lvl><w:lvl w:ilvl="1"><w:numFmt w:val="bullet"/></w:lvl></w:abstractNum><w:num w:numId="1"><w:abstractNumId w:val="0"/></w:num></w:numbering>"#
+        );
+        let rels = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rImg" Type="image" Target="media/image1.png"/><Relationship Id="rLink" Type="hyperlink" Target="https://example.com" TargetMode="External"/></Relationships>"#;
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in [
+            ("word/document.xml", document.as_bytes()),
+            ("word/styles.xml", styles.as_bytes()),
+            ("word/numbering.xml", numbering.as_bytes()),
+            ("word/_rels/document.xml.rels", rels.as_bytes()),
+            ("word/media/image1.png", b"\x89PNG".as_slice()),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        let data = zip.finish().unwrap().into_inner();
+
+        let doc = docx_to_markdown(&data).unwrap();
+        assert_eq!(
+            doc.markdown,
+            "# Intro\n\n\
+Hello **bold** and \\*plain\\* [link](https://example.com)\n\n\
+1. item\n   - sub\n\n\
+| H |\n| --- |\n| V |\n\n\
+![Chart](images/image1.png)\n"
+        );
+        assert_eq!(doc.images, vec![("images/image1.png".to_string(), b"\x89PNG".to_vec())]);
+    }
+}
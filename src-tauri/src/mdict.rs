@@ -0,0 +1,1173 @@
+use crate::epub_meta::decode_html_entities;
+use ripemd::{Digest, Ripemd128};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// Registration info for MDict files whose key index is encrypted (`Encrypted` bit 1).
+/// `regcode` is the 32-hex-digit registration code, `userid` the e-mail it was issued to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MdictPasscode {
+    pub regcode: String,
+    pub userid: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MdictHeader {
+    pub version: f32,
+    pub encrypted: u32,
+    pub encoding: &'static encoding_rs::Encoding,
+    pub title: String,
+    /// `StyleSheet` header: numbered (begin, end) markup pairs substituted for `` `N` `` markers.
+    pub stylesheet: HashMap<u32, (String, String)>,
+    pub key_case_sensitive: bool,
+}
+
+struct RecordBlock {
+    file_offset: u64,
+    comp_size: u64,
+    decomp_offset: u64,
+    decomp_size: u64,
+}
+
+/// A parsed `.mdx` (definitions) or `.mdd` (resources) file.
+/// Keys are loaded eagerly; record blocks are read and decompressed on demand.
+pub struct MdictFile {
+    pub header: MdictHeader,
+    is_mdd: bool,
+    file: File,
+    keys: Vec<(String, u64)>,
+    index: HashMap<String, Vec<usize>>,
+    record_blocks: Vec<RecordBlock>,
+    total_decomp: u64,
+    block_cache: Option<(usize, Vec<u8>)>,
+}
+
+// ---------------------------------------------------------------------------
+// Primitives: ripemd128-keyed "fast decrypt", Salsa20/8 and LZO1X
+// ---------------------------------------------------------------------------
+
+fn ripemd128(data: &[u8]) -> [u8; 16] {
+    let mut h = Ripemd128::new();
+    h.update(data);
+    h.finalize().into()
+}
+
+fn fast_decrypt(data: &mut [u8], key: &[u8]) {
+    let mut previous: u8 = 0x36;
+    for (i, b) in data.iter_mut().enumerate() {
+        let cur = *b;
+        let t = cur.rotate_left(4) ^ previous ^ (i as u8) ^ key[i % key.len()];
+        previous = cur;
+        *b = t;
+    }
+}
+
+/// Decrypts a key-block-info block (`Encrypted` bit 2). The first 8 bytes are left untouched.
+fn mdx_decrypt(block: &mut [u8]) -> Result<(), String> {
+    if block.len() < 8 {
+        return Err("mdict: encrypted block too short".to_string());
+    }
+    let mut seed = [0u8; 8];
+    seed[..4].copy_from_slice(&block[4..8]);
+    seed[4..].copy_from_slice(&0x3695u32.to_le_bytes());
+    let key = ripemd128(&seed);
+    fast_decrypt(&mut block[8..], &key);
+    Ok(())
+}
+
+fn salsa20_block(input: &[u32; 16], rounds: usize) -> [u8; 64] {
+    let mut x = *input;
+    fn qr(x: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+        x[b] ^= x[a].wrapping_add(x[d]).rotate_left(7);
+        x[c] ^= x[b].wrapping_add(x[a]).rotate_left(9);
+        x[d] ^= x[c].wrapping_add(x[b]).rotate_left(13);
+        x[a] ^= x[d].wrapping_add(x[c]).rotate_left(18);
+    }
+    for _ in 0..rounds / 2 {
+        qr(&mut x, 0, 4, 8, 12);
+        qr(&mut x, 5, 9, 13, 1);
+        qr(&mut x, 10, 14, 2, 6);
+        qr(&mut x, 15, 3, 7, 11);
+        qr(&mut x, 0, 1, 2, 3);
+        qr(&mut x, 5, 6, 7, 4);
+        qr(&mut x, 10, 11, 8, 9);
+        qr(&mut x, 15, 12, 13, 14);
+    }
+    let mut out = [0u8; 64];
+    for i in 0..16 {
+        out[i * 4..i * 4 + 4].copy_from_slice(&x[i].wrapping_add(input[i]).to_le_bytes());
+    }
+    out
+}
+
+/// Salsa20 with a 128-bit key and zero IV, as used by MDict (with 8 rounds).
+fn salsa20_xor(key: &[u8; 16], data: &[u8], rounds: usize) -> Vec<u8> {
+    const TAU: &[u8; 16] = b"expand 16-byte k";
+    let word = |b: &[u8], i: usize| u32::from_le_bytes([b[i * 4], b[i * 4 + 1], b[i * 4 + 2], b[i * 4 + 3]]);
+    let mut input = [0u32; 16];
+    input[0] = word(TAU, 0);
+    input[5] = word(TAU, 1);
+    input[10] = word(TAU, 2);
+    input[15] = word(TAU, 3);
+    for i in 0..4 {
+        input[1 + i] = word(key, i);
+        input[11 + i] = word(key, i);
+    }
+    let mut out = Vec::with_capacity(data.len());
+    for (counter, chunk) in data.chunks(64).enumerate() {
+        let counter = counter as u64;
+        input[8] = counter as u32;
+        input[9] = (counter >> 32) as u32;
+        let ks = salsa20_block(&input, rounds);
+        out.extend(chunk.iter().zip(ks.iter()).map(|(a, b)| a ^ b));
+    }
+    out
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn passcode_key(passcode: &MdictPasscode) -> Result<[u8; 16], String> {
+    let regcode = parse_hex(&passcode.regcode)
+        .filter(|r| r.len() == 16)
+        .ok_or_else(|| "mdict: regcode must be 32 hex digits".to_string())?;
+    let userid: Vec<u8> = passcode
+        .userid
+        .encode_utf16()
+        .flat_map(|u| u.to_le_bytes())
+        .collect();
+    let decrypted = salsa20_xor(&ripemd128(&userid), &regcode, 8);
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&decrypted);
+    Ok(key)
+}
+
+/// LZO1X decompression (the format written by miniLZO's `lzo1x_1_compress`).
+pub(crate) fn lzo1x_decompress(src: &[u8], size_hint: usize) -> Result<Vec<u8>, String> {
+    let err = || "mdict: corrupt LZO block".to_string();
+    let mut out: Vec<u8> = Vec::with_capacity(size_hint);
+    let mut ip = 0usize;
+
+    let byte = |ip: &mut usize| -> Result<usize, String> {
+        let b = *src.get(*ip).ok_or_else(err)?;
+        *ip += 1;
+        Ok(b as usize)
+    };
+    let ext_len = |ip: &mut usize| -> Result<usize, String> {
+        let mut extra = 0usize;
+        while *src.get(*ip).ok_or_else(err)? == 0 {
+            extra += 255;
+            *ip += 1;
+        }
+        Ok(extra + byte(ip)?)
+    };
+    let copy_literals = |out: &mut Vec<u8>, ip: &mut usize, n: usize| -> Result<(), String> {
+        let lit = src.get(*ip..*ip + n).ok_or_else(err)?;
+        out.extend_from_slice(lit);
+        *ip += n;
+        Ok(())
+    };
+
+    let mut state: usize = 0;
+    if src.len() < 3 {
+        return Err(err());
+    }
+    if src[0] >= 22 {
+        ip = 1;
+        copy_literals(&mut out, &mut ip, src[0] as usize - 17)?;
+        state = 4;
+    } else if src[0] >= 18 {
+        ip = 1;
+        state = src[0] as usize - 17;
+        copy_literals(&mut out, &mut ip, state)?;
+    }
+
+    loop {
+        let inst = byte(&mut ip)?;
+        let (back, len, next_state);
+        if inst & 0xC0 != 0 {
+            // M2: 3..8 byte match within 2 KiB
+            back = (byte(&mut ip)? << 3) + ((inst >> 2) & 0x7) + 1;
+            len = (inst >> 5) + 1;
+            next_state = inst & 0x3;
+        } else if inst & 0x20 != 0 {
+            // M3: match within 16 KiB
+            let mut l = (inst & 0x1f) + 2;
+            if l == 2 {
+                l += 31 + ext_len(&mut ip)?;
+            }
+            let d = byte(&mut ip)? | (byte(&mut ip)? << 8);
+            back = (d >> 2) + 1;
+            len = l;
+            next_state = d & 0x3;
+        } else if inst & 0x10 != 0 {
+            // M4: match within 48 KiB, or end of stream
+            let mut l = (inst & 0x7) + 2;
+            if l == 2 {
+                l += 7 + ext_len(&mut ip)?;
+            }
+            let d = byte(&mut ip)? | (byte(&mut ip)? << 8);
+            let dist = ((inst & 0x8) << 11) + (d >> 2);
+            if dist == 0 {
+                break;
+            }
+            back = dist + 16384;
+            len = l;
+            next_state = d & 0x3;
+        } else if state == 0 {
+            // Literal run
+            let mut l = inst + 3;
+            if l == 3 {
+                l += 15 + ext_len(&mut ip)?;
+            }
+            copy_literals(&mut out, &mut ip, l)?;
+            state = 4;
+            continue;
+        } else if state != 4 {
+            back = (inst >> 2) + (byte(&mut ip)? << 2) + 1;
+            len = 2;
+            next_state = inst & 0x3;
+        } else {
+            back = (inst >> 2) + (byte(&mut ip)? << 2) + 2049;
+            len = 3;
+            next_state = inst & 0x3;
+        }
+
+        if back > out.len() {
+            return Err(err());
+        }
+        let from = out.len() - back;
+        for i in from..from + len {
+            let b = out[i];
+            out.push(b);
+        }
+        state = next_state;
+        copy_literals(&mut out, &mut ip, next_state)?;
+    }
+
+    Ok(out)
+}
+
+fn decompress_block(block: &[u8], decomp_size: u64) -> Result<Vec<u8>, String> {
+    if block.len() < 8 {
+        return Err("mdict: block too short".to_string());
+    }
+    let kind = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+    let data = &block[8..];
+    match kind {
+        0 => Ok(data.to_vec()),
+        1 => lzo1x_decompress(data, decomp_size.min(MAX_PREALLOC) as usize),
+        2 => {
+            let mut out = Vec::with_capacity(decomp_size.min(MAX_PREALLOC) as usize);
+            flate2::read::ZlibDecoder::new(data)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("mdict: zlib: {e}"))?;
+            Ok(out)
+        }
+        other => Err(format!("mdict: unknown block compression {other}")),
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// Upper bound for buffers pre-sized from lengths stored in the file; larger data still grows on demand.
+const MAX_PREALLOC: u64 = 16 * 1024 * 1024;
+
+fn read_exact_at(f: &mut File, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    // Sizes come from the file itself; never allocate past its end.
+    let file_len = f.metadata().map_err(|e| e.to_string())?.len();
+    if offset.checked_add(len).is_none_or(|end| end > file_len) {
+        return Err("mdict: truncated file".to_string());
+    }
+    f.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    let mut buf = vec![0u8; len as usize];
+    f.read_exact(&mut buf).map_err(|e| format!("mdict: truncated file: {e}"))?;
+    Ok(buf)
+}
+
+fn read_number(buf: &[u8], pos: &mut usize, width: usize) -> Result<u64, String> {
+    let b = buf
+        .get(*pos..*pos + width)
+        .ok_or_else(|| "mdict: truncated number".to_string())?;
+    *pos += width;
+    Ok(b.iter().fold(0u64, |acc, x| (acc << 8) | *x as u64))
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_header_attrs(text: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = text;
+    while let Some(eq) = rest.find("=\"") {
+        let name = rest[..eq]
+            .rsplit(|c: char| c.is_whitespace() || c == '<')
+            .next()
+            .unwrap_or("")
+            .to_string();
+        let after = &rest[eq + 2..];
+        let Some(end) = after.find('"') else {
+            break;
+        };
+        if !name.is_empty() {
+            attrs.insert(name, unescape_xml(&after[..end]));
+        }
+        rest = &after[end + 1..];
+    }
+    attrs
+}
+
+fn parse_header(text: &str, is_mdd: bool) -> MdictHeader {
+    let attrs = parse_header_attrs(text);
+    let get = |k: &str| attrs.get(k).cloned().unwrap_or_default();
+
+    let version = get("GeneratedByEngineVersion").trim().parse::<f32>().unwrap_or(2.0);
+    let encrypted = match get("Encrypted").trim() {
+        "" | "No" | "no" => 0,
+        "Yes" | "yes" => 1,
+        v => v.parse::<u32>().unwrap_or(0),
+    };
+    let encoding = if is_mdd {
+        encoding_rs::UTF_16LE
+    } else {
+        let label = get("Encoding").trim().to_ascii_lowercase();
+        match label.as_str() {
+            "" => encoding_rs::UTF_8,
+            "gbk" | "gb2312" => encoding_rs::GB18030,
+            _ => encoding_rs::Encoding::for_label(label.as_bytes()).unwrap_or(encoding_rs::UTF_8),
+        }
+    };
+
+    MdictHeader {
+        version,
+        encrypted,
+        encoding,
+        title: get("Title"),
+        stylesheet: parse_stylesheet(&get("StyleSheet")),
+        key_case_sensitive: get("KeyCaseSensitive").eq_ignore_ascii_case("yes"),
+    }
+}
+
+/// The `StyleSheet` attribute is a list of line triples: number, begin markup, end markup.
+fn parse_stylesheet(text: &str) -> HashMap<u32, (String, String)> {
+    let lines: Vec<&str> = text.lines().map(|l| l.trim_end_matches('\r')).collect();
+    let mut out = HashMap::new();
+    let mut i = 0;
+    while i + 2 < lines.len() {
+        match lines[i].trim().parse::<u32>() {
+            Ok(n) => {
+                out.insert(n, (lines[i + 1].to_string(), lines[i + 2].to_string()));
+                i += 3;
+            }
+            Err(_) => i += 1,
+        }
+    }
+    out
+}
+
+/// Expands `` `N` `` style markers; each marker closes the previous style before opening its own.
+fn apply_stylesheet(text: &str, styles: &HashMap<u32, (String, String)>) -> String {
+    if styles.is_empty() || !text.contains('`') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut pending_end: Option<&str> = None;
+    let mut rest = text;
+    while let Some(start) = rest.find('`') {
+        let after = &rest[start + 1..];
+        let marker = after
+            .find('`')
+            .and_then(|end| after[..end].parse::<u32>().ok().map(|n| (n, end)))
+            .and_then(|(n, end)| styles.get(&n).map(|s| (s, end)));
+        match marker {
+            Some(((begin, end_tag), end)) => {
+                out.push_str(&rest[..start]);
+                if let Some(e) = pending_end.take() {
+                    out.push_str(e);
+                }
+                out.push_str(begin);
+                pending_end = Some(end_tag.as_str());
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[..start + 1]);
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    if let Some(e) = pending_end {
+        out.push_str(e);
+    }
+    out
+}
+
+fn is_utf16(enc: &'static encoding_rs::Encoding) -> bool {
+    enc == encoding_rs::UTF_16LE || enc == encoding_rs::UTF_16BE
+}
+
+fn decode_text(enc: &'static encoding_rs::Encoding, bytes: &[u8]) -> String {
+    let (s, _, _) = enc.decode(bytes);
+    s.trim_end_matches('\0').to_string()
+}
+
+/// Normalizes a key for lookups. MDD resource keys are paths like `\img\a.png`.
+fn normalize_key(key: &str, is_mdd: bool, case_sensitive: bool) -> String {
+    if is_mdd {
+        let k = key.trim().replace('/', "\\");
+        let k = if k.starts_with('\\') { k } else { format!("\\{}", k) };
+        return k.to_lowercase();
+    }
+    let k = key.trim();
+    if case_sensitive {
+        k.to_string()
+    } else {
+        k.to_lowercase()
+    }
+}
+
+impl MdictFile {
+    pub fn open(path: &Path, passcode: Option<&MdictPasscode>) -> Result<Self, String> {
+        let is_mdd = path
+            .extension()
+            .and_then(|s| s.to_str())
+            .map(|e| e.eq_ignore_ascii_case("mdd"))
+            .unwrap_or(false);
+        let mut file = File::open(path).map_err(|e| e.to_string())?;
+
+        // Header: big-endian length, UTF-16LE XML, little-endian adler32.
+        let len_buf = read_exact_at(&mut file, 0, 4)?;
+        let header_len = u32::from_be_bytes([len_buf[0], len_buf[1], len_buf[2], len_buf[3]]) as u64;
+        if header_len == 0 || header_len > 16 * 1024 * 1024 {
+            return Err("mdict: invalid header".to_string());
+        }
+        let header_bytes = read_exact_at(&mut file, 4, header_len)?;
+        let header_text = decode_text(encoding_rs::UTF_16LE, &header_bytes);
+        let header = parse_header(&header_text, is_mdd);
+        let mut offset = 4 + header_len + 4;
+
+        let v2 = header.version >= 2.0;
+        let nw: usize = if v2 { 8 } else { 4 };
+        let unit = if is_utf16(header.encoding) { 2 } else { 1 };
+
+        // Key section header.
+        let key_header_len = if v2 { 8 * 5 } else { 4 * 4 };
+        let mut key_header = read_exact_at(&mut file, offset, key_header_len)?;
+        offset += key_header_len;
+        if header.encrypted & 1 != 0 {
+            let passcode = passcode
+                .ok_or_else(|| "mdict: dictionary is encrypted; a registration code is required".to_string())?;
+            key_header = salsa20_xor(&passcode_key(passcode)?, &key_header, 8);
+        }
+        let mut p = 0usize;
+        let _num_key_blocks = read_number(&key_header, &mut p, nw)?;
+        let num_entries = read_number(&key_header, &mut p, nw)?;
+        if v2 {
+            let _key_info_decomp_size = read_number(&key_header, &mut p, nw)?;
+        }
+        let key_info_size = read_number(&key_header, &mut p, nw)?;
+        let key_blocks_size = read_number(&key_header, &mut p, nw)?;
+        if v2 {
+            offset += 4; // adler32 of the key section header
+        }
+
+        // Key block info.
+        let mut key_info = read_exact_at(&mut file, offset, key_info_size)?;
+        offset += key_info_size;
+        if v2 {
+            if header.encrypted & 2 != 0 {
+                mdx_decrypt(&mut key_info)?;
+            }
+            key_info = decompress_block(&key_info, 0)?;
+        }
+        let mut key_block_sizes: Vec<(u64, u64)> = vec![];
+        let (sw, term) = if v2 { (2usize, 1usize) } else { (1usize, 0usize) };
+        let mut p = 0usize;
+        while p < key_info.len() {
+            let _entries = read_number(&key_info, &mut p, nw)?;
+            let head = read_number(&key_info, &mut p, sw)? as usize;
+            p += (head + term) * unit;
+            let tail = read_number(&key_info, &mut p, sw)? as usize;
+            p += (tail + term) * unit;
+            let comp = read_number(&key_info, &mut p, nw)?;
+            let decomp = read_number(&key_info, &mut p, nw)?;
+            key_block_sizes.push((comp, decomp));
+        }
+
+        // Key blocks.
+        let key_blocks = read_exact_at(&mut file, offset, key_blocks_size)?;
+        offset += key_blocks_size;
+        let mut keys: Vec<(String, u64)> = Vec::with_capacity(num_entries.min(4_000_000) as usize);
+        let mut start = 0usize;
+        for (comp, decomp) in key_block_sizes {
+            let end = start + comp as usize;
+            let raw = key_blocks
+                .get(start..end)
+                .ok_or_else(|| "mdict: key block out of range".to_string())?;
+            let block = decompress_block(raw, decomp)?;
+            let mut i = 0usize;
+            while i + nw <= block.len() {
+                let record_offset = read_number(&block, &mut i, nw)?;
+                let text_start = i;
+                let mut text_end = block.len();
+                let mut j = i;
+                while j + unit <= block.len() {
+                    if block[j..j + unit].iter().all(|b| *b == 0) {
+                        text_end = j;
+                        break;
+                    }
+                    j += unit;
+                }
+                keys.push((decode_text(header.encoding, &block[text_start..text_end]), record_offset));
+                i = text_end + unit;
+            }
+            start = end;
+        }
+
+        // Record section.
+        let rec_header = read_exact_at(&mut file, offset, (nw * 4) as u64)?;
+        offset += (nw * 4) as u64;
+        let mut p = 0usize;
+        let num_record_blocks = read_number(&rec_header, &mut p, nw)?;
+        let _num_entries = read_number(&rec_header, &mut p, nw)?;
+        let record_info_size = read_number(&rec_header, &mut p, nw)?;
+        let _record_blocks_size = read_number(&rec_header, &mut p, nw)?;
+        let rec_info = read_exact_at(&mut file, offset, record_info_size)?;
+        offset += record_info_size;
+
+        let mut record_blocks: Vec<RecordBlock> = Vec::with_capacity(num_record_blocks.min((rec_info.len() / (2 * nw)) as u64) as usize);
+        let mut p = 0usize;
+        let mut decomp_offset = 0u64;
+        for _ in 0..num_record_blocks {
+            let comp_size = read_number(&rec_info, &mut p, nw)?;
+            let decomp_size = read_number(&rec_info, &mut p, nw)?;
+            record_blocks.push(RecordBlock {
+                file_offset: offset,
+                comp_size,
+                decomp_offset,
+                decomp_size,
+            });
+            offset += comp_size;
+            decomp_offset += decomp_size;
+        }
+
+        let mut index: HashMap<String, Vec<usize>> = HashMap::with_capacity(keys.len());
+        for (i, (k, _)) in keys.iter().enumerate() {
+            index
+                .entry(normalize_key(k, is_mdd, header.key_case_sensitive))
+                .or_default()
+                .push(i);
+        }
+
+        Ok(Self {
+            header,
+            is_mdd,
+            file,
+            keys,
+            index,
+            record_blocks,
+            total_decomp: decomp_offset,
+            block_cache: None,
+        })
+    }
+
+    fn record_block(&mut self, idx: usize) -> Result<&[u8], String> {
+        if self.block_cache.as_ref().map(|(i, _)| *i) != Some(idx) {
+            let (file_offset, comp_size, decomp_size) = {
+                let b = &self.record_blocks[idx];
+                (b.file_offset, b.comp_size, b.decomp_size)
+            };
+            let raw = read_exact_at(&mut self.file, file_offset, comp_size)?;
+            let data = decompress_block(&raw, decomp_size)?;
+            self.block_cache = Some((idx, data));
+        }
+        Ok(&self.block_cache.as_ref().unwrap().1)
+    }
+
+    fn record_bytes(&mut self, key_idx: usize) -> Result<Vec<u8>, String> {
+        let start = self.keys[key_idx].1;
+        let end = self
+            .keys
+            .get(key_idx + 1)
+            .map(|k| k.1)
+            .unwrap_or(self.total_decomp);
+        if end < start {
+            return Err("mdict: record offsets out of order".to_string());
+        }
+
+        let mut out = Vec::with_capacity((end - start).min(MAX_PREALLOC) as usize);
+        let mut pos = start;
+        while pos < end {
+            let block_idx = self
+                .record_blocks
+                .partition_point(|b| b.decomp_offset + b.decomp_size <= pos);
+            if block_idx >= self.record_blocks.len() {
+                return Err("mdict: record out of range".to_string());
+            }
+            let block_start = self.record_blocks[block_idx].decomp_offset;
+            let data = self.record_block(block_idx)?;
+            let from = (pos - block_start) as usize;
+            let to = ((end - block_start) as usize).min(data.len());
+            if from >= to {
+                return Err("mdict: record out of range".to_string());
+            }
+            out.extend_from_slice(&data[from..to]);
+            pos = block_start + to as u64;
+        }
+        Ok(out)
+    }
+
+    /// Returns the raw HTML definitions for `word` (following `@@@LINK=` redirects).
+    pub fn lookup(&mut self, word: &str) -> Result<Vec<String>, String> {
+        let mut out: Vec<String> = vec![];
+        let mut target = word.to_string();
+        for _ in 0..5 {
+            let key = normalize_key(&target, self.is_mdd, self.header.key_case_sensitive);
+            let Some(ids) = self.index.get(&key).cloned() else {
+                break;
+            };
+            let mut redirect: Option<String> = None;
+            for id in ids {
+                let bytes = self.record_bytes(id)?;
+                let text = decode_text(self.header.encoding, &bytes);
+                let trimmed = text.trim();
+                if let Some(link) = trimmed.strip_prefix("@@@LINK=") {
+                    redirect = Some(link.trim().to_string());
+                } else if !trimmed.is_empty() {
+                    let expanded = apply_stylesheet(trimmed, &self.header.stylesheet);
+                    if !out.contains(&expanded) {
+                        out.push(expanded);
+                    }
+                }
+            }
+            match redirect {
+                Some(r) if out.is_empty() => target = r,
+                _ => break,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Returns the bytes of a resource stored in an `.mdd` file.
+    pub fn resource(&mut self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        let key = normalize_key(path, true, false);
+        let Some(id) = self.index.get(&key).and_then(|v| v.first().copied()) else {
+            return Ok(None);
+        };
+        self.record_bytes(id).map(Some)
+    }
+}
+
+/// An `.mdx` together with its companion `.mdd` resource files (`x.mdd`, `x.1.mdd`, ...).
+pub struct MdictDictionary {
+    pub mdx: MdictFile,
+    mdd_paths: Vec<PathBuf>,
+    mdd: Vec<MdictFile>,
+}
+
+impl MdictDictionary {
+    pub fn open(mdx_path: &Path, passcode: Option<&MdictPasscode>) -> Result<Self, String> {
+        let mdx = MdictFile::open(mdx_path, passcode)?;
+        Ok(Self {
+            mdx,
+            mdd_paths: companion_mdd_paths(mdx_path),
+            mdd: vec![],
+        })
+    }
+
+    pub fn resource(&mut self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        if self.mdd.len() < self.mdd_paths.len() {
+            self.mdd = self
+                .mdd_paths
+                .iter()
+                .filter_map(|p| match MdictFile::open(p, None) {
+                    Ok(f) => Some(f),
+                    Err(e) => {
+                        log::warn!("[mdict] failed to open {}: {}", p.to_string_lossy(), e);
+                        None
+                    }
+                })
+                .collect();
+            self.mdd_paths.truncate(self.mdd.len());
+        }
+        for f in self.mdd.iter_mut() {
+            if let Some(bytes) = f.resource(path)? {
+                return Ok(Some(bytes));
+            }
+        }
+        Ok(None)
+    }
+}
+
+pub(crate) fn companion_mdd_paths(mdx_path: &Path) -> Vec<PathBuf> {
+    let Some(stem) = mdx_path.file_stem().and_then(|s| s.to_str()) else {
+        return vec![];
+    };
+    let dir = mdx_path.parent().unwrap_or(Path::new("."));
+    let mut out = vec![];
+    let main = dir.join(format!("{}.mdd", stem));
+    if main.exists() {
+        out.push(main);
+    }
+    for i in 1..100 {
+        let p = dir.join(format!("{}.{}.mdd", stem, i));
+        if !p.exists() {
+            break;
+        }
+        out.push(p);
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// HTML sanitizing
+// ---------------------------------------------------------------------------
+
+const DROP_WITH_CONTENT: &[&str] = &["script", "style", "iframe", "object", "embed", "applet", "noscript", "template"];
+const DROP_TAG: &[&str] = &["link", "meta", "base", "form", "input", "button", "textarea", "select", "frame", "frameset"];
+
+/// Lowercase, without the whitespace and control characters browsers ignore inside a scheme.
+fn compact_lower(v: &str) -> String {
+    v.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+/// Resolves CSS escapes (`\6a`, `\:`) so that `java\script:` cannot slip past the style check.
+fn css_unescape(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    let mut chars = v.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let mut hex = String::new();
+        while hex.len() < 6 && chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(chars.next().unwrap());
+        }
+        if hex.is_empty() {
+            out.extend(chars.next());
+        } else {
+            out.push(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).unwrap_or('\u{fffd}'));
+            chars.next_if(|c| c.is_whitespace());
+        }
+    }
+    out
+}
+
+fn url_allowed(v: &str) -> bool {
+    let lower = compact_lower(&decode_html_entities(v));
+    match lower.find(':') {
+        None => true,
+        Some(i) if lower[..i].contains('/') || lower[..i].contains('#') || lower[..i].contains('?') => true,
+        Some(i) => matches!(
+            &lower[..i],
+            "http" | "https" | "entry" | "sound" | "bword" | "mailto"
+        ) || lower.starts_with("data:image/"),
+    }
+}
+
+fn sanitize_tag(raw: &str) -> Option<String> {
+    // raw is the inside of `<...>`
+    let closing = raw.starts_with('/');
+    let body = raw.trim_start_matches('/');
+    let name_end = body
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(body.len());
+    let name = body[..name_end].to_ascii_lowercase();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    if DROP_TAG.contains(&name.as_str()) {
+        return None;
+    }
+    if closing {
+        return Some(format!("</{}>", name));
+    }
+
+    let mut out = format!("<{}", name);
+    let attrs = &body[name_end..];
+    let chars: Vec<char> = attrs.chars().collect();
+    let mut i = 0usize;
+    while i < chars.len() {
+        while i < chars.len() && (chars[i].is_whitespace() || chars[i] == '/') {
+            i += 1;
+        }
+        let ns = i;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '=' && chars[i] != '/' {
+            i += 1;
+        }
+        let attr: String = chars[ns..i].iter().collect::<String>().to_ascii_lowercase();
+        if attr.is_empty() {
+            i += 1;
+            continue;
+        }
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        let mut value: Option<String> = None;
+        if i < chars.len() && chars[i] == '=' {
+            i += 1;
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == '"' || chars[i] == '\'') {
+                let q = chars[i];
+                i += 1;
+                let vs = i;
+                while i < chars.len() && chars[i] != q {
+                    i += 1;
+                }
+                value = Some(chars[vs..i].iter().collect());
+                i += 1;
+            } else {
+                let vs = i;
+                while i < chars.len() && !chars[i].is_whitespace() {
+                    i += 1;
+                }
+                value = Some(chars[vs..i].iter().collect());
+            }
+        }
+
+        if attr.starts_with("on") || attr == "srcdoc" || attr == "formaction" {
+            continue;
+        }
+        if matches!(attr.as_str(), "href" | "src" | "xlink:href" | "action" | "background" | "poster")
+            && !value.as_deref().map(url_allowed).unwrap_or(true)
+        {
+            continue;
+        }
+        if attr == "style" {
+            let lower = compact_lower(&css_unescape(&decode_html_entities(value.as_deref().unwrap_or(""))));
+            if lower.contains("expression(") || lower.contains("javascript:") || lower.contains("vbscript:") {
+                continue;
+            }
+        }
+        match value {
+            Some(v) => out.push_str(&format!(" {}=\"{}\"", attr, v.replace('"', "&quot;"))),
+            None => out.push_str(&format!(" {}", attr)),
+        }
+    }
+    if raw.trim_end().ends_with('/') {
+        out.push_str(" /");
+    }
+    out.push('>');
+    Some(out)
+}
+
+/// Removes scripts, embedded content, event handlers and non-allowlisted URL schemes
+/// from dictionary HTML while keeping its formatting markup.
+pub(crate) fn sanitize_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let lower = input.to_ascii_lowercase();
+    let mut i = 0usize;
+    while i < input.len() {
+        let rest = &input[i..];
+        if rest.starts_with("<!--") {
+            i += rest.find("-->").map(|e| e + 3).unwrap_or(rest.len());
+            continue;
+        }
+        if rest.starts_with('<') {
+            let Some(end) = rest.find('>') else {
+                out.push_str("&lt;");
+                i += 1;
+                continue;
+            };
+            let inner = &rest[1..end];
+            let name: String = inner
+                .trim_start_matches('/')
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase();
+            if !inner.starts_with('/') && DROP_WITH_CONTENT.contains(&name.as_str()) {
+                let close = format!("</{}", name);
+                i = match lower[i + end..].find(&close) {
+                    Some(c) => {
+                        let after = i + end + c;
+                        after + input[after..].find('>').map(|x| x + 1).unwrap_or(input.len() - after)
+                    }
+                    None => input.len(),
+                };
+                continue;
+            }
+            if inner.starts_with('/') && DROP_WITH_CONTENT.contains(&name.as_str()) {
+                i += end + 1;
+                continue;
+            }
+            if let Some(tag) = sanitize_tag(inner) {
+                out.push_str(&tag);
+            }
+            i += end + 1;
+            continue;
+        }
+        let next = rest.find('<').unwrap_or(rest.len());
+        out.push_str(&rest[..next]);
+        i += next;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_salsa20_ecrypt_vector() {
+        // eSTREAM Salsa20/20, 128-bit key, set 1 vector 0
+        let mut key = [0u8; 16];
+        key[0] = 0x80;
+        let ks = salsa20_xor(&key, &[0u8; 16], 20);
+        assert_eq!(
+            ks,
+            vec![0x4D, 0xFA, 0x5E, 0x48, 0x1D, 0xA2, 0x3E, 0xA0, 0x9A, 0x31, 0x02, 0x20, 0x50, 0x85, 0x99, 0x36]
+        );
+    }
+
+    #[test]
+    fn test_salsa20_roundtrip() {
+        let key = *b"0123456789abcdef";
+        let data: Vec<u8> = (0..200u32).map(|x| x as u8).collect();
+        let enc = salsa20_xor(&key, &data, 8);
+        assert_ne!(enc, data);
+        assert_eq!(salsa20_xor(&key, &enc, 8), data);
+    }
+
+    #[test]
+    fn test_ripemd128_vector() {
+        assert_eq!(
+            ripemd128(b"abc"),
+            [0xc1, 0x4a, 0x12, 0x19, 0x9c, 0x66, 0xe4, 0xba, 0x84, 0x63, 0x6b, 0x0f, 0x69, 0x14, 0x4c, 0x77]
+        );
+    }
+
+    #[test]
+    fn test_fast_decrypt_inverts() {
+        let key = ripemd128(b"k");
+        let plain = b"hello mdict key block info".to_vec();
+        // encrypt: inverse of fast_decrypt
+        let mut enc = plain.clone();
+        let mut previous: u8 = 0x36;
+        for (i, b) in enc.iter_mut().enumerate() {
+            let t = *b ^ previous ^ (i as u8) ^ key[i % key.len()];
+            *b = t.rotate_left(4);
+            previous = *b;
+        }
+        fast_decrypt(&mut enc, &key);
+        assert_eq!(enc, plain);
+    }
+
+    #[test]
+    fn test_lzo_literals_only() {
+        let src = [22u8, b'h', b'e', b'l', b'l', b'o', 0x11, 0, 0];
+        assert_eq!(lzo1x_decompress(&src, 5).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_lzo_with_match() {
+        // "abc" literal, then M2 match len 6 at distance 3
+        let src = [20u8, b'a', b'b', b'c', 0xA8, 0x00, 0x11, 0, 0];
+        assert_eq!(lzo1x_decompress(&src, 9).unwrap(), b"abcabcabc");
+    }
+
+    #[test]
+    fn test_lzo_rejects_bad_distance() {
+        let src = [20u8, b'a', b'b', b'c', 0xA8, 0xFF, 0x11, 0, 0];
+        assert!(lzo1x_decompress(&src, 9).is_err());
+    }
+
+    #[test]
+    fn test_read_exact_at_rejects_lengths_past_end() {
+        let path = std::env::temp_dir().join(format!("aireader-mdict-{}.mdx", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let mut f = File::open(&path).unwrap();
+        assert_eq!(read_exact_at(&mut f, 2, 3).unwrap(), b"234");
+        assert!(read_exact_at(&mut f, 4, 1 << 40).is_err());
+        assert!(read_exact_at(&mut f, u64::MAX, 2).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_header_attrs() {
+        let h = parse_header(
+            r#"<Dictionary GeneratedByEngineVersion="2.0" RequiredEngineVersion="2.0" Encrypted="2" Encoding="UTF-8" Format="Html" KeyCaseSensitive="No" Title="Test &amp; Co" />"#,
+            false,
+        );
+        assert_eq!(h.version, 2.0);
+        assert_eq!(h.encrypted, 2);
+        assert_eq!(h.title, "Test & Co");
+        assert!(!h.key_case_sensitive);
+        assert_eq!(h.encoding, encoding_rs::UTF_8);
+    }
+
+    #[test]
+    fn test_stylesheet() {
+        let styles = parse_stylesheet("1\n<b>\n</b>\n2\n<i>\n</i>\n");
+        assert_eq!(styles.len(), 2);
+        assert_eq!(apply_stylesheet("`1`bold`2`it", &styles), "<b>bold</b><i>it</i>");
+        assert_eq!(apply_stylesheet("a `9` b", &styles), "a `9` b");
+        assert_eq!(apply_stylesheet("plain", &HashMap::new()), "plain");
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let html = r#"<div class="e" onclick="x()"><script>alert(1)</script><a href="javascript:evil()">a</a><a href="entry://word">w</a><img src="img/a.png" onerror="x"><style>p{}</style></div>"#;
+        let s = sanitize_html(html);
+        assert_eq!(s, r#"<div class="e"><a>a</a><a href="entry://word">w</a><img src="img/a.png"></div>"#);
+
+        let encoded = concat!(
+            r#"<a href="javascript&#58;alert(1)">1</a><a href="&#x6A;avascript:x()">2</a>"#,
+            r#"<a href="java&Tab;script&colon;x()">3</a><p style="background:url(java\73 cript:x)">4</p>"#,
+            r#"<a href="entry://caf&eacute;">5</a>"#,
+        );
+        assert_eq!(
+            sanitize_html(encoded),
+            r#"<a>1</a><a>2</a><a>3</a><p>4</p><a href="entry://caf&eacute;">5</a>"#
+        );
+    }
+
+    // --- A tiny v2.0 MDX writer used to exercise the reader end to end. ---
+
+    fn zlib_block(data: &[u8]) -> Vec<u8> {
+        let mut enc = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        let mut out = vec![2, 0, 0, 0, 0, 0, 0, 0];
+        out.extend(enc.finish().unwrap());
+        out
+    }
+
+    fn write_test_mdx(path: &Path, entries: &[(&str, &str)], encrypt_key_info: bool) {
+        let be = |n: u64| n.to_be_bytes().to_vec();
+        let mut f = Vec::new();
+
+        let header = format!(
+            r#"<Dictionary GeneratedByEngineVersion="2.0" Encrypted="{}" Encoding="UTF-8" KeyCaseSensitive="No" Title="Tiny"/>"#,
+            if encrypt_key_info { 2 } else { 0 }
+        );
+        let mut hb: Vec<u8> = header.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        hb.extend([0, 0]);
+        f.extend((hb.len() as u32).to_be_bytes());
+        f.extend(&hb);
+        f.extend([0u8; 4]);
+
+        // one key block + one record block
+        let mut key_block = vec![];
+        let mut records = vec![];
+        for (k, v) in entries {
+            key_block.extend(be(records.len() as u64));
+            key_block.extend(k.as_bytes());
+            key_block.push(0);
+            records.extend(v.as_bytes());
+            records.push(0);
+        }
+        let key_block_c = zlib_block(&key_block);
+
+        let (first, last) = (entries[0].0, entries[entries.len() - 1].0);
+        let mut info = vec![];
+        info.extend(be(entries.len() as u64));
+        info.extend((first.len() as u16).to_be_bytes());
+        info.extend(first.as_bytes());
+        info.push(0);
+        info.extend((last.len() as u16).to_be_bytes());
+        info.extend(last.as_bytes());
+        info.push(0);
+        info.extend(be(key_block_c.len() as u64));
+        info.extend(be(key_block.len() as u64));
+        let mut info_c = zlib_block(&info);
+        if encrypt_key_info {
+            info_c[4..8].copy_from_slice(&[1, 2, 3, 4]);
+            let mut seed = [0u8; 8];
+            seed[..4].copy_from_slice(&info_c[4..8]);
+            seed[4..].copy_from_slice(&0x3695u32.to_le_bytes());
+            let key = ripemd128(&seed);
+            let mut previous: u8 = 0x36;
+            for (i, b) in info_c[8..].iter_mut().enumerate() {
+                let t = *b ^ previous ^ (i as u8) ^ key[i % key.len()];
+                *b = t.rotate_left(4);
+                previous = *b;
+            }
+        }
+
+        f.extend(be(1));
+        f.extend(be(entries.len() as u64));
+        f.extend(be(info.len() as u64));
+        f.extend(be(info_c.len() as u64));
+        f.extend(be(key_block_c.len() as u64));
+        f.extend([0u8; 4]);
+        f.extend(&info_c);
+        f.extend(&key_block_c);
+
+        let rec_c = zlib_block(&records);
+        f.extend(be(1));
+        f.extend(be(entries.len() as u64));
+        f.extend(be(16));
+        f.extend(be(rec_c.len() as u64));
+        f.extend(be(rec_c.len() as u64));
+        f.extend(be(records.len() as u64));
+        f.extend(&rec_c);
+
+        std::fs::write(path, f).unwrap();
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aireader_mdict_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(name)
+    }
+
+    #[test]
+    fn test_read_tiny_mdx() {
+        let p = temp_path("tiny.mdx");
+        write_test_mdx(
+            &p,
+            &[("apple", "<b>apple</b> n. 苹果"), ("Apples", "@@@LINK=apple"), ("banana", "<i>banana</i>")],
+            false,
+        );
+        let mut d = MdictFile::open(&p, None).unwrap();
+        assert_eq!(d.keys.len(), 3);
+        assert_eq!(d.header.title, "Tiny");
+        assert_eq!(d.lookup("APPLE").unwrap(), vec!["<b>apple</b> n. 苹果".to_string()]);
+        assert_eq!(d.lookup("apples").unwrap(), vec!["<b>apple</b> n. 苹果".to_string()]);
+        assert_eq!(d.lookup("banana").unwrap(), vec!["<i>banana</i>".to_string()]);
+        assert!(d.lookup("cherry").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_mdx_with_encrypted_key_info() {
+        let p = temp_path("enc.mdx");
+        write_test_mdx(&p, &[("a", "first"), ("b", "second")], true);
+        let mut d = MdictFile::open(&p, None).unwrap();
+        assert_eq!(d.lookup("b").unwrap(), vec!["second".to_string()]);
+    }
+
+    #[test]
+    fn test_companion_mdd_paths() {
+        let p = temp_path("comp.mdx");
+        std::fs::write(&p, b"").unwrap();
+        std::fs::write(temp_path("comp.mdd"), b"").unwrap();
+        std::fs::write(temp_path("comp.1.mdd"), b"").unwrap();
+        let found = companion_mdd_paths(&p);
+        assert_eq!(found.len(), 2);
+    }
+}
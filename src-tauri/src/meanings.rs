@@ -0,0 +1,313 @@
+use crate::dictionary::{clean_definition_text, DictionaryMeaning};
+
+/// Part-of-speech abbreviations used by ECDICT and common StarDict dictionaries.
+const POS_TAGS: &[&str] = &[
+    "n", "v", "vt", "vi", "adj", "a", "adv", "ad", "prep", "conj", "pron", "art", "num", "int", "interj",
+    "aux", "abbr", "pl", "phr", "suf", "pref", "det", "modal", "link-v", "na",
+];
+
+/// Prefixes that mark an example sentence line.
+const EXAMPLE_PREFIXES: &[&str] = &["e.g.", "eg.", "eg:", "例：", "例:", "例句：", "例句:", "Example:", "Ex:"];
+
+/// Class names whose elements hold example sentences in StarDict HTML.
+const EXAMPLE_CLASSES: &[&str] = &["example", "examples", "ex", "eg", "sentence", "sent", "x"];
+
+pub(crate) fn is_cjk(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c)
+}
+
+/// Splits a leading part-of-speech marker off a definition line: `vt. 放弃` → (`vt.`, `放弃`),
+/// `vt. & vi. 开始` → (`vt. & vi.`, `开始`), `[网络] 放弃` → (`[网络]`, `放弃`).
+pub(crate) fn split_pos(line: &str) -> Option<(String, &str)> {
+    let line = line.trim_start();
+    if let Some(inner) = line.strip_prefix('[') {
+        let close = inner.find(']')?;
+        let label = &inner[..close];
+        // Only CJK domain labels such as [网络] or [计]; `[bʊk]` is a phonetic.
+        if label.chars().count() > 8 || !label.chars().any(is_cjk) {
+            return None;
+        }
+        return Some((format!("[{}]", label), inner[close + 1..].trim_start()));
+    }
+
+    let mut rest = line;
+    let mut tags: Vec<&str> = vec![];
+    let mut seps: Vec<&str> = vec![];
+    loop {
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphabetic() || c == '-'))
+            .unwrap_or(rest.len());
+        let tag = &rest[..len];
+        if len == 0 || !rest[len..].starts_with('.') || !POS_TAGS.contains(&tag.to_ascii_lowercase().as_str()) {
+            break;
+        }
+        tags.push(&rest[..len + 1]);
+        rest = &rest[len + 1..];
+
+        let trimmed = rest.trim_start();
+        let sep = ["&", "/", ","].iter().find(|s| trimmed.starts_with(**s));
+        match sep {
+            Some(s) => {
+                seps.push(s);
+                rest = trimmed[s.len()..].trim_start();
+            }
+            None => break,
+        }
+    }
+    if tags.is_empty() || seps.len() >= tags.len() {
+        // No tag, or a dangling separator with no tag after it.
+        return None;
+    }
+    let mut pos = tags[0].to_string();
+    for (sep, tag) in seps.iter().zip(tags.iter().skip(1)) {
+        if *sep == "&" {
+            pos.push_str(" & ");
+        } else {
+            pos.push_str(sep);
+        }
+        pos.push_str(tag);
+    }
+    Some((pos, rest.trim_start()))
+}
+
+fn strip_example_prefix(line: &str) -> Option<&str> {
+    EXAMPLE_PREFIXES
+        .iter()
+        .find_map(|p| line.strip_prefix(p))
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+}
+
+/// Groups definition lines by part of speech, keeping first-appearance order. Lines without
+/// a marker continue the current group; example lines are attached to it as examples.
+pub(crate) fn group_definition_lines<S: AsRef<str>>(lines: &[S]) -> Vec<DictionaryMeaning> {
+    let mut groups: Vec<DictionaryMeaning> = vec![];
+    let mut current: Option<usize> = None;
+
+    for line in lines {
+        let line = line.as_ref().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let idx = |groups: &mut Vec<DictionaryMeaning>, pos: &str| match groups.iter().position(|g| g.part_of_speech == pos) {
+            Some(i) => i,
+            None => {
+                groups.push(DictionaryMeaning {
+                    part_of_speech: pos.to_string(),
+                    definitions: vec![],
+                    examples: vec![],
+                });
+                groups.len() - 1
+            }
+        };
+
+        if let Some(example) = strip_example_prefix(line) {
+            let i = current.unwrap_or_else(|| idx(&mut groups, ""));
+            groups[i].examples.push(example.to_string());
+            current = Some(i);
+            continue;
+        }
+
+        let (pos, text) = match split_pos(line) {
+            Some((pos, text)) => (Some(pos), text),
+            None => (None, line),
+        };
+        let i = match pos {
+            Some(p) => idx(&mut groups, &p),
+            None => current.unwrap_or_else(|| idx(&mut groups, "")),
+        };
+        current = Some(i);
+        if !text.is_empty() && !groups[i].definitions.iter().any(|d| d == text) {
+            groups[i].definitions.push(text.to_string());
+        }
+    }
+
+    groups.retain(|g| !g.definitions.is_empty() || !g.examples.is_empty());
+    groups
+}
+
+/// A whole-line phonetic such as `*[ə'bændən]` or `/bʊk/`, as StarDict exports of ECDICT
+/// put before the definitions.
+pub(crate) fn phonetic_line(line: &str) -> Option<String> {
+    let t = line.trim();
+    let t = t.strip_prefix('*').unwrap_or(t);
+    let inner = t
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .or_else(|| t.strip_prefix('/').and_then(|s| s.strip_suffix('/')))?;
+    if inner.is_empty() || inner.chars().any(is_cjk) {
+        return None;
+    }
+    Some(inner.trim().to_string())
+}
+
+fn class_is_example(tag: &str) -> bool {
+    let lower = tag.to_ascii_lowercase();
+    let Some(pos) = lower.find("class=") else {
+        return false;
+    };
+    let rest = &lower[pos + 6..];
+    let value = match rest.chars().next() {
+        Some(q @ ('"' | '\'')) => rest[1..].split(q).next().unwrap_or(""),
+        _ => rest.split(|c: char| c.is_whitespace() || c == '>').next().unwrap_or(""),
+    };
+    value.split_whitespace().any(|c| EXAMPLE_CLASSES.contains(&c))
+}
+
+/// Rewrites HTML elements whose class marks them as examples into `e.g. …` lines so that
+/// they survive tag stripping and can be told apart from definitions.
+pub(crate) fn mark_html_examples(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(lt) = rest.find('<') {
+        out.push_str(&rest[..lt]);
+        let after = &rest[lt..];
+        let Some(gt) = after.find('>') else {
+            out.push_str(after);
+            return out;
+        };
+        let tag = &after[..=gt];
+        let name: String = tag[1..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        if name.is_empty() || !class_is_example(tag) || tag.ends_with("/>") {
+            out.push_str(tag);
+            rest = &after[gt + 1..];
+            continue;
+        }
+
+        // Find the matching close tag, counting nested elements of the same name.
+        let body = &after[gt + 1..];
+        let lower = body.to_ascii_lowercase();
+        let open = format!("<{}", name);
+        let close = format!("</{}", name);
+        let mut depth = 1;
+        let mut i = 0;
+        let mut end = None;
+        while i < lower.len() {
+            let next_open = lower[i..].find(&open).map(|p| p + i);
+            let next_close = lower[i..].find(&close).map(|p| p + i);
+            match (next_open, next_close) {
+                (Some(o), Some(c)) if o < c => {
+                    depth += 1;
+                    i = o + open.len();
+                }
+                (_, Some(c)) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(c);
+                        break;
+                    }
+                    i = c + close.len();
+                }
+                _ => break,
+            }
+        }
+        let Some(end) = end else {
+            out.push_str(tag);
+            rest = &after[gt + 1..];
+            continue;
+        };
+        let inner = clean_definition_text(&body[..end]).replace('\n', " ");
+        let inner = inner.trim();
+        if !inner.is_empty() {
+            out.push_str("<br>e.g. ");
+            out.push_str(inner);
+            out.push_str("<br>");
+        }
+        let close_end = body[end..].find('>').map(|p| end + p + 1).unwrap_or(body.len());
+        rest = &body[close_end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Turns a raw definition (plain text or HTML) into trimmed, non-empty lines, with HTML
+/// examples marked and block elements ending lines.
+pub(crate) fn definition_lines(raw: &str) -> Vec<String> {
+    let marked = if raw.contains('<') {
+        mark_html_examples(raw)
+            .replace("</p>", "<br>")
+            .replace("</div>", "<br>")
+            .replace("</li>", "<br>")
+    } else {
+        raw.to_string()
+    };
+    clean_definition_text(&marked)
+        .split('\n')
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(s: &str) -> Vec<String> {
+        definition_lines(s)
+    }
+
+    #[test]
+    fn test_split_pos() {
+        assert_eq!(split_pos("vt. 放弃, 抛弃"), Some(("vt.".to_string(), "放弃, 抛弃")));
+        assert_eq!(split_pos("vt. & vi. 开始"), Some(("vt. & vi.".to_string(), "开始")));
+        assert_eq!(split_pos("n./v. 书"), Some(("n./v.".to_string(), "书")));
+        assert_eq!(split_pos("[网络] 放弃"), Some(("[网络]".to_string(), "放弃")));
+        assert_eq!(split_pos("[bʊk]"), None);
+        assert_eq!(split_pos("no. 7"), None);
+        assert_eq!(split_pos("a book"), None);
+    }
+
+    /// ECDICT `abandon` row (translation and definition columns, `\n` escaped as in the CSV).
+    #[test]
+    fn test_ecdict_abandon() {
+        let translation = "vt. 放弃, 抛弃, 遗弃, 使屈服, 沉溺, 放纵\\nn. 放任, 狂热\\n[网络] 放弃；遗弃；抛弃";
+        let groups = group_definition_lines(&lines(translation));
+        let pos: Vec<&str> = groups.iter().map(|g| g.part_of_speech.as_str()).collect();
+        assert_eq!(pos, vec!["vt.", "n.", "[网络]"]);
+        assert_eq!(groups[0].definitions, vec!["放弃, 抛弃, 遗弃, 使屈服, 沉溺, 放纵"]);
+
+        let definition = "n. the trait of lacking restraint or control; reckless freedom from inhibition or worry\\nn. a feeling of extreme emotional intensity\\nv. forsake, leave behind\\nv. give up with the intent of never claiming again";
+        let groups = group_definition_lines(&lines(definition));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].definitions.len(), 2);
+        assert_eq!(groups[1].part_of_speech, "v.");
+        assert_eq!(groups[1].definitions[0], "forsake, leave behind");
+    }
+
+    /// ECDICT StarDict export entry for `book`: phonetic line, then one line per POS.
+    #[test]
+    fn test_stardict_ecdict_book() {
+        let raw = "*[bʊk]\nn. 书, 书籍, 账簿, 名册, 工作簿\nvt. 预订, 登记\n[网络] 图书；预订；书本";
+        let ls = lines(raw);
+        assert_eq!(phonetic_line(&ls[0]).as_deref(), Some("bʊk"));
+        let groups = group_definition_lines(&ls[1..]);
+        let pos: Vec<&str> = groups.iter().map(|g| g.part_of_speech.as_str()).collect();
+        assert_eq!(pos, vec!["n.", "vt.", "[网络]"]);
+    }
+
+    /// HTML StarDict entry (sametypesequence=h) with numbered senses and example spans.
+    #[test]
+    fn test_stardict_html_examples() {
+        let html = r#"<b>run</b> <font color="blue">/rʌn/</font><br><i>v.</i> 跑, 奔跑<br><span class="example">He <b>ran</b> to the door.</span><br>1. 经营<div class="ex">She runs a small hotel. 她经营一家小旅馆。</div><i>n.</i> 跑步<br>例：a morning run 晨跑"#;
+        let ls = lines(html);
+        let groups = group_definition_lines(&ls);
+        let v = groups.iter().find(|g| g.part_of_speech == "v.").unwrap();
+        assert_eq!(v.definitions, vec!["跑, 奔跑", "1. 经营"]);
+        assert_eq!(v.examples, vec!["He ran to the door.", "She runs a small hotel. 她经营一家小旅馆。"]);
+        let n = groups.iter().find(|g| g.part_of_speech == "n.").unwrap();
+        assert_eq!(n.examples, vec!["a morning run 晨跑"]);
+    }
+
+    #[test]
+    fn test_lines_without_pos() {
+        let groups = group_definition_lines(&["你好", "hello"]);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].part_of_speech, "");
+        assert_eq!(groups[0].definitions.len(), 2);
+        assert_eq!(phonetic_line("[网络] 书"), None);
+    }
+}
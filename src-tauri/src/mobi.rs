@@ -0,0 +1,1545 @@
+use encoding_rs::WINDOWS_1252;
+use flate2::read::ZlibDecoder;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Read;
+
+use crate::epub_meta::{named_entity, replace_html_entities};
+use crate::epub_repair::declare_utf8;
+use crate::epub_writer::{escape_xml, xhtml_document, Chapter, ConvertedBook, Resource, TocNode};
+
+const NULL_INDEX: u32 = 0xFFFF_FFFF;
+const BOUNDARY: &[u8] = b"BOUNDARY";
+const HUFF_DEPTH_LIMIT: usize = 32;
+/// Output cap for books that do not declare their text length.
+const MAX_TEXT_BYTES: usize = 256 * 1024 * 1024;
+/// Uncompressed size of a text record; the last one may run past the declared text length.
+const TEXT_RECORD_SIZE: usize = 4096;
+
+fn u16_at(d: &[u8], off: usize) -> Option<u16> {
+    d.get(off..off + 2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+fn u32_at(d: &[u8], off: usize) -> Option<u32> {
+    d.get(off..off + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Splits a Palm database into its records.
+fn palmdb_records(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let count = u16_at(data, 76).ok_or("file is too short to be a Palm database")? as usize;
+    let offsets = (0..count)
+        .map(|i| u32_at(data, 78 + i * 8).map(|o| o as usize))
+        .collect::<Option<Vec<_>>>()
+        .ok_or("truncated Palm database record list")?;
+    let mut records = Vec::with_capacity(count);
+    for (i, &start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).copied().unwrap_or(data.len()).min(data.len());
+        records.push(data.get(start..end.max(start)).unwrap_or_default());
+    }
+    Ok(records)
+}
+
+/// Record 0 of a MOBI book (or of the KF8 half of a combined file). Offsets are from the record start.
+#[derive(Debug, Clone, Default)]
+struct MobiHeader {
+    /// Index of this header's record; every record number below is relative to it.
+    start: usize,
+    compression: u16,
+    text_length: usize,
+    text_records: usize,
+    encryption: u16,
+    utf8: bool,
+    version: u32,
+    full_name: Option<String>,
+    first_image: u32,
+    huff_record: u32,
+    huff_count: u32,
+    extra_flags: u16,
+    fdst: u32,
+    ncx: u32,
+    frag: u32,
+    skel: u32,
+    exth: Vec<(u32, Vec<u8>)>,
+}
+
+impl MobiHeader {
+    fn parse(records: &[&[u8]], start: usize) -> Result<Self, String> {
+        let rec = records.get(start).ok_or("missing MOBI header record")?;
+        let mut h = MobiHeader {
+            start,
+            compression: u16_at(rec, 0).ok_or("truncated MOBI header")?,
+            text_length: u32_at(rec, 4).unwrap_or(0) as usize,
+            text_records: u16_at(rec, 8).unwrap_or(0) as usize,
+            encryption: u16_at(rec, 12).unwrap_or(0),
+            first_image: NULL_INDEX,
+            fdst: NULL_INDEX,
+            ncx: NULL_INDEX,
+            frag: NULL_INDEX,
+            skel: NULL_INDEX,
+            ..Default::default()
+        };
+        // Plain PalmDOC files stop after the first 16 bytes.
+        if rec.get(16..20) != Some(b"MOBI") {
+            return Ok(h);
+        }
+        let header_len = u32_at(rec, 20).unwrap_or(0) as usize;
+        let field = |off: usize| {
+            if off + 4 <= 16 + header_len {
+                u32_at(rec, off)
+            } else {
+                None
+            }
+        };
+        h.utf8 = field(0x1C) == Some(65001);
+        h.version = field(0x24).unwrap_or(0);
+        h.first_image = field(0x6C).unwrap_or(NULL_INDEX);
+        h.huff_record = field(0x70).unwrap_or(0);
+        h.huff_count = field(0x74).unwrap_or(0);
+        h.ncx = field(0xF4).unwrap_or(NULL_INDEX);
+        if h.version >= 5 && header_len >= 0xE4 {
+            h.extra_flags = u16_at(rec, 0xF2).unwrap_or(0);
+        }
+        if h.version >= 8 {
+            h.fdst = field(0xC0).unwrap_or(NULL_INDEX);
+            h.frag = field(0xF8).unwrap_or(NULL_INDEX);
+            h.skel = field(0xFC).unwrap_or(NULL_INDEX);
+        }
+        if let (Some(off), Some(len)) = (field(0x54), field(0x58)) {
+            h.full_name = rec
+                .get(off as usize..(off as usize).saturating_add(len as usize))
+                .map(|b| h.decode(b))
+                .filter(|s| !s.trim().is_empty());
+        }
+        if field(0x80).is_some_and(|flags| flags & 0x40 != 0) {
+            let exth = 16 + header_len;
+            if rec.get(exth..exth + 4) == Some(b"EXTH") {
+                let count = u32_at(rec, exth + 8).unwrap_or(0);
+                let mut pos = exth + 12;
+                for _ in 0..count {
+                    let (Some(kind), Some(len)) = (u32_at(rec, pos), u32_at(rec, pos + 4)) else {
+                        break;
+                    };
+                    let Some(value) = rec.get(pos + 8..pos + (len as usize).max(8)) else {
+                        break;
+                    };
+                    h.exth.push((kind, value.to_vec()));
+                    pos += (len as usize).max(8);
+                }
+            }
+        }
+        Ok(h)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        if self.utf8 {
+            String::from_utf8_lossy(bytes).into_owned()
+        } else {
+            WINDOWS_1252.decode(bytes).0.into_owned()
+        }
+    }
+
+    fn exth_strings(&self, kind: u32) -> impl Iterator<Item = String> + '_ {
+        self.exth
+            .iter()
+            .filter(move |(k, _)| *k == kind)
+            .map(|(_, v)| self.decode(v).trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+
+    fn exth_u32(&self, kind: u32) -> Option<u32> {
+        self.exth
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .and_then(|(_, v)| u32_at(v, 0))
+    }
+
+    /// Absolute record number of a header-relative index, if set.
+    fn record(&self, index: u32) -> Option<usize> {
+        (index != NULL_INDEX).then(|| self.start + index as usize)
+    }
+
+    /// Concatenates and decompresses the text records.
+    fn read_text(&self, records: &[&[u8]]) -> Result<Vec<u8>, String> {
+        let mut huff = match self.compression {
+            1 | 2 => None,
+            17480 => {
+                let first = self.start + self.huff_record as usize;
+                let tables = records
+                    .get(first..first + self.huff_count as usize)
+                    .ok_or("HUFF/CDIC records are missing")?;
+                Some(HuffCdic::new(tables)?)
+            }
+            other => return Err(format!("unsupported MOBI compression {}", other)),
+        };
+        let limit = match self.text_length {
+            0 => MAX_TEXT_BYTES,
+            n => n.saturating_add(TEXT_RECORD_SIZE),
+        };
+        let stored: usize = (1..=self.text_records)
+            .filter_map(|i| records.get(self.start + i))
+            .map(|rec| rec.len())
+            .sum();
+        let mut text = Vec::with_capacity(self.text_length.min(stored));
+        for i in 1..=self.text_records {
+            let Some(rec) = records.get(self.start + i) else { break };
+            let rec = &rec[..rec.len() - trailing_size(rec, self.extra_flags).min(rec.len())];
+            match (&mut huff, self.compression) {
+                (Some(h), _) => text.extend(h.unpack(rec, 0, limit - text.len())?),
+                (None, 2) => text.extend(palmdoc_decompress(rec)),
+                _ => text.extend_from_slice(rec),
+            }
+            if text.len() > limit {
+                return Err("decompressed text exceeds the declared length".to_string());
+            }
+        }
+        if self.text_length > 0 {
+            text.truncate(self.text_length);
+        }
+        Ok(text)
+    }
+}
+
+/// Size of the extra data appended to a text record, as announced by the header's extra flags.
+fn trailing_size(rec: &[u8], flags: u16) -> usize {
+    let entry = |end: usize| {
+        let (mut value, mut shift, mut i) = (0usize, 0, end);
+        while i > 0 {
+            let b = rec[i - 1];
+            value |= ((b & 0x7F) as usize) << shift;
+            shift += 7;
+            i -= 1;
+            if b & 0x80 != 0 || shift >= 28 {
+                break;
+            }
+        }
+        value
+    };
+    let mut size = 0;
+    let mut bits = flags >> 1;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            size += entry(rec.len().saturating_sub(size));
+        }
+        bits >>= 1;
+    }
+    // Multibyte overlap: the low two bits of the last remaining byte count the bytes before it.
+    if flags & 1 != 0 {
+        if let Some(b) = rec.len().checked_sub(size + 1).map(|i| rec[i]) {
+            size += (b & 0x3) as usize + 1;
+        }
+    }
+    size
+}
+
+/// PalmDOC LZ77 decompression.
+fn palmdoc_decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() * 2);
+    let mut i = 0;
+    while i < data.len() {
+        let c = data[i];
+        i += 1;
+        match c {
+            1..=8 => {
+                let end = (i + c as usize).min(data.len());
+                out.extend_from_slice(&data[i..end]);
+                i = end;
+            }
+            0x80..=0xBF => {
+                let Some(&next) = data.get(i) else { break };
+                i += 1;
+                let pair = ((c as usize) << 8) | next as usize;
+                let dist = (pair >> 3) & 0x7FF;
+                let len = (pair & 7) + 3;
+                if dist == 0 || dist > out.len() {
+                    continue;
+                }
+                let from = out.len() - dist;
+                for k in 0..len {
+                    out.push(out[from + k]);
+                }
+            }
+            0xC0..=0xFF => {
+                out.push(b' ');
+                out.push(c ^ 0x80);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Huffman/CDIC decompression used by Mobipocket's "HUFF" compression.
+struct HuffCdic {
+    /// Per leading byte: (code length, terminal, max code).
+    dict1: Vec<(u32, bool, u64)>,
+    mincode: [u64; 33],
+    maxcode: [u64; 33],
+    /// Phrases with a flag telling whether they are already decompressed. `None` while being expanded.
+    phrases: Vec<Option<(Vec<u8>, bool)>>,
+}
+
+impl HuffCdic {
+    fn new(tables: &[&[u8]]) -> Result<Self, String> {
+        let huff = tables.first().ok_or("missing HUFF record")?;
+        if huff.get(0..8) != Some(b"HUFF\x00\x00\x00\x18") {
+            return Err("invalid HUFF record".to_string());
+        }
+        let off1 = u32_at(huff, 8).ok_or("invalid HUFF record")? as usize;
+        let off2 = u32_at(huff, 12).ok_or("invalid HUFF record")? as usize;
+        let mut dict1 = Vec::with_capacity(256);
+        for i in 0..256 {
+            let v = u32_at(huff, off1 + i * 4).ok_or("truncated HUFF table")?;
+            let len = v & 0x1F;
+            if len == 0 {
+                return Err("invalid HUFF code length".to_string());
+            }
+            let max = (((v >> 8) as u64 + 1) << (32 - len)) - 1;
+            dict1.push((len, v & 0x80 != 0, max));
+        }
+        let mut mincode = [0u64; 33];
+        let mut maxcode = [u32::MAX as u64; 33];
+        for len in 1..=32usize {
+            let min = u32_at(huff, off2 + (len - 1) * 8).ok_or("truncated HUFF table")? as u64;
+            let max = u32_at(huff, off2 + (len - 1) * 8 + 4).ok_or("truncated HUFF table")? as u64;
+            mincode[len] = min << (32 - len);
+            maxcode[len] = ((max + 1) << (32 - len)) - 1;
+        }
+        let mut phrases = Vec::new();
+        for cdic in &tables[1..] {
+            if cdic.get(0..8) != Some(b"CDIC\x00\x00\x00\x10") {
+                return Err("invalid CDIC record".to_string());
+            }
+            let total = u32_at(cdic, 8).unwrap_or(0) as usize;
+            let bits = u32_at(cdic, 12).unwrap_or(0).min(16);
+            let n = (1usize << bits).min(total.saturating_sub(phrases.len()));
+            for i in 0..n {
+                let off = u16_at(cdic, 16 + i * 2).ok_or("truncated CDIC record")? as usize;
+                let blen = u16_at(cdic, 16 + off).ok_or("truncated CDIC record")? as usize;
+                let slice = cdic
+                    .get(18 + off..18 + off + (blen & 0x7FFF))
+                    .ok_or("truncated CDIC record")?;
+                phrases.push(Some((slice.to_vec(), blen & 0x8000 != 0)));
+            }
+        }
+        Ok(Self {
+            dict1,
+            mincode,
+            maxcode,
+            phrases,
+        })
+    }
+
+    /// Decompresses `data`, failing once the output would grow past `limit` bytes.
+    fn unpack(&mut self, data: &[u8], depth: usize, limit: usize) -> Result<Vec<u8>, String> {
+        if depth > HUFF_DEPTH_LIMIT {
+            return Err("HUFF phrases nest too deeply".to_string());
+        }
+        let mut buf = data.to_vec();
+        buf.extend_from_slice(&[0; 8]);
+        let word = |pos: usize| u64::from_be_bytes(buf[pos..pos + 8].try_into().unwrap());
+        let mut bits_left = data.len() as i64 * 8;
+        let mut pos = 0;
+        let mut x = word(0);
+        let mut n: i64 = 32;
+        let mut out = Vec::new();
+        loop {
+            if n <= 0 {
+                pos += 4;
+                if pos + 8 > buf.len() {
+                    break;
+                }
+                x = word(pos);
+                n += 32;
+            }
+            let code = (x >> n) & 0xFFFF_FFFF;
+            let (mut len, term, mut max) = self.dict1[(code >> 24) as usize];
+            if !term {
+                while len < 32 && code < self.mincode[len as usize] {
+                    len += 1;
+                }
+                max = self.maxcode[len as usize];
+            }
+            n -= len as i64;
+            bits_left -= len as i64;
+            if bits_left < 0 {
+                break;
+            }
+            let index = (max.checked_sub(code).ok_or("corrupt HUFF data")? >> (32 - len)) as usize;
+            let (phrase, done) = self
+                .phrases
+                .get_mut(index)
+                .and_then(Option::take)
+                .ok_or("corrupt HUFF data")?;
+            let phrase = if done {
+                phrase
+            } else {
+                self.unpack(&phrase, depth + 1, limit)?
+            };
+            if out.len() + phrase.len() > limit {
+                return Err("decompressed text exceeds the declared length".to_string());
+            }
+            out.extend_from_slice(&phrase);
+            self.phrases[index] = Some((phrase, true));
+        }
+        Ok(out)
+    }
+}
+
+/// Forward variable-width integer: 7 bits per byte, the high bit marks the last byte.
+fn decint(data: &[u8]) -> (u32, usize) {
+    let mut value = 0u32;
+    for (i, &b) in data.iter().enumerate() {
+        value = (value << 7) | (b & 0x7F) as u32;
+        if b & 0x80 != 0 {
+            return (value, i + 1);
+        }
+    }
+    (value, data.len())
+}
+
+struct IndexEntry {
+    ident: Vec<u8>,
+    tags: HashMap<u8, Vec<u32>>,
+}
+
+impl IndexEntry {
+    fn tag(&self, tag: u8, i: usize) -> Option<u32> {
+        self.tags.get(&tag).and_then(|v| v.get(i)).copied()
+    }
+}
+
+/// String table shared by the entries of an index.
+struct Cncx<'a>(&'a [&'a [u8]]);
+
+impl Cncx<'_> {
+    fn get(&self, offset: u32, h: &MobiHeader) -> Option<String> {
+        let rec = self.0.get((offset >> 16) as usize)?;
+        let pos = (offset & 0xFFFF) as usize;
+        let (len, used) = decint(rec.get(pos..)?);
+        rec.get(pos + used..pos + used + len as usize).map(|b| h.decode(b))
+    }
+}
+
+/// Reads an INDX table: a header record followed by entry records, then the CNCX string records.
+fn read_index<'a>(records: &'a [&'a [u8]], first: usize) -> Result<(Vec<IndexEntry>, Cncx<'a>), String> {
+    let head = records
+        .get(first)
+        .filter(|r| r.starts_with(b"INDX"))
+        .ok_or("missing INDX record")?;
+    let count = u32_at(head, 24).unwrap_or(0) as usize;
+    let cncx_count = u32_at(head, 52).unwrap_or(0) as usize;
+    let tagx = u32_at(head, 4).unwrap_or(0) as usize;
+    let tagx = head
+        .get(tagx..)
+        .filter(|t| t.starts_with(b"TAGX"))
+        .ok_or("missing TAGX section")?;
+    let tagx_end = (u32_at(tagx, 4).unwrap_or(12) as usize).min(tagx.len());
+    let control_bytes = u32_at(tagx, 8).unwrap_or(1) as usize;
+    // (tag, values per entry, mask, end-of-control-byte flag)
+    let tags: Vec<[u8; 4]> = tagx[12.min(tagx_end)..tagx_end]
+        .chunks_exact(4)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .collect();
+
+    let mut entries = Vec::new();
+    for rec in records.iter().skip(first + 1).take(count) {
+        let idxt = u32_at(rec, 20).unwrap_or(0) as usize;
+        let n = u32_at(rec, 24).unwrap_or(0) as usize;
+        let mut starts: Vec<usize> = (0..n)
+            .filter_map(|j| u16_at(rec, idxt + 4 + 2 * j).map(usize::from))
+            .collect();
+        starts.push(idxt);
+        for w in starts.windows(2) {
+            let Some(raw) = rec.get(w[0]..w[1]) else { continue };
+            let Some(&len) = raw.first() else { continue };
+            let ident = raw.get(1..1 + len as usize).unwrap_or_default().to_vec();
+            let mut data = raw.get(1 + len as usize..).unwrap_or_default();
+            let mut controls = data.get(..control_bytes).unwrap_or_default().to_vec();
+            data = data.get(control_bytes..).unwrap_or_default();
+
+            // (tag, value count, value byte length, values per entry)
+            let mut present = Vec::new();
+            for &[tag, per_entry, mask, eof] in &tags {
+                if eof == 1 {
+                    if !controls.is_empty() {
+                        controls.remove(0);
+                    }
+                    continue;
+                }
+                let value = controls.first().copied().unwrap_or(0) & mask;
+                if value == 0 {
+                    continue;
+                }
+                if value == mask {
+                    if mask.count_ones() > 1 {
+                        let (bytes, used) = decint(data);
+                        data = &data[used..];
+                        present.push((tag, None, Some(bytes as usize), per_entry));
+                    } else {
+                        present.push((tag, Some(1), None, per_entry));
+                    }
+                } else {
+                    present.push((tag, Some((value >> mask.trailing_zeros()) as usize), None, per_entry));
+                }
+            }
+            let mut tags_map = HashMap::new();
+            for (tag, count, bytes, per_entry) in present {
+                let mut values = Vec::new();
+                match (count, bytes) {
+                    (Some(count), _) => {
+                        for _ in 0..count * per_entry as usize {
+                            let (v, used) = decint(data);
+                            data = &data[used..];
+                            values.push(v);
+                        }
+                    }
+                    (None, Some(total)) => {
+                        let mut consumed = 0;
+                        while consumed < total && !data.is_empty() {
+                            let (v, used) = decint(data);
+                            data = &data[used..];
+                            consumed += used;
+                            values.push(v);
+                        }
+                    }
+                    _ => {}
+                }
+                tags_map.insert(tag, values);
+            }
+            entries.push(IndexEntry { ident, tags: tags_map });
+        }
+    }
+    let cncx_start = (first + 1 + count).min(records.len());
+    let cncx = &records[cncx_start..(cncx_start + cncx_count).min(records.len())];
+    Ok((entries, Cncx(cncx)))
+}
+
+/// Reads a table of contents index as (label, position, parent) triples.
+fn read_ncx(records: &[&[u8]], h: &MobiHeader) -> Vec<(String, NcxTarget, Option<usize>)> {
+    let Some(first) = h.record(h.ncx) else {
+        return Vec::new();
+    };
+    let (entries, cncx) = match read_index(records, first) {
+        Ok(index) => index,
+        Err(e) => {
+            log::warn!("[mobi] ncx index: {}", e);
+            return Vec::new();
+        }
+    };
+    entries
+        .iter()
+        .filter_map(|e| {
+            let label = e.tag(3, 0).and_then(|off| cncx.get(off, h))?;
+            let target = match (e.tag(6, 0), e.tag(6, 1)) {
+                (Some(fid), Some(off)) => NcxTarget::Fragment(fid as usize, off as usize),
+                _ => NcxTarget::Position(e.tag(1, 0)? as usize),
+            };
+            Some((label.trim().to_string(), target, e.tag(21, 0).map(|p| p as usize)))
+        })
+        .collect()
+}
+
+enum NcxTarget {
+    Position(usize),
+    Fragment(usize, usize),
+}
+
+/// Nests flat NCX entries by their parent index.
+fn toc_tree(flat: Vec<(String, Option<String>, Option<usize>)>) -> Vec<TocNode> {
+    let mut kids: Vec<Vec<usize>> = vec![Vec::new(); flat.len()];
+    let mut roots = Vec::new();
+    for (i, (_, _, parent)) in flat.iter().enumerate() {
+        match parent {
+            Some(p) if *p < i => kids[*p].push(i),
+            _ => roots.push(i),
+        }
+    }
+    fn build(i: usize, flat: &[(String, Option<String>, Option<usize>)], kids: &[Vec<usize>]) -> Option<TocNode> {
+        let children: Vec<TocNode> = kids[i].iter().filter_map(|&k| build(k, flat, kids)).collect();
+        let href = flat[i].1.clone().or_else(|| children.first().map(|c| c.href.clone()))?;
+        Some(TocNode {
+            label: flat[i].0.clone(),
+            href,
+            children,
+        })
+    }
+    roots.into_iter().filter_map(|i| build(i, &flat, &kids)).collect()
+}
+
+fn image_extension(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("jpg")
+    } else if data.starts_with(b"\x89PNG") {
+        Some("png")
+    } else if data.starts_with(b"GIF8") {
+        Some("gif")
+    } else if data.starts_with(b"BM") {
+        Some("bmp")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some("webp")
+    } else {
+        None
+    }
+}
+
+/// Decodes a KF8 FONT record: optionally XOR-obfuscated, then zlib-compressed.
+fn font_record(rec: &[u8]) -> Option<(Vec<u8>, &'static str)> {
+    let flags = u32_at(rec, 8)?;
+    let start = u32_at(rec, 12)? as usize;
+    let mut data = rec.get(start..)?.to_vec();
+    if flags & 0b10 != 0 {
+        let key_len = u32_at(rec, 16)? as usize;
+        let key_start = u32_at(rec, 20)? as usize;
+        let key = rec.get(key_start..key_start + key_len).filter(|k| !k.is_empty())?;
+        for (i, b) in data.iter_mut().take(1040).enumerate() {
+            *b ^= key[i % key.len()];
+        }
+    }
+    if flags & 0b1 != 0 {
+        let mut out = Vec::new();
+        ZlibDecoder::new(&data[..]).read_to_end(&mut out).ok()?;
+        data = out;
+    }
+    let ext = if data.starts_with(b"OTTO") {
+        "otf"
+    } else if data.starts_with(b"wOFF") {
+        "woff"
+    } else {
+        "ttf"
+    };
+    Some((data, ext))
+}
+
+/// Collects image and font records from `first` up to `end`; the result is indexed by resource number - 1.
+fn collect_resources(records: &[&[u8]], first: u32, end: usize, book: &mut ConvertedBook) -> Vec<Option<String>> {
+    if first == NULL_INDEX {
+        return Vec::new();
+    }
+    let mut hrefs = Vec::new();
+    for (i, rec) in records.iter().enumerate().take(end).skip(first as usize) {
+        let n = i - first as usize + 1;
+        let resource = if rec.starts_with(b"FONT") {
+            font_record(rec).map(|(data, ext)| (format!("fonts/font{:05}.{}", n, ext), data))
+        } else {
+            image_extension(rec).map(|ext| (format!("images/image{:05}.{}", n, ext), rec.to_vec()))
+        };
+        hrefs.push(resource.map(|(href, data)| {
+            book.resources.push(Resource::new(href.clone(), data));
+            href
+        }));
+    }
+    hrefs
+}
+
+fn base32(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 32).ok()
+}
+
+fn find_ci(hay: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    hay.get(from..)?
+        .windows(needle.len())
+        .position(|w| w.eq_ignore_ascii_case(needle))
+        .map(|p| p + from)
+}
+
+/// Escapes text taken from tag soup, keeping character references and decoding HTML named entities.
+fn escape_text(s: &str, out: &mut String) {
+    let mut rest = s;
+    while let Some(i) = rest.find(['&', '<', '>', '"']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        match tail.as_bytes()[0] {
+            b'<' => out.push_str("&lt;"),
+            b'>' => out.push_str("&gt;"),
+            b'"' => out.push_str("&quot;"),
+            _ => {
+                let name_len = tail[1..].find(';').filter(|&n| n > 0 && n <= 32).unwrap_or(0);
+                let name = &tail[1..1 + name_len];
+                let known = if let Some(num) = name.strip_prefix('#') {
+                    match num.strip_prefix(['x', 'X']) {
+                        Some(hex) => !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()),
+                        None => !num.is_empty() && num.chars().all(|c| c.is_ascii_digit()),
+                    }
+                } else if matches!(name, "amp" | "lt" | "gt" | "quot" | "apos") {
+                    true
+                } else if let Some(decoded) = (name_len > 0).then(|| named_entity(&tail[1..=name_len + 1])).flatten() {
+                    // HTML-only names are undefined in XHTML; write the character itself.
+                    escape_text(&decoded, out);
+                    rest = &tail[name_len + 2..];
+                    continue;
+                } else {
+                    false
+                };
+                out.push_str(if known { "&" } else { "&amp;" });
+            }
+        }
+        rest = &tail[1..];
+    }
+    out.push_str(rest);
+}
+
+fn map_tag(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "p" => "p",
+        "div" | "center" => "div",
+        "span" | "font" => "span",
+        "a" => "a",
+        "b" => "b",
+        "i" => "i",
+        "u" => "u",
+        "em" => "em",
+        "strong" => "strong",
+        "h1" => "h1",
+        "h2" => "h2",
+        "h3" => "h3",
+        "h4" => "h4",
+        "h5" => "h5",
+        "h6" => "h6",
+        "blockquote" => "blockquote",
+        "ul" => "ul",
+        "ol" => "ol",
+        "li" => "li",
+        "dl" => "dl",
+        "dt" => "dt",
+        "dd" => "dd",
+        "table" => "table",
+        "caption" => "caption",
+        "thead" => "thead",
+        "tbody" => "tbody",
+        "tfoot" => "tfoot",
+        "tr" => "tr",
+        "td" => "td",
+        "th" => "th",
+        "br" => "br",
+        "hr" => "hr",
+        "img" => "img",
+        "sup" => "sup",
+        "sub" => "sub",
+        "small" => "small",
+        "big" => "big",
+        "pre" => "pre",
+        "code" | "tt" => "code",
+        "cite" => "cite",
+        "q" => "q",
+        "s" | "strike" | "del" => "del",
+        "ins" => "ins",
+        _ => return None,
+    })
+}
+
+/// Ends heading capture once the heading opened at stack depth `.0` has been closed.
+fn finish_heading(capture: &mut Option<(usize, String)>, heading: &mut Option<String>, depth: usize) {
+    if capture.as_ref().is_some_and(|(at, _)| *at >= depth) {
+        let (_, text) = capture.take().unwrap();
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = text
+            .replace("&nbsp;", " ")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&amp;", "&");
+        if !text.trim().is_empty() {
+            *heading = Some(text.trim().to_string());
+        }
+    }
+}
+
+const BLOCKS: [&str; 16] = [
+    "p",
+    "div",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "ul",
+    "ol",
+    "dl",
+    "table",
+    "pre",
+    "hr",
+    "li",
+];
+
+/// Turns Mobipocket tag soup into well-formed XHTML body markup. Returns the markup and the first heading.
+fn sanitize(html: &str, image: &dyn Fn(usize) -> Option<String>) -> (String, Option<String>) {
+    let bytes = html.as_bytes();
+    let mut out = String::with_capacity(html.len());
+    let mut stack: Vec<&'static str> = Vec::new();
+    let mut skip: Option<String> = None;
+    let mut heading: Option<String> = None;
+    let mut capture: Option<(usize, String)> = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'<' {
+            let end = html[i..].find('<').map_or(html.len(), |p| p + i);
+            if skip.is_none() {
+                escape_text(&html[i..end], &mut out);
+                if let Some((_, text)) = capture.as_mut() {
+                    text.push_str(&html[i..end]);
+                }
+            }
+            i = end;
+            continue;
+        }
+        let rest = &html[i..];
+        if rest.starts_with("<!--") {
+            i = rest.find("-->").map_or(html.len(), |p| i + p + 3);
+            continue;
+        }
+        let next = bytes.get(i + 1).copied().unwrap_or(0);
+        if next == b'!' || next == b'?' {
+            i = rest.find('>').map_or(html.len(), |p| i + p + 1);
+            continue;
+        }
+        let closing = next == b'/';
+        let name_start = i + 1 + closing as usize;
+        let name_len = bytes[name_start.min(bytes.len())..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == b':' || **c == b'-')
+            .count();
+        if name_len == 0 || !bytes[name_start].is_ascii_alphabetic() {
+            if skip.is_none() {
+                out.push_str("&lt;");
+            }
+            i += 1;
+            continue;
+        }
+        let name = html[name_start..name_start + name_len].to_ascii_lowercase();
+
+        // Attributes up to the closing '>'.
+        let mut attrs: Vec<(String, String)> = Vec::new();
+        let mut j = name_start + name_len;
+        let mut self_closing = false;
+        loop {
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            match bytes.get(j) {
+                None => break,
+                Some(b'>') => {
+                    j += 1;
+                    break;
+                }
+                Some(b'/') => {
+                    self_closing = bytes.get(j + 1) == Some(&b'>');
+                    j += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let key_start = j;
+            while j < bytes.len() && !bytes[j].is_ascii_whitespace() && !matches!(bytes[j], b'=' | b'>') {
+                j += 1;
+            }
+            let key = html[key_start..j].to_ascii_lowercase();
+            while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            let mut value = String::new();
+            if bytes.get(j) == Some(&b'=') {
+                j += 1;
+                while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                    j += 1;
+                }
+                match bytes.get(j) {
+                    Some(&q) if q == b'"' || q == b'\'' => {
+                        let end = html[j + 1..].find(q as char).map_or(html.len(), |p| j + 1 + p);
+                        value = html[j + 1..end].to_string();
+                        j = (end + 1).min(html.len());
+                    }
+                    _ => {
+                        let start = j;
+                        while j < bytes.len() && !bytes[j].is_ascii_whitespace() && bytes[j] != b'>' {
+                            j += 1;
+                        }
+                        value = html[start..j].to_string();
+                    }
+                }
+            }
+            if !key.is_empty() {
+                attrs.push((key, value));
+            }
+        }
+        i = j;
+
+        if let Some(skipping) = &skip {
+            if closing && *skipping == name {
+                skip = None;
+            }
+            continue;
+        }
+        if !closing && !self_closing && matches!(name.as_str(), "head" | "script" | "style" | "guide" | "title") {
+            skip = Some(name);
+            continue;
+        }
+        let Some(tag) = map_tag(&name) else { continue };
+
+        let close_to = |stack: &mut Vec<&'static str>, out: &mut String, at: usize| {
+            while stack.len() > at {
+                out.push_str(&format!("</{}>", stack.pop().unwrap()));
+            }
+        };
+        if closing {
+            if let Some(at) = stack.iter().rposition(|t| *t == tag) {
+                close_to(&mut stack, &mut out, at);
+                finish_heading(&mut capture, &mut heading, at);
+            }
+            continue;
+        }
+
+        // Implicit closes that HTML parsers would apply.
+        if BLOCKS.contains(&tag) {
+            if let Some(at) = stack.iter().rposition(|t| *t == "p") {
+                close_to(&mut stack, &mut out, at);
+            }
+        }
+        // (elements this one closes, containers that stop the search)
+        let scope: Option<(&[&str], &[&str])> = match tag {
+            "li" => Some((&["li"], &["ul", "ol"])),
+            "tr" => Some((&["tr"], &["table", "tbody", "thead", "tfoot"])),
+            "td" | "th" => Some((&["td", "th"], &["tr"])),
+            "dt" | "dd" => Some((&["dt", "dd"], &["dl"])),
+            "a" => Some((&["a"], &[])),
+            _ => None,
+        };
+        if let Some((same, containers)) = scope {
+            if let Some(at) = stack.iter().rposition(|t| same.contains(t)) {
+                if !stack[at..].iter().any(|t| containers.contains(t)) {
+                    close_to(&mut stack, &mut out, at);
+                }
+            }
+        }
+        finish_heading(&mut capture, &mut heading, stack.len());
+
+        let attr = |k: &str| attrs.iter().find(|(key, _)| key == k).map(|(_, v)| v.as_str());
+        let mut rendered = String::new();
+        let mut push_attr = |k: &str, v: &str| {
+            rendered.push_str(&format!(" {}=\"", k));
+            escape_text(v, &mut rendered);
+            rendered.push('"');
+        };
+        if let Some(id) = attr("id").or_else(|| if tag == "a" { attr("name") } else { None }) {
+            push_attr("id", id);
+        }
+        match tag {
+            "a" => {
+                if let Some(pos) =
+                    attr("filepos").and_then(|p| p.trim_start_matches('0').parse::<usize>().ok().or(Some(0)))
+                {
+                    push_attr("href", &format!("#filepos{}", pos));
+                } else if let Some(href) = attr("href").filter(|h| !h.to_ascii_lowercase().starts_with("javascript:")) {
+                    push_attr("href", href);
+                }
+            }
+            "img" => {
+                let Some(src) = attr("recindex").and_then(|r| r.parse::<usize>().ok()).and_then(image) else {
+                    continue;
+                };
+                push_attr("src", &format!("../{}", src));
+                push_attr("alt", attr("alt").unwrap_or_default());
+            }
+            "td" | "th" => {
+                for k in ["colspan", "rowspan"] {
+                    if let Some(v) = attr(k).filter(|v| v.chars().all(|c| c.is_ascii_digit()) && !v.is_empty()) {
+                        push_attr(k, v);
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(align) = attr("align").map(|a| a.to_ascii_lowercase()) {
+            if matches!(align.as_str(), "left" | "right" | "center" | "justify") {
+                push_attr("style", &format!("text-align: {}", align));
+            }
+        } else if name == "center" {
+            push_attr("style", "text-align: center");
+        }
+        if matches!(tag, "br" | "hr" | "img") {
+            out.push_str(&format!("<{}{}/>", tag, rendered));
+            continue;
+        }
+        out.push_str(&format!("<{}{}>", tag, rendered));
+        if self_closing {
+            out.push_str(&format!("</{}>", tag));
+            continue;
+        }
+        if heading.is_none() && capture.is_none() && matches!(tag, "h1" | "h2" | "h3") {
+            capture = Some((stack.len(), String::new()));
+        }
+        stack.push(tag);
+    }
+    while let Some(tag) = stack.pop() {
+        out.push_str(&format!("</{}>", tag));
+    }
+    (out, heading)
+}
+
+/// Finds the `filepos=` link targets of a MOBI 6 text.
+fn filepos_targets(text: &[u8]) -> Vec<usize> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(at) = find_ci(text, b"filepos=", from) {
+        let mut j = at + 8;
+        while matches!(text.get(j), Some(b'"' | b'\'')) {
+            j += 1;
+        }
+        let digits = text[j..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if let Some(pos) = std::str::from_utf8(&text[j..j + digits])
+            .ok()
+            .and_then(|d| d.parse().ok())
+        {
+            found.push(pos);
+        }
+        from = j + digits;
+    }
+    found
+}
+
+/// Inserts `<a id="fileposN">` anchors at byte positions, moving any that fall inside a tag past it.
+fn insert_anchors(text: &[u8], positions: &BTreeSet<usize>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() + positions.len() * 24);
+    let (mut last, mut scanned, mut in_tag) = (0, 0, false);
+    for &p in positions.iter().filter(|&&p| p <= text.len()) {
+        if p >= scanned {
+            for &c in &text[scanned..p] {
+                match c {
+                    b'<' => in_tag = true,
+                    b'>' => in_tag = false,
+                    _ => {}
+                }
+            }
+            scanned = p;
+            if in_tag {
+                if let Some(e) = text[p..].iter().position(|&c| c == b'>') {
+                    scanned = p + e + 1;
+                    in_tag = false;
+                }
+            }
+            out.extend_from_slice(&text[last..scanned]);
+            last = scanned;
+        }
+        out.extend_from_slice(format!("<a id=\"filepos{}\"></a>", p).as_bytes());
+    }
+    out.extend_from_slice(&text[last..]);
+    out
+}
+
+/// Points `href="#id"` links at the chapter that holds `id` when it is not the current one.
+fn retarget_links(body: &str, anchors: &HashMap<String, usize>, current: usize, hrefs: &[String]) -> String {
+    let mut out = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(i) = rest.find("href=\"#") {
+        out.push_str(&rest[..i + 6]);
+        rest = &rest[i + 7..];
+        let end = rest.find('"').unwrap_or(rest.len());
+        let id = &rest[..end];
+        if let Some(&chapter) = anchors.get(id).filter(|&&c| c != current) {
+            out.push_str(file_name(&hrefs[chapter]));
+        }
+        out.push('#');
+    }
+    out.push_str(rest);
+    out
+}
+
+fn file_name(href: &str) -> &str {
+    href.rsplit('/').next().unwrap_or(href)
+}
+
+fn chapter_href(i: usize) -> String {
+    format!("text/ch{:04}.xhtml", i + 1)
+}
+
+/// MOBI 6: one HTML stream split at page breaks, with `filepos` byte offsets as link targets.
+fn convert_mobi6(
+    records: &[&[u8]],
+    h: &MobiHeader,
+    images: &[Option<String>],
+    book: &mut ConvertedBook,
+) -> Result<(), String> {
+    let text = h.read_text(records)?;
+    let ncx = read_ncx(records, h);
+    let mut positions: BTreeSet<usize> = filepos_targets(&text).into_iter().collect();
+    for (_, target, _) in &ncx {
+        if let NcxTarget::Position(p) = target {
+            positions.insert(*p);
+        }
+    }
+    let html = h.decode(&insert_anchors(&text, &positions));
+
+    let lower = html.to_ascii_lowercase();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut from = 0;
+    while let Some(at) = lower[from..].find("<mbp:pagebreak").map(|p| p + from) {
+        chunks.push(&html[start..at]);
+        start = lower[at..].find('>').map_or(html.len(), |p| at + p + 1);
+        from = start;
+    }
+    chunks.push(&html[start..]);
+
+    let image = |n: usize| n.checked_sub(1).and_then(|i| images.get(i)).cloned().flatten();
+    let mut bodies = Vec::new();
+    for chunk in chunks {
+        let (body, heading) = sanitize(chunk, &image);
+        let visible = body.contains("<img") || {
+            let mut text = String::new();
+            let mut inside = false;
+            for c in body.chars() {
+                match c {
+                    '<' => inside = true,
+                    '>' => inside = false,
+                    c if !inside => text.push(c),
+                    _ => {}
+                }
+            }
+            !text.trim().is_empty()
+        };
+        // Blank chunks are dropped unless they hold link targets.
+        if visible || body.contains("id=\"") {
+            bodies.push((body, heading));
+        }
+    }
+    if bodies.is_empty() {
+        return Err("MOBI book has no text".to_string());
+    }
+
+    let hrefs: Vec<String> = (0..bodies.len()).map(chapter_href).collect();
+    let mut anchors = HashMap::new();
+    for (i, (body, _)) in bodies.iter().enumerate() {
+        let mut rest = body.as_str();
+        while let Some(p) = rest.find(" id=\"") {
+            rest = &rest[p + 5..];
+            let end = rest.find('"').unwrap_or(rest.len());
+            anchors.entry(rest[..end].to_string()).or_insert(i);
+        }
+    }
+    for (i, (body, heading)) in bodies.iter().enumerate() {
+        let title = heading.clone().unwrap_or_else(|| format!("{} {}", book.title, i + 1));
+        let body = retarget_links(body, &anchors, i, &hrefs);
+        book.chapters.push(Chapter {
+            href: hrefs[i].clone(),
+            xhtml: replace_html_entities(&xhtml_document(&title, book.language.as_deref(), &[], &body)),
+            title,
+        });
+    }
+
+    let flat = ncx
+        .into_iter()
+        .map(|(label, target, parent)| {
+            let href = match target {
+                NcxTarget::Position(p) => {
+                    let id = format!("filepos{}", p);
+                    anchors.get(&id).map(|&c| format!("{}#{}", hrefs[c], id))
+                }
+                NcxTarget::Fragment(..) => None,
+            };
+            (label, href, parent)
+        })
+        .collect();
+    book.toc = toc_tree(flat);
+    Ok(())
+}
+
+/// One reconstructed KF8 file and the text positions it covers.
+struct Kf8Part {
+    data: Vec<u8>,
+    start: usize,
+    end: usize,
+}
+
+/// Id of the last element that starts before `offset` in `data`, or of the tag `offset` points into.
+fn id_before(data: &[u8], offset: usize) -> Option<String> {
+    let offset = offset.min(data.len());
+    let next_gt = data[offset..].iter().position(|&c| c == b'>').map(|p| p + offset);
+    let next_lt = data[offset..].iter().position(|&c| c == b'<').map(|p| p + offset);
+    let limit = match (next_gt, next_lt) {
+        (Some(gt), Some(lt)) if lt == offset || gt < lt => gt + 1,
+        (Some(gt), None) => gt + 1,
+        _ => offset,
+    };
+    let mut found = None;
+    let mut i = 0;
+    while let Some(lt) = data[i..limit].iter().position(|&c| c == b'<').map(|p| p + i) {
+        let Some(gt) = data[lt..limit].iter().position(|&c| c == b'>').map(|p| p + lt) else {
+            break;
+        };
+        let tag = &data[lt..gt];
+        let mut from = 0;
+        while let Some(at) = find_ci(tag, b"id=", from) {
+            from = at + 3;
+            if at == 0 || !tag[at - 1].is_ascii_whitespace() {
+                continue;
+            }
+            let Some(&q) = tag.get(at + 3).filter(|q| **q == b'"' || **q == b'\'') else {
+                continue;
+            };
+            let value = &tag[at + 4..];
+            let end = value.iter().position(|&c| c == q).unwrap_or(value.len());
+            found = Some(String::from_utf8_lossy(&value[..end]).into_owned());
+            break;
+        }
+        i = gt + 1;
+    }
+    found
+}
+
+/// Rewrites `kindle:` URLs in markup or CSS with `resolve`, leaving unknown ones untouched.
+fn rewrite_kindle_links(text: &str, resolve: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find("kindle:") {
+        out.push_str(&rest[..i]);
+        let link = &rest[i..];
+        let end = link
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ')' | '>' | '<'))
+            .unwrap_or(link.len());
+        match resolve(&link[..end]) {
+            Some(target) => out.push_str(&escape_xml(&target)),
+            None => out.push_str(&link[..end]),
+        }
+        rest = &link[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn part_title(xhtml: &str) -> Option<String> {
+    let start = xhtml.find("<title>")? + 7;
+    let end = xhtml[start..].find("</title>")? + start;
+    let title = xhtml[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+/// KF8: flows split by the FDST, the first rebuilt into files from skeleton and fragment tables.
+fn convert_kf8(
+    records: &[&[u8]],
+    h: &MobiHeader,
+    resources: &[Option<String>],
+    book: &mut ConvertedBook,
+) -> Result<(), String> {
+    let text = h.read_text(records)?;
+    let mut flows: Vec<&[u8]> = Vec::new();
+    if let Some(fdst) = h
+        .record(h.fdst)
+        .and_then(|i| records.get(i))
+        .filter(|r| r.starts_with(b"FDST"))
+    {
+        let table = u32_at(fdst, 4).unwrap_or(12) as usize;
+        let count = u32_at(fdst, 8).unwrap_or(0) as usize;
+        for i in 0..count {
+            let (Some(s), Some(e)) = (u32_at(fdst, table + i * 8), u32_at(fdst, table + i * 8 + 4)) else {
+                break;
+            };
+            flows.push(text.get(s as usize..(e as usize).min(text.len())).unwrap_or_default());
+        }
+    }
+    if flows.is_empty() {
+        flows.push(&text);
+    }
+    let raw = flows[0];
+
+    let skel_first = h.record(h.skel).ok_or("KF8 book has no skeleton index")?;
+    let (skeletons, _) = read_index(records, skel_first)?;
+    let fragments = match h.record(h.frag) {
+        Some(first) => read_index(records, first)?.0,
+        None => Vec::new(),
+    };
+    // (insert position, start, length) per fragment.
+    let frags: Vec<(usize, usize, usize)> = fragments
+        .iter()
+        .map(|f| {
+            let insert = std::str::from_utf8(&f.ident)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0);
+            (
+                insert,
+                f.tag(6, 0).unwrap_or(0) as usize,
+                f.tag(6, 1).unwrap_or(0) as usize,
+            )
+        })
+        .collect();
+
+    let mut parts = Vec::new();
+    let mut next_frag = 0;
+    for skel in &skeletons {
+        let count = skel.tag(1, 0).unwrap_or(0) as usize;
+        let start = skel.tag(6, 0).unwrap_or(0) as usize;
+        let len = skel.tag(6, 1).unwrap_or(0) as usize;
+        let mut base = start + len;
+        let mut data = raw.get(start..base.min(raw.len())).unwrap_or_default().to_vec();
+        for &(insert, _, frag_len) in frags.iter().skip(next_frag).take(count) {
+            let piece = raw.get(base..(base + frag_len).min(raw.len())).unwrap_or_default();
+            let at = insert.saturating_sub(start).min(data.len());
+            data.splice(at..at, piece.iter().copied());
+            base += frag_len;
+        }
+        next_frag += count;
+        parts.push(Kf8Part { data, start, end: base });
+    }
+    if parts.is_empty() {
+        parts.push(Kf8Part {
+            data: raw.to_vec(),
+            start: 0,
+            end: raw.len(),
+        });
+    }
+
+    let hrefs: Vec<String> = (0..parts.len()).map(|i| format!("text/part{:04}.xhtml", i)).collect();
+    let locate = |pos: usize| -> Option<String> {
+        let i = parts.iter().position(|p| pos >= p.start && pos < p.end)?;
+        Some(match id_before(&parts[i].data, pos - parts[i].start) {
+            Some(id) => format!("{}#{}", hrefs[i], id),
+            None => hrefs[i].clone(),
+        })
+    };
+    let fragment_pos = |fid: usize, off: usize| frags.get(fid).map(|f| f.0 + off);
+
+    let flow_hrefs: Vec<Option<String>> = flows
+        .iter()
+        .enumerate()
+        .map(|(i, flow)| {
+            let head = String::from_utf8_lossy(&flow[..flow.len().min(256)])
+                .trim_start()
+                .to_ascii_lowercase();
+            match i {
+                0 => None,
+                _ if head.starts_with("<svg") || head.starts_with("<?xml") => Some(format!("images/flow{:04}.svg", i)),
+                _ => Some(format!("styles/flow{:04}.css", i)),
+            }
+        })
+        .collect();
+    let resolve = |link: &str| -> Option<String> {
+        let link = link.split('?').next().unwrap_or(link);
+        if let Some(n) = link.strip_prefix("kindle:embed:") {
+            return base32(n)?
+                .checked_sub(1)
+                .and_then(|i| resources.get(i))
+                .cloned()
+                .flatten()
+                .map(|h| format!("../{}", h));
+        }
+        if let Some(n) = link.strip_prefix("kindle:flow:") {
+            return flow_hrefs
+                .get(base32(n)?)
+                .cloned()
+                .flatten()
+                .map(|h| format!("../{}", h));
+        }
+        let rest = link.strip_prefix("kindle:pos:fid:")?;
+        let (fid, off) = rest.split_once(":off:")?;
+        let target = locate(fragment_pos(base32(fid)?, base32(off)?)?)?;
+        Some(file_name(&target).to_string())
+    };
+
+    for (i, flow) in flows.iter().enumerate().skip(1) {
+        let Some(href) = &flow_hrefs[i] else { continue };
+        let content = rewrite_kindle_links(&h.decode(flow), &resolve);
+        book.resources.push(Resource::new(href.clone(), content.into_bytes()));
+    }
+    for (i, part) in parts.iter().enumerate() {
+        let decoded = h.decode(&part.data);
+        let mut xhtml = rewrite_kindle_links(&declare_utf8(&decoded), &resolve);
+        if !xhtml.contains("<html") {
+            xhtml = xhtml_document(&book.title, book.language.as_deref(), &[], &xhtml);
+        }
+        let xhtml = replace_html_entities(&xhtml);
+        book.chapters.push(Chapter {
+            href: hrefs[i].clone(),
+            title: part_title(&xhtml).unwrap_or_else(|| format!("{} {}", book.title, i + 1)),
+            xhtml,
+        });
+    }
+
+    let flat = read_ncx(records, h)
+        .into_iter()
+        .map(|(label, target, parent)| {
+            let pos = match target {
+                NcxTarget::Position(p) => Some(p),
+                NcxTarget::Fragment(fid, off) => fragment_pos(fid, off),
+            };
+            (label, pos.and_then(locate), parent)
+        })
+        .collect();
+    book.toc = toc_tree(flat);
+    Ok(())
+}
+
+/// Converts a DRM-free MOBI, AZW or AZW3 file, preferring the KF8 half of combined files.
+pub(crate) fn convert_mobi(data: &[u8]) -> Result<ConvertedBook, String> {
+    let records = palmdb_records(data)?;
+    let kind = data.get(60..68).unwrap_or_default();
+    if kind != b"BOOKMOBI" && kind != b"TEXtREAd" {
+        return Err("not a MOBI or PalmDOC file".to_string());
+    }
+    let first = MobiHeader::parse(&records, 0)?;
+    let kf8 = if first.version >= 8 {
+        Some(first.clone())
+    } else {
+        records
+            .iter()
+            .position(|r| *r == BOUNDARY)
+            .and_then(|i| MobiHeader::parse(&records, i + 1).ok())
+            .filter(|h| h.version >= 8)
+    };
+    let header = kf8.as_ref().unwrap_or(&first);
+    if first.encryption != 0 || header.encryption != 0 {
+        return Err(
+            "This book is protected by Kindle DRM; only DRM-free MOBI and AZW3 files can be imported.".to_string(),
+        );
+    }
+
+    let palm_name = String::from_utf8_lossy(&data[..32.min(data.len())])
+        .trim_end_matches('\0')
+        .replace('_', " ");
+    let mut book = ConvertedBook {
+        title: header
+            .exth_strings(503)
+            .next()
+            .or_else(|| header.full_name.clone())
+            .unwrap_or(palm_name),
+        authors: header.exth_strings(100).collect(),
+        publisher: header.exth_strings(101).next(),
+        description: header.exth_strings(103).next(),
+        identifier: header.exth_strings(104).next().map(|isbn| format!("urn:isbn:{}", isbn)),
+        language: header.exth_strings(524).next(),
+        ..Default::default()
+    };
+
+    // Resources live in the first half of combined files; the KF8 half only references them.
+    let end = if kf8.is_some() && header.start > 0 {
+        header.start - 1
+    } else {
+        records.len()
+    };
+    let resources = collect_resources(&records, first.first_image, end, &mut book);
+    book.cover = header
+        .exth_u32(201)
+        .filter(|&c| c != NULL_INDEX)
+        .and_then(|c| resources.get(c as usize).cloned().flatten())
+        .filter(|h| h.starts_with("images/"));
+
+    match &kf8 {
+        Some(h) => convert_kf8(&records, h, &resources, &mut book)?,
+        None => convert_mobi6(&records, &first, &resources, &mut book)?,
+    }
+    Ok(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palmdoc_round_trips_literals_pairs_and_spaces() {
+        // "abc" literal, back-reference of 3 at distance 3, " x" packed in one byte, a raw run of two.
+        let packed = [b'a', b'b', b'c', 0x80, 0x18, 0xF8, 0x02, 0xC1, 0xC2];
+        assert_eq!(palmdoc_decompress(&packed), b"abcabc x\xC1\xC2".to_vec());
+    }
+
+    #[test]
+    fn strips_trailing_entries_and_multibyte_overlap() {
+        // One trailing entry of size 3 (flag bit 1) and a multibyte tail of 1+1 bytes (flag bit 0).
+        let rec = [b'h', b'i', 0xE4, 0x01, 0xAA, 0xBB, 0x83];
+        assert_eq!(trailing_size(&rec, 0b11), 5);
+        assert_eq!(trailing_size(&rec, 0b10), 3);
+    }
+
+    #[test]
+    fn huff_output_is_bounded() {
+        // Every code is one byte wide; byte `b` selects phrase `255 - b`.
+        let mut phrases = vec![None; 256];
+        phrases[255] = Some((b"abcd".to_vec(), true));
+        phrases[254] = Some((vec![0, 0], false));
+        let mut huff = HuffCdic {
+            dict1: vec![(8, true, u32::MAX as u64); 256],
+            mincode: [0; 33],
+            maxcode: [0; 33],
+            phrases,
+        };
+        assert_eq!(huff.unpack(&[0, 1], 0, 12).unwrap(), b"abcdabcdabcd".to_vec());
+        assert!(huff.unpack(&[1, 1], 0, 12).is_err());
+    }
+
+    fn palmdb(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![0u8; 78];
+        out[..8].copy_from_slice(b"Sample\0\0");
+        out[60..68].copy_from_slice(b"BOOKMOBI");
+        out[76..78].copy_from_slice(&(records.len() as u16).to_be_bytes());
+        let mut offset = 78 + records.len() * 8 + 2;
+        for r in records {
+            out.extend_from_slice(&(offset as u32).to_be_bytes());
+            out.extend_from_slice(&[0; 4]);
+            offset += r.len();
+        }
+        out.extend_from_slice(&[0, 0]);
+        for r in records {
+            out.extend_from_slice(r);
+        }
+        out
+    }
+
+    #[test]
+    fn converts_a_mobi6_book() {
+        let html = b"<html><head><guide></guide></head><body><h1>One</h1><p>See <a filepos=0000000099>two</a>\
+<p>Caf&eacute; &nbsp;<img recindex=\"00001\"><mbp:pagebreak/><h2>Two</h2><p>End & done</body></html>";
+        let mut html = html.to_vec();
+        let target = html.windows(4).position(|w| w == b"<h2>").unwrap();
+        let link = format!("{:010}", target);
+        let at = html.windows(8).position(|w| w == b"filepos=").unwrap() + 8;
+        html[at..at + 10].copy_from_slice(link.as_bytes());
+
+        let mut rec0 = vec![0u8; 16];
+        rec0[0..2].copy_from_slice(&1u16.to_be_bytes());
+        rec0[4..8].copy_from_slice(&(html.len() as u32).to_be_bytes());
+        rec0[8..10].copy_from_slice(&1u16.to_be_bytes());
+        let mut mobi = vec![0u8; 0xE8];
+        mobi[0..4].copy_from_slice(b"MOBI");
+        mobi[4..8].copy_from_slice(&0xE8u32.to_be_bytes());
+        mobi[12..16].copy_from_slice(&65001u32.to_be_bytes());
+        mobi[20..24].copy_from_slice(&6u32.to_be_bytes());
+        mobi[0x5C..0x60].copy_from_slice(&2u32.to_be_bytes());
+        mobi[0x70..0x74].copy_from_slice(&0x40u32.to_be_bytes());
+        mobi[0xE4..0xE8].copy_from_slice(&NULL_INDEX.to_be_bytes());
+        rec0.extend(mobi);
+        let title = "Sample Title".as_bytes();
+        let mut exth = b"EXTH".to_vec();
+        let entries: [(u32, &[u8]); 3] = [(503, title), (100, b"Jane Doe"), (201, &0u32.to_be_bytes())];
+        let body: Vec<u8> = entries
+            .iter()
+            .flat_map(|(k, v)| [&k.to_be_bytes()[..], &(v.len() as u32 + 8).to_be_bytes()[..], v].concat())
+            .collect();
+        exth.extend_from_slice(&(body.len() as u32 + 12).to_be_bytes());
+        exth.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        exth.extend(body);
+        rec0.extend(exth);
+        let png = b"\x89PNG\r\n\x1a\n".to_vec();
+
+        let book = convert_mobi(&palmdb(&[rec0, html, png])).unwrap();
+        assert_eq!(book.title, "Sample Title");
+        assert_eq!(book.authors, vec!["Jane Doe".to_string()]);
+        assert_eq!(book.cover.as_deref(), Some("images/image00001.png"));
+        assert_eq!(book.chapters.len(), 2);
+        assert_eq!(book.chapters[0].title, "One");
+        assert_eq!(book.chapters[1].title, "Two");
+        let first = &book.chapters[0].xhtml;
+        assert!(
+            first.contains(&format!("href=\"ch0002.xhtml#filepos{}\"", target)),
+            "{}",
+            first
+        );
+        assert!(first.contains("<img src=\"../images/image00001.png\" alt=\"\"/>"));
+        assert!(first.contains("Café \u{a0}"), "{}", first);
+        assert!(book.chapters[1]
+            .xhtml
+            .contains(&format!("<a id=\"filepos{}\"></a><h2>Two</h2>", target)));
+        assert!(book.chapters[1].xhtml.contains("<p>End &amp; done</p>"));
+        for c in &book.chapters {
+            crate::epub_meta::parse_xml(&c.xhtml).unwrap();
+        }
+    }
+}
@@ -0,0 +1,1527 @@
+use lopdf::content::Content;
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tauri::State;
+
+use crate::AppState;
+
+/// Parsed documents kept for page-by-page requests.
+const CACHED_DOCUMENTS: usize = 4;
+/// Largest `/St` honoured in page labels.
+const MAX_LABEL_START: i64 = 100_000;
+/// Largest page number written as roman numerals or letters.
+const MAX_STYLED_LABEL: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfOutlineItem {
+    pub title: String,
+    /// Zero-based page index of the destination, when it resolves to a page of this document.
+    pub page: Option<usize>,
+    pub children: Vec<PdfOutlineItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+    pub page_count: usize,
+    /// Display label of every page, e.g. `iv` or `A-3`; plain page numbers when the document defines none.
+    pub page_labels: Vec<String>,
+    pub outline: Vec<PdfOutlineItem>,
+}
+
+/// A run of text drawn by one text-showing operator.
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfSpan {
+    /// Char offsets into the page text.
+    pub start: usize,
+    pub end: usize,
+    /// `[x0, y0, x1, y1]` in PDF user space (origin bottom-left), as pdf.js viewports convert it.
+    pub rect: [f32; 4],
+    pub font_size: f32,
+}
+
+/// A paragraph-like group of lines.
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfBlock {
+    pub start: usize,
+    pub end: usize,
+    pub rect: [f32; 4],
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PdfPageText {
+    pub index: usize,
+    pub label: String,
+    /// CropBox (or MediaBox) in user space.
+    pub view_box: [f32; 4],
+    pub rotate: i64,
+    /// Blocks in reading order separated by blank lines, lines by newlines.
+    pub text: String,
+    pub blocks: Vec<PdfBlock>,
+    pub spans: Vec<PdfSpan>,
+}
+
+// ---------------------------------------------------------------------------------------------
+// Object helpers
+
+fn resolve<'a>(doc: &'a Document, obj: &'a Object) -> &'a Object {
+    doc.dereference(obj).map(|(_, o)| o).unwrap_or(obj)
+}
+
+fn dict_get<'a>(doc: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dict.get(key).ok().map(|o| resolve(doc, o))
+}
+
+fn get_dict<'a>(doc: &'a Document, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Dictionary> {
+    match dict_get(doc, dict, key)? {
+        Object::Dictionary(d) => Some(d),
+        Object::Stream(s) => Some(&s.dict),
+        _ => None,
+    }
+}
+
+fn get_number(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<f32> {
+    dict_get(doc, dict, key).and_then(|o| o.as_float().ok())
+}
+
+fn numbers(doc: &Document, obj: &Object) -> Vec<f32> {
+    match resolve(doc, obj) {
+        Object::Array(items) => items.iter().filter_map(|o| resolve(doc, o).as_float().ok()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn text_string(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<String> {
+    let text = decode_text_string(dict_get(doc, dict, key)?).ok()?;
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Page attribute looked up through the page tree, as Resources, MediaBox, CropBox and Rotate inherit.
+fn inherited<'a>(doc: &'a Document, page: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut node = page;
+    for _ in 0..32 {
+        if let Some(value) = dict_get(doc, node, key) {
+            return Some(value);
+        }
+        node = get_dict(doc, node, b"Parent")?;
+    }
+    None
+}
+
+fn open_document(path: &Path) -> Result<Document, String> {
+    let doc = Document::load(path).map_err(|e| format!("failed to read PDF: {}", e))?;
+    if doc.is_encrypted() && doc.authenticate_password("").is_err() {
+        return Err("PDF is password-protected".to_string());
+    }
+    Ok(doc)
+}
+
+type CachedDocument = (PathBuf, (u64, Option<SystemTime>), Arc<Document>);
+
+/// Recently parsed documents by path, least recently used first. An entry is reparsed when the
+/// file's size or modification time changes.
+pub struct PdfDocuments {
+    docs: Mutex<VecDeque<CachedDocument>>,
+}
+
+impl PdfDocuments {
+    pub fn new() -> Self {
+        Self {
+            docs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn open(&self, path: &Path) -> Result<Arc<Document>, String> {
+        let meta = std::fs::metadata(path).map_err(|e| e.to_string())?;
+        let stamp = (meta.len(), meta.modified().ok());
+        {
+            let mut docs = self.docs.lock().unwrap();
+            if let Some(i) = docs.iter().position(|(p, s, _)| p == path && *s == stamp) {
+                let entry = docs.remove(i).unwrap();
+                let doc = entry.2.clone();
+                docs.push_back(entry);
+                return Ok(doc);
+            }
+        }
+        let doc = Arc::new(open_document(path)?);
+        let mut docs = self.docs.lock().unwrap();
+        docs.retain(|(p, ..)| p != path);
+        docs.push_back((path.to_path_buf(), stamp, doc.clone()));
+        while docs.len() > CACHED_DOCUMENTS {
+            docs.pop_front();
+        }
+        Ok(doc)
+    }
+
+    fn remove(&self, path: &Path) {
+        self.docs.lock().unwrap().retain(|(p, ..)| p != path);
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Metadata, outline and page labels
+
+type NamedDests<'a> = HashMap<Vec<u8>, &'a Object>;
+
+fn name_tree<'a>(doc: &'a Document, node: &'a Dictionary, out: &mut NamedDests<'a>, depth: usize) {
+    if depth > 32 {
+        return;
+    }
+    if let Some(Object::Array(names)) = dict_get(doc, node, b"Names") {
+        for pair in names.chunks_exact(2) {
+            if let Ok(key) = resolve(doc, &pair[0]).as_str() {
+                out.insert(key.to_vec(), &pair[1]);
+            }
+        }
+    }
+    if let Some(Object::Array(kids)) = dict_get(doc, node, b"Kids") {
+        for kid in kids {
+            if let Ok(kid) = resolve(doc, kid).as_dict() {
+                name_tree(doc, kid, out, depth + 1);
+            }
+        }
+    }
+}
+
+fn named_dests(doc: &Document) -> NamedDests<'_> {
+    let mut out = HashMap::new();
+    let Ok(catalog) = doc.catalog() else { return out };
+    if let Some(dests) = get_dict(doc, catalog, b"Dests") {
+        for (key, value) in dests.iter() {
+            out.insert(key.clone(), value);
+        }
+    }
+    if let Some(tree) = get_dict(doc, catalog, b"Names").and_then(|names| get_dict(doc, names, b"Dests")) {
+        name_tree(doc, tree, &mut out, 0);
+    }
+    out
+}
+
+fn dest_page(
+    doc: &Document,
+    dest: &Object,
+    pages: &HashMap<ObjectId, usize>,
+    named: &NamedDests,
+    depth: usize,
+) -> Option<usize> {
+    if depth > 8 {
+        return None;
+    }
+    match dest {
+        Object::Reference(id) if pages.contains_key(id) => pages.get(id).copied(),
+        _ => match resolve(doc, dest) {
+            Object::Array(items) => match items.first()? {
+                Object::Reference(id) => pages.get(id).copied(),
+                Object::Integer(n) => usize::try_from(*n).ok().filter(|n| *n < pages.len()),
+                _ => None,
+            },
+            Object::Name(name) | Object::String(name, _) => {
+                dest_page(doc, named.get(name.as_slice())?, pages, named, depth + 1)
+            }
+            Object::Dictionary(d) => dest_page(doc, d.get(b"D").ok()?, pages, named, depth + 1),
+            _ => None,
+        },
+    }
+}
+
+fn outline_items(
+    doc: &Document,
+    first: Option<&Dictionary>,
+    pages: &HashMap<ObjectId, usize>,
+    named: &NamedDests,
+    seen: &mut HashSet<*const Dictionary>,
+    depth: usize,
+) -> Vec<PdfOutlineItem> {
+    let mut out = Vec::new();
+    let mut next = first;
+    while let Some(item) = next {
+        if depth > 32 || !seen.insert(item as *const Dictionary) {
+            break;
+        }
+        let dest = item.get(b"Dest").ok().or_else(|| {
+            let action = get_dict(doc, item, b"A")?;
+            (dict_get(doc, action, b"S")?.as_name().ok()? == b"GoTo").then(|| action.get(b"D").ok())?
+        });
+        let children = outline_items(doc, get_dict(doc, item, b"First"), pages, named, seen, depth + 1);
+        out.push(PdfOutlineItem {
+            title: text_string(doc, item, b"Title").unwrap_or_default(),
+            page: dest.and_then(|d| dest_page(doc, d, pages, named, 0)),
+            children,
+        });
+        next = get_dict(doc, item, b"Next");
+    }
+    out
+}
+
+fn roman(mut n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "m"),
+        (900, "cm"),
+        (500, "d"),
+        (400, "cd"),
+        (100, "c"),
+        (90, "xc"),
+        (50, "l"),
+        (40, "xl"),
+        (10, "x"),
+        (9, "ix"),
+        (5, "v"),
+        (4, "iv"),
+        (1, "i"),
+    ];
+    let mut out = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            out.push_str(numeral);
+            n -= value;
+        }
+    }
+    out
+}
+
+/// `a`..`z`, then `aa`..`zz` and so on, as the PDF page label letter styles count.
+fn letters(n: usize) -> String {
+    let n = n.max(1);
+    let letter = (b'a' + ((n - 1) % 26) as u8) as char;
+    letter.to_string().repeat((n - 1) / 26 + 1)
+}
+
+fn number_tree<'a>(doc: &'a Document, node: &'a Dictionary, out: &mut Vec<(i64, &'a Dictionary)>, depth: usize) {
+    if depth > 32 {
+        return;
+    }
+    if let Some(Object::Array(nums)) = dict_get(doc, node, b"Nums") {
+        for pair in nums.chunks_exact(2) {
+            if let (Ok(key), Ok(value)) = (resolve(doc, &pair[0]).as_i64(), resolve(doc, &pair[1]).as_dict()) {
+                out.push((key, value));
+            }
+        }
+    }
+    if let Some(Object::Array(kids)) = dict_get(doc, node, b"Kids") {
+        for kid in kids {
+            if let Ok(kid) = resolve(doc, kid).as_dict() {
+                number_tree(doc, kid, out, depth + 1);
+            }
+        }
+    }
+}
+
+/// Label ranges of the catalog's `/PageLabels` number tree, by first page index.
+fn label_ranges(doc: &Document) -> Vec<(i64, &Dictionary)> {
+    let mut ranges = Vec::new();
+    if let Some(tree) = doc.catalog().ok().and_then(|c| get_dict(doc, c, b"PageLabels")) {
+        number_tree(doc, tree, &mut ranges, 0);
+    }
+    ranges.sort_by_key(|(start, _)| *start);
+    ranges
+}
+
+/// Label of page `i`. Roman and letter numbering above [`MAX_STYLED_LABEL`] falls back to decimal,
+/// since both grow linearly with the number.
+fn page_label(doc: &Document, ranges: &[(i64, &Dictionary)], i: usize) -> String {
+    let Some((start, range)) = ranges.iter().rev().find(|(start, _)| *start as usize <= i) else {
+        return (i + 1).to_string();
+    };
+    let first = dict_get(doc, range, b"St")
+        .and_then(|o| o.as_i64().ok())
+        .unwrap_or(1)
+        .clamp(1, MAX_LABEL_START) as usize;
+    let n = first + i - *start as usize;
+    let prefix = text_string(doc, range, b"P").unwrap_or_default();
+    let style = dict_get(doc, range, b"S").and_then(|o| o.as_name().ok());
+    let number = match style {
+        Some(b"r" | b"R" | b"a" | b"A") if n > MAX_STYLED_LABEL => n.to_string(),
+        Some(b"D") => n.to_string(),
+        Some(b"r") => roman(n),
+        Some(b"R") => roman(n).to_uppercase(),
+        Some(b"a") => letters(n),
+        Some(b"A") => letters(n).to_uppercase(),
+        _ => String::new(),
+    };
+    format!("{}{}", prefix, number)
+}
+
+fn page_labels(doc: &Document, page_count: usize) -> Vec<String> {
+    let ranges = label_ranges(doc);
+    (0..page_count).map(|i| page_label(doc, &ranges, i)).collect()
+}
+
+fn metadata(doc: &Document) -> PdfMetadata {
+    let pages: HashMap<ObjectId, usize> = doc.get_pages().values().enumerate().map(|(i, id)| (*id, i)).collect();
+    let info = doc
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|o| resolve(doc, o).as_dict().ok());
+    let field = |key: &[u8]| info.and_then(|d| text_string(doc, d, key));
+    let named = named_dests(doc);
+    let first = doc
+        .catalog()
+        .ok()
+        .and_then(|c| get_dict(doc, c, b"Outlines"))
+        .and_then(|o| get_dict(doc, o, b"First"));
+    PdfMetadata {
+        title: field(b"Title"),
+        author: field(b"Author"),
+        subject: field(b"Subject"),
+        keywords: field(b"Keywords"),
+        creator: field(b"Creator"),
+        producer: field(b"Producer"),
+        page_count: pages.len(),
+        page_labels: page_labels(doc, pages.len()),
+        outline: outline_items(doc, first, &pages, &named, &mut HashSet::new(), 0),
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Fonts
+
+/// Glyph names of printable ASCII, 0x20..=0x7E.
+const ASCII_GLYPHS: &str = concat!(
+    "space exclam quotedbl numbersign dollar percent ampersand quotesingle parenleft parenright ",
+    "asterisk plus comma hyphen period slash zero one two three four five six seven eight nine colon ",
+    "semicolon less equal greater question at A B C D E F G H I J K L M N O P Q R S T U V W X Y Z ",
+    "bracketleft backslash bracketright asciicircum underscore grave a b c d e f g h i j k l m n o p ",
+    "q r s t u v w x y z braceleft bar braceright asciitilde"
+);
+
+/// Glyph names of Latin-1, 0xA0..=0xFF.
+const LATIN1_GLYPHS: &str = concat!(
+    "nbspace exclamdown cent sterling currency yen brokenbar section dieresis copyright ordfeminine ",
+    "guillemotleft logicalnot sfthyphen registered macron degree plusminus twosuperior threesuperior ",
+    "acute mu paragraph periodcentered cedilla onesuperior ordmasculine guillemotright onequarter ",
+    "onehalf threequarters questiondown Agrave Aacute Acircumflex Atilde Adieresis Aring AE Ccedilla ",
+    "Egrave Eacute Ecircumflex Edieresis Igrave Iacute Icircumflex Idieresis Eth Ntilde Ograve Oacute ",
+    "Ocircumflex Otilde Odieresis multiply Oslash Ugrave Uacute Ucircumflex Udieresis Yacute Thorn ",
+    "germandbls agrave aacute acircumflex atilde adieresis aring ae ccedilla egrave eacute ",
+    "ecircumflex edieresis igrave iacute icircumflex idieresis eth ntilde ograve oacute ocircumflex ",
+    "otilde odieresis divide oslash ugrave uacute ucircumflex udieresis yacute thorn ydieresis"
+);
+
+const OTHER_GLYPHS: [(&str, &str); 30] = [
+    ("quoteleft", "\u{2018}"),
+    ("quoteright", "\u{2019}"),
+    ("quotedblleft", "\u{201c}"),
+    ("quotedblright", "\u{201d}"),
+    ("quotesinglbase", "\u{201a}"),
+    ("quotedblbase", "\u{201e}"),
+    ("guilsinglleft", "\u{2039}"),
+    ("guilsinglright", "\u{203a}"),
+    ("endash", "\u{2013}"),
+    ("emdash", "\u{2014}"),
+    ("bullet", "\u{2022}"),
+    ("ellipsis", "\u{2026}"),
+    ("dagger", "\u{2020}"),
+    ("daggerdbl", "\u{2021}"),
+    ("perthousand", "\u{2030}"),
+    ("trademark", "\u{2122}"),
+    ("Euro", "\u{20ac}"),
+    ("minus", "\u{2212}"),
+    ("fraction", "\u{2044}"),
+    ("dotlessi", "\u{131}"),
+    ("oe", "\u{153}"),
+    ("OE", "\u{152}"),
+    ("Scaron", "\u{160}"),
+    ("scaron", "\u{161}"),
+    ("Zcaron", "\u{17d}"),
+    ("zcaron", "\u{17e}"),
+    ("florin", "\u{192}"),
+    ("fi", "fi"),
+    ("fl", "fl"),
+    ("space", " "),
+];
+
+/// Unicode for a glyph name: `uniXXXX`, `uXXXX`, ligatures joined by `_`, and the common Latin names.
+fn glyph_unicode(name: &str) -> Option<String> {
+    let name = name.split('.').next().unwrap_or(name);
+    if name.contains('_') {
+        return name.split('_').map(glyph_unicode).collect();
+    }
+    if let Some(hex) = name.strip_prefix("uni").filter(|h| h.len() >= 4 && h.len() % 4 == 0) {
+        let units: Option<Vec<u16>> = (0..hex.len())
+            .step_by(4)
+            .map(|i| u16::from_str_radix(&hex[i..i + 4], 16).ok())
+            .collect();
+        return String::from_utf16(&units?).ok();
+    }
+    if let Some(hex) = name.strip_prefix('u').filter(|h| (4..=6).contains(&h.len())) {
+        if let Some(c) = u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+            return Some(c.to_string());
+        }
+    }
+    match name {
+        "ff" => return Some("ff".into()),
+        "ffi" => return Some("ffi".into()),
+        "ffl" => return Some("ffl".into()),
+        _ => {}
+    }
+    if let Some(i) = ASCII_GLYPHS.split(' ').position(|g| g == name) {
+        return Some(((0x20 + i) as u8 as char).to_string());
+    }
+    if let Some(i) = LATIN1_GLYPHS.split(' ').position(|g| g == name) {
+        return char::from_u32(0xA0 + i as u32).map(String::from);
+    }
+    OTHER_GLYPHS
+        .iter()
+        .find(|(g, _)| *g == name)
+        .map(|(_, u)| u.to_string())
+}
+
+/// Base encodings of simple fonts.
+fn base_encoding(name: &[u8]) -> Vec<String> {
+    let single = |encoding: &'static encoding_rs::Encoding| -> Vec<String> {
+        (0..=255u8)
+            .map(|b| {
+                if b < 0x20 {
+                    String::new()
+                } else {
+                    encoding.decode_without_bom_handling(&[b]).0.into_owned()
+                }
+            })
+            .collect()
+    };
+    match name {
+        b"MacRomanEncoding" => single(encoding_rs::MACINTOSH),
+        b"StandardEncoding" => {
+            let mut table = single(encoding_rs::WINDOWS_1252);
+            for (code, text) in [
+                (0x27, "\u{2019}"),
+                (0x60, "\u{2018}"),
+                (0xA9, "'"),
+                (0xAA, "\u{201c}"),
+                (0xAE, "fi"),
+                (0xAF, "fl"),
+                (0xB1, "\u{2013}"),
+                (0xB7, "\u{2022}"),
+                (0xBA, "\u{201d}"),
+                (0xBC, "\u{2026}"),
+                (0xD0, "\u{2014}"),
+            ] {
+                table[code] = text.to_string();
+            }
+            table
+        }
+        _ => single(encoding_rs::WINDOWS_1252),
+    }
+}
+
+/// Parses the `bfchar`/`bfrange` mappings and the code length of a ToUnicode CMap.
+fn parse_to_unicode(data: &[u8]) -> (HashMap<u32, String>, Option<usize>) {
+    fn hex_tokens(section: &str) -> Vec<Result<Vec<u8>, Vec<Vec<u8>>>> {
+        // Ok(hex string) or Err(array of hex strings).
+        let mut out = Vec::new();
+        let mut array: Option<Vec<Vec<u8>>> = None;
+        let mut chars = section.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '<' => {
+                    let end = section[i + 1..].find('>').map_or(section.len(), |e| i + 1 + e);
+                    let hex: String = section[i + 1..end].chars().filter(|c| c.is_ascii_hexdigit()).collect();
+                    let bytes: Vec<u8> = (0..hex.len() / 2)
+                        .filter_map(|j| u8::from_str_radix(&hex[j * 2..j * 2 + 2], 16).ok())
+                        .collect();
+                    match array.as_mut() {
+                        Some(items) => items.push(bytes),
+                        None => out.push(Ok(bytes)),
+                    }
+                    while chars.peek().is_some_and(|(j, _)| *j <= end) {
+                        chars.next();
+                    }
+                }
+                '[' => array = Some(Vec::new()),
+                ']' => out.extend(array.take().map(Err)),
+                _ => {}
+            }
+        }
+        out
+    }
+    let code = |b: &[u8]| b.iter().fold(0u32, |acc, x| (acc << 8) | *x as u32);
+    let utf16 = |b: &[u8]| {
+        let units: Vec<u16> = b
+            .chunks(2)
+            .map(|c| ((c[0] as u16) << 8) | *c.get(1).unwrap_or(&0) as u16)
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+
+    let text = String::from_utf8_lossy(data);
+    let mut map = HashMap::new();
+    let mut code_len = None;
+    let sections = |begin: &'static str, end: &'static str| {
+        text.split(begin)
+            .skip(1)
+            .filter_map(move |s| s.split(end).next())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    for section in sections("begincodespacerange", "endcodespacerange") {
+        if let Some(Ok(lo)) = hex_tokens(&section).first() {
+            code_len.get_or_insert(lo.len());
+        }
+    }
+    for section in sections("beginbfchar", "endbfchar") {
+        for pair in hex_tokens(&section).chunks_exact(2) {
+            if let (Ok(src), Ok(dst)) = (&pair[0], &pair[1]) {
+                map.insert(code(src), utf16(dst));
+            }
+        }
+    }
+    for section in sections("beginbfrange", "endbfrange") {
+        for triple in hex_tokens(&section).chunks_exact(3) {
+            let (Ok(lo), Ok(hi)) = (&triple[0], &triple[1]) else {
+                continue;
+            };
+            let (lo, hi) = (code(lo), code(hi));
+            if hi < lo || hi - lo > 0xFFFF {
+                continue;
+            }
+            match &triple[2] {
+                Ok(dst) if !dst.is_empty() => {
+                    let mut units: Vec<u16> = dst
+                        .chunks(2)
+                        .map(|c| ((c[0] as u16) << 8) | *c.get(1).unwrap_or(&0) as u16)
+                        .collect();
+                    for c in lo..=hi {
+                        map.insert(c, String::from_utf16_lossy(&units));
+                        if let Some(last) = units.last_mut() {
+                            *last = last.wrapping_add(1);
+                        }
+                    }
+                }
+                Err(items) => {
+                    for (c, dst) in (lo..=hi).zip(items) {
+                        map.insert(c, utf16(dst));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    (map, code_len)
+}
+
+struct PdfFont {
+    two_byte: bool,
+    to_unicode: HashMap<u32, String>,
+    /// Simple fonts: text of every code.
+    encoding: Vec<String>,
+    /// Two-byte fonts without a ToUnicode map whose codes are UCS-2 (`Uni*-UCS2-*`/`UTF16` CMaps).
+    ucs2: bool,
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+    /// Glyph space to text space, 1/1000 except for Type3 fonts.
+    scale: f32,
+    ascent: f32,
+    descent: f32,
+}
+
+impl PdfFont {
+    fn load(doc: &Document, font: &Dictionary) -> PdfFont {
+        let subtype = dict_get(doc, font, b"Subtype")
+            .and_then(|o| o.as_name().ok())
+            .unwrap_or_default();
+        let base_font = dict_get(doc, font, b"BaseFont")
+            .and_then(|o| o.as_name().ok())
+            .unwrap_or_default();
+        let (to_unicode, code_len) = match dict_get(doc, font, b"ToUnicode") {
+            Some(Object::Stream(s)) => parse_to_unicode(&s.get_plain_content().unwrap_or_default()),
+            _ => (HashMap::new(), None),
+        };
+        let encoding_obj = dict_get(doc, font, b"Encoding");
+        let mut widths = HashMap::new();
+        let mut default_width = if String::from_utf8_lossy(base_font).contains("Courier") {
+            600.0
+        } else {
+            500.0
+        };
+        let mut scale = 0.001;
+        let descriptor;
+
+        let two_byte = subtype == b"Type0";
+        let mut encoding = Vec::new();
+        let mut ucs2 = false;
+        if two_byte {
+            let encoding_name = encoding_obj.and_then(|o| o.as_name().ok()).unwrap_or_default();
+            ucs2 =
+                encoding_name.starts_with(b"Uni") && (encoding_name.windows(4).any(|w| w == b"UCS2" || w == b"UTF1"));
+            let descendant = match dict_get(doc, font, b"DescendantFonts") {
+                Some(Object::Array(items)) => items.first().and_then(|o| resolve(doc, o).as_dict().ok()),
+                _ => None,
+            };
+            default_width = descendant.and_then(|d| get_number(doc, d, b"DW")).unwrap_or(1000.0);
+            if let Some(Object::Array(w)) = descendant.and_then(|d| dict_get(doc, d, b"W")) {
+                let mut i = 0;
+                while i + 1 < w.len() {
+                    let Ok(first) = resolve(doc, &w[i]).as_i64() else { break };
+                    match resolve(doc, &w[i + 1]) {
+                        Object::Array(list) => {
+                            for (j, width) in list.iter().enumerate() {
+                                if let Ok(width) = resolve(doc, width).as_float() {
+                                    widths.insert(first as u32 + j as u32, width);
+                                }
+                            }
+                            i += 2;
+                        }
+                        last => {
+                            let (Ok(last), Some(width)) = (
+                                last.as_i64(),
+                                w.get(i + 2).and_then(|o| resolve(doc, o).as_float().ok()),
+                            ) else {
+                                break;
+                            };
+                            for c in first..=last.min(first + 0xFFFF) {
+                                widths.insert(c as u32, width);
+                            }
+                            i += 3;
+                        }
+                    }
+                }
+            }
+            descriptor = descendant.and_then(|d| get_dict(doc, d, b"FontDescriptor"));
+        } else {
+            let (base, differences) = match encoding_obj {
+                Some(Object::Name(name)) => (name.as_slice(), None),
+                Some(Object::Dictionary(d)) => (
+                    dict_get(doc, d, b"BaseEncoding")
+                        .and_then(|o| o.as_name().ok())
+                        .unwrap_or_default(),
+                    dict_get(doc, d, b"Differences"),
+                ),
+                _ if subtype == b"Type1" => (b"StandardEncoding".as_slice(), None),
+                _ => (b"WinAnsiEncoding".as_slice(), None),
+            };
+            encoding = base_encoding(base);
+            if let Some(Object::Array(diffs)) = differences {
+                let mut code = 0usize;
+                for item in diffs {
+                    match resolve(doc, item) {
+                        Object::Integer(n) => code = (*n).clamp(0, 255) as usize,
+                        Object::Name(name) => {
+                            if code < 256 {
+                                if let Some(text) = glyph_unicode(&String::from_utf8_lossy(name)) {
+                                    encoding[code] = text;
+                                }
+                            }
+                            code += 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            let first = get_number(doc, font, b"FirstChar").unwrap_or(0.0) as u32;
+            if let Ok(w) = font.get(b"Widths") {
+                for (i, width) in numbers(doc, w).into_iter().enumerate() {
+                    widths.insert(first + i as u32, width);
+                }
+            }
+            if subtype == b"Type3" {
+                if let Some(m) = font
+                    .get(b"FontMatrix")
+                    .ok()
+                    .map(|m| numbers(doc, m))
+                    .filter(|m| m.len() == 6)
+                {
+                    scale = m[0];
+                }
+            }
+            descriptor = get_dict(doc, font, b"FontDescriptor");
+            if let Some(missing) = descriptor.and_then(|d| get_number(doc, d, b"MissingWidth")) {
+                if missing > 0.0 {
+                    default_width = missing;
+                }
+            }
+        }
+        let ascent = descriptor
+            .and_then(|d| get_number(doc, d, b"Ascent"))
+            .filter(|a| *a > 0.0)
+            .unwrap_or(800.0);
+        let descent = descriptor
+            .and_then(|d| get_number(doc, d, b"Descent"))
+            .filter(|d| *d < 0.0)
+            .unwrap_or(-200.0);
+        PdfFont {
+            two_byte: match code_len {
+                Some(len) if two_byte => len >= 2,
+                _ => two_byte,
+            },
+            to_unicode,
+            encoding,
+            ucs2,
+            widths,
+            default_width,
+            scale,
+            ascent: ascent / 1000.0,
+            descent: descent / 1000.0,
+        }
+    }
+
+    /// Character codes of a string operand.
+    fn codes<'a>(&self, bytes: &'a [u8]) -> impl Iterator<Item = (u32, bool)> + 'a {
+        let step = if self.two_byte { 2 } else { 1 };
+        bytes.chunks(step).map(move |c| {
+            let code = c.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32);
+            (code, step == 1 && code == 32)
+        })
+    }
+
+    fn text(&self, code: u32) -> String {
+        if let Some(text) = self.to_unicode.get(&code) {
+            return text.clone();
+        }
+        if self.two_byte {
+            return match char::from_u32(code).filter(|_| self.ucs2) {
+                Some(c) => c.to_string(),
+                None => String::new(),
+            };
+        }
+        self.encoding.get(code as usize).cloned().unwrap_or_default()
+    }
+
+    /// Advance of a glyph in text space units per unit font size.
+    fn width(&self, code: u32) -> f32 {
+        self.widths.get(&code).copied().unwrap_or(self.default_width) * self.scale
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Content stream interpretation
+
+type Matrix = [f32; 6];
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn multiply(m1: &Matrix, m2: &Matrix) -> Matrix {
+    [
+        m1[0] * m2[0] + m1[1] * m2[2],
+        m1[0] * m2[1] + m1[1] * m2[3],
+        m1[2] * m2[0] + m1[3] * m2[2],
+        m1[2] * m2[1] + m1[3] * m2[3],
+        m1[4] * m2[0] + m1[5] * m2[2] + m2[4],
+        m1[4] * m2[1] + m1[5] * m2[3] + m2[5],
+    ]
+}
+
+fn apply(m: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (x * m[0] + y * m[2] + m[4], x * m[1] + y * m[3] + m[5])
+}
+
+fn translate(tx: f32, ty: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+/// A text-showing operator's output in user space.
+#[derive(Debug, Clone)]
+struct RawSpan {
+    text: String,
+    /// Baseline start and end.
+    x0: f32,
+    x1: f32,
+    y: f32,
+    rect: [f32; 4],
+    size: f32,
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    char_spacing: f32,
+    word_spacing: f32,
+    scale: f32,
+    leading: f32,
+    font: Option<usize>,
+    font_size: f32,
+    rise: f32,
+}
+
+struct Interpreter<'a> {
+    doc: &'a Document,
+    fonts: Vec<PdfFont>,
+    font_ids: HashMap<*const Dictionary, usize>,
+    spans: Vec<RawSpan>,
+    forms: Vec<ObjectId>,
+}
+
+fn operand(ops: &[Object], i: usize) -> f32 {
+    ops.get(i).and_then(|o| o.as_float().ok()).unwrap_or(0.0)
+}
+
+impl<'a> Interpreter<'a> {
+    fn resource(&self, resources: &[&'a Dictionary], category: &[u8], name: &[u8]) -> Option<&'a Object> {
+        resources.iter().find_map(|r| {
+            let group = get_dict(self.doc, r, category)?;
+            dict_get(self.doc, group, name)
+        })
+    }
+
+    fn font(&mut self, resources: &[&'a Dictionary], name: &[u8]) -> Option<usize> {
+        let dict = self.resource(resources, b"Font", name)?.as_dict().ok()?;
+        let key = dict as *const Dictionary;
+        if let Some(i) = self.font_ids.get(&key) {
+            return Some(*i);
+        }
+        self.fonts.push(PdfFont::load(self.doc, dict));
+        self.font_ids.insert(key, self.fonts.len() - 1);
+        Some(self.fonts.len() - 1)
+    }
+
+    fn run(&mut self, content: &[u8], resources: &[&'a Dictionary], gs: GraphicsState) {
+        let Ok(content) = Content::decode(content) else { return };
+        let mut stack: Vec<GraphicsState> = Vec::new();
+        let mut gs = gs;
+        let mut tm = IDENTITY;
+        let mut tlm = IDENTITY;
+        for op in &content.operations {
+            let ops = &op.operands;
+            match op.operator.as_str() {
+                "q" => stack.push(gs.clone()),
+                "Q" => {
+                    if let Some(saved) = stack.pop() {
+                        gs = saved;
+                    }
+                }
+                "cm" if ops.len() == 6 => {
+                    let m = [0, 1, 2, 3, 4, 5].map(|i| operand(ops, i));
+                    gs.ctm = multiply(&m, &gs.ctm);
+                }
+                "BT" => {
+                    tm = IDENTITY;
+                    tlm = IDENTITY;
+                }
+                "Tc" => gs.char_spacing = operand(ops, 0),
+                "Tw" => gs.word_spacing = operand(ops, 0),
+                "Tz" => gs.scale = operand(ops, 0) / 100.0,
+                "TL" => gs.leading = operand(ops, 0),
+                "Ts" => gs.rise = operand(ops, 0),
+                "Tf" => {
+                    gs.font = ops
+                        .first()
+                        .and_then(|o| o.as_name().ok())
+                        .and_then(|n| self.font(resources, n));
+                    gs.font_size = operand(ops, 1);
+                }
+                "Td" | "TD" => {
+                    if op.operator == "TD" {
+                        gs.leading = -operand(ops, 1);
+                    }
+                    tlm = multiply(&translate(operand(ops, 0), operand(ops, 1)), &tlm);
+                    tm = tlm;
+                }
+                "Tm" if ops.len() == 6 => {
+                    tlm = [0, 1, 2, 3, 4, 5].map(|i| operand(ops, i));
+                    tm = tlm;
+                }
+                "T*" => {
+                    tlm = multiply(&translate(0.0, -gs.leading), &tlm);
+                    tm = tlm;
+                }
+                "Tj" | "'" | "\"" | "TJ" => {
+                    if op.operator == "\"" {
+                        gs.word_spacing = operand(ops, 0);
+                        gs.char_spacing = operand(ops, 1);
+                    }
+                    if op.operator == "'" || op.operator == "\"" {
+                        tlm = multiply(&translate(0.0, -gs.leading), &tlm);
+                        tm = tlm;
+                    }
+                    let items: Vec<&Object> = match (op.operator.as_str(), ops.first()) {
+                        ("TJ", Some(Object::Array(items))) => items.iter().collect(),
+                        _ => ops.last().into_iter().collect(),
+                    };
+                    self.show(&items, &gs, &mut tm);
+                }
+                "Do" => {
+                    let Some(name) = ops.first().and_then(|o| o.as_name().ok()) else {
+                        continue;
+                    };
+                    self.form(resources, name, &gs);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn form(&mut self, resources: &[&'a Dictionary], name: &[u8], gs: &GraphicsState) {
+        let Some(Ok(id)) = resources.iter().find_map(|r| {
+            let group = get_dict(self.doc, r, b"XObject")?;
+            group.get(name).ok().map(|o| o.as_reference())
+        }) else {
+            return;
+        };
+        if self.forms.contains(&id) || self.forms.len() >= 8 {
+            return;
+        }
+        let Ok(Object::Stream(stream)) = self.doc.get_object(id) else {
+            return;
+        };
+        if dict_get(self.doc, &stream.dict, b"Subtype").and_then(|o| o.as_name().ok()) != Some(b"Form") {
+            return;
+        }
+        let Ok(content) = stream.get_plain_content() else {
+            return;
+        };
+        let mut inner = resources.to_vec();
+        if let Some(own) = get_dict(self.doc, &stream.dict, b"Resources") {
+            inner.insert(0, own);
+        }
+        let mut gs = gs.clone();
+        if let Some(m) = stream
+            .dict
+            .get(b"Matrix")
+            .ok()
+            .map(|m| numbers(self.doc, m))
+            .filter(|m| m.len() == 6)
+        {
+            gs.ctm = multiply(&[m[0], m[1], m[2], m[3], m[4], m[5]], &gs.ctm);
+        }
+        self.forms.push(id);
+        self.run(&content, &inner, gs);
+        self.forms.pop();
+    }
+
+    fn show(&mut self, items: &[&Object], gs: &GraphicsState, tm: &mut Matrix) {
+        let Some(font) = gs.font.and_then(|i| self.fonts.get(i)) else {
+            return;
+        };
+        let start = multiply(tm, &gs.ctm);
+        let mut text = String::new();
+        let mut advance = 0.0f32;
+        for item in items {
+            match item {
+                Object::String(bytes, _) => {
+                    for (code, is_space) in font.codes(bytes) {
+                        text.push_str(&font.text(code));
+                        let mut tx = font.width(code) * gs.font_size + gs.char_spacing;
+                        if is_space {
+                            tx += gs.word_spacing;
+                        }
+                        let tx = tx * gs.scale;
+                        advance += tx;
+                        *tm = multiply(&translate(tx, 0.0), tm);
+                    }
+                }
+                number => {
+                    let Ok(n) = number.as_float() else { continue };
+                    let tx = -n / 1000.0 * gs.font_size * gs.scale;
+                    // Kerning wider than a fifth of an em separates words.
+                    if -n > 200.0 && !text.is_empty() && !text.ends_with(char::is_whitespace) {
+                        text.push(' ');
+                    }
+                    advance += tx;
+                    *tm = multiply(&translate(tx, 0.0), tm);
+                }
+            }
+        }
+        let text: String = text
+            .chars()
+            .map(|c| if c == '\u{a0}' || c.is_control() { ' ' } else { c })
+            .collect();
+        if text.trim().is_empty() {
+            return;
+        }
+        let low = gs.rise + font.descent * gs.font_size;
+        let high = gs.rise + font.ascent * gs.font_size;
+        let corners = [(0.0, low), (advance, low), (advance, high), (0.0, high)].map(|(x, y)| apply(&start, x, y));
+        let xs = corners.map(|c| c.0);
+        let ys = corners.map(|c| c.1);
+        let (x0, y) = apply(&start, 0.0, gs.rise);
+        let (x1, _) = apply(&start, advance, gs.rise);
+        self.spans.push(RawSpan {
+            text,
+            x0,
+            x1,
+            y,
+            rect: [
+                xs.iter().copied().fold(f32::MAX, f32::min),
+                ys.iter().copied().fold(f32::MAX, f32::min),
+                xs.iter().copied().fold(f32::MIN, f32::max),
+                ys.iter().copied().fold(f32::MIN, f32::max),
+            ],
+            size: (start[2].hypot(start[3]) * gs.font_size).abs().max(1.0),
+        });
+    }
+}
+
+// ---------------------------------------------------------------------------------------------
+// Reading order
+
+fn union(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+    [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])]
+}
+
+struct Line {
+    spans: Vec<RawSpan>,
+    rect: [f32; 4],
+    y: f32,
+    size: f32,
+}
+
+impl Line {
+    fn end(&self) -> f32 {
+        self.spans.last().map_or(self.rect[2], |s| s.x1)
+    }
+}
+
+struct Block {
+    lines: Vec<Line>,
+    rect: [f32; 4],
+}
+
+/// Joins spans that continue each other on a baseline. Spans may arrive out of order, so every open line
+/// is a candidate, not just the last one.
+fn group_lines(spans: Vec<RawSpan>) -> Vec<Line> {
+    let mut lines: Vec<Line> = Vec::new();
+    for span in spans {
+        let target = lines.iter_mut().rev().find(|line| {
+            let size = line.size.min(span.size);
+            (line.y - span.y).abs() < size * 0.5
+                && span.x0 >= line.end() - size * 0.5
+                && span.x0 - line.end() < size * 3.0
+        });
+        match target {
+            Some(line) => {
+                line.rect = union(line.rect, span.rect);
+                line.size = line.size.max(span.size);
+                line.spans.push(span);
+            }
+            None => lines.push(Line {
+                rect: span.rect,
+                y: span.y,
+                size: span.size,
+                spans: vec![span],
+            }),
+        }
+    }
+    lines
+}
+
+/// Stacks lines into blocks when one sits directly below another and they overlap horizontally.
+fn group_blocks(lines: Vec<Line>) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for line in lines {
+        let target = blocks.iter_mut().rev().find(|block| {
+            let last = block.lines.last().unwrap();
+            let gap = last.y - line.y;
+            let ratio = last.size.max(line.size) / last.size.min(line.size);
+            gap > 0.0
+                && gap < last.size.max(line.size) * 1.8
+                && ratio < 1.3
+                && line.rect[0] < block.rect[2]
+                && line.rect[2] > block.rect[0]
+        });
+        match target {
+            Some(block) => {
+                block.rect = union(block.rect, line.rect);
+                block.lines.push(line);
+            }
+            None => blocks.push(Block {
+                rect: line.rect,
+                lines: vec![line],
+            }),
+        }
+    }
+    blocks
+}
+
+/// The widest empty band along one axis that splits `blocks`, as (lower group, upper group).
+fn split(blocks: &[Block], axis: usize) -> Option<(Vec<usize>, Vec<usize>)> {
+    let mut order: Vec<usize> = (0..blocks.len()).collect();
+    order.sort_by(|a, b| blocks[*a].rect[axis].total_cmp(&blocks[*b].rect[axis]));
+    let mut reach = f32::MIN;
+    let mut best: Option<(f32, usize)> = None;
+    for (i, idx) in order.iter().enumerate() {
+        let rect = &blocks[*idx].rect;
+        if i > 0 && rect[axis] - reach > 1.0 && best.is_none_or(|(gap, _)| rect[axis] - reach > gap) {
+            best = Some((rect[axis] - reach, i));
+        }
+        reach = reach.max(rect[axis + 2]);
+    }
+    let (_, at) = best?;
+    Some((order[..at].to_vec(), order[at..].to_vec()))
+}
+
+/// Recursive XY-cut: columns are read left to right, bands top to bottom.
+fn reading_order(blocks: Vec<Block>, out: &mut Vec<Block>) {
+    if blocks.len() <= 1 {
+        out.extend(blocks);
+        return;
+    }
+    let (first, second) = match split(&blocks, 0) {
+        Some((left, right)) => (left, right),
+        None => match split(&blocks, 1) {
+            Some((below, above)) => (above, below),
+            None => {
+                let mut blocks = blocks;
+                blocks.sort_by(|a, b| b.rect[3].total_cmp(&a.rect[3]).then(a.rect[0].total_cmp(&b.rect[0])));
+                out.extend(blocks);
+                return;
+            }
+        },
+    };
+    let mut slots: Vec<Option<Block>> = blocks.into_iter().map(Some).collect();
+    let mut take = |indices: Vec<usize>| indices.into_iter().filter_map(|i| slots[i].take()).collect::<Vec<_>>();
+    let first = take(first);
+    let second = take(second);
+    reading_order(first, out);
+    reading_order(second, out);
+}
+
+fn layout(spans: Vec<RawSpan>) -> (String, Vec<PdfBlock>, Vec<PdfSpan>) {
+    let mut ordered = Vec::new();
+    reading_order(group_blocks(group_lines(spans)), &mut ordered);
+
+    let mut text = String::new();
+    let mut len = 0usize;
+    let mut push = |text: &mut String, s: &str| {
+        text.push_str(s);
+        len += s.chars().count();
+        len
+    };
+    let mut blocks = Vec::new();
+    let mut spans = Vec::new();
+    for mut block in ordered {
+        if !text.is_empty() {
+            push(&mut text, "\n\n");
+        }
+        let block_start = push(&mut text, "");
+        block
+            .lines
+            .sort_by(|a, b| b.y.total_cmp(&a.y).then(a.rect[0].total_cmp(&b.rect[0])));
+        for (i, line) in block.lines.iter().enumerate() {
+            if i > 0 {
+                push(&mut text, "\n");
+            }
+            let mut prev_end: Option<f32> = None;
+            for span in &line.spans {
+                let gap = prev_end.map(|end| span.x0 - end);
+                if gap.is_some_and(|g| g > span.size * 0.15)
+                    && !text.ends_with(char::is_whitespace)
+                    && !span.text.starts_with(char::is_whitespace)
+                {
+                    push(&mut text, " ");
+                }
+                let start = push(&mut text, "");
+                let end = push(&mut text, &span.text);
+                spans.push(PdfSpan {
+                    start,
+                    end,
+                    rect: span.rect,
+                    font_size: span.size,
+                });
+                prev_end = Some(span.x1);
+            }
+        }
+        let end = push(&mut text, "");
+        blocks.push(PdfBlock {
+            start: block_start,
+            end,
+            rect: block.rect,
+        });
+    }
+    (text, blocks, spans)
+}
+
+fn page_text(doc: &Document, index: usize, page_id: ObjectId, label: String) -> Result<PdfPageText, String> {
+    let page = doc.get_dictionary(page_id).map_err(|e| e.to_string())?;
+    let media = inherited(doc, page, b"MediaBox")
+        .map(|o| numbers(doc, o))
+        .filter(|b| b.len() == 4);
+    let crop = inherited(doc, page, b"CropBox")
+        .map(|o| numbers(doc, o))
+        .filter(|b| b.len() == 4);
+    let view_box = crop.or(media).map_or([0.0, 0.0, 612.0, 792.0], |b| {
+        [b[0].min(b[2]), b[1].min(b[3]), b[0].max(b[2]), b[1].max(b[3])]
+    });
+    let rotate = inherited(doc, page, b"Rotate")
+        .and_then(|o| o.as_i64().ok())
+        .unwrap_or(0)
+        .rem_euclid(360);
+
+    let resources: Vec<&Dictionary> = inherited(doc, page, b"Resources")
+        .and_then(|o| o.as_dict().ok())
+        .into_iter()
+        .collect();
+    let content = doc.get_page_content(page_id).map_err(|e| e.to_string())?;
+    let mut interpreter = Interpreter {
+        doc,
+        fonts: Vec::new(),
+        font_ids: HashMap::new(),
+        spans: Vec::new(),
+        forms: Vec::new(),
+    };
+    interpreter.run(
+        &content,
+        &resources,
+        GraphicsState {
+            ctm: IDENTITY,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scale: 1.0,
+            leading: 0.0,
+            font: None,
+            font_size: 0.0,
+            rise: 0.0,
+        },
+    );
+    let (text, blocks, spans) = layout(interpreter.spans);
+    Ok(PdfPageText {
+        index,
+        label,
+        view_box,
+        rotate,
+        text,
+        blocks,
+        spans,
+    })
+}
+
+/// Document info, outline and page labels.
+#[tauri::command]
+pub async fn pdf_metadata(state: State<'_, AppState>, path: String) -> Result<PdfMetadata, String> {
+    let docs = state.pdf_documents.clone();
+    tokio::task::spawn_blocking(move || Ok(metadata(&*docs.open(Path::new(&path))?)))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Drops the parsed document for `path` once its reader closes.
+#[tauri::command]
+pub fn pdf_close_document(state: State<AppState>, path: String) {
+    state.pdf_documents.remove(Path::new(&path));
+}
+
+/// Text of one page (zero-based) in reading order, with span coordinates for highlights.
+#[tauri::command]
+pub async fn pdf_page_text(state: State<'_, AppState>, path: String, index: usize) -> Result<PdfPageText, String> {
+    let docs = state.pdf_documents.clone();
+    tokio::task::spawn_blocking(move || {
+        let doc = docs.open(Path::new(&path))?;
+        let pages = doc.get_pages();
+        let page_id = *pages
+            .values()
+            .nth(index)
+            .ok_or_else(|| format!("page {} out of range", index))?;
+        let label = page_label(&doc, &label_ranges(&doc), index);
+        page_text(&doc, index, page_id, label)
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Text of every page of `doc`, read from `path`. Pages that fail to parse are logged and skipped.
+fn document_text(doc: &Document, path: &Path) -> Vec<PdfPageText> {
+    let pages = doc.get_pages();
+    let labels = page_labels(doc, pages.len());
+    pages
+        .values()
+        .zip(labels)
+        .enumerate()
+        .filter_map(|(i, (id, label))| match page_text(doc, i, *id, label) {
+            Ok(page) => Some(page),
+            Err(e) => {
+                log::warn!("[pdf_text] {} page {i}: {e}", path.display());
+                None
+            }
+        })
+        .collect()
+}
+
+/// Plain text of every page as (zero-based index, text), for indexing without the layout.
+pub(crate) fn page_texts(path: &Path) -> Result<Vec<(usize, String)>, String> {
+    let doc = open_document(path)?;
+    Ok(document_text(&doc, path).into_iter().map(|p| (p.index, p.text)).collect())
+}
+
+/// Text of every page. Pages that fail to parse are logged and skipped.
+#[tauri::command]
+pub async fn pdf_document_text(state: State<'_, AppState>, path: String) -> Result<Vec<PdfPageText>, String> {
+    let docs = state.pdf_documents.clone();
+    tokio::task::spawn_blocking(move || {
+        let path = PathBuf::from(path);
+        Ok(document_text(&*docs.open(&path)?, &path))
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Stream};
+
+    /// Two-column page with a full-width title, drawn right column first.
+    fn sample() -> Document {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let to_unicode = doc.add_object(Stream::new(
+            dictionary! {},
+            b"begincmap 1 begincodespacerange <0000> <FFFF> endcodespacerange \
+2 beginbfchar <0001> <0048> <0002> <0069> endbfchar 1 beginbfrange <0010> <0012> <4E2D> endbfrange endcmap"
+                .to_vec(),
+        ));
+        let helvetica = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Helvetica",
+            "Encoding" => dictionary! {
+                "Type" => "Encoding",
+                "BaseEncoding" => "WinAnsiEncoding",
+                "Differences" => vec![0x80.into(), "fi".into(), "quoteright".into()],
+            },
+        });
+        let cid = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => "Song",
+            "Encoding" => "Identity-H",
+            "ToUnicode" => to_unicode,
+            "DescendantFonts" => vec![dictionary! {
+                "Type" => "Font",
+                "Subtype" => "CIDFontType2",
+                "DW" => 1000,
+            }.into()],
+        });
+        let content = b"BT /F1 20 Tf 1 0 0 1 72 740 Tm (Big Title) Tj ET \
+BT /F1 10 Tf 1 0 0 1 320 680 Tm (Right \x80rst line) Tj 0 -12 Td [(right) -300 (second)] TJ ET \
+BT /F1 10 Tf 72 680 Td (Left column) Tj T* ET \
+BT /F1 10 Tf 12 TL 72 668 Td (it\x81s next) Tj ET \
+BT /F2 10 Tf 72 600 Td <000100020010> Tj ET";
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.to_vec()));
+        let page = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        let empty = doc.add_object(Stream::new(dictionary! {}, Vec::new()));
+        let second = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => empty,
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page.into(), second.into()],
+                "Count" => 2,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => helvetica, "F2" => cid } },
+            }),
+        );
+        let outline_id = doc.new_object_id();
+        let child = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Part"),
+            "Parent" => outline_id,
+            "Dest" => vec![second.into(), "Fit".into()],
+        });
+        let top = doc.add_object(dictionary! {
+            "Title" => Object::String(b"\xFE\xFF\x00C\x00h\x00.\x00 \x001".to_vec(), lopdf::StringFormat::Literal),
+            "Parent" => outline_id,
+            "A" => dictionary! { "S" => "GoTo", "D" => Object::string_literal("intro") },
+            "First" => child,
+            "Last" => child,
+        });
+        doc.objects.insert(
+            outline_id,
+            Object::Dictionary(dictionary! { "First" => top, "Last" => top }),
+        );
+        let catalog = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Outlines" => outline_id,
+            "Dests" => dictionary! { "intro" => vec![page.into(), "XYZ".into()] },
+            "PageLabels" => dictionary! { "Nums" => vec![
+                0.into(), dictionary! { "S" => "r" }.into(),
+                1.into(), dictionary! { "S" => "D", "P" => Object::string_literal("A-"), "St" => 3 }.into(),
+            ] },
+        });
+        let info = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Sample"),
+            "Author" => Object::string_literal("Someone "),
+        });
+        doc.trailer.set("Root", catalog);
+        doc.trailer.set("Info", info);
+        doc
+    }
+
+    #[test]
+    fn caches_parsed_documents_until_the_file_changes() {
+        let dir = std::env::temp_dir().join(format!("aireader_pdf_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.pdf");
+        sample().save(&path).unwrap();
+
+        let docs = PdfDocuments::new();
+        let first = docs.open(&path).unwrap();
+        assert!(Arc::ptr_eq(&first, &docs.open(&path).unwrap()));
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1)).unwrap();
+        assert!(!Arc::ptr_eq(&first, &docs.open(&path).unwrap()));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reads_metadata_outline_and_labels() {
+        let meta = metadata(&sample());
+        assert_eq!(meta.title.as_deref(), Some("Sample"));
+        assert_eq!(meta.author.as_deref(), Some("Someone"));
+        assert_eq!(meta.subject, None);
+        assert_eq!(meta.page_labels, vec!["i", "A-3"]);
+        assert_eq!(meta.outline.len(), 1);
+        assert_eq!(meta.outline[0].title, "Ch. 1");
+        assert_eq!(meta.outline[0].page, Some(0));
+        assert_eq!(meta.outline[0].children[0].title, "Part");
+        assert_eq!(meta.outline[0].children[0].page, Some(1));
+    }
+
+    #[test]
+    fn bounds_huge_label_starts() {
+        let mut doc = sample();
+        let labels = dictionary! { "Nums" => vec![
+            0.into(), dictionary! { "S" => "R", "St" => i64::MAX }.into(),
+            1.into(), dictionary! { "S" => "D", "St" => i64::MAX }.into(),
+        ] };
+        doc.catalog_mut().unwrap().set("PageLabels", labels);
+        let ranges = label_ranges(&doc);
+        assert_eq!(page_label(&doc, &ranges, 0), "100000");
+        assert_eq!(page_label(&doc, &ranges, 1), "100000");
+        assert_eq!(page_labels(&doc, 2), vec!["100000", "100000"]);
+    }
+
+    #[test]
+    fn extracts_text_in_reading_order_with_coordinates() {
+        let doc = sample();
+        let page_id = *doc.get_pages().values().next().unwrap();
+        let page = page_text(&doc, 0, page_id, "i".into()).unwrap();
+        assert_eq!(page.view_box, [0.0, 0.0, 612.0, 792.0]);
+        assert_eq!(
+            page.text,
+            "Big Title\n\nLeft column\nit\u{2019}s next\n\nHi\u{4e2d}\n\nRight first line\nright second"
+        );
+        assert_eq!(page.blocks.len(), 4);
+
+        let title = &page.spans[0];
+        assert_eq!((title.start, title.end), (0, 9));
+        assert_eq!(title.font_size, 20.0);
+        assert!((title.rect[0] - 72.0).abs() < 0.01);
+        assert!((title.rect[1] - 736.0).abs() < 0.01);
+        assert!((title.rect[3] - 756.0).abs() < 0.01);
+
+        let right = page
+            .spans
+            .iter()
+            .find(|s| page.text.chars().skip(s.start).take(5).collect::<String>() == "Right")
+            .unwrap();
+        assert!((right.rect[0] - 320.0).abs() < 0.01);
+        let second = page.spans.last().unwrap();
+        assert!((second.rect[1] - (668.0 - 2.0)).abs() < 0.01);
+    }
+}
@@ -0,0 +1,305 @@
+use serde::Serialize;
+
+/// One syllable of a CEDICT pinyin string such as `ni3 hao3`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PinyinSyllable {
+    /// The syllable as stored in CEDICT (`hao3`, `lu:4`, `r5`).
+    pub numbered: String,
+    /// Tone-marked form (`hǎo`); non-syllables such as `,` are copied through.
+    pub marked: String,
+    /// Zhuyin/bopomofo form (`ㄏㄠˇ`), when the syllable is a standard one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zhuyin: Option<String>,
+    /// 1–4 for the four tones, 5 for the neutral tone, `None` for non-syllables.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tone: Option<u8>,
+}
+
+const INITIALS: &[(&str, &str)] = &[
+    ("zh", "ㄓ"),
+    ("ch", "ㄔ"),
+    ("sh", "ㄕ"),
+    ("b", "ㄅ"),
+    ("p", "ㄆ"),
+    ("m", "ㄇ"),
+    ("f", "ㄈ"),
+    ("d", "ㄉ"),
+    ("t", "ㄊ"),
+    ("n", "ㄋ"),
+    ("l", "ㄌ"),
+    ("g", "ㄍ"),
+    ("k", "ㄎ"),
+    ("h", "ㄏ"),
+    ("j", "ㄐ"),
+    ("q", "ㄑ"),
+    ("x", "ㄒ"),
+    ("r", "ㄖ"),
+    ("z", "ㄗ"),
+    ("c", "ㄘ"),
+    ("s", "ㄙ"),
+];
+
+const FINALS: &[(&str, &str)] = &[
+    ("a", "ㄚ"),
+    ("o", "ㄛ"),
+    ("e", "ㄜ"),
+    ("ê", "ㄝ"),
+    ("ai", "ㄞ"),
+    ("ei", "ㄟ"),
+    ("ao", "ㄠ"),
+    ("ou", "ㄡ"),
+    ("an", "ㄢ"),
+    ("en", "ㄣ"),
+    ("ang", "ㄤ"),
+    ("eng", "ㄥ"),
+    ("ong", "ㄨㄥ"),
+    ("er", "ㄦ"),
+    ("i", "ㄧ"),
+    ("ia", "ㄧㄚ"),
+    ("ie", "ㄧㄝ"),
+    ("iao", "ㄧㄠ"),
+    ("iu", "ㄧㄡ"),
+    ("iou", "ㄧㄡ"),
+    ("ian", "ㄧㄢ"),
+    ("in", "ㄧㄣ"),
+    ("iang", "ㄧㄤ"),
+    ("ing", "ㄧㄥ"),
+    ("iong", "ㄩㄥ"),
+    ("u", "ㄨ"),
+    ("ua", "ㄨㄚ"),
+    ("uo", "ㄨㄛ"),
+    ("uai", "ㄨㄞ"),
+    ("ui", "ㄨㄟ"),
+    ("uei", "ㄨㄟ"),
+    ("uan", "ㄨㄢ"),
+    ("un", "ㄨㄣ"),
+    ("uen", "ㄨㄣ"),
+    ("uang", "ㄨㄤ"),
+    ("ueng", "ㄨㄥ"),
+    ("ü", "ㄩ"),
+    ("üe", "ㄩㄝ"),
+    ("üan", "ㄩㄢ"),
+    ("ün", "ㄩㄣ"),
+];
+
+/// Splits `hao3` into (`hao`, Some(3)). The body has `u:`/`v` normalised to `ü`.
+fn split_tone(raw: &str) -> Option<(String, Option<u8>)> {
+    let (body, tone) = match raw.chars().last()? {
+        c @ '0'..='5' => (&raw[..raw.len() - 1], Some(if c == '0' { 5 } else { c as u8 - b'0' })),
+        _ => (raw, None),
+    };
+    if body.is_empty() {
+        return None;
+    }
+    let body = body.replace("u:", "ü").replace("U:", "Ü").replace('v', "ü").replace('V', "Ü");
+    if !body.chars().all(|c| c.is_alphabetic()) || !body.is_ascii() && !body.contains(['ü', 'Ü', 'ê', 'Ê']) {
+        return None;
+    }
+    Some((body, tone))
+}
+
+fn mark_vowel(c: char, tone: u8) -> char {
+    let table: &[(char, [char; 4])] = &[
+        ('a', ['ā', 'á', 'ǎ', 'à']),
+        ('e', ['ē', 'é', 'ě', 'è']),
+        ('i', ['ī', 'í', 'ǐ', 'ì']),
+        ('o', ['ō', 'ó', 'ǒ', 'ò']),
+        ('u', ['ū', 'ú', 'ǔ', 'ù']),
+        ('ü', ['ǖ', 'ǘ', 'ǚ', 'ǜ']),
+        ('ê', ['ê', 'ế', 'ê', 'ề']),
+        ('A', ['Ā', 'Á', 'Ǎ', 'À']),
+        ('E', ['Ē', 'É', 'Ě', 'È']),
+        ('O', ['Ō', 'Ó', 'Ǒ', 'Ò']),
+    ];
+    match (tone, table.iter().find(|(v, _)| *v == c)) {
+        (1..=4, Some((_, marks))) => marks[tone as usize - 1],
+        _ => c,
+    }
+}
+
+/// Puts the tone mark on `a`/`e` if present, on the `o` of `ou`, otherwise on the last vowel.
+fn add_tone_mark(body: &str, tone: u8) -> String {
+    let lower = body.to_lowercase();
+    let chars: Vec<char> = body.chars().collect();
+    let lower_chars: Vec<char> = lower.chars().collect();
+    let is_vowel = |c: char| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'ü' | 'ê');
+
+    let idx = lower_chars
+        .iter()
+        .position(|&c| c == 'a' || c == 'e' || c == 'ê')
+        .or_else(|| lower.find("ou").map(|b| lower[..b].chars().count()))
+        .or_else(|| lower_chars.iter().rposition(|&c| is_vowel(c)));
+
+    chars
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| if Some(i) == idx { mark_vowel(c, tone) } else { c })
+        .collect()
+}
+
+fn to_zhuyin(body: &str, tone: Option<u8>) -> Option<String> {
+    let s = body.to_lowercase();
+    let tone_mark = match tone {
+        Some(2) => "ˊ",
+        Some(3) => "ˇ",
+        Some(4) => "ˋ",
+        _ => "",
+    };
+    let with_tone = |core: String| {
+        if tone == Some(5) {
+            format!("˙{}", core)
+        } else {
+            format!("{}{}", core, tone_mark)
+        }
+    };
+
+    // Erhua suffix and standalone `er`.
+    if s == "r" {
+        return Some(with_tone("ㄦ".to_string()));
+    }
+
+    // Syllables written with y/w map back onto the i/u/ü finals.
+    let normalized = if let Some(rest) = s.strip_prefix('y') {
+        if let Some(after_u) = rest.strip_prefix('u') {
+            format!("ü{}", after_u)
+        } else if rest.starts_with('i') {
+            rest.to_string()
+        } else {
+            format!("i{}", rest)
+        }
+    } else if let Some(rest) = s.strip_prefix('w') {
+        if rest.starts_with('u') {
+            rest.to_string()
+        } else {
+            format!("u{}", rest)
+        }
+    } else {
+        s.clone()
+    };
+
+    let (initial, final_part) = match INITIALS.iter().find(|(p, _)| normalized.starts_with(p)) {
+        Some((p, z)) => (Some((*p, *z)), &normalized[p.len()..]),
+        None => (None, normalized.as_str()),
+    };
+
+    let core = match initial {
+        // zhi, chi, shi, ri, zi, ci, si: the vowel is not written in zhuyin.
+        Some((p, z)) if final_part == "i" && matches!(p, "zh" | "ch" | "sh" | "r" | "z" | "c" | "s") => z.to_string(),
+        Some((p, z)) => {
+            // After j/q/x a written `u` is really `ü`.
+            let fin = match final_part.strip_prefix('u') {
+                Some(after_u) if matches!(p, "j" | "q" | "x") => format!("ü{}", after_u),
+                _ => final_part.to_string(),
+            };
+            let zf = FINALS.iter().find(|(f, _)| *f == fin)?.1;
+            format!("{}{}", z, zf)
+        }
+        None => FINALS.iter().find(|(f, _)| *f == final_part)?.1.to_string(),
+    };
+    Some(with_tone(core))
+}
+
+/// Parses one whitespace-separated CEDICT pinyin token.
+pub fn parse_syllable(raw: &str) -> PinyinSyllable {
+    match split_tone(raw) {
+        Some((body, tone)) => PinyinSyllable {
+            numbered: raw.to_string(),
+            marked: match tone {
+                Some(t) => add_tone_mark(&body, t),
+                None => body.clone(),
+            },
+            zhuyin: tone.and_then(|t| to_zhuyin(&body, Some(t))),
+            tone,
+        },
+        None => PinyinSyllable {
+            numbered: raw.to_string(),
+            marked: raw.to_string(),
+            zhuyin: None,
+            tone: None,
+        },
+    }
+}
+
+/// Splits a CEDICT pinyin string (`ni3 hao3`) into annotated syllables.
+pub fn syllables(pinyin: &str) -> Vec<PinyinSyllable> {
+    pinyin.split_whitespace().map(parse_syllable).collect()
+}
+
+/// `ni3 hao3` → `nǐ hǎo`.
+pub fn numbered_to_marked(pinyin: &str) -> String {
+    syllables(pinyin)
+        .into_iter()
+        .map(|s| s.marked)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `ni3 hao3` → `ㄋㄧˇ ㄏㄠˇ`. Tokens without a zhuyin form are copied through.
+pub fn numbered_to_zhuyin(pinyin: &str) -> String {
+    syllables(pinyin)
+        .into_iter()
+        .map(|s| s.zhuyin.unwrap_or(s.numbered))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinyinStyle {
+    Marks,
+    Zhuyin,
+    Numbers,
+}
+
+/// Converts numbered pinyin to the requested display style.
+#[tauri::command]
+pub fn pinyin_convert(pinyin: String, style: Option<PinyinStyle>) -> Result<String, String> {
+    Ok(match style.unwrap_or(PinyinStyle::Marks) {
+        PinyinStyle::Marks => numbered_to_marked(&pinyin),
+        PinyinStyle::Zhuyin => numbered_to_zhuyin(&pinyin),
+        PinyinStyle::Numbers => pinyin.split_whitespace().collect::<Vec<_>>().join(" "),
+    })
+}
+
+/// Per-syllable tone marks, zhuyin and tone numbers, e.g. for coloring tones in the UI.
+#[tauri::command]
+pub fn pinyin_syllables(pinyin: String) -> Result<Vec<PinyinSyllable>, String> {
+    Ok(syllables(&pinyin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tone_marks() {
+        assert_eq!(numbered_to_marked("ni3 hao3"), "nǐ hǎo");
+        assert_eq!(numbered_to_marked("Bei3 jing1"), "Běi jīng");
+        assert_eq!(numbered_to_marked("gou3 liu2 xue2"), "gǒu liú xué");
+        assert_eq!(numbered_to_marked("lu:4 nu:3"), "lǜ nǚ");
+        assert_eq!(numbered_to_marked("lve4"), "lüè");
+        assert_eq!(numbered_to_marked("ma5 er2 r5"), "ma ér r");
+        assert_eq!(numbered_to_marked("xx5 , A"), "xx , A");
+        assert_eq!(numbered_to_marked("gui4 Ou1"), "guì Ōu");
+    }
+
+    #[test]
+    fn test_zhuyin() {
+        assert_eq!(numbered_to_zhuyin("ni3 hao3"), "ㄋㄧˇ ㄏㄠˇ");
+        assert_eq!(numbered_to_zhuyin("zhi1 shi5"), "ㄓ ˙ㄕ");
+        assert_eq!(numbered_to_zhuyin("xue2 yu3 yuan2 you3 wei4"), "ㄒㄩㄝˊ ㄩˇ ㄩㄢˊ ㄧㄡˇ ㄨㄟˋ");
+        assert_eq!(numbered_to_zhuyin("lu:4 jiong3 wo3 er4"), "ㄌㄩˋ ㄐㄩㄥˇ ㄨㄛˇ ㄦˋ");
+        assert_eq!(numbered_to_zhuyin("yi1 ying1 weng1"), "ㄧ ㄧㄥ ㄨㄥ");
+        assert_eq!(numbered_to_zhuyin("xx5 ,"), "xx5 ,");
+    }
+
+    #[test]
+    fn test_syllable_tones() {
+        let s = syllables("zhong1 guo2 ren2 ma5 ,");
+        let tones: Vec<Option<u8>> = s.iter().map(|x| x.tone).collect();
+        assert_eq!(tones, vec![Some(1), Some(2), Some(2), Some(5), None]);
+        assert_eq!(s[1].marked, "guó");
+        assert_eq!(s[1].zhuyin.as_deref(), Some("ㄍㄨㄛˊ"));
+    }
+}
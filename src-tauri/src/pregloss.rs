@@ -0,0 +1,580 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri::State;
+
+use crate::dictionary::{clean_definition_text, ecdict_manager_ready, stardict_definitions_to_result};
+use crate::AppState;
+
+/// Upper bound on distinct words per call, to keep one IPC round trip bounded.
+const MAX_DISTINCT_WORDS: usize = 5000;
+/// Short glosses keep at most this many senses of the first translation line.
+const GLOSS_SENSES: usize = 2;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreglossOptions {
+    /// Only return words at least this rare (COCA/BNC rank); words without a rank are kept.
+    pub min_rank: Option<u32>,
+    /// Skip words carrying any of these exam tags (e.g. `["zk", "gk"]`).
+    #[serde(default)]
+    pub exclude_tags: Vec<String>,
+    /// Document whose glossary should override dictionary glosses.
+    pub document_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreglossToken {
+    /// Character (not byte) offsets into the input, end exclusive.
+    pub start: usize,
+    pub end: usize,
+    pub word: String,
+    /// Dictionary headword the gloss belongs to (`went` → `go`).
+    pub lemma: String,
+    pub gloss: String,
+    /// COCA frequency rank (lower is more common).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frq: Option<u32>,
+    /// British National Corpus frequency rank.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bnc: Option<u32>,
+    /// Collins star rating, 1–5.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collins: Option<u8>,
+    pub oxford: bool,
+    /// ECDICT exam tags: zk, gk, cet4, cet6, ky, toefl, ielts, gre.
+    pub tags: Vec<String>,
+}
+
+/// Dictionary data for one headword, shared by every occurrence of it in the text.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct GlossInfo {
+    lemma: String,
+    gloss: String,
+    frq: Option<u32>,
+    bnc: Option<u32>,
+    collins: Option<u8>,
+    oxford: bool,
+    tags: Vec<String>,
+}
+
+impl GlossInfo {
+    fn rank(&self) -> Option<u32> {
+        match (self.frq, self.bnc) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// English word tokens with character offsets. Apostrophes and hyphens are kept inside
+/// words (`don't`, `well-known`), not at their edges.
+fn tokenize(text: &str) -> Vec<(usize, usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if !chars[i].is_ascii_alphabetic() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() {
+            let c = chars[i];
+            let joiner = matches!(c, '\'' | '’' | '-')
+                && chars.get(i + 1).is_some_and(|n| n.is_ascii_alphabetic());
+            if c.is_ascii_alphabetic() || joiner {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        let word: String = chars[start..i].iter().collect();
+        out.push((start, i, word.replace('’', "'")));
+    }
+    out
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u')
+}
+
+/// Candidate base forms for an inflected English word, most likely first. Used when the
+/// dictionary has no entry (or no lemma link) for the surface form.
+fn lemma_candidates(word: &str) -> Vec<String> {
+    let w = word.to_lowercase();
+    let mut out: Vec<String> = vec![];
+    let mut push = |s: String| {
+        if s.len() >= 2 && s != w && !out.contains(&s) {
+            out.push(s);
+        }
+    };
+
+    if let Some(base) = w.strip_suffix("'s").or_else(|| w.strip_suffix("s'")) {
+        push(base.to_string());
+    }
+    if let Some(base) = w.strip_suffix("ies") {
+        push(format!("{}y", base));
+    }
+    if let Some(base) = w.strip_suffix("es") {
+        if base.ends_with("sh") || base.ends_with("ch") || base.ends_with(['s', 'x', 'z', 'o']) {
+            push(base.to_string());
+        }
+    }
+    if let Some(base) = w.strip_suffix('s') {
+        if !base.ends_with('s') {
+            push(base.to_string());
+        }
+    }
+    if let Some(base) = w.strip_suffix("ied") {
+        push(format!("{}y", base));
+    }
+    for suffix in ["ed", "ing", "er", "est"] {
+        let Some(base) = w.strip_suffix(suffix) else {
+            continue;
+        };
+        let chars: Vec<char> = base.chars().collect();
+        // stopped → stop, running → run
+        if chars.len() >= 3 && chars[chars.len() - 1] == chars[chars.len() - 2] && !is_vowel(chars[chars.len() - 1]) {
+            push(chars[..chars.len() - 1].iter().collect());
+        }
+        push(base.to_string());
+        push(format!("{}e", base));
+        if suffix != "ing" {
+            if let Some(stem) = base.strip_suffix('i') {
+                push(format!("{}y", stem));
+            }
+        }
+    }
+    if let Some(base) = w.strip_suffix("ly") {
+        push(base.to_string());
+    }
+    out
+}
+
+/// Keeps the first `GLOSS_SENSES` senses of the first line of a definition.
+fn short_gloss(definition: &str) -> String {
+    let cleaned = clean_definition_text(definition);
+    let first = cleaned
+        .split('\n')
+        .map(|s| s.trim())
+        .find(|s| !s.is_empty())
+        .unwrap_or("");
+    let mut senses = vec![];
+    let mut cur = String::new();
+    for c in first.chars() {
+        if matches!(c, ',' | '，' | ';' | '；') {
+            if !cur.trim().is_empty() {
+                senses.push(cur.trim().to_string());
+            }
+            cur.clear();
+            if senses.len() >= GLOSS_SENSES {
+                break;
+            }
+        } else {
+            cur.push(c);
+        }
+    }
+    if senses.len() < GLOSS_SENSES && !cur.trim().is_empty() {
+        senses.push(cur.trim().to_string());
+    }
+    senses.join("，")
+}
+
+/// Lemma from an ECDICT `exchange` field (`0:go/1:p` on the entry for `went`).
+fn exchange_lemma(exchange: &str) -> Option<String> {
+    exchange
+        .split('/')
+        .find_map(|part| part.strip_prefix("0:"))
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// Inflections listed in an ECDICT `exchange` field (`p:went/d:gone/i:going/3:goes`).
+fn exchange_forms(exchange: &str) -> Vec<String> {
+    exchange
+        .split('/')
+        .filter_map(|part| part.split_once(':'))
+        .filter(|(kind, _)| matches!(*kind, "p" | "d" | "i" | "3" | "r" | "t" | "s"))
+        .map(|(_, form)| form.trim().to_string())
+        .filter(|form| !form.is_empty())
+        .collect()
+}
+
+struct EcdictRow {
+    word: String,
+    translation: Option<String>,
+    definition: Option<String>,
+    collins: Option<i64>,
+    oxford: Option<i64>,
+    tag: Option<String>,
+    bnc: Option<i64>,
+    frq: Option<i64>,
+    exchange: Option<String>,
+}
+
+/// Builds the single-row query for whichever ECDICT table exists, substituting NULL for
+/// columns that older installs (built before frequencies were imported) lack.
+fn ecdict_row_query(conn: &Connection) -> Option<String> {
+    for (table, collate) in [("entries", ""), ("stardict", " COLLATE NOCASE")] {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).ok()?;
+        let cols: Vec<String> = stmt
+            .query_map([], |r| r.get(1))
+            .ok()?
+            .filter_map(|r| r.ok())
+            .collect();
+        if !cols.iter().any(|c| c == "word") {
+            continue;
+        }
+        let select = ["word", "translation", "definition", "collins", "oxford", "tag", "bnc", "frq", "exchange"]
+            .iter()
+            .map(|c| if cols.iter().any(|x| x == c) { c.to_string() } else { "NULL".to_string() })
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Some(format!("SELECT {} FROM {} WHERE word = ?1{} LIMIT 1", select, table, collate));
+    }
+    None
+}
+
+fn query_row(conn: &Connection, sql: &str, word: &str) -> Result<Option<EcdictRow>, String> {
+    let mut stmt = conn.prepare_cached(sql).map_err(|e| e.to_string())?;
+    stmt.query_row([word], |r| {
+        Ok(EcdictRow {
+            word: r.get(0)?,
+            translation: r.get(1)?,
+            definition: r.get(2)?,
+            collins: r.get(3)?,
+            oxford: r.get(4)?,
+            tag: r.get(5)?,
+            bnc: r.get(6)?,
+            frq: r.get(7)?,
+            exchange: r.get(8)?,
+        })
+    })
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn row_to_info(row: EcdictRow) -> Option<GlossInfo> {
+    let gloss = short_gloss(row.translation.as_deref().filter(|t| !t.trim().is_empty()).or(row.definition.as_deref())?);
+    if gloss.is_empty() {
+        return None;
+    }
+    let rank = |v: Option<i64>| v.filter(|n| *n > 0).and_then(|n| u32::try_from(n).ok());
+    Some(GlossInfo {
+        lemma: row.word,
+        gloss,
+        frq: rank(row.frq),
+        bnc: rank(row.bnc),
+        collins: row.collins.filter(|n| (1..=5).contains(n)).map(|n| n as u8),
+        oxford: row.oxford.unwrap_or(0) > 0,
+        tags: row
+            .tag
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect(),
+    })
+}
+
+/// Resolves one word against ECDICT: the word itself, following its `exchange` lemma link
+/// to the base entry, then rule-based lemma candidates.
+fn lookup_info(mut lookup: impl FnMut(&str) -> Result<Option<EcdictRow>, String>, word: &str) -> Result<Option<GlossInfo>, String> {
+    let lower = word.to_lowercase();
+    let mut tries = vec![word.to_string()];
+    if lower != word {
+        tries.push(lower.clone());
+    }
+
+    for w in &tries {
+        let Some(row) = lookup(w)? else {
+            continue;
+        };
+        if let Some(lemma) = row.exchange.as_deref().and_then(exchange_lemma) {
+            if lemma.to_lowercase() != w.to_lowercase() {
+                if let Some(base) = lookup(&lemma)?.and_then(row_to_info) {
+                    return Ok(Some(base));
+                }
+            }
+        }
+        if let Some(info) = row_to_info(row) {
+            return Ok(Some(info));
+        }
+    }
+
+    for cand in lemma_candidates(word) {
+        if let Some(info) = lookup(&cand)?.and_then(row_to_info) {
+            return Ok(Some(info));
+        }
+    }
+    Ok(None)
+}
+
+/// `word`, its lemma and every inflection of that lemma ECDICT lists (`went` → went, go,
+/// goes, going, gone), without duplicates.
+fn word_forms_sqlite(conn: &Connection, word: &str) -> Result<Vec<String>, String> {
+    let mut forms = vec![word.to_string()];
+    let Some(sql) = ecdict_row_query(conn) else {
+        return Ok(forms);
+    };
+    let lower = word.to_lowercase();
+    let row = match query_row(conn, &sql, word)? {
+        Some(row) => Some(row),
+        None if lower != word => query_row(conn, &sql, &lower)?,
+        None => None,
+    };
+    let Some(row) = row else {
+        return Ok(forms);
+    };
+
+    let lemma_row = match row.exchange.as_deref().and_then(exchange_lemma) {
+        Some(lemma) if !lemma.eq_ignore_ascii_case(&row.word) => query_row(conn, &sql, &lemma)?.unwrap_or(row),
+        _ => row,
+    };
+    let mut candidates = vec![lemma_row.word.clone()];
+    candidates.extend(lemma_row.exchange.as_deref().map(exchange_forms).unwrap_or_default());
+    for form in candidates {
+        if !forms.iter().any(|f| f.eq_ignore_ascii_case(&form)) {
+            forms.push(form);
+        }
+    }
+    Ok(forms)
+}
+
+/// Word forms for matching `word` in running text. Only the SQLite ECDICT carries
+/// inflection data; otherwise this is just `word`.
+pub(crate) fn ecdict_word_forms(state: &AppState, word: &str) -> Vec<String> {
+    ecdict_manager_ready(state);
+    if !state.dictionary.uses_db() {
+        return vec![word.to_string()];
+    }
+    state
+        .dictionary
+        .with_db(|conn| word_forms_sqlite(conn, word))
+        .unwrap_or_else(|_| vec![word.to_string()])
+}
+
+fn lookup_all_sqlite(conn: &Connection, words: &[String]) -> Result<HashMap<String, GlossInfo>, String> {
+    let Some(sql) = ecdict_row_query(conn) else {
+        return Ok(HashMap::new());
+    };
+    let mut out = HashMap::new();
+    for w in words {
+        if let Some(info) = lookup_info(|x| query_row(conn, &sql, x), w)? {
+            out.insert(w.clone(), info);
+        }
+    }
+    Ok(out)
+}
+
+fn lookup_all_stardict(dict: &mut stardict::StarDictStd, words: &[String]) -> Result<HashMap<String, GlossInfo>, String> {
+    use stardict::StarDict;
+
+    let mut out = HashMap::new();
+    for w in words {
+        let mut lookup = |x: &str| -> Result<Option<EcdictRow>, String> {
+            let defs = dict
+                .lookup(x)
+                .map_err(|e| format!("stardict lookup failed: {e:?}"))?;
+            Ok(defs.and_then(|d| stardict_definitions_to_result(x, d)).map(|r| EcdictRow {
+                word: x.to_string(),
+                translation: r.translation,
+                definition: None,
+                collins: None,
+                oxford: None,
+                tag: None,
+                bnc: None,
+                frq: None,
+                exchange: None,
+            }))
+        };
+        if let Some(info) = lookup_info(&mut lookup, w)? {
+            out.insert(w.clone(), info);
+        }
+    }
+    Ok(out)
+}
+
+fn keep_token(info: &GlossInfo, options: &PreglossOptions) -> bool {
+    if let (Some(min), Some(rank)) = (options.min_rank, info.rank()) {
+        if rank < min {
+            return false;
+        }
+    }
+    !info.tags.iter().any(|t| options.exclude_tags.iter().any(|x| x.eq_ignore_ascii_case(t)))
+}
+
+/// Tokenizes `text`, lemmatizes and looks every distinct word up in ECDICT under a single
+/// lock, and returns one gloss per occurrence. User glossary entries override the gloss.
+#[tauri::command]
+pub fn dictionary_pregloss(
+    state: State<AppState>,
+    text: String,
+    options: Option<PreglossOptions>,
+) -> Result<Vec<PreglossToken>, String> {
+    let options = options.unwrap_or_default();
+    let tokens = tokenize(&text);
+
+    let mut distinct: Vec<String> = vec![];
+    let mut seen = std::collections::HashSet::new();
+    for (_, _, w) in &tokens {
+        if seen.insert(w.clone()) {
+            distinct.push(w.clone());
+        }
+    }
+    if distinct.len() > MAX_DISTINCT_WORDS {
+        return Err(format!("text has too many distinct words (max {})", MAX_DISTINCT_WORDS));
+    }
+
+    ecdict_manager_ready(&state);
+    let mut infos = if state.dictionary.uses_db() {
+        state.dictionary.with_db(|conn| lookup_all_sqlite(conn, &distinct))?
+    } else {
+        state.dictionary.with_stardict(|dict| lookup_all_stardict(dict, &distinct))?
+    };
+
+    // Glossary terms win over dictionary glosses, as in single-word lookups.
+    let glossary = state
+        .db
+        .get_applicable_glossary_entries(options.document_id.as_deref())
+        .unwrap_or_default();
+    for w in &distinct {
+        let hit = glossary.iter().find(|e| {
+            if e.case_sensitive {
+                e.term == *w
+            } else {
+                e.term.eq_ignore_ascii_case(w)
+            }
+        });
+        if let Some(e) = hit {
+            let info = infos.entry(w.clone()).or_insert_with(|| GlossInfo {
+                lemma: e.term.clone(),
+                ..Default::default()
+            });
+            info.gloss = e.translation.clone();
+        }
+    }
+
+    Ok(tokens
+        .into_iter()
+        .filter_map(|(start, end, word)| {
+            let info = infos.get(&word)?;
+            if !keep_token(info, &options) {
+                return None;
+            }
+            Some(PreglossToken {
+                start,
+                end,
+                word,
+                lemma: info.lemma.clone(),
+                gloss: info.gloss.clone(),
+                frq: info.frq,
+                bnc: info.bnc,
+                collins: info.collins,
+                oxford: info.oxford,
+                tags: info.tags.clone(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (word TEXT PRIMARY KEY COLLATE NOCASE, phonetic TEXT, definition TEXT, \
+             translation TEXT, pos TEXT, audio TEXT, collins INTEGER, oxford INTEGER, tag TEXT, bnc INTEGER, \
+             frq INTEGER, exchange TEXT);
+             INSERT INTO entries(word, translation, collins, oxford, tag, bnc, frq, exchange) VALUES
+               ('go', 'v. 去, 走, 离开\\nn. 尝试', 5, 1, 'zk gk', 40, 35, 'p:went/d:gone/i:going/3:goes'),
+               ('went', 'v. 去（go的过去式）', NULL, NULL, NULL, NULL, NULL, '0:go/1:p'),
+               ('ubiquitous', 'a. 普遍存在的, 无所不在的', 1, 0, 'cet6 ky toefl ielts gre', 9000, 8000, NULL),
+               ('stop', 'v. 停止；阻止', 5, 1, 'zk gk', 300, 250, NULL),
+               ('city', 'n. 城市', 5, 1, 'zk gk', 200, 150, NULL);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_tokenize_offsets() {
+        let t = tokenize("He didn’t go — well-known 3D “cities”.");
+        let words: Vec<&str> = t.iter().map(|x| x.2.as_str()).collect();
+        assert_eq!(words, vec!["He", "didn't", "go", "well-known", "D", "cities"]);
+        assert_eq!((t[2].0, t[2].1), (10, 12));
+    }
+
+    #[test]
+    fn test_lemma_candidates() {
+        assert!(lemma_candidates("stopped").contains(&"stop".to_string()));
+        assert!(lemma_candidates("cities").contains(&"city".to_string()));
+        assert!(lemma_candidates("making").contains(&"make".to_string()));
+        assert!(lemma_candidates("boxes").contains(&"box".to_string()));
+        assert!(lemma_candidates("happier").contains(&"happy".to_string()));
+    }
+
+    #[test]
+    fn test_short_gloss() {
+        assert_eq!(short_gloss("v. 去, 走, 离开\\nn. 尝试"), "v. 去，走");
+        assert_eq!(short_gloss("n. 城市"), "n. 城市");
+    }
+
+    #[test]
+    fn test_batch_lookup_lemmatizes() {
+        let conn = test_db();
+        let words: Vec<String> = ["went", "Stopped", "cities", "ubiquitous", "zzzz"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let infos = lookup_all_sqlite(&conn, &words).unwrap();
+        assert_eq!(infos["went"].lemma, "go");
+        assert_eq!(infos["went"].tags, vec!["zk", "gk"]);
+        assert_eq!(infos["Stopped"].lemma, "stop");
+        assert_eq!(infos["cities"].gloss, "n. 城市");
+        assert_eq!(infos["ubiquitous"].collins, Some(1));
+        assert!(!infos.contains_key("zzzz"));
+    }
+
+    #[test]
+    fn test_word_forms_follow_lemma() {
+        let conn = test_db();
+        assert_eq!(word_forms_sqlite(&conn, "Went").unwrap(), vec!["Went", "go", "gone", "going", "goes"]);
+        assert_eq!(word_forms_sqlite(&conn, "go").unwrap(), vec!["go", "went", "gone", "going", "goes"]);
+        assert_eq!(word_forms_sqlite(&conn, "city").unwrap(), vec!["city"]);
+        assert_eq!(word_forms_sqlite(&conn, "xyzzy").unwrap(), vec!["xyzzy"]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let conn = test_db();
+        let infos = lookup_all_sqlite(&conn, &["go".to_string(), "ubiquitous".to_string()]).unwrap();
+        let opts = PreglossOptions {
+            min_rank: Some(1000),
+            ..Default::default()
+        };
+        assert!(!keep_token(&infos["go"], &opts));
+        assert!(keep_token(&infos["ubiquitous"], &opts));
+        let opts = PreglossOptions {
+            exclude_tags: vec!["GRE".to_string()],
+            ..Default::default()
+        };
+        assert!(!keep_token(&infos["ubiquitous"], &opts));
+    }
+
+    #[test]
+    fn test_old_schema_without_frequencies() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE entries (word TEXT PRIMARY KEY COLLATE NOCASE, phonetic TEXT, definition TEXT, translation TEXT, pos TEXT, audio TEXT);
+             INSERT INTO entries(word, translation) VALUES ('book', 'n. 书');",
+        )
+        .unwrap();
+        let infos = lookup_all_sqlite(&conn, &["books".to_string()]).unwrap();
+        assert_eq!(infos["books"].gloss, "n. 书");
+        assert_eq!(infos["books"].frq, None);
+    }
+}
@@ -0,0 +1,273 @@
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+use crate::text_encoding::decode_text;
+
+/// Lines longer than this are body text, whatever they start with.
+const MAX_TITLE_CHARS: usize = 60;
+/// Fewer blank-line headings than this are treated as coincidence.
+const MIN_BLANK_LINE_HEADINGS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct TextChapter {
+    pub title: String,
+    /// 1 = part/volume, 2 = chapter, 3 = section.
+    pub level: u8,
+    /// Zero-based line number of the heading.
+    pub line: usize,
+    /// Heading position in UTF-16 code units, matching JavaScript string indices.
+    pub offset: usize,
+    /// Heading position in the decoded UTF-8 text.
+    pub byte_offset: usize,
+}
+
+fn chinese_heading() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(concat!(
+            r"^第[0-9０-９零〇一二三四五六七八九十百千万两壹贰叁肆伍陆柒捌玖拾佰]+([卷部集篇章回节節])",
+            r"(?:$|[\s:：.、，,·\-—]|[^\s]{0,30}$)"
+        ))
+        .expect("valid chinese heading regex")
+    })
+}
+
+fn chinese_named_heading() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^(?:序章|序言|序|楔子|引子|前言|后记|後記|尾声|尾聲|番外[0-9一二三四五六七八九十]*|终章|終章)(?:$|[\s:：])")
+            .expect("valid chinese named heading regex")
+    })
+}
+
+fn english_heading() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)^(chapter|chap\.|part|book|volume|vol\.|section)\s+",
+            r"(\d+|[ivxlcdm]+|(?:twenty|thirty|forty|fifty|sixty|seventy|eighty|ninety)(?:[- ]?(?:one|two|three|four|five|six|seven|eight|nine))?",
+            r"|one|two|three|four|five|six|seven|eight|nine|ten|eleven|twelve|thirteen|fourteen|fifteen|sixteen|seventeen|eighteen|nineteen",
+            r"|first|second|third|fourth|fifth|sixth|seventh|eighth|ninth|tenth|last|the\s+\w+)",
+            r"(?:$|[\s:.\-–—]\s*\S.*$|[:.]$)"
+        ))
+        .expect("valid english heading regex")
+    })
+}
+
+fn english_named_heading() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"(?i)^(?:prologue|epilogue|preface|foreword|introduction|interlude|afterword)(?:$|[\s:.\-–—])")
+            .expect("valid english named heading regex")
+    })
+}
+
+/// Heading level for a built-in pattern match, or `None` when the line is body text.
+fn builtin_level(line: &str) -> Option<u8> {
+    if let Some(c) = chinese_heading().captures(line) {
+        return Some(match &c[1] {
+            "卷" | "部" | "集" | "篇" => 1,
+            "节" | "節" => 3,
+            _ => 2,
+        });
+    }
+    if chinese_named_heading().is_match(line) || english_named_heading().is_match(line) {
+        return Some(2);
+    }
+    let c = english_heading().captures(line)?;
+    Some(match c[1].to_ascii_lowercase().as_str() {
+        "part" | "book" | "volume" | "vol." => 1,
+        "section" => 3,
+        _ => 2,
+    })
+}
+
+/// A short, isolated line that does not read like the end of a sentence.
+fn looks_like_blank_line_heading(line: &str) -> bool {
+    let last = line.chars().last().unwrap_or('.');
+    !matches!(
+        last,
+        '.' | ',' | ';' | '。' | '，' | '；' | '、' | '"' | '”' | '’' | ')' | '）'
+    ) && line.chars().filter(|c| c.is_alphanumeric()).count() > 0
+}
+
+struct Line<'a> {
+    text: &'a str,
+    byte_offset: usize,
+    offset: usize,
+}
+
+fn split_lines(text: &str) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let (mut byte_offset, mut offset) = (0, 0);
+    for raw in text.split_inclusive('\n') {
+        lines.push(Line {
+            text: raw.trim_end_matches(['\n', '\r']),
+            byte_offset,
+            offset,
+        });
+        byte_offset += raw.len();
+        offset += raw.encode_utf16().count();
+    }
+    lines
+}
+
+/// Drops tables of contents: runs of three or more same-level headings on nearly consecutive lines.
+fn drop_listings(chapters: Vec<TextChapter>) -> Vec<TextChapter> {
+    let mut keep = vec![true; chapters.len()];
+    let mut i = 0;
+    while i < chapters.len() {
+        let mut j = i;
+        while j + 1 < chapters.len()
+            && chapters[j + 1].level == chapters[i].level
+            && chapters[j + 1].line - chapters[j].line <= 2
+        {
+            j += 1;
+        }
+        if j - i >= 2 {
+            keep[i..=j].iter_mut().for_each(|k| *k = false);
+        }
+        i = j + 1;
+    }
+    chapters
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(c, k)| k.then_some(c))
+        .collect()
+}
+
+/// Finds chapter headings in plain text.
+///
+/// With `custom`, every line it matches is a heading, titled by its `title` group or first group when present.
+/// Otherwise built-in patterns ("Chapter 12", "CHAPTER XII", "第十二章", "Part One", …) are tried, falling
+/// back to short lines set apart by blank lines.
+pub fn detect_chapters(text: &str, custom: Option<&Regex>) -> Vec<TextChapter> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let lines = split_lines(text);
+    let heading = |i: usize, title: &str, level: u8| TextChapter {
+        title: title.to_string(),
+        level,
+        line: i,
+        offset: lines[i].offset,
+        byte_offset: lines[i].byte_offset,
+    };
+
+    if let Some(re) = custom {
+        return lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let c = re.captures(line.text)?;
+                let title = c
+                    .name("title")
+                    .or_else(|| c.get(1))
+                    .map_or(line.text, |m| m.as_str())
+                    .trim();
+                (!title.is_empty()).then(|| heading(i, title, 1))
+            })
+            .collect();
+    }
+
+    let matched: Vec<TextChapter> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let title = line.text.trim();
+            if title.chars().count() > MAX_TITLE_CHARS {
+                return None;
+            }
+            builtin_level(title).map(|level| heading(i, title, level))
+        })
+        .collect();
+    let matched = drop_listings(matched);
+    if !matched.is_empty() {
+        return matched;
+    }
+
+    let blank = |i: usize| lines.get(i).is_none_or(|l| l.text.trim().is_empty());
+    let isolated: Vec<TextChapter> = (0..lines.len())
+        .filter(|&i| {
+            let title = lines[i].text.trim();
+            !title.is_empty()
+                && title.chars().count() <= MAX_TITLE_CHARS / 2
+                && (i == 0 || (i >= 2 && blank(i - 1) && blank(i - 2)))
+                && blank(i + 1)
+                && looks_like_blank_line_heading(title)
+        })
+        .map(|i| heading(i, lines[i].text.trim(), 2))
+        .collect();
+    if isolated.len() >= MIN_BLANK_LINE_HEADINGS {
+        isolated
+    } else {
+        Vec::new()
+    }
+}
+
+/// Detected chapters of a TXT/MD file, optionally using a user-provided line regex.
+#[tauri::command]
+pub async fn text_chapters(path: String, pattern: Option<String>) -> Result<Vec<TextChapter>, String> {
+    tokio::task::spawn_blocking(move || {
+        let custom = match pattern.as_deref().map(str::trim).filter(|p| !p.is_empty()) {
+            Some(p) => Some(Regex::new(p).map_err(|e| format!("invalid chapter pattern: {}", e))?),
+            None => None,
+        };
+        // Decoded like `text_read`, so offsets index the text the reader shows.
+        let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+        Ok(detect_chapters(&decode_text(&bytes), custom.as_ref()))
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(chapters: &[TextChapter]) -> Vec<(&str, u8)> {
+        chapters.iter().map(|c| (c.title.as_str(), c.level)).collect()
+    }
+
+    #[test]
+    fn detects_builtin_headings_and_skips_contents_listing() {
+        let text = "目录\n第一章 开始\n第二章 相遇\n第三章 离别\n\n第一卷 风起\n\n第一章 开始\r\n“你好。”他说。\n\n\
+                    第十二章：相遇\n第十二章的故事说来话长，要从很久很久以前的一个下雨的早晨讲起，那时候谁也不知道后来会发生什么事情。\n\
+                    番外 春天\n\nPART ONE\n\nChapter 12\nCHAPTER XII. The Return\nChapter twenty-one\n\n\
+                    Part of the reason was obvious.\nPrologue\n";
+        let chapters = detect_chapters(text, None);
+        assert_eq!(
+            titles(&chapters),
+            vec![
+                ("第一卷 风起", 1),
+                ("第一章 开始", 2),
+                ("第十二章：相遇", 2),
+                ("番外 春天", 2),
+                ("PART ONE", 1),
+                ("Prologue", 2),
+            ]
+        );
+        // Chapter 12 / CHAPTER XII / Chapter twenty-one sit on consecutive lines and look like a listing.
+        let third = &chapters[2];
+        assert_eq!(
+            &text[third.byte_offset..third.byte_offset + "第十二章".len()],
+            "第十二章"
+        );
+        assert_eq!(third.offset, text[..third.byte_offset].encode_utf16().count());
+    }
+
+    #[test]
+    fn falls_back_to_blank_lines_and_honours_custom_pattern() {
+        let text = "The Storm\n\nIt was dark.\n\n\nThe Harbour\n\nShips came in.\n\n\nHome Again\n\nThe end.\n";
+        assert_eq!(
+            titles(&detect_chapters(text, None)),
+            vec![("The Storm", 2), ("The Harbour", 2), ("Home Again", 2)]
+        );
+
+        let custom = Regex::new(r"^=+\s*(.+?)\s*=+$").unwrap();
+        let text = "== One ==\nbody\n== Two ==\nmore\n";
+        let chapters = detect_chapters(text, Some(&custom));
+        assert_eq!(titles(&chapters), vec![("One", 1), ("Two", 1)]);
+        assert_eq!(chapters[1].offset, 15);
+    }
+}
@@ -0,0 +1,404 @@
+use crate::AppState;
+use encoding_rs::Encoding;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::State;
+
+/// Bytes scored by the statistical detector.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Frequent hanzi in simplified and traditional form; text decoded with the wrong CJK codec rarely hits them.
+const COMMON_HANZI: &str = concat!(
+    "的一是不了在人有我他这个们中来上大为和国地到以说时要就出会可也你对生能而子那得于着下自之年过发后作里",
+    "用道行所然家种事成方多经么去法学如都同现当没动面起看定天分还进好小部其些主样理心她本前开但因只从想实",
+    "日军者意无力它与长把机十民第公此已工使情明性知全三又关点正业外将两高间由问很最重并物手应战向头文体政",
+    "美相见被利什二等产或新己制身果加西斯月话合回特代内信表化老给世位次度门任常先海通教儿原东声提立及比员",
+    "解水名真论处走义各入几口认条平系气题活尔更别打女变四神总何电数安少报才结反受目太量再感建务做接必场件",
+    "计管期市直德资命山金指克许统区保至队形社便空决治展马科司五基眼书非则听白却界达光放强即像难且权思王象",
+    "這個們來為國說時會對於著過發後裡種經麼學現當沒動還進樣開從實軍與長機關點業將兩間問應戰頭體見產話",
+    "內給門兒東聲員題爾別變總電數報結務場計許統區隊決馬書則聽卻達強難權爭運們麼嗎呢吧啊",
+);
+
+/// Frequent Hangul syllables.
+const COMMON_HANGUL: &str = concat!(
+    "이다는에의을가고하지한로서기리수시사그일들어있나대정도자인아적게해것부우으제주전보상와국면를은라만니",
+    "여요거원화성동장소계무내중경생발했습니까죠네께서도록면서었던겠어요데때문말씀그래서오늘우리너무많이좋은",
+    "없다같은알고싶지만잘모르겠음것을하는있는되는했다한다에서으로부터까지에게처럼보다마다입니다습니다",
+);
+
+/// Most frequent Russian letters; Cyrillic decoded from the wrong code page seldom keeps to them.
+const COMMON_CYRILLIC: &str = "оеаинтсрвлкмдпуяыьгзбчйОЕАИНТСРВЛКМДПУЯ";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TextEncodingReport {
+    /// Encoding the imported text was decoded with.
+    pub encoding: String,
+    /// What detection picks for the original bytes.
+    pub detected: String,
+    /// 0–1; 1 for a byte order mark or valid UTF-8.
+    pub confidence: f32,
+    pub bom: bool,
+    /// Whether the untouched original is kept next to the converted file.
+    pub has_original: bool,
+    /// Encodings the user can re-import with.
+    pub choices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Detection {
+    pub encoding: &'static Encoding,
+    pub confidence: f32,
+    pub bom: bool,
+}
+
+fn choices() -> [&'static Encoding; 9] {
+    [
+        encoding_rs::UTF_8,
+        encoding_rs::UTF_16LE,
+        encoding_rs::UTF_16BE,
+        encoding_rs::GBK,
+        encoding_rs::BIG5,
+        encoding_rs::SHIFT_JIS,
+        encoding_rs::EUC_KR,
+        encoding_rs::WINDOWS_1251,
+        encoding_rs::WINDOWS_1252,
+    ]
+}
+
+fn char_set(chars: &'static str, cell: &'static OnceLock<HashSet<char>>) -> &'static HashSet<char> {
+    cell.get_or_init(|| chars.chars().collect())
+}
+
+fn is_latin_letter(c: char) -> bool {
+    matches!(c, '\u{c0}'..='\u{24f}') && c != '\u{d7}' && c != '\u{f7}'
+}
+
+fn is_cyrillic(c: char) -> bool {
+    matches!(c, '\u{400}'..='\u{4ff}')
+}
+
+/// Average per-character plausibility of decoded text. ASCII is shared by every candidate and scores nothing;
+/// letters that mix scripts inside a word, stray symbols and control characters count against.
+fn plausibility(text: &str) -> f32 {
+    static HANZI: OnceLock<HashSet<char>> = OnceLock::new();
+    static HANGUL: OnceLock<HashSet<char>> = OnceLock::new();
+    static CYRILLIC: OnceLock<HashSet<char>> = OnceLock::new();
+    let hanzi = char_set(COMMON_HANZI, &HANZI);
+    let hangul = char_set(COMMON_HANGUL, &HANGUL);
+    let cyrillic = char_set(COMMON_CYRILLIC, &CYRILLIC);
+
+    let mut score = 0.0f32;
+    let mut count = 0usize;
+    let mut prev = ' ';
+    for c in text.chars() {
+        if c.is_ascii() {
+            if c.is_ascii_control() && !matches!(c, '\n' | '\r' | '\t' | '\u{c}') {
+                score -= 3.0;
+                count += 1;
+            }
+            prev = c;
+            continue;
+        }
+        count += 1;
+        score += match c {
+            '\u{fffd}' => -5.0,
+            c if c.is_control() => -3.0,
+            '\u{4e00}'..='\u{9fff}' => hanzi.contains(&c) as u8 as f32,
+            '\u{3040}'..='\u{30ff}' => 1.0,
+            '\u{ac00}'..='\u{d7a3}' => hangul.contains(&c) as u8 as f32,
+            '\u{3000}'..='\u{303f}' | '\u{ff00}'..='\u{ffef}' | '\u{2010}'..='\u{2027}' => 0.5,
+            c if is_cyrillic(c) => {
+                if prev.is_ascii_alphabetic() || (c.is_uppercase() && is_cyrillic(prev) && prev.is_lowercase()) {
+                    -0.5
+                } else if cyrillic.contains(&c) {
+                    1.0
+                } else {
+                    0.3
+                }
+            }
+            c if is_latin_letter(c) => {
+                if is_latin_letter(prev) || is_cyrillic(prev) {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            _ => -0.5,
+        };
+        prev = c;
+    }
+    if count == 0 {
+        0.0
+    } else {
+        score / count as f32
+    }
+}
+
+/// UTF-16 without a BOM shows up as NUL bytes on every other position.
+fn sniff_utf16(sample: &[u8]) -> Option<&'static Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs < 4 {
+        return None;
+    }
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+    if odd * 10 > pairs * 3 && even * 20 < pairs {
+        Some(encoding_rs::UTF_16LE)
+    } else if even * 10 > pairs * 3 && odd * 20 < pairs {
+        Some(encoding_rs::UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Byte order mark first, then UTF-8 validity, then the legacy codec whose decoding reads most plausibly.
+pub(crate) fn detect_encoding(bytes: &[u8]) -> Detection {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return Detection {
+            encoding,
+            confidence: 1.0,
+            bom: true,
+        };
+    }
+    let sample = &bytes[..bytes.len().min(SAMPLE_SIZE)];
+    if let Some(encoding) = sniff_utf16(sample) {
+        return Detection {
+            encoding,
+            confidence: 0.9,
+            bom: false,
+        };
+    }
+    let valid_utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        // A sample cut inside a multi-byte character.
+        Err(e) => e.error_len().is_none() && sample.len() < bytes.len(),
+    };
+    if valid_utf8 {
+        return Detection {
+            encoding: encoding_rs::UTF_8,
+            confidence: 1.0,
+            bom: false,
+        };
+    }
+
+    let (encoding, score) = choices()
+        .into_iter()
+        .filter(|e| *e != encoding_rs::UTF_8 && *e != encoding_rs::UTF_16LE && *e != encoding_rs::UTF_16BE)
+        .map(|e| (e, plausibility(&e.decode_without_bom_handling(sample).0)))
+        .fold((encoding_rs::WINDOWS_1252, f32::MIN), |best, cur| {
+            if cur.1 > best.1 {
+                cur
+            } else {
+                best
+            }
+        });
+    Detection {
+        encoding,
+        confidence: score.clamp(0.0, 1.0),
+        bom: false,
+    }
+}
+
+/// LF line endings, no BOM and no control characters other than tabs and newlines.
+pub(crate) fn normalize_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                out.push('\n');
+            }
+            '\n' | '\u{c}' | '\u{85}' | '\u{2028}' | '\u{2029}' => out.push('\n'),
+            '\t' => out.push('\t'),
+            '\u{feff}' => {}
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn transcode(bytes: &[u8], encoding: &'static Encoding) -> String {
+    normalize_text(&encoding.decode(bytes).0)
+}
+
+/// Decodes text in memory with the detected encoding; used for files that are read but never rewritten.
+pub(crate) fn decode_text(bytes: &[u8]) -> String {
+    transcode(bytes, detect_encoding(bytes).encoding)
+}
+
+/// Canonical `path` when it lies inside the documents folder. Files opened in place are never rewritten.
+fn managed_copy(documents_dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let root = std::fs::canonicalize(documents_dir).map_err(|e| e.to_string())?;
+    let target = std::fs::canonicalize(path).map_err(|e| e.to_string())?;
+    if target != root && target.starts_with(&root) {
+        Ok(target)
+    } else {
+        Err("not a managed document copy".to_string())
+    }
+}
+
+/// Where the untouched bytes of an imported text file are kept.
+pub(crate) fn original_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".orig");
+    path.with_file_name(name)
+}
+
+/// Writes `src` to `dest` as normalized UTF-8, keeping the original next to it when the bytes change.
+pub(crate) fn import_text(src: &Path, dest: &Path) -> Result<(), String> {
+    let bytes = std::fs::read(src).map_err(|e| e.to_string())?;
+    let text = decode_text(&bytes);
+    if text.as_bytes() != bytes.as_slice() {
+        std::fs::write(original_path(dest), &bytes).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(dest, text).map_err(|e| e.to_string())
+}
+
+fn report(path: &Path) -> Result<TextEncodingReport, String> {
+    let original = original_path(path);
+    let has_original = original.is_file();
+    let current = std::fs::read(path).map_err(|e| e.to_string())?;
+    let bytes = if has_original {
+        std::fs::read(&original).map_err(|e| e.to_string())?
+    } else {
+        current.clone()
+    };
+    let detection = detect_encoding(&bytes);
+    // The current text is a transcoding of the original; find the encoding that produced it.
+    let applied = if has_original {
+        std::iter::once(detection.encoding)
+            .chain(choices())
+            .find(|e| transcode(&bytes, e).as_bytes() == current.as_slice())
+    } else {
+        Some(detection.encoding)
+    };
+    Ok(TextEncodingReport {
+        encoding: applied.unwrap_or(detection.encoding).name().to_string(),
+        detected: detection.encoding.name().to_string(),
+        confidence: detection.confidence,
+        bom: detection.bom,
+        has_original,
+        choices: choices().iter().map(|e| e.name().to_string()).collect(),
+    })
+}
+
+fn reimport(path: &Path, encoding: &str) -> Result<TextEncodingReport, String> {
+    let encoding = Encoding::for_label(encoding.as_bytes()).ok_or_else(|| format!("unknown encoding: {}", encoding))?;
+    let original = original_path(path);
+    if !original.is_file() {
+        std::fs::copy(path, &original).map_err(|e| e.to_string())?;
+    }
+    let bytes = std::fs::read(&original).map_err(|e| e.to_string())?;
+    std::fs::write(path, transcode(&bytes, encoding)).map_err(|e| e.to_string())?;
+    report(path)
+}
+
+/// Text of a TXT/MD file decoded with the detected encoding, without touching the file.
+#[tauri::command]
+pub async fn text_read(path: String) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || {
+        let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+        Ok(decode_text(&bytes))
+    })
+    .await
+    .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Detected and applied encoding of an imported text file.
+#[tauri::command]
+pub async fn text_encoding_report(state: State<'_, AppState>, path: String) -> Result<TextEncodingReport, String> {
+    let documents_dir = state.documents_dir.read().unwrap().clone();
+    tokio::task::spawn_blocking(move || report(&managed_copy(&documents_dir, &path)?))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))?
+}
+
+/// Re-converts an imported text file from its original bytes with the encoding the user picked.
+#[tauri::command]
+pub async fn text_reimport(
+    state: State<'_, AppState>,
+    path: String,
+    encoding: String,
+    document_id: Option<String>,
+) -> Result<TextEncodingReport, String> {
+    let documents_dir = state.documents_dir.read().unwrap().clone();
+    let report = tokio::task::spawn_blocking(move || reimport(&managed_copy(&documents_dir, &path)?, &encoding))
+        .await
+        .map_err(|e| format!("spawn_blocking failed: {}", e))??;
+    // Sentences indexed from the old decoding are stale; the reader indexes the file again.
+    if let Some(id) = document_id {
+        state.db.delete_concordance_document(&id).map_err(|e| e.to_string())?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_common_encodings() {
+        let chinese = "第一章 我们的国家\r\n他说：“这是一个很好的问题。”\r\n";
+        let cases: [(&str, &'static Encoding); 6] = [
+            (chinese, encoding_rs::GBK),
+            ("這是我們的國家，他說時間還有很多。", encoding_rs::BIG5),
+            (
+                "Le café était déjà fermé à côté de l'église.",
+                encoding_rs::WINDOWS_1252,
+            ),
+            ("Привет, как дела? Это проверка кодировки.", encoding_rs::WINDOWS_1251),
+            (
+                "これは日本語のテキストです。ひらがなとカタカナ。",
+                encoding_rs::SHIFT_JIS,
+            ),
+            ("한국어 텍스트입니다. 이것은 테스트입니다.", encoding_rs::EUC_KR),
+        ];
+        for (text, encoding) in cases {
+            let (bytes, _, _) = encoding.encode(text);
+            assert_eq!(detect_encoding(&bytes).encoding, encoding, "{}", text);
+        }
+
+        let utf16: Vec<u8> = "plain text".encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+        assert_eq!(detect_encoding(&utf16).encoding, encoding_rs::UTF_16LE);
+        let mut bom = vec![0xFE, 0xFF];
+        bom.extend("中文".encode_utf16().flat_map(|u| u.to_be_bytes()));
+        let detection = detect_encoding(&bom);
+        assert!(detection.bom);
+        assert_eq!(detection.encoding, encoding_rs::UTF_16BE);
+        assert_eq!(detect_encoding("naïve".as_bytes()).encoding, encoding_rs::UTF_8);
+    }
+
+    #[test]
+    fn imports_transcoded_text_and_reimports_with_override() {
+        let dir = std::env::temp_dir().join(format!("aireader-text-{}", uuid::Uuid::new_v4()));
+        let library = dir.join("library");
+        std::fs::create_dir_all(&library).unwrap();
+        let src = dir.join("novel-src.txt");
+        let dest = library.join("novel.txt");
+        let (gbk, _, _) = encoding_rs::GBK.encode("第一章\r\n我们的\u{7}国家\r\n");
+        std::fs::write(&src, &gbk).unwrap();
+
+        import_text(&src, &dest).unwrap();
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "第一章\n我们的国家\n");
+        assert_eq!(std::fs::read(original_path(&dest)).unwrap(), gbk.to_vec());
+        let first = report(&dest).unwrap();
+        assert_eq!(first.encoding, "GBK");
+        assert!(first.has_original);
+
+        // Only copies inside the documents folder may be rewritten.
+        assert!(managed_copy(&library, &src.to_string_lossy()).is_err());
+        let managed = managed_copy(&library, &dest.to_string_lossy()).unwrap();
+        let second = reimport(&managed, "windows-1252").unwrap();
+        assert_eq!(second.encoding, "windows-1252");
+        assert_eq!(second.detected, "GBK");
+        assert_ne!(std::fs::read_to_string(&dest).unwrap(), "第一章\n我们的国家\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
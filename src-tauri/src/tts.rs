@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tauri::{AppHandle, State};
+use tauri_plugin_dialog::DialogExt;
+
+use crate::concordance::split_sentences;
+use crate::AppState;
+
+const CONFIG_FILE: &str = "tts.json";
+const CACHE_DIR: &str = "tts_cache";
+/// Longest text read in one call, to keep synthesis time bounded.
+const MAX_TEXT_CHARS: usize = 20_000;
+
+/// A local speech synthesizer. Engines write a WAV file; caching and sentence timing are
+/// handled on top of this by [`speak`].
+pub trait TtsEngine {
+    /// Identifies the engine and its settings, so cached audio from another engine or
+    /// configuration is not reused.
+    fn cache_id(&self) -> String;
+
+    fn synthesize(&self, text: &str, voice: Option<&str>, output: &Path) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsConfig {
+    /// Local TTS executable, e.g. `piper` or `espeak-ng`.
+    pub executable: Option<String>,
+    /// Arguments with `{text}`, `{voice}` and `{output}` placeholders. When no argument
+    /// contains `{text}`, the text is written to stdin. Empty means the preset for known
+    /// executables.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Default voice: a model path for piper, a voice name for espeak-ng.
+    pub voice: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsSentenceTiming {
+    pub text: String,
+    /// Char offsets into the requested text.
+    pub start: usize,
+    pub end: usize,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsResult {
+    /// WAV file to play.
+    pub path: String,
+    pub duration_ms: u64,
+    pub sentences: Vec<TtsSentenceTiming>,
+}
+
+/// Runs a configured command-line synthesizer once per utterance.
+pub struct CommandLineEngine {
+    executable: String,
+    args: Vec<String>,
+}
+
+impl CommandLineEngine {
+    pub fn from_config(config: &TtsConfig) -> Result<Self, String> {
+        let executable = config
+            .executable
+            .clone()
+            .filter(|e| !e.trim().is_empty())
+            .ok_or_else(|| "no TTS executable configured".to_string())?;
+        let args = if config.args.is_empty() {
+            preset_args(&executable).ok_or_else(|| "TTS arguments are required for this executable".to_string())?
+        } else {
+            config.args.clone()
+        };
+        Ok(Self { executable, args })
+    }
+}
+
+/// Default arguments for well-known synthesizers.
+fn preset_args(executable: &str) -> Option<Vec<String>> {
+    let stem = Path::new(executable)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let args: &[&str] = match stem.as_str() {
+        "piper" => &["--model", "{voice}", "--output_file", "{output}"],
+        "espeak-ng" | "espeak" => &["-v", "{voice}", "-w", "{output}", "--", "{text}"],
+        _ => return None,
+    };
+    Some(args.iter().map(|s| s.to_string()).collect())
+}
+
+/// Substitutes placeholders. A `{voice}` argument is dropped together with the flag before
+/// it when no voice is set, so the engine falls back to its own default.
+fn expand_args(args: &[String], text: &str, voice: Option<&str>, output: &Path) -> (Vec<String>, bool) {
+    let mut out: Vec<String> = vec![];
+    let mut text_in_args = false;
+    for arg in args {
+        if arg.contains("{voice}") && voice.is_none() {
+            if out.last().is_some_and(|prev| prev.starts_with('-')) {
+                out.pop();
+            }
+            continue;
+        }
+        text_in_args |= arg.contains("{text}");
+        out.push(
+            arg.replace("{voice}", voice.unwrap_or(""))
+                .replace("{output}", &output.to_string_lossy())
+                .replace("{text}", text),
+        );
+    }
+    (out, text_in_args)
+}
+
+impl TtsEngine for CommandLineEngine {
+    fn cache_id(&self) -> String {
+        format!("{} {}", self.executable, self.args.join(" "))
+    }
+
+    fn synthesize(&self, text: &str, voice: Option<&str>, output: &Path) -> Result<(), String> {
+        let (args, text_in_args) = expand_args(&self.args, text, voice, output);
+        let mut cmd = Command::new(&self.executable);
+        cmd.args(&args)
+            .stdin(if text_in_args { Stdio::null() } else { Stdio::piped() })
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        #[cfg(target_os = "windows")]
+        {
+            use std::os::windows::process::CommandExt;
+            cmd.creation_flags(0x08000000);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {}", self.executable, e))?;
+        if !text_in_args {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+            }
+        }
+        let out = child.wait_with_output().map_err(|e| e.to_string())?;
+        if !out.status.success() {
+            return Err(format!(
+                "{} failed: {}",
+                self.executable,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ));
+        }
+        if !output.is_file() {
+            return Err(format!("{} produced no audio", self.executable));
+        }
+        Ok(())
+    }
+}
+
+/// The parts of a PCM WAV file needed to time and join clips.
+struct Wav {
+    fmt: Vec<u8>,
+    byte_rate: u32,
+    data: Vec<u8>,
+}
+
+impl Wav {
+    fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err("not a WAV file".to_string());
+        }
+        let mut fmt: Option<Vec<u8>> = None;
+        let mut pos = 12;
+        while pos + 8 <= bytes.len() {
+            let id = &bytes[pos..pos + 4];
+            let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            let body = pos + 8;
+            // Streaming writers leave a placeholder size on the data chunk.
+            let end = (body + size).min(bytes.len());
+            match id {
+                b"fmt " => fmt = Some(bytes[body..end].to_vec()),
+                b"data" => {
+                    let fmt = fmt.ok_or_else(|| "WAV data before fmt chunk".to_string())?;
+                    if fmt.len() < 16 {
+                        return Err("invalid WAV fmt chunk".to_string());
+                    }
+                    let byte_rate = u32::from_le_bytes(fmt[8..12].try_into().unwrap());
+                    return Ok(Self {
+                        fmt,
+                        byte_rate,
+                        data: bytes[body..end].to_vec(),
+                    });
+                }
+                _ => {}
+            }
+            pos = end + (size & 1);
+        }
+        Err("WAV file has no data".to_string())
+    }
+
+    fn duration_ms(&self) -> u64 {
+        if self.byte_rate == 0 {
+            return 0;
+        }
+        self.data.len() as u64 * 1000 / self.byte_rate as u64
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(28 + self.fmt.len() + self.data.len());
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&((20 + self.fmt.len() + self.data.len()) as u32).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&(self.fmt.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.fmt);
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+}
+
+/// Stable across builds and Rust versions, unlike `DefaultHasher`, so the cache outlives updates.
+fn cache_key(engine: &dyn TtsEngine, voice: Option<&str>, text: &str) -> String {
+    let mut h = Sha1::new();
+    h.update([voice.is_some() as u8]);
+    for part in [engine.cache_id().as_str(), voice.unwrap_or(""), text] {
+        h.update((part.len() as u64).to_le_bytes());
+        h.update(part.as_bytes());
+    }
+    h.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A temporary file next to `path`, unique per call so concurrent writers of the same
+/// cache entry do not clobber each other before the rename.
+fn temp_path(path: &Path) -> PathBuf {
+    path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4().simple()))
+}
+
+/// Synthesizes `text` into the cache unless it is already there.
+fn synthesize_cached(engine: &dyn TtsEngine, cache_dir: &Path, text: &str, voice: Option<&str>) -> Result<PathBuf, String> {
+    let path = cache_dir.join(format!("{}.wav", cache_key(engine, voice, text)));
+    if path.is_file() {
+        return Ok(path);
+    }
+    std::fs::create_dir_all(cache_dir).map_err(|e| e.to_string())?;
+    let tmp = temp_path(&path);
+    let result = engine.synthesize(text, voice, &tmp);
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    std::fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Sentences of `text` as (start, end, sentence), with char offsets into `text`.
+fn sentence_spans(text: &str) -> Vec<(usize, usize, String)> {
+    let chars: Vec<char> = text.chars().collect();
+    let sentences = split_sentences(text);
+    let mut out = vec![];
+    for (i, (start, sentence)) in sentences.iter().enumerate() {
+        let mut end = sentences.get(i + 1).map(|(s, _)| *s).unwrap_or(chars.len());
+        while end > *start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        out.push((*start, end, sentence.clone()));
+    }
+    out
+}
+
+/// Reads `text` sentence by sentence and joins the clips into one WAV, so each sentence
+/// has its own timing and stays cached when the surrounding text changes.
+fn speak(engine: &dyn TtsEngine, cache_dir: &Path, text: &str, voice: Option<&str>) -> Result<TtsResult, String> {
+    let spans = sentence_spans(text);
+    if spans.is_empty() {
+        return Err("nothing to read".to_string());
+    }
+    if spans.len() == 1 {
+        let path = synthesize_cached(engine, cache_dir, &spans[0].2, voice)?;
+        let wav = Wav::parse(&std::fs::read(&path).map_err(|e| e.to_string())?)?;
+        let (start, end, sentence) = spans.into_iter().next().unwrap();
+        return Ok(TtsResult {
+            path: path.to_string_lossy().to_string(),
+            duration_ms: wav.duration_ms(),
+            sentences: vec![TtsSentenceTiming {
+                text: sentence,
+                start,
+                end,
+                start_ms: 0,
+                end_ms: wav.duration_ms(),
+            }],
+        });
+    }
+
+    let mut joined: Option<Wav> = None;
+    let mut timings = vec![];
+    for (start, end, sentence) in spans {
+        let clip = synthesize_cached(engine, cache_dir, &sentence, voice)?;
+        let wav = Wav::parse(&std::fs::read(&clip).map_err(|e| e.to_string())?)?;
+        let start_ms = joined.as_ref().map(|j| j.duration_ms()).unwrap_or(0);
+        match joined.as_mut() {
+            None => joined = Some(wav),
+            Some(j) if j.fmt == wav.fmt => j.data.extend_from_slice(&wav.data),
+            Some(_) => return Err("TTS engine produced clips in different audio formats".to_string()),
+        }
+        timings.push(TtsSentenceTiming {
+            text: sentence,
+            start,
+            end,
+            start_ms,
+            end_ms: joined.as_ref().map(|j| j.duration_ms()).unwrap_or(0),
+        });
+    }
+
+    let joined = joined.ok_or_else(|| "nothing to read".to_string())?;
+    let path = cache_dir.join(format!("{}.wav", cache_key(engine, voice, text)));
+    let tmp = temp_path(&path);
+    if let Err(e) = std::fs::write(&tmp, joined.to_bytes()) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.to_string());
+    }
+    std::fs::rename(&tmp, &path).map_err(|e| e.to_string())?;
+    Ok(TtsResult {
+        path: path.to_string_lossy().to_string(),
+        duration_ms: joined.duration_ms(),
+        sentences: timings,
+    })
+}
+
+fn load_config(app_data_dir: &Path) -> TtsConfig {
+    std::fs::read_to_string(app_data_dir.join(CONFIG_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+async fn speak_with_config(state: &AppState, text: String, voice: Option<String>) -> Result<TtsResult, String> {
+    let config = load_config(&state.app_data_dir);
+    let engine = CommandLineEngine::from_config(&config)?;
+    let voice = voice.or(config.voice).filter(|v| !v.trim().is_empty());
+    let cache_dir = state.app_data_dir.join(CACHE_DIR);
+    tauri::async_runtime::spawn_blocking(move || speak(&engine, &cache_dir, &text, voice.as_deref()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn tts_get_config(state: State<AppState>) -> Result<TtsConfig, String> {
+    Ok(load_config(&state.app_data_dir))
+}
+
+fn save_config(app_data_dir: &Path, config: &TtsConfig) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(config).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(app_data_dir).map_err(|e| e.to_string())?;
+    std::fs::write(app_data_dir.join(CONFIG_FILE), json).map_err(|e| e.to_string())
+}
+
+/// The configured executable is run with user text, so it must name an existing file by
+/// absolute path rather than whatever a bare name resolves to.
+fn validate_executable(executable: &str) -> Result<(), String> {
+    let path = Path::new(executable);
+    if !path.is_absolute() {
+        return Err("TTS executable must be an absolute path".to_string());
+    }
+    if !path.is_file() {
+        return Err(format!("TTS executable not found: {}", executable));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn tts_save_config(state: State<AppState>, config: TtsConfig) -> Result<(), String> {
+    if let Some(executable) = config.executable.as_deref().filter(|e| !e.trim().is_empty()) {
+        validate_executable(executable)?;
+    }
+    save_config(&state.app_data_dir, &config)
+}
+
+/// Lets the user choose the TTS executable in a native file dialog and saves it. Returns the
+/// updated config, or `None` when the dialog was cancelled.
+#[tauri::command]
+pub async fn tts_pick_executable(app: AppHandle, state: State<'_, AppState>) -> Result<Option<TtsConfig>, String> {
+    let picked = tauri::async_runtime::spawn_blocking(move || app.dialog().file().blocking_pick_file())
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(picked) = picked else {
+        return Ok(None);
+    };
+    let executable = picked.into_path().map_err(|e| e.to_string())?;
+    let executable = executable.to_string_lossy().to_string();
+    validate_executable(&executable)?;
+    let mut config = load_config(&state.app_data_dir);
+    config.executable = Some(executable);
+    save_config(&state.app_data_dir, &config)?;
+    Ok(Some(config))
+}
+
+/// Pronounces a single word or phrase.
+#[tauri::command]
+pub async fn tts_pronounce(state: State<'_, AppState>, word: String, voice: Option<String>) -> Result<TtsResult, String> {
+    let word = word.split_whitespace().collect::<Vec<_>>().join(" ");
+    if word.is_empty() {
+        return Err("nothing to read".to_string());
+    }
+    speak_with_config(&state, word, voice).await
+}
+
+/// Reads a passage aloud, returning per-sentence timings for highlighting.
+#[tauri::command]
+pub async fn tts_read(state: State<'_, AppState>, text: String, voice: Option<String>) -> Result<TtsResult, String> {
+    if text.chars().count() > MAX_TEXT_CHARS {
+        return Err(format!("text is too long to read at once (max {} characters)", MAX_TEXT_CHARS));
+    }
+    speak_with_config(&state, text, voice).await
+}
+
+#[tauri::command]
+pub fn tts_clear_cache(state: State<AppState>) -> Result<(), String> {
+    let dir = state.app_data_dir.join(CACHE_DIR);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Writes 8 kHz mono 8-bit silence, 1 ms per character.
+    struct FakeEngine {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl TtsEngine for FakeEngine {
+        fn cache_id(&self) -> String {
+            "fake".to_string()
+        }
+
+        fn synthesize(&self, text: &str, _voice: Option<&str>, output: &Path) -> Result<(), String> {
+            self.calls.borrow_mut().push(text.to_string());
+            let mut fmt = vec![];
+            fmt.extend_from_slice(&1u16.to_le_bytes()); // PCM
+            fmt.extend_from_slice(&1u16.to_le_bytes()); // mono
+            fmt.extend_from_slice(&8000u32.to_le_bytes());
+            fmt.extend_from_slice(&8000u32.to_le_bytes()); // byte rate
+            fmt.extend_from_slice(&1u16.to_le_bytes());
+            fmt.extend_from_slice(&8u16.to_le_bytes());
+            let wav = Wav {
+                fmt,
+                byte_rate: 8000,
+                data: vec![128; text.chars().count() * 8],
+            };
+            std::fs::write(output, wav.to_bytes()).map_err(|e| e.to_string())
+        }
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("aireader_tts_{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_expand_args() {
+        let args: Vec<String> = preset_args("/usr/bin/espeak-ng").unwrap();
+        let (out, text_in_args) = expand_args(&args, "-hi", None, Path::new("/tmp/a.wav"));
+        assert_eq!(out, vec!["-w", "/tmp/a.wav", "--", "-hi"]);
+        assert!(text_in_args);
+
+        let args = preset_args("/opt/piper/piper").unwrap();
+        let (out, text_in_args) = expand_args(&args, "hi", Some("en.onnx"), Path::new("o.wav"));
+        assert_eq!(out, vec!["--model", "en.onnx", "--output_file", "o.wav"]);
+        assert!(!text_in_args);
+        assert!(preset_args("say").is_none());
+    }
+
+    #[test]
+    fn test_read_times_sentences_and_caches_clips() {
+        let dir = temp_dir();
+        let engine = FakeEngine { calls: RefCell::new(vec![]) };
+        let text = "Hello there.  How are you?";
+        let r = speak(&engine, &dir, text, None).unwrap();
+        assert_eq!(r.duration_ms, 24);
+        assert_eq!(r.sentences.len(), 2);
+        assert_eq!((r.sentences[0].start, r.sentences[0].end), (0, 12));
+        assert_eq!((r.sentences[0].start_ms, r.sentences[0].end_ms), (0, 12));
+        assert_eq!((r.sentences[1].start, r.sentences[1].end), (14, 26));
+        assert_eq!((r.sentences[1].start_ms, r.sentences[1].end_ms), (12, 24));
+        let joined = Wav::parse(&std::fs::read(&r.path).unwrap()).unwrap();
+        assert_eq!(joined.duration_ms(), 24);
+
+        // Clips are cached per sentence.
+        speak(&engine, &dir, "How are you? Fine.", None).unwrap();
+        assert_eq!(*engine.calls.borrow(), vec!["Hello there.", "How are you?", "Fine."]);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_validate_executable_requires_an_existing_absolute_path() {
+        assert!(validate_executable("piper").is_err());
+        let missing = temp_dir().join("no-such-tts");
+        assert!(validate_executable(&missing.to_string_lossy()).is_err());
+        let exe = std::env::current_exe().unwrap();
+        assert!(validate_executable(&exe.to_string_lossy()).is_ok());
+    }
+
+    #[test]
+    fn test_wav_parse_tolerates_placeholder_data_size() {
+        let dir = temp_dir();
+        std::fs::create_dir_all(&dir).unwrap();
+        let engine = FakeEngine { calls: RefCell::new(vec![]) };
+        let path = dir.join("a.wav");
+        engine.synthesize("abcd", None, &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let size_at = bytes.windows(4).position(|w| w == b"data").unwrap() + 4;
+        bytes[size_at..size_at + 4].copy_from_slice(&0x7fff_f000u32.to_le_bytes());
+        assert_eq!(Wav::parse(&bytes).unwrap().duration_ms(), 4);
+        assert!(Wav::parse(b"nope").is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,561 @@
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::dictionary::{write_meta, DictionaryMeaning, DictionaryResult};
+use crate::dictionary_registry::{
+    new_install_dir, user_registry_entry, DictionaryDirection, DictionaryFormat, DictionaryInstallOptions,
+    RegistryEntry,
+};
+use crate::AppState;
+
+/// Table that marks a SQLite dictionary as a Wiktionary import.
+pub(crate) const WIKTIONARY_TABLE: &str = "wiktionary_entries";
+const DB_FILE: &str = "wiktionary.sqlite";
+/// Lines between progress events.
+const PROGRESS_EVERY: u64 = 20_000;
+const MAX_EXAMPLES_PER_SENSE: usize = 3;
+const MAX_FORMS_SHOWN: usize = 40;
+/// Form tags that mark inflection-table metadata rather than real word forms.
+const SKIPPED_FORM_TAGS: &[&str] = &["table-tags", "inflection-template", "class", "romanization"];
+
+// --- kaikki.org JSONL input. Only the fields we keep are declared; serde skips the rest. ---
+
+#[derive(Deserialize)]
+struct RawEntry {
+    word: String,
+    #[serde(default)]
+    lang_code: Option<String>,
+    #[serde(default)]
+    pos: Option<String>,
+    #[serde(default)]
+    senses: Vec<RawSense>,
+    #[serde(default)]
+    sounds: Vec<RawSound>,
+    #[serde(default)]
+    etymology_text: Option<String>,
+    #[serde(default)]
+    forms: Vec<Form>,
+}
+
+#[derive(Deserialize)]
+struct RawSense {
+    #[serde(default)]
+    glosses: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    examples: Vec<RawExample>,
+}
+
+#[derive(Deserialize)]
+struct RawExample {
+    #[serde(default)]
+    text: Option<String>,
+    /// Translation of non-English examples.
+    #[serde(default)]
+    english: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawSound {
+    #[serde(default)]
+    ipa: Option<String>,
+}
+
+// --- Stored form, one row per (word, part of speech) entry. ---
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Sense {
+    /// Most general first, as in kaikki (`["To move.", "To travel somewhere."]`).
+    glosses: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    examples: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Form {
+    #[serde(default)]
+    form: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WiktionaryImportProgress {
+    pub lines: u64,
+    pub entries: u64,
+    pub bytes_read: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WiktionaryImport {
+    pub dictionary: RegistryEntry,
+    pub entries: u64,
+    pub forms: u64,
+    /// Lines that were not valid JSON entries.
+    pub skipped: u64,
+}
+
+fn convert_senses(raw: Vec<RawSense>) -> Vec<Sense> {
+    raw.into_iter()
+        .filter_map(|s| {
+            let glosses: Vec<String> = s
+                .glosses
+                .into_iter()
+                .map(|g| g.trim().to_string())
+                .filter(|g| !g.is_empty())
+                .collect();
+            if glosses.is_empty() {
+                return None;
+            }
+            let examples = s
+                .examples
+                .into_iter()
+                .filter_map(|e| {
+                    let text = e.text.map(|t| t.trim().to_string()).filter(|t| !t.is_empty())?;
+                    Some(match e.english.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()) {
+                        Some(english) => format!("{} — {}", text, english),
+                        None => text,
+                    })
+                })
+                .take(MAX_EXAMPLES_PER_SENSE)
+                .collect();
+            Some(Sense {
+                glosses,
+                tags: s.tags,
+                examples,
+            })
+        })
+        .collect()
+}
+
+fn convert_forms(word: &str, raw: Vec<Form>) -> Vec<Form> {
+    let mut out: Vec<Form> = vec![];
+    for f in raw {
+        let form = f.form.trim();
+        if form.is_empty() || form == word || form.starts_with('-') {
+            continue;
+        }
+        if f.tags.iter().any(|t| SKIPPED_FORM_TAGS.contains(&t.as_str())) {
+            continue;
+        }
+        if !out.iter().any(|o| o.form == form && o.tags == f.tags) {
+            out.push(Form {
+                form: form.to_string(),
+                tags: f.tags,
+            });
+        }
+    }
+    out
+}
+
+/// Streams a kaikki.org JSONL extract into a SQLite dictionary at `db_path`, one line at a
+/// time so multi-gigabyte files never have to fit in memory. `lang_code` keeps only entries
+/// of one language when the extract covers several; entries without a language are dropped then.
+fn build_wiktionary_sqlite(
+    jsonl: &Path,
+    db_path: &Path,
+    lang_code: Option<&str>,
+    mut on_progress: impl FnMut(&WiktionaryImportProgress),
+) -> Result<(u64, u64, u64), String> {
+    let file = std::fs::File::open(jsonl).map_err(|e| e.to_string())?;
+    let mut progress = WiktionaryImportProgress {
+        total_bytes: file.metadata().map(|m| m.len()).unwrap_or(0),
+        ..Default::default()
+    };
+    let mut reader = BufReader::with_capacity(1 << 20, file);
+
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    conn.execute_batch(&format!(
+        "PRAGMA journal_mode=OFF;
+         PRAGMA synchronous=OFF;
+         PRAGMA temp_store=MEMORY;
+         CREATE TABLE {} (
+            id INTEGER PRIMARY KEY,
+            word TEXT NOT NULL COLLATE NOCASE,
+            pos TEXT,
+            ipa TEXT,
+            etymology TEXT,
+            senses TEXT NOT NULL,
+            forms TEXT
+         );
+         CREATE TABLE forms (form TEXT NOT NULL COLLATE NOCASE, word TEXT NOT NULL);",
+        WIKTIONARY_TABLE
+    ))
+    .map_err(|e| e.to_string())?;
+
+    let (mut forms_count, mut skipped) = (0u64, 0u64);
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut ins_entry = tx
+            .prepare(&format!(
+                "INSERT INTO {}(word, pos, ipa, etymology, senses, forms) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                WIKTIONARY_TABLE
+            ))
+            .map_err(|e| e.to_string())?;
+        let mut ins_form = tx
+            .prepare("INSERT INTO forms(form, word) VALUES (?1, ?2)")
+            .map_err(|e| e.to_string())?;
+
+        let mut line: Vec<u8> = Vec::with_capacity(64 * 1024);
+        loop {
+            line.clear();
+            let n = reader.read_until(b'\n', &mut line).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            progress.lines += 1;
+            progress.bytes_read += n as u64;
+            if progress.lines.is_multiple_of(PROGRESS_EVERY) {
+                on_progress(&progress);
+            }
+            if line.iter().all(|b| b.is_ascii_whitespace()) {
+                continue;
+            }
+
+            let entry: RawEntry = match serde_json::from_slice(&line) {
+                Ok(e) => e,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            if lang_code.is_some() && entry.lang_code.as_deref() != lang_code {
+                continue;
+            }
+            let word = entry.word.trim().to_string();
+            let senses = convert_senses(entry.senses);
+            if word.is_empty() || senses.is_empty() {
+                continue;
+            }
+            let forms = convert_forms(&word, entry.forms);
+            let ipa = entry
+                .sounds
+                .into_iter()
+                .filter_map(|s| s.ipa)
+                .find(|s| !s.trim().is_empty());
+
+            ins_entry
+                .execute(params![
+                    word,
+                    entry.pos,
+                    ipa,
+                    entry.etymology_text.filter(|t| !t.trim().is_empty()),
+                    serde_json::to_string(&senses).map_err(|e| e.to_string())?,
+                    if forms.is_empty() { None } else { Some(serde_json::to_string(&forms).map_err(|e| e.to_string())?) },
+                ])
+                .map_err(|e| e.to_string())?;
+            for f in &forms {
+                ins_form.execute(params![f.form, word]).map_err(|e| e.to_string())?;
+                forms_count += 1;
+            }
+            progress.entries += 1;
+        }
+    }
+    write_meta(
+        &tx,
+        &[
+            ("source", jsonl.file_name().map(|n| n.to_string_lossy().to_string())),
+            ("lang_code", lang_code.map(|s| s.to_string())),
+        ],
+    )?;
+    tx.commit().map_err(|e| e.to_string())?;
+    conn.execute_batch(&format!(
+        "CREATE INDEX idx_wiktionary_word ON {}(word);
+         CREATE INDEX idx_forms_form ON forms(form);",
+        WIKTIONARY_TABLE
+    ))
+    .map_err(|e| e.to_string())?;
+    on_progress(&progress);
+
+    Ok((progress.entries, forms_count, skipped))
+}
+
+struct EntryRow {
+    word: String,
+    pos: Option<String>,
+    ipa: Option<String>,
+    etymology: Option<String>,
+    senses: Vec<Sense>,
+    forms: Vec<Form>,
+}
+
+fn query_entries(conn: &Connection, word: &str) -> Result<Vec<EntryRow>, String> {
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT word, pos, ipa, etymology, senses, forms FROM {} WHERE word = ?1 \
+             ORDER BY word = ?1 COLLATE BINARY DESC, id LIMIT 20",
+            WIKTIONARY_TABLE
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([word], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, Option<String>>(1)?,
+                r.get::<_, Option<String>>(2)?,
+                r.get::<_, Option<String>>(3)?,
+                r.get::<_, String>(4)?,
+                r.get::<_, Option<String>>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut out = vec![];
+    for row in rows {
+        let (word, pos, ipa, etymology, senses, forms) = row.map_err(|e| e.to_string())?;
+        out.push(EntryRow {
+            word,
+            pos,
+            ipa,
+            etymology,
+            senses: serde_json::from_str(&senses).unwrap_or_default(),
+            forms: forms.and_then(|f| serde_json::from_str(&f).ok()).unwrap_or_default(),
+        });
+    }
+    Ok(out)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn sense_text(sense: &Sense) -> String {
+    let gloss = sense.glosses.join(": ");
+    if sense.tags.is_empty() {
+        gloss
+    } else {
+        format!("({}) {}", sense.tags.join(", "), gloss)
+    }
+}
+
+/// Full entry as HTML: senses with examples, etymology and the inflection table, none of
+/// which fit the plain `meanings` list.
+fn render_html(rows: &[EntryRow]) -> String {
+    let mut html = String::from("<div class=\"wiktionary\">");
+    for row in rows {
+        html.push_str("<div class=\"entry\">");
+        if let Some(pos) = &row.pos {
+            html.push_str(&format!("<h4>{}</h4>", escape_html(pos)));
+        }
+        if let Some(ipa) = &row.ipa {
+            html.push_str(&format!("<div class=\"ipa\">{}</div>", escape_html(ipa)));
+        }
+        html.push_str("<ol>");
+        for sense in &row.senses {
+            html.push_str(&format!("<li>{}", escape_html(&sense_text(sense))));
+            if !sense.examples.is_empty() {
+                html.push_str("<ul class=\"examples\">");
+                for ex in &sense.examples {
+                    html.push_str(&format!("<li><i>{}</i></li>", escape_html(ex)));
+                }
+                html.push_str("</ul>");
+            }
+            html.push_str("</li>");
+        }
+        html.push_str("</ol>");
+        if let Some(ety) = &row.etymology {
+            html.push_str(&format!("<p class=\"etymology\"><b>Etymology</b> {}</p>", escape_html(ety)));
+        }
+        if !row.forms.is_empty() {
+            html.push_str("<table class=\"forms\">");
+            for f in row.forms.iter().take(MAX_FORMS_SHOWN) {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>",
+                    escape_html(&f.form),
+                    escape_html(&f.tags.join(" "))
+                ));
+            }
+            html.push_str("</table>");
+        }
+        html.push_str("</div>");
+    }
+    html.push_str("</div>");
+    html
+}
+
+/// Looks `word` up in a Wiktionary import, falling back to the entries `word` is an
+/// inflected form of (`went` → `go`).
+pub(crate) fn lookup_wiktionary_conn(conn: &Connection, word: &str) -> Result<Option<DictionaryResult>, String> {
+    let mut rows = query_entries(conn, word)?;
+    if rows.is_empty() {
+        let mut stmt = conn
+            .prepare_cached("SELECT DISTINCT word FROM forms WHERE form = ?1 LIMIT 3")
+            .map_err(|e| e.to_string())?;
+        let lemmas = stmt
+            .query_map([word], |r| r.get::<_, String>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        for lemma in lemmas {
+            rows.extend(query_entries(conn, &lemma)?);
+        }
+    }
+    let Some(first) = rows.first() else {
+        return Ok(None);
+    };
+
+    let meanings = rows
+        .iter()
+        .map(|row| DictionaryMeaning {
+            part_of_speech: row.pos.clone().unwrap_or_default(),
+            definitions: row.senses.iter().map(sense_text).collect(),
+            examples: row.senses.iter().flat_map(|s| s.examples.iter().cloned()).collect(),
+        })
+        .collect();
+    Ok(Some(DictionaryResult {
+        word: first.word.clone(),
+        phonetic: rows.iter().find_map(|r| r.ipa.clone()),
+        audio_url: None,
+        translation: first.senses.first().map(|s| s.glosses.join(": ")),
+        meanings,
+        html: Some(render_html(&rows)),
+        pinyin: None,
+    }))
+}
+
+/// Lookup direction of an extract. kaikki.org extracts come from the English Wiktionary, so
+/// glosses are English whatever language the headwords are in.
+fn direction_for(lang_code: Option<&str>) -> DictionaryDirection {
+    match lang_code {
+        Some("en") => DictionaryDirection::EnEn,
+        Some("zh") => DictionaryDirection::ZhEn,
+        _ => DictionaryDirection::Other,
+    }
+}
+
+/// Imports a locally downloaded kaikki.org Wiktionary JSONL extract as a user dictionary.
+/// Progress is emitted as `wiktionary-import-progress` events.
+#[tauri::command]
+pub async fn dictionary_import_wiktionary(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+    lang_code: Option<String>,
+    options: Option<DictionaryInstallOptions>,
+) -> Result<WiktionaryImport, String> {
+    let source = PathBuf::from(&path);
+    if !source.is_file() {
+        return Err("Wiktionary file not found".to_string());
+    }
+    let dict_dir = state.dictionaries_dir.read().unwrap().clone();
+    let dest = new_install_dir(&dict_dir)?;
+    let db_path = dest.join(DB_FILE);
+
+    let db_path2 = db_path.clone();
+    let lang = lang_code.clone();
+    let built = tauri::async_runtime::spawn_blocking(move || {
+        build_wiktionary_sqlite(&source, &db_path2, lang.as_deref(), |p| {
+            let _ = app.emit("wiktionary-import-progress", p);
+        })
+    })
+    .await
+    .map_err(|e| e.to_string())
+    .and_then(|r| r);
+    let (entries, forms, skipped) = match built {
+        Ok(counts) if counts.0 > 0 => counts,
+        Ok(_) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            return Err("no Wiktionary entries found in file".to_string());
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&dest);
+            return Err(e);
+        }
+    };
+
+    let options = options.unwrap_or_default();
+    let direction = direction_for(lang_code.as_deref());
+    let name = match (options.name.as_deref().map(str::trim), lang_code) {
+        (Some(n), _) if !n.is_empty() => n.to_string(),
+        (_, Some(l)) => format!("Wiktionary ({})", l),
+        _ => "Wiktionary".to_string(),
+    };
+    let entry = user_registry_entry(&dict_dir, DictionaryFormat::Sqlite, &db_path, name, &options, direction);
+    state.dictionary_registry.register(&dict_dir, vec![entry.clone()])?;
+    Ok(WiktionaryImport {
+        dictionary: entry,
+        entries,
+        forms,
+        skipped,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"{"word": "go", "lang": "English", "lang_code": "en", "pos": "verb", "sounds": [{"audio": "x.ogg"}, {"ipa": "/ɡəʊ/", "tags": ["Received-Pronunciation"]}], "etymology_text": "From Middle English gon, from Old English gān.", "forms": [{"form": "goes", "tags": ["present", "singular", "third-person"]}, {"form": "went", "tags": ["past"]}, {"form": "gone", "tags": ["participle", "past"]}, {"form": "en-conj", "tags": ["inflection-template"]}], "senses": [{"glosses": ["To move from one place to another."], "tags": ["intransitive"], "examples": [{"text": "We went to the shop."}]}, {"glosses": []}]}
+{"word": "go", "lang": "English", "lang_code": "en", "pos": "noun", "senses": [{"glosses": ["An attempt."], "examples": [{"text": "Have a go!"}]}], "forms": [{"form": "goes", "tags": ["plural"]}]}
+not json
+{"word": "gehen", "lang": "German", "lang_code": "de", "pos": "verb", "senses": [{"glosses": ["to go"]}]}
+{"word": "ciao", "pos": "intj", "senses": [{"glosses": ["hello"]}]}
+
+{"word": "went", "lang": "English", "lang_code": "en", "pos": "verb", "senses": [{"glosses": ["simple past of go"], "tags": ["form-of"]}]}
+"#;
+
+    fn build(lang: Option<&str>) -> (PathBuf, Connection, (u64, u64, u64)) {
+        let dir = std::env::temp_dir().join(format!("aireader_wikt_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("kaikki.jsonl");
+        std::fs::write(&src, SAMPLE).unwrap();
+        let db = dir.join(DB_FILE);
+        let mut events = 0;
+        let counts = build_wiktionary_sqlite(&src, &db, lang, |_| events += 1).unwrap();
+        assert!(events >= 1);
+        (dir, Connection::open(&db).unwrap(), counts)
+    }
+
+    #[test]
+    fn test_import_counts_and_language_filter() {
+        let (dir, _conn, counts) = build(Some("en"));
+        // Three English entries, four real forms, one malformed line.
+        assert_eq!(counts, (3, 4, 1));
+        let (dir2, _conn2, counts2) = build(None);
+        assert_eq!(counts2.0, 5);
+        let _ = std::fs::remove_dir_all(dir);
+        let _ = std::fs::remove_dir_all(dir2);
+    }
+
+    #[test]
+    fn test_direction_follows_language() {
+        assert_eq!(direction_for(Some("en")), DictionaryDirection::EnEn);
+        assert_eq!(direction_for(Some("zh")), DictionaryDirection::ZhEn);
+        assert_eq!(direction_for(Some("de")), DictionaryDirection::Other);
+        assert_eq!(direction_for(None), DictionaryDirection::Other);
+    }
+
+    #[test]
+    fn test_lookup_groups_pos_and_renders_extras() {
+        let (dir, conn, _) = build(Some("en"));
+        let r = lookup_wiktionary_conn(&conn, "Go").unwrap().unwrap();
+        assert_eq!(r.word, "go");
+        assert_eq!(r.phonetic.as_deref(), Some("/ɡəʊ/"));
+        assert_eq!(r.translation.as_deref(), Some("To move from one place to another."));
+        assert_eq!(r.meanings.len(), 2);
+        assert_eq!(r.meanings[0].part_of_speech, "verb");
+        assert_eq!(r.meanings[0].definitions, vec!["(intransitive) To move from one place to another."]);
+        assert_eq!(r.meanings[1].examples, vec!["Have a go!"]);
+        let html = r.html.unwrap();
+        assert!(html.contains("Old English gān"));
+        assert!(html.contains("<td>gone</td><td>participle past</td>"));
+        assert!(!html.contains("en-conj"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_lookup_follows_inflected_forms() {
+        let (dir, conn, _) = build(Some("en"));
+        // `went` has its own entry; `gone` is only known as a form of `go`.
+        assert_eq!(lookup_wiktionary_conn(&conn, "went").unwrap().unwrap().word, "went");
+        assert_eq!(lookup_wiktionary_conn(&conn, "gone").unwrap().unwrap().word, "go");
+        assert!(lookup_wiktionary_conn(&conn, "xyzzy").unwrap().is_none());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
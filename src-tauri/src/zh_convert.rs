@@ -0,0 +1,155 @@
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tauri::State;
+
+use crate::dictionary::cedict_manager_ready;
+use crate::AppState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZhScript {
+    Simplified,
+    Traditional,
+}
+
+/// Simplified↔traditional converter built from the CEDICT (simplified, traditional) pairs.
+/// Multi-character headwords are matched first (longest match), so one-to-many characters
+/// such as 发 → 發/髮 resolve by context; single characters fall back to their most
+/// common counterpart across all headwords.
+pub struct ScriptConverter {
+    to_traditional: HashMap<String, String>,
+    to_simplified: HashMap<String, String>,
+    max_len: usize,
+}
+
+/// Picks the most frequent mapping per key (ties go to the lexically smallest for stability).
+fn most_common(counts: HashMap<String, HashMap<String, u32>>) -> HashMap<String, String> {
+    counts
+        .into_iter()
+        .filter_map(|(k, v)| {
+            v.into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+                .map(|(t, _)| (k, t))
+        })
+        .collect()
+}
+
+impl ScriptConverter {
+    pub fn from_pairs<I: IntoIterator<Item = (String, String)>>(pairs: I) -> Self {
+        let mut s2t: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        let mut t2s: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        let mut max_len = 1;
+
+        for (simp, trad) in pairs {
+            let sc: Vec<char> = simp.chars().collect();
+            let tc: Vec<char> = trad.chars().collect();
+            if sc.len() != tc.len() || sc.is_empty() {
+                continue;
+            }
+            if sc.len() > 1 {
+                max_len = max_len.max(sc.len());
+                *s2t.entry(simp.clone()).or_default().entry(trad.clone()).or_insert(0) += 1;
+                *t2s.entry(trad.clone()).or_default().entry(simp.clone()).or_insert(0) += 1;
+            }
+            for (s, t) in sc.iter().zip(tc.iter()) {
+                *s2t.entry(s.to_string()).or_default().entry(t.to_string()).or_insert(0) += 1;
+                *t2s.entry(t.to_string()).or_default().entry(s.to_string()).or_insert(0) += 1;
+            }
+        }
+
+        Self {
+            to_traditional: most_common(s2t),
+            to_simplified: most_common(t2s),
+            max_len,
+        }
+    }
+
+    pub fn from_cedict(conn: &Connection) -> Result<Self, String> {
+        let mut stmt = conn
+            .prepare("SELECT simplified, traditional FROM entries")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))
+            .map_err(|e| e.to_string())?;
+        let pairs = rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?;
+        Ok(Self::from_pairs(pairs))
+    }
+
+    pub fn convert(&self, text: &str, target: ZhScript) -> String {
+        let map = match target {
+            ZhScript::Simplified => &self.to_simplified,
+            ZhScript::Traditional => &self.to_traditional,
+        };
+        let chars: Vec<char> = text.chars().collect();
+        let mut out = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let mut matched = false;
+            let longest = self.max_len.min(chars.len() - i);
+            for len in (1..=longest).rev() {
+                let key: String = chars[i..i + len].iter().collect();
+                if let Some(v) = map.get(&key) {
+                    out.push_str(v);
+                    i += len;
+                    matched = true;
+                    break;
+                }
+            }
+            if !matched {
+                out.push(chars[i]);
+                i += 1;
+            }
+        }
+        out
+    }
+}
+
+/// Converts `text` to simplified or traditional characters.
+#[tauri::command]
+pub fn cedict_convert_script(state: State<AppState>, text: String, target: ZhScript) -> Result<String, String> {
+    cedict_manager_ready(&state)?;
+    let conv = state.cedict.script_converter()?;
+    Ok(conv.convert(&text, target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conv() -> ScriptConverter {
+        ScriptConverter::from_pairs(
+            [
+                ("头发", "頭髮"),
+                ("发展", "發展"),
+                ("发", "發"),
+                ("出发", "出發"),
+                ("发", "髮"),
+                ("中国", "中國"),
+                ("里", "裡"),
+                ("里", "裏"),
+                ("公里", "公里"),
+                ("后", "後"),
+                ("后", "后"),
+            ]
+            .into_iter()
+            .map(|(s, t)| (s.to_string(), t.to_string())),
+        )
+    }
+
+    #[test]
+    fn test_word_context_wins() {
+        let c = conv();
+        assert_eq!(c.convert("头发发展", ZhScript::Traditional), "頭髮發展");
+        assert_eq!(c.convert("出发了", ZhScript::Traditional), "出發了");
+        assert_eq!(c.convert("三公里", ZhScript::Traditional), "三公里");
+    }
+
+    #[test]
+    fn test_roundtrip_and_passthrough() {
+        let c = conv();
+        assert_eq!(c.convert("中國 ABC", ZhScript::Simplified), "中国 ABC");
+        assert_eq!(c.convert("頭髮", ZhScript::Simplified), "头发");
+        assert_eq!(c.convert("", ZhScript::Simplified), "");
+    }
+}
@@ -0,0 +1,316 @@
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use tauri::State;
+
+use crate::dictionary::{cedict_manager_ready, lookup_cedict_conn};
+use crate::AppState;
+
+/// Unigram segmenter over the CEDICT headwords. CEDICT has no corpus frequencies, so the
+/// number of definitions a headword has is used as its weight. Each word costs about
+/// `ln(total)`, which dwarfs the weight differences, so the DAG search prefers the fewest
+/// words and mostly uses the weights to break ties.
+pub struct Segmenter {
+    words: HashMap<String, u32>,
+    total: f64,
+    max_len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentToken {
+    pub text: String,
+    /// Character (not byte) offsets into the input, end exclusive.
+    pub start: usize,
+    pub end: usize,
+    /// Whether the token is a dictionary word.
+    pub known: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentCandidate {
+    pub word: String,
+    pub start: usize,
+    pub end: usize,
+    pub pinyin: Option<String>,
+    pub definitions: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentAtResult {
+    /// The word the segmenter chose at the offset.
+    pub word: String,
+    pub start: usize,
+    pub end: usize,
+    /// Every dictionary word covering the offset, longest first.
+    pub candidates: Vec<SegmentCandidate>,
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c,
+        '\u{3007}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+impl Segmenter {
+    pub fn from_words<I: IntoIterator<Item = (String, u32)>>(words: I) -> Self {
+        let mut map: HashMap<String, u32> = HashMap::new();
+        for (w, n) in words {
+            if w.is_empty() {
+                continue;
+            }
+            *map.entry(w).or_insert(0) += n.max(1);
+        }
+        let total = map.values().map(|v| *v as f64).sum::<f64>().max(1.0);
+        let max_len = map.keys().map(|k| k.chars().count()).max().unwrap_or(1);
+        Self {
+            words: map,
+            total,
+            max_len,
+        }
+    }
+
+    /// Builds the word list from a CC-CEDICT `entries` table (both scripts).
+    pub fn from_cedict(conn: &Connection) -> Result<Self, String> {
+        let mut stmt = conn
+            .prepare("SELECT simplified, traditional, defs FROM entries")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, String>(1)?,
+                    r.get::<_, String>(2).unwrap_or_default(),
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let mut words: Vec<(String, u32)> = vec![];
+        for row in rows {
+            let (simp, trad, defs) = row.map_err(|e| e.to_string())?;
+            let weight = defs.split('\n').filter(|s| !s.trim().is_empty()).count() as u32;
+            if trad != simp {
+                words.push((trad, weight));
+            }
+            words.push((simp, weight));
+        }
+        Ok(Self::from_words(words))
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains_key(word)
+    }
+
+    fn cost(&self, word: &str) -> f64 {
+        match self.words.get(word) {
+            Some(n) => (self.total / *n as f64).ln(),
+            // Unknown single characters are possible but always worse than a known word.
+            None => (self.total * 10.0).ln(),
+        }
+    }
+
+    /// End offsets of the edges leaving every position: dictionary words, runs of
+    /// non-Han letters/digits as one token, and always the single character.
+    fn dag(&self, chars: &[char]) -> Vec<Vec<usize>> {
+        let n = chars.len();
+        let mut dag: Vec<Vec<usize>> = vec![vec![]; n];
+        for (i, edges) in dag.iter_mut().enumerate() {
+            if !is_han(chars[i]) && chars[i].is_alphanumeric() {
+                let mut j = i;
+                while j < n && !is_han(chars[j]) && chars[j].is_alphanumeric() {
+                    j += 1;
+                }
+                edges.push(j);
+            }
+            let mut buf = String::new();
+            for (j, c) in chars.iter().enumerate().skip(i).take(self.max_len) {
+                buf.push(*c);
+                if self.words.contains_key(&buf) && !edges.contains(&(j + 1)) {
+                    edges.push(j + 1);
+                }
+            }
+            if !edges.contains(&(i + 1)) {
+                edges.push(i + 1);
+            }
+        }
+        dag
+    }
+
+    /// Segments `text` by the cheapest path through the word DAG.
+    pub fn segment(&self, text: &str) -> Vec<SegmentToken> {
+        let chars: Vec<char> = text.chars().collect();
+        let n = chars.len();
+        if n == 0 {
+            return vec![];
+        }
+        let dag = self.dag(&chars);
+
+        // best[i] = (cost of chars[i..], end of the first word)
+        let mut best: Vec<(f64, usize)> = vec![(0.0, n); n + 1];
+        for i in (0..n).rev() {
+            best[i] = dag[i]
+                .iter()
+                .map(|&j| {
+                    let w: String = chars[i..j].iter().collect();
+                    (self.cost(&w) + best[j].0, j)
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0).then(b.1.cmp(&a.1)))
+                .unwrap_or((f64::INFINITY, i + 1));
+        }
+
+        let mut out = vec![];
+        let mut i = 0;
+        while i < n {
+            let j = best[i].1;
+            let text: String = chars[i..j].iter().collect();
+            out.push(SegmentToken {
+                known: self.contains(&text),
+                text,
+                start: i,
+                end: j,
+            });
+            i = j;
+        }
+        out
+    }
+
+    /// All dictionary words in `chars` that contain position `offset`, longest first.
+    pub fn covering(&self, chars: &[char], offset: usize) -> Vec<(usize, usize)> {
+        if offset >= chars.len() {
+            return vec![];
+        }
+        let mut out = vec![];
+        let first = (offset + 1).saturating_sub(self.max_len);
+        for start in first..=offset {
+            let last = (start + self.max_len).min(chars.len());
+            for end in (offset + 1)..=last {
+                let w: String = chars[start..end].iter().collect();
+                if self.contains(&w) {
+                    out.push((start, end));
+                }
+            }
+        }
+        out.sort_by(|a, b| (b.1 - b.0).cmp(&(a.1 - a.0)).then(a.0.cmp(&b.0)));
+        out
+    }
+}
+
+/// Segments `text` with the CEDICT word list.
+#[tauri::command]
+pub fn cedict_segment(state: State<AppState>, text: String) -> Result<Vec<SegmentToken>, String> {
+    cedict_manager_ready(&state)?;
+    let seg = state.cedict.segmenter()?;
+    Ok(seg.segment(&text))
+}
+
+/// Finds the word at character `offset` of `text` (e.g. the clicked position in a clause),
+/// plus every CEDICT word that covers that position.
+#[tauri::command]
+pub fn cedict_segment_at(
+    state: State<AppState>,
+    text: String,
+    offset: usize,
+) -> Result<Option<SegmentAtResult>, String> {
+    cedict_manager_ready(&state)?;
+    let seg = state.cedict.segmenter()?;
+    let chars: Vec<char> = text.chars().collect();
+    if offset >= chars.len() {
+        return Ok(None);
+    }
+
+    let Some(token) = seg
+        .segment(&text)
+        .into_iter()
+        .find(|t| t.start <= offset && offset < t.end)
+    else {
+        return Ok(None);
+    };
+
+    let spans = seg.covering(&chars, offset);
+    let candidates = state.cedict.with_conn(|conn| {
+        let mut out = vec![];
+        for (start, end) in spans {
+            let word: String = chars[start..end].iter().collect();
+            let Some(res) = lookup_cedict_conn(conn, &word)? else {
+                continue;
+            };
+            let mut definitions: Vec<String> = res.translation.into_iter().collect();
+            definitions.extend(res.meanings.into_iter().flat_map(|m| m.definitions));
+            out.push(SegmentCandidate {
+                word,
+                start,
+                end,
+                pinyin: res.phonetic,
+                definitions,
+            });
+        }
+        Ok(out)
+    })?;
+
+    Ok(Some(SegmentAtResult {
+        word: token.text,
+        start: token.start,
+        end: token.end,
+        candidates,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg() -> Segmenter {
+        Segmenter::from_words(
+            [
+                ("研究", 3),
+                ("研究生", 2),
+                ("生命", 2),
+                ("命", 1),
+                ("起源", 2),
+                ("的", 5),
+                ("中华人民共和国", 1),
+                ("人民", 2),
+                ("共和国", 1),
+                ("中华", 1),
+            ]
+            .into_iter()
+            .map(|(w, n)| (w.to_string(), n)),
+        )
+    }
+
+    fn texts(tokens: &[SegmentToken]) -> Vec<&str> {
+        tokens.iter().map(|t| t.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_segment_prefers_fewer_words() {
+        let s = seg();
+        assert_eq!(texts(&s.segment("研究生命的起源")), vec!["研究", "生命", "的", "起源"]);
+        assert_eq!(texts(&s.segment("中华人民共和国")), vec!["中华人民共和国"]);
+    }
+
+    #[test]
+    fn test_segment_unknown_and_latin() {
+        let s = seg();
+        let tokens = s.segment("用GPU4研究。");
+        assert_eq!(texts(&tokens), vec!["用", "GPU4", "研究", "。"]);
+        assert!(!tokens[0].known);
+        assert!(tokens[2].known);
+        assert_eq!((tokens[2].start, tokens[2].end), (5, 7));
+        assert!(s.segment("").is_empty());
+    }
+
+    #[test]
+    fn test_covering_words() {
+        let s = seg();
+        let chars: Vec<char> = "研究生命".chars().collect();
+        let spans = s.covering(&chars, 2);
+        assert_eq!(spans, vec![(0, 3), (2, 4)]);
+        assert!(s.covering(&chars, 10).is_empty());
+    }
+}
@@ -15,11 +15,12 @@ import { SetupWizard } from "@/components/setup/SetupWizard";
 import { useDocumentStore } from "@/stores/documentStore";
 import { invoke, Channel } from "@tauri-apps/api/core";
 import { isSingleCJKWord, isSingleWord } from "@/services/dictionary";
+import { indexDocument } from "@/services/concordance";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { useI18n } from "@/i18n";
 import { Menu, Moon, Sun, Bot, X } from "lucide-react";
 import { Button } from "@/components/ui/Button";
-import type { TextSelection, Document } from "@/types";
+import type { TextSelection, Document, EpubMetadata } from "@/types";
 
 type DocType = "pdf" | "epub" | "txt" | "md";
 
@@ -31,6 +32,43 @@ function getDocType(filePath: string): DocType {
   return "pdf";
 }
 
+/** Formats the backend converts to EPUB before reading. */
+const CONVERTIBLE_EXTENSIONS = ["fb2", "mobi", "azw", "azw3"];
+
+function isConvertible(filePath: string): boolean {
+  const ext = filePath.split(".").pop()?.toLowerCase() ?? "";
+  return CONVERTIBLE_EXTENSIONS.includes(ext);
+}
+
+/** Formats the backend converts to Markdown before reading. */
+const MARKDOWN_CONVERTIBLE_EXTENSIONS = ["docx", "html", "htm", "rtf"];
+
+function isMarkdownConvertible(filePath: string): boolean {
+  const ext = filePath.split(".").pop()?.toLowerCase() ?? "";
+  return MARKDOWN_CONVERTIBLE_EXTENSIONS.includes(ext);
+}
+
+/** Paths to open in place: convertible documents are replaced by their cached EPUB or Markdown conversion. */
+async function resolveInPlacePaths(paths: string[]): Promise<{ openPaths: string[]; originalPaths: string[] }> {
+  const openPaths: string[] = [];
+  const originalPaths: string[] = [];
+  for (const p of paths) {
+    if (!isConvertible(p) && !isMarkdownConvertible(p)) {
+      openPaths.push(p);
+      originalPaths.push(p);
+      continue;
+    }
+    try {
+      const command = isConvertible(p) ? "convert_ebook" : "markdown_convert";
+      openPaths.push(await invoke<string>(command, { path: p }));
+      originalPaths.push(p);
+    } catch (err) {
+      console.warn(`[App] Failed to convert "${p}":`, err);
+    }
+  }
+  return { openPaths, originalPaths };
+}
+
 function App() {
   const { t } = useI18n();
   const [setupDone, setSetupDone] = useState(() => localStorage.getItem('aireader_setup_completed') === '1');
@@ -98,6 +136,24 @@ function App() {
     return null;
   }, []);
 
+  // Replace the file name with the title and authors from the EPUB package metadata.
+  const applyEpubMetadata = async (docId: string, path: string) => {
+    try {
+      const meta = await invoke<EpubMetadata>("epub_metadata", { path });
+      const title = meta.title?.trim();
+      const author = meta.authors.join(", ");
+      if (!title && !author) return;
+      const patch = (d: Document): Document =>
+        d.id === docId ? { ...d, title: title || d.title, author: author || d.author } : d;
+      useDocumentStore.setState((s) => ({
+        documents: s.documents.map(patch),
+        currentDocument: s.currentDocument ? patch(s.currentDocument) : s.currentDocument,
+      }));
+    } catch (err) {
+      console.warn(`[App] Failed to read EPUB metadata for "${path}":`, err);
+    }
+  };
+
   const addPathsToLibrary = useCallback(
     (paths: string[], autoOpen = true, opts?: { isCopy?: boolean; originalPaths?: string[] }) => {
       if (paths.length === 0) return;
@@ -141,6 +197,9 @@ function App() {
 
       if (nextDocs.length !== documents.length) {
         setDocuments(nextDocs);
+        for (const doc of nextDocs.slice(documents.length)) {
+          if (doc.type === "epub") void applyEpubMetadata(doc.id, doc.path);
+        }
       }
       if (autoOpen && firstToOpen) {
         setCurrentDocument(firstToOpen);
@@ -251,6 +310,11 @@ function App() {
       }
     })();
   }, [documents, currentDocument, setCurrentDocument, setDocuments]);
+
+  // Opened documents feed the library's example-sentence index.
+  useEffect(() => {
+    if (currentDocument) void indexDocument(currentDocument);
+  }, [currentDocument]);
   
   useEffect(() => {
     const handleDoubleClick = (e: MouseEvent) => {
@@ -418,7 +482,7 @@ function App() {
         filters: [
           {
             name: t("app.open_dialog.filter_name"),
-            extensions: ["pdf", "epub", "txt", "md"],
+            extensions: ["pdf", "epub", "txt", "md", ...CONVERTIBLE_EXTENSIONS, ...MARKDOWN_CONVERTIBLE_EXTENSIONS],
           },
         ],
       });
@@ -444,7 +508,7 @@ function App() {
         for (const p of paths) {
           try {
             const selectedType = getDocType(p);
-            if (selectedType === "md") {
+            if (selectedType === "md" || isMarkdownConvertible(p)) {
               imported.push(
                 await invoke<string>("import_markdown_copy", {
                   sourcePath: p,
@@ -478,8 +542,9 @@ function App() {
         }
       } else {
         // Open directly — use original paths without copying
-        const isBatch = paths.length > 1;
-        addPathsToLibrary(paths, !isBatch, { isCopy: false });
+        const { openPaths, originalPaths } = await resolveInPlacePaths(paths);
+        const isBatch = openPaths.length > 1;
+        addPathsToLibrary(openPaths, !isBatch, { isCopy: false, originalPaths });
       }
     } catch (error) {
       console.error("Failed to open file:", error);
@@ -513,7 +578,8 @@ function App() {
         addPathsToLibrary(imported, false, { isCopy: true, originalPaths });
       } else {
         const paths = await invoke<string[]>("scan_folder_documents", { folderPath: folder });
-        addPathsToLibrary(paths, false, { isCopy: false });
+        const { openPaths, originalPaths } = await resolveInPlacePaths(paths);
+        addPathsToLibrary(openPaths, false, { isCopy: false, originalPaths });
       }
     } catch (error) {
       console.error("Failed to import folder:", error);
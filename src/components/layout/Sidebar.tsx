@@ -51,7 +51,7 @@ export function Sidebar({ onOpenFile, style }: SidebarProps) {
     if (!ok) return;
     // If it's a copy, delete physical file
     if (doc.isCopy === true) {
-      try { await invoke('delete_document_copy', { path: doc.path, documentsDir: documentsDir || null }); } catch (err) { console.warn('[Sidebar] delete copy failed:', err); }
+      try { await invoke('delete_document_copy', { path: doc.path, documentsDir: documentsDir || null, documentId: doc.id }); } catch (err) { console.warn('[Sidebar] delete copy failed:', err); }
     }
     const newDocs = documents.filter((d) => d.id !== doc.id);
     setDocuments(newDocs);
@@ -1,4 +1,4 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { FileText, Trash2, Clock, BookOpen, Search, FolderOpen, CheckSquare, Square, Loader2 } from "lucide-react";
 import { Button } from "@/components/ui/Button";
 import { useDocumentStore } from "@/stores/documentStore";
@@ -8,6 +8,30 @@ import { cn } from "@/lib/utils";
 import type { Document } from "@/types";
 import { invoke } from "@tauri-apps/api/core";
 import { useI18n } from "@/i18n";
+import { getThumbnailUrl } from "@/services/thumbnails";
+
+function DocumentCover({ doc, fallback }: { doc: Document; fallback: React.ReactNode }) {
+  const [url, setUrl] = useState<string | null>(null);
+
+  useEffect(() => {
+    let cancelled = false;
+    getThumbnailUrl(doc).then((u) => {
+      if (!cancelled) setUrl(u);
+    });
+    return () => {
+      cancelled = true;
+    };
+  }, [doc.path, doc.type]);
+
+  if (!url) {
+    return (
+      <div className="w-10 h-10 rounded-lg bg-muted/60 flex items-center justify-center flex-shrink-0 text-lg">
+        {fallback}
+      </div>
+    );
+  }
+  return <img src={url} alt="" className="w-10 h-14 rounded object-cover flex-shrink-0 shadow-sm" />;
+}
 
 interface DocumentLibraryProps {
   onImportFiles: () => void;
@@ -30,7 +54,8 @@ export function DocumentLibrary({ onImportFiles, onImportFolder, onClose }: Docu
 
   const filteredDocs = documents
     .filter((doc) =>
-      doc.title.toLowerCase().includes(searchQuery.toLowerCase())
+      doc.title.toLowerCase().includes(searchQuery.toLowerCase()) ||
+      !!doc.author?.toLowerCase().includes(searchQuery.toLowerCase())
     )
     .filter((doc) => (typeFilter === "all" ? true : doc.type === typeFilter))
     .sort((a, b) => {
@@ -66,7 +91,7 @@ export function DocumentLibrary({ onImportFiles, onImportFolder, onClose }: Docu
 
     try {
       if (doc.isCopy === true) {
-        await invoke("delete_document_copy", { path: doc.path, documentsDir: documentsDir || null });
+        await invoke("delete_document_copy", { path: doc.path, documentsDir: documentsDir || null, documentId: doc.id });
       }
     } catch {
     }
@@ -106,7 +131,7 @@ export function DocumentLibrary({ onImportFiles, onImportFolder, onClose }: Docu
         removeFromCache(doc.path);
         if (currentDocument?.id === doc.id) setCurrentDocument(null);
         if (doc.isCopy === true) {
-          try { await invoke("delete_document_copy", { path: doc.path, documentsDir: documentsDir || null }); } catch {}
+          try { await invoke("delete_document_copy", { path: doc.path, documentsDir: documentsDir || null, documentId: doc.id }); } catch {}
         }
       }
       setSelectedIds(new Set());
@@ -282,9 +307,7 @@ export function DocumentLibrary({ onImportFiles, onImportFolder, onClose }: Docu
                         : <Square className="w-5 h-5 text-muted-foreground" />}
                     </button>
                   ) : (
-                    <div className="w-10 h-10 rounded-lg bg-muted/60 flex items-center justify-center flex-shrink-0 text-lg">
-                      {getDocTypeIcon(doc.type)}
-                    </div>
+                    <DocumentCover doc={doc} fallback={getDocTypeIcon(doc.type)} />
                   )}
                   <div className="flex-1 min-w-0">
                     <div className="flex items-center gap-1.5">
@@ -301,6 +324,7 @@ export function DocumentLibrary({ onImportFiles, onImportFolder, onClose }: Docu
                       )}
                     </div>
                     <div className="flex items-center gap-3 text-xs text-muted-foreground mt-0.5">
+                      {doc.author && <span className="truncate max-w-[12rem]">{doc.author}</span>}
                       <span className="flex items-center gap-1">
                         <Clock className="w-3 h-3" />
                         {formatDate(doc.updatedAt)}
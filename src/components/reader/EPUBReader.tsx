@@ -1,6 +1,6 @@
 import { useEffect, useMemo, useRef, useState } from "react";
 import ePub, { Book, Rendition } from "epubjs";
-import type { TextSelection } from "@/types";
+import { isEpubDrmError, type TextSelection } from "@/types";
 import { useDocumentStore } from "@/stores/documentStore";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { FloatingReaderToolbar } from "@/components/reader/FloatingReaderToolbar";
@@ -10,6 +10,11 @@ import { ChevronLeft } from "lucide-react";
 import { useI18n } from "@/i18n";
 import { getErrorMessage } from "@/lib/utils";
 
+// Absolute paths under this prefix are entries of the archive served through the `epub://` scheme.
+const ARCHIVE_ROOT = '/__epub_archive__';
+
+const isArchiveUrl = (url: string) => url.startsWith('epub:') || url.includes('epub.localhost');
+
 interface EPUBReaderProps {
   filePath: string;
   onTextSelect: (selection: TextSelection) => void;
@@ -25,6 +30,9 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
   const firstRenderedRef = useRef(false);
   const lastAssetUrlRef = useRef<string>('');
   const opfAbsPathRef = useRef<string>('');
+  const archiveRef = useRef<{ doc: string; baseUrl: string } | null>(null);
+  // Opened from the extraction cache; released on close so the cache may evict it again.
+  const extractedRef = useRef(false);
   const opfDirAbsPathRef = useRef<string>('');
   const extractedRootAbsPathRef = useRef<string>('');
   const opfDirUrlRef = useRef<string>('');
@@ -357,11 +365,22 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
       typeof urlStr === 'string' &&
       (
         urlStr.includes('epub_extracted') ||
+        isArchiveUrl(urlStr) ||
         urlStr.includes('asset.localhost') ||
         urlStr.startsWith('asset://') ||
         urlStr.startsWith('asset:')
       );
 
+    // Archive entries go through `epub://`; extracted files through the asset protocol.
+    const resourceUrl = (abs: string): string => {
+      const archive = archiveRef.current;
+      if (archive && abs.startsWith(`${ARCHIVE_ROOT}/`)) {
+        const rel = abs.slice(ARCHIVE_ROOT.length + 1);
+        return archive.baseUrl + rel.split('/').map(encodeURIComponent).join('/');
+      }
+      return convertFileSrc(abs);
+    };
+
     const normalizeRelPath = (raw: string): string | null => {
       const s = String(raw || '').replace(/\\/g, '/').replace(/^\/+/, '');
       const parts = s.split('/');
@@ -386,7 +405,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
       const baseAbs = norm === 'mimetype' || norm.startsWith('META-INF/') ? (rootBaseAbs || opfBaseAbs) : opfBaseAbs;
       if (!baseAbs) return null;
       const abs = `${baseAbs}/${norm}`;
-      const out = convertFileSrc(abs);
+      const out = resourceUrl(abs);
       return `${out}${search || ''}${hash || ''}`;
     };
 
@@ -471,7 +490,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
       }
       const norm = normalizeRelPath(rel);
       if (!norm) return null;
-      return convertFileSrc(`${sectionBaseAbs}/${norm}`);
+      return resourceUrl(`${sectionBaseAbs}/${norm}`);
     };
 
     const sharedRewriteInlineCss = (text: string, baseDirAbs: string): string => {
@@ -483,13 +502,13 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
         const href = String(a || b || c || '').trim();
         if (!href) return full;
         const lo = href.toLowerCase();
-        if (lo.startsWith('data:') || lo.startsWith('http://') || lo.startsWith('https://') || lo.startsWith('blob:') || lo.startsWith('asset:')) return full;
+        if (lo.startsWith('data:') || lo.startsWith('http://') || lo.startsWith('https://') || lo.startsWith('blob:') || lo.startsWith('asset:') || lo.startsWith('epub:')) return full;
         let rw: string | null = null;
         if (href.startsWith('/') && !href.startsWith('//') && !/^\/[a-zA-Z]:\//.test(href)) {
           rw = rewriteRelToAssetUrl(href.replace(/^\/+/, ''));
         } else {
           const norm = normalizeRelPath(href.replace(/\\/g, '/'));
-          if (norm) rw = convertFileSrc(`${base}/${norm}`);
+          if (norm) rw = resourceUrl(`${base}/${norm}`);
         }
         if (!rw) return full;
         const tail = String(rest || '').trim();
@@ -499,13 +518,13 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
         const u = String(raw || '').trim();
         if (!u) return full;
         const lo = u.toLowerCase();
-        if (lo.startsWith('data:') || lo.startsWith('http://') || lo.startsWith('https://') || lo.startsWith('blob:') || lo.startsWith('asset:')) return full;
+        if (lo.startsWith('data:') || lo.startsWith('http://') || lo.startsWith('https://') || lo.startsWith('blob:') || lo.startsWith('asset:') || lo.startsWith('epub:')) return full;
         let rw: string | null = null;
         if (u.startsWith('/') && !u.startsWith('//') && !/^\/[a-zA-Z]:\//.test(u)) {
           rw = rewriteRelToAssetUrl(u.replace(/^\/+/, ''));
         } else {
           const norm = normalizeRelPath(u.replace(/\\/g, '/'));
-          if (norm) rw = convertFileSrc(`${base}/${norm}`);
+          if (norm) rw = resourceUrl(`${base}/${norm}`);
         }
         if (!rw) return full;
         return `url(${q || '"'}${rw}${q || '"'})`;
@@ -663,6 +682,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
         typeof urlStr === 'string' &&
         (
           urlStr.includes('epub_extracted') ||
+          isArchiveUrl(urlStr) ||
           urlStr.includes('asset.localhost') ||
           urlStr.startsWith('asset://') ||
           urlStr.startsWith('asset:')
@@ -817,7 +837,24 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
         bookRef.current = book;
 
         setStage('Extracting');
-        const opfAbs = await withTimeout(invoke<string>('epub_extract', { path: filePath }), 120000, 'EPUB解压超时(120s)');
+        let opfAbs: string;
+        try {
+          // Serve entries straight from the zip; unpack to disk only if the archive cannot be indexed.
+          const archive = await withTimeout(
+            invoke<{ doc: string; base_url: string; opf_path: string }>('epub_open_archive', { path: filePath }),
+            30000,
+            'EPUB索引超时(30s)'
+          );
+          archiveRef.current = { doc: archive.doc, baseUrl: archive.base_url };
+          opfAbs = `${ARCHIVE_ROOT}/${archive.opf_path}`;
+        } catch (e) {
+          // DRM-protected books fail the same way when extracted; surface the reason instead.
+          if (isEpubDrmError(e)) throw e;
+          console.warn("[EPUB] 无法直接读取压缩包，改为解压:", e);
+          archiveRef.current = null;
+          extractedRef.current = true;
+          opfAbs = await withTimeout(invoke<string>('epub_extract', { path: filePath }), 120000, 'EPUB解压超时(120s)');
+        }
         if (!ensureLatest()) return;
         const opfAbsNorm = String(opfAbs || '').replace(/\\/g, '/');
         opfAbsPathRef.current = opfAbsNorm;
@@ -832,7 +869,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
           opfDirAbsPathRef.current = '';
           extractedRootAbsPathRef.current = '';
         }
-        const opfUrl = convertFileSrc(opfAbsNorm);
+        const opfUrl = resourceUrl(opfAbsNorm);
         try {
           const u = new URL(opfUrl);
           const dirUrl = new URL('.', u);
@@ -1139,6 +1176,11 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
 
               const decodeAssetAbsPath = (assetUrl: string): string | null => {
                 try {
+                  const archive = archiveRef.current;
+                  if (archive && assetUrl.startsWith(archive.baseUrl)) {
+                    const rel = assetUrl.slice(archive.baseUrl.length).split(/[?#]/)[0];
+                    return `${ARCHIVE_ROOT}/${decodeURIComponent(rel)}`;
+                  }
                   const u = new URL(assetUrl);
                   const isAssetHost = u.hostname === 'asset.localhost' || (u.protocol === 'asset:' && u.hostname === 'localhost');
                   if (!isAssetHost) return null;
@@ -1200,7 +1242,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
                         continue;
                       }
                       const importAbs = `${baseDirAbs.replace(/\\/g, '/').replace(/\/+$/, '')}/${absHrefNorm}`;
-                      const importUrl = convertFileSrc(importAbs);
+                      const importUrl = resourceUrl(importAbs);
                       const r = await fetch(importUrl);
                       if (!r.ok) {
                         outImports.push(m[0]);
@@ -1222,7 +1264,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
                       const u = String(raw || '').trim();
                       if (!u) return _full;
                       const lower = u.toLowerCase();
-                      if (lower.startsWith('data:') || lower.startsWith('http://') || lower.startsWith('https://') || lower.startsWith('blob:') || lower.startsWith('asset:')) {
+                      if (lower.startsWith('data:') || lower.startsWith('http://') || lower.startsWith('https://') || lower.startsWith('blob:') || lower.startsWith('asset:') || lower.startsWith('epub:')) {
                         return _full;
                       }
 
@@ -1233,7 +1275,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
                         const norm = normalizeRelPath(u.replace(/\\/g, '/'));
                         if (norm) {
                           const abs = `${baseDirAbs.replace(/\\/g, '/').replace(/\/+$/, '')}/${norm}`;
-                          rewritten = convertFileSrc(abs);
+                          rewritten = resourceUrl(abs);
                         }
                       }
                       if (!rewritten) return _full;
@@ -1288,6 +1330,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
                         typeof urlStr === 'string' &&
                         (
                           urlStr.includes('epub_extracted') ||
+                          isArchiveUrl(urlStr) ||
                           urlStr.includes('asset.localhost') ||
                           urlStr.startsWith('asset://') ||
                           urlStr.startsWith('asset:')
@@ -1329,6 +1372,7 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
                             typeof urlStr === 'string' &&
                             (
                               urlStr.includes('epub_extracted') ||
+                              isArchiveUrl(urlStr) ||
                               urlStr.includes('asset.localhost') ||
                               urlStr.startsWith('asset://') ||
                               urlStr.startsWith('asset:')
@@ -1721,6 +1765,15 @@ export function EPUBReader({ filePath, onTextSelect, onFatalError }: EPUBReaderP
           console.warn("[EPUB] 清理时销毁出错:", e);
         }
       }
+      const archive = archiveRef.current;
+      if (archive) {
+        archiveRef.current = null;
+        invoke("epub_close_archive", { doc: archive.doc }).catch(() => {});
+      }
+      if (extractedRef.current) {
+        extractedRef.current = false;
+        invoke("epub_release_extract", { path: filePath }).catch(() => {});
+      }
     };
   }, [filePath, normalizeHref]);
 
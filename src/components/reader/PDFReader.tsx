@@ -1,5 +1,6 @@
 import { useState, useEffect, useRef, useCallback, useMemo } from "react";
 import { Document, Page, pdfjs } from "react-pdf";
+import { invoke } from "@tauri-apps/api/core";
 import { FloatingReaderToolbar } from "@/components/reader/FloatingReaderToolbar";
 import { useDocumentStore } from "@/stores/documentStore";
 import { useDocumentCacheStore } from "@/stores/documentCacheStore";
@@ -233,6 +234,13 @@ export function PDFReader({ filePath, onTextSelect }: PDFReaderProps) {
     };
   }, [filePath]);
 
+  // 关闭文档时释放后端缓存的解析结果
+  useEffect(() => {
+    return () => {
+      invoke("pdf_close_document", { path: filePath }).catch(() => {});
+    };
+  }, [filePath]);
+
   // 组件卸载时清理 Blob URL
   useEffect(() => {
     return () => {
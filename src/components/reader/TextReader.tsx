@@ -1,4 +1,4 @@
-import { useEffect, useState, useRef } from "react";
+import { useEffect, useLayoutEffect, useState, useRef } from "react";
 import { Markdown } from "@/components/ui/Markdown";
 import { useSettingsStore } from "@/stores/settingsStore";
 import { useDocumentStore } from "@/stores/documentStore";
@@ -9,6 +9,24 @@ import type { TextSelection } from "@/types";
 import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import { getErrorMessage } from "@/lib/utils";
 import { useI18n } from "@/i18n";
+import { reindexDocument } from "@/services/concordance";
+
+interface TextEncodingReport {
+  encoding: string;
+  detected: string;
+  confidence: number;
+  bom: boolean;
+  has_original: boolean;
+  choices: string[];
+}
+
+interface TextChapter {
+  title: string;
+  level: number;
+  line: number;
+  offset: number;
+  byte_offset: number;
+}
 
 interface TextReaderProps {
   filePath: string;
@@ -20,18 +38,36 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
   const [content, setContent] = useState<string>("");
   const [error, setError] = useState<string | null>(null);
   const [loading, setLoading] = useState(true);
+  const [reloadKey, setReloadKey] = useState(0);
+  const [encodingReport, setEncodingReport] = useState<TextEncodingReport | null>(null);
+  const [reimporting, setReimporting] = useState(false);
   const { currentDocument, updateDocumentProgress } = useDocumentStore();
   const markdownScale = useSettingsStore((s) => s.markdownScale);
   const setMarkdownScale = useSettingsStore((s) => s.setMarkdownScale);
   const saveSettings = useSettingsStore((s) => s.saveSettings);
+  // 章节规则按文档保存，切换文档时不会沿用上一本书的规则
+  const chapterPatternKey = currentDocument?.id ?? filePath;
+  const textChapterPattern = useSettingsStore((s) => s.textChapterPatterns[chapterPatternKey] ?? '');
+  const setTextChapterPattern = useSettingsStore((s) => s.setTextChapterPattern);
   const onTextSelectRef = useRef(onTextSelect);
   const [appDark, setAppDark] = useState(() => document.documentElement.classList.contains('dark'));
   const [docDarkOverride, setDocDarkOverride] = useState<boolean | null>(null);
   const containerRef = useRef<HTMLDivElement>(null);
+  const preRef = useRef<HTMLPreElement>(null);
   const [renderWindow, setRenderWindow] = useState<{ start: number; end: number }>({ start: 0, end: 0 });
+  const renderWindowRef = useRef(renderWindow);
+  renderWindowRef.current = renderWindow;
+  const contentLengthRef = useRef(content.length);
+  contentLengthRef.current = content.length;
+  // 章节跳转后窗口固定在跳转位置，待渲染后滚动到 pendingOffset 对应的文本
+  const pinnedWindowRef = useRef(false);
+  const pendingOffsetRef = useRef<number | null>(null);
   const [mdToc, setMdToc] = useState<{ id: string; text: string; level: number }[]>([]);
   const [tocOpen, setTocOpen] = useState(false);
   const [tocWidth, setTocWidth] = useState(240);
+  const [textToc, setTextToc] = useState<TextChapter[]>([]);
+  const [chapterPatternDraft, setChapterPatternDraft] = useState(textChapterPattern);
+  const [chapterPatternError, setChapterPatternError] = useState<string | null>(null);
 
   const getDirName = (path: string) => {
     const normalized = path.replace(/\\/g, "/");
@@ -106,13 +142,13 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
       try {
         setLoading(true);
         setError(null);
-        const { readTextFile, readFile } = await import("@tauri-apps/plugin-fs");
-
         let text: string;
         try {
-          text = await readTextFile(filePath);
+          // 后端按检测到的编码在内存中解码，原地打开的文件不会被改写
+          text = await invoke<string>("text_read", { path: filePath });
         } catch (err) {
-          console.warn("[TXT] readTextFile 失败，尝试 readFile + TextDecoder:", err);
+          console.warn("[TXT] text_read 失败，尝试 readFile + TextDecoder:", err);
+          const { readFile } = await import("@tauri-apps/plugin-fs");
           const bytes = await readFile(filePath);
           text = new TextDecoder("utf-8", { fatal: false }).decode(bytes);
         }
@@ -137,7 +173,114 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
     };
 
     loadText();
-  }, [filePath]);
+  }, [filePath, reloadKey]);
+
+  useEffect(() => {
+    let cancelled = false;
+    setEncodingReport(null);
+    if (isMarkdown) return;
+    invoke<TextEncodingReport>("text_encoding_report", { path: filePath })
+      .then((report) => {
+        if (!cancelled) setEncodingReport(report);
+      })
+      .catch(() => {
+        // 非导入副本或读取失败时不显示编码选择
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [filePath, reloadKey]);
+
+  useEffect(() => {
+    let cancelled = false;
+    invoke<TextChapter[]>("text_chapters", { path: filePath, pattern: textChapterPattern || null })
+      .then((chapters) => {
+        if (cancelled) return;
+        setTextToc(chapters);
+        setChapterPatternError(null);
+      })
+      .catch((err) => {
+        if (cancelled) return;
+        setTextToc([]);
+        setChapterPatternError(getErrorMessage(err));
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [filePath, reloadKey, textChapterPattern]);
+
+  useEffect(() => {
+    setChapterPatternDraft(textChapterPattern);
+  }, [chapterPatternKey, textChapterPattern]);
+
+  const applyChapterPattern = () => {
+    const next = chapterPatternDraft.trim();
+    if (next === textChapterPattern) return;
+    setTextChapterPattern(chapterPatternKey, next);
+    saveSettings();
+  };
+
+  // 滚动到当前窗口内的字符偏移；TXT 按文本节点定位，Markdown 按窗口内比例估算
+  const scrollToOffset = (offset: number) => {
+    const c = containerRef.current;
+    if (!c) return;
+    const { start, end } = renderWindowRef.current;
+    const textNode = preRef.current?.firstChild;
+    if (textNode && textNode.nodeType === Node.TEXT_NODE) {
+      const len = (textNode as Text).length;
+      const local = Math.min(Math.max(0, offset - start), len);
+      const range = document.createRange();
+      range.setStart(textNode, local);
+      range.setEnd(textNode, Math.min(local + 1, len));
+      const top = range.getBoundingClientRect().top - c.getBoundingClientRect().top + c.scrollTop;
+      c.scrollTo({ top: Math.max(0, top - 8) });
+      return;
+    }
+    const maxScroll = Math.max(0, c.scrollHeight - c.clientHeight);
+    c.scrollTo({ top: ((offset - start) / Math.max(1, end - start)) * maxScroll });
+  };
+
+  // 按字符偏移跳转：长文本只渲染窗口，先把窗口起点移到该偏移，渲染后再滚动到对应文本
+  const jumpToOffset = (offset: number) => {
+    const total = content.length;
+    if (!total) return;
+    const win = Math.min(total, 140_000);
+    if (total <= win) {
+      scrollToOffset(offset);
+      return;
+    }
+    const start = Math.max(0, Math.min(offset, total - win));
+    pinnedWindowRef.current = true;
+    pendingOffsetRef.current = offset;
+    setRenderWindow({ start, end: start + win });
+  };
+
+  useLayoutEffect(() => {
+    const offset = pendingOffsetRef.current;
+    if (offset === null) return;
+    pendingOffsetRef.current = null;
+    scrollToOffset(offset);
+  }, [renderWindow]);
+
+  const handleEncodingChange = async (encoding: string) => {
+    if (!encodingReport || encoding === encodingReport.encoding) return;
+    setReimporting(true);
+    try {
+      await invoke("text_reimport", { path: filePath, encoding, documentId: currentDocument?.id ?? null });
+      setReloadKey((k) => k + 1);
+      if (currentDocument) void reindexDocument(currentDocument);
+    } catch (err) {
+      const msg = getErrorMessage(err);
+      console.error("[TXT] 重新解码失败:", err);
+      try {
+        await invoke("append_log", { level: "error", message: `[TXT] 重新解码失败: ${filePath} :: ${msg}` });
+      } catch {
+        // ignore
+      }
+    } finally {
+      setReimporting(false);
+    }
+  };
 
   useEffect(() => {
     if (!isMarkdown || !mdFull) {
@@ -205,6 +348,7 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
     if (!el) return;
 
     if (isMarkdown && mdFull) return;
+    pinnedWindowRef.current = false;
 
     const update = () => {
       const total = content.length;
@@ -216,6 +360,19 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
         return;
       }
 
+      // 跳转后的窗口：在窗口内阅读时保持不动，接近边缘时以可见位置为锚点重新取窗口
+      if (pinnedWindowRef.current) {
+        const { start, end } = renderWindowRef.current;
+        const maxScroll = Math.max(1, el.scrollHeight - el.clientHeight);
+        const local = el.scrollTop / maxScroll;
+        if (!(local < 0.02 && start > 0) && !(local > 0.98 && end < total)) return;
+        const visible = Math.floor(start + (el.scrollTop / Math.max(1, el.scrollHeight)) * (end - start));
+        const next = Math.max(0, Math.min(visible - Math.floor(win / 2), total - win));
+        pendingOffsetRef.current = visible;
+        setRenderWindow({ start: next, end: next + win });
+        return;
+      }
+
       const top = el.scrollTop;
       const height = el.clientHeight || 1;
       const scrollHeight = el.scrollHeight || 1;
@@ -247,7 +404,13 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
         const clientHeight = el.clientHeight;
         const maxScroll = scrollHeight - clientHeight;
         if (maxScroll <= 0) return;
-        const progress = Math.min(1, Math.max(0, scrollTop / maxScroll));
+        const local = Math.min(1, Math.max(0, scrollTop / maxScroll));
+        // 跳转后窗口固定时，滚动位置只对应窗口内的文本，进度按窗口在全文中的位置换算
+        const { start, end } = renderWindowRef.current;
+        const total = contentLengthRef.current;
+        const progress = pinnedWindowRef.current && total > 0 && end > start
+          ? Math.min(1, Math.max(0, (start + local * (end - start)) / total))
+          : local;
         updateDocumentProgress(currentDocument.id, 1, progress);
       });
     };
@@ -279,6 +442,11 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
     }
   };
 
+  const mdTocActive = isMarkdown && mdFull && mdToc.length > 0;
+  // 纯文本（以及没有标题的 Markdown）使用后端检测的章节目录
+  const textTocActive = !mdTocActive && (!isMarkdown || textToc.length > 0 || !!textChapterPattern);
+  const hasToc = mdTocActive || textTocActive;
+
   if (error) {
     return (
       <div className="flex items-center justify-center h-full">
@@ -298,7 +466,7 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
   return (
     <div className="flex-1 flex flex-col overflow-hidden relative">
       <div className="flex-1 flex overflow-hidden">
-        {isMarkdown && mdFull && mdToc.length > 0 && (
+        {hasToc && (
           <div
             className={`shrink-0 bg-card/60 backdrop-blur overflow-hidden transition-[width] duration-200 ${
               tocOpen ? 'border-r border-border' : ''
@@ -320,8 +488,47 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
                     <ChevronLeft className="w-3.5 h-3.5" />
                   </button>
                 </div>
+                {textTocActive && (
+                  <div className="px-2 py-1.5 border-b border-border/60">
+                    <input
+                      type="text"
+                      value={chapterPatternDraft}
+                      onChange={(e) => setChapterPatternDraft(e.target.value)}
+                      onBlur={applyChapterPattern}
+                      onKeyDown={(e) => {
+                        if (e.key === 'Enter') applyChapterPattern();
+                      }}
+                      placeholder={b('自定义章节正则（留空自动检测）', 'Custom chapter regex (empty = auto)')}
+                      className="w-full h-7 rounded-md border border-border bg-background px-2 text-xs font-mono"
+                    />
+                    {chapterPatternError && (
+                      <div className="mt-1 text-[11px] text-destructive break-all">{chapterPatternError}</div>
+                    )}
+                  </div>
+                )}
                 <div className="p-1.5 text-sm overflow-auto h-full">
-                  {mdToc.map((it) => (
+                  {textTocActive && textToc.length === 0 && !chapterPatternError && (
+                    <div className="px-2 py-1 text-xs text-muted-foreground">{b('未检测到章节', 'No chapters detected')}</div>
+                  )}
+                  {textTocActive && textToc.map((it) => (
+                    <button
+                      key={it.offset}
+                      type="button"
+                      className={`w-full text-left px-2 py-1 rounded-md hover:bg-muted/80 transition-colors truncate leading-snug ${
+                        it.level === 1
+                          ? 'text-[13px] font-medium'
+                          : it.level === 2
+                            ? 'text-[12px]'
+                            : 'text-[11px] text-muted-foreground'
+                      }`}
+                      style={{ paddingLeft: 8 + (it.level - 1) * 14, borderLeft: it.level > 1 ? '2px solid var(--border)' : 'none' }}
+                      onClick={() => jumpToOffset(it.offset)}
+                      title={it.title}
+                    >
+                      {it.title}
+                    </button>
+                  ))}
+                  {mdTocActive && mdToc.map((it) => (
                     <button
                       key={it.id}
                       type="button"
@@ -378,7 +585,7 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
           </div>
         )}
 
-        {isMarkdown && mdFull && mdToc.length > 0 && tocOpen && (
+        {hasToc && tocOpen && (
           <ResizeHandle
             direction="right"
             onResize={(delta) => {
@@ -388,6 +595,24 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
         )}
 
         <div ref={containerRef} className={`flex-1 overflow-auto p-4 ${effectiveDocDark ? 'bg-zinc-900' : 'bg-muted/30'}`}>
+          {encodingReport && (
+            <div className="w-full max-w-[794px] mx-auto mb-2 flex items-center justify-end gap-2 text-xs text-muted-foreground">
+              <span>{b('编码', 'Encoding')}</span>
+              <select
+                className="h-7 rounded-md border border-border bg-background px-2 text-xs text-foreground"
+                value={encodingReport.encoding}
+                disabled={reimporting}
+                title={b(`检测结果：${encodingReport.detected}`, `Detected: ${encodingReport.detected}`)}
+                onChange={(e) => void handleEncodingChange(e.target.value)}
+              >
+                {encodingReport.choices.map((name) => (
+                  <option key={name} value={name}>
+                    {name}
+                  </option>
+                ))}
+              </select>
+            </div>
+          )}
           <div
             className="w-full max-w-[794px] mx-auto bg-white shadow-lg rounded-sm p-8 prose prose-sm prose-h1:text-[1.8em] prose-h2:text-[1.5em] prose-h3:text-[1.25em] prose-h4:text-[1.1em] prose-pre:bg-muted prose-pre:text-foreground"
             style={{ fontSize: `${markdownScale}rem`, filter: effectiveDocDark ? 'invert(0.88) hue-rotate(180deg)' : undefined }}
@@ -397,6 +622,7 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
               <Markdown resolveImageSrc={resolveMarkdownSrc}>{mdFull ? content : content.slice(renderWindow.start, renderWindow.end)}</Markdown>
             ) : (
               <pre
+                ref={preRef}
                 className="whitespace-pre-wrap font-sans text-base leading-relaxed"
                 style={{ fontSize: `${markdownScale}rem` }}
               >
@@ -431,10 +657,10 @@ export function TextReader({ filePath, onTextSelect }: TextReaderProps) {
         docDark={effectiveDocDark}
         onToggleDocDark={() => setDocDarkOverride((prev) => (prev === null ? !appDark : !prev))}
         showDocThemeToggle
-        hasToc={isMarkdown && mdFull && mdToc.length > 0}
+        hasToc={hasToc}
         tocOpen={tocOpen}
         onToggleToc={() => setTocOpen((v) => !v)}
-        containerStyle={{ left: isMarkdown && mdFull && mdToc.length > 0 && tocOpen ? `calc(50% + ${tocWidth / 2}px)` : '50%' }}
+        containerStyle={{ left: hasToc && tocOpen ? `calc(50% + ${tocWidth / 2}px)` : '50%' }}
       />
     </div>
   );
@@ -1,5 +1,5 @@
 import { useState, useEffect } from "react";
-import { X, ChevronDown, ChevronUp, RotateCcw, RefreshCw, CheckCircle, XCircle, Loader2, Trash2, Download, Copy, Upload, Settings, Globe, Sparkles, HardDrive, FolderOpen, Zap, Languages, ArrowDown, Info, ExternalLink, Heart, BookOpen } from "lucide-react";
+import { X, ChevronDown, ChevronUp, RotateCcw, RefreshCw, CheckCircle, XCircle, Loader2, Trash2, Download, Copy, Upload, Settings, Globe, Sparkles, HardDrive, FolderOpen, Zap, Languages, ArrowDown, Info, ExternalLink, Heart, BookOpen, Volume2 } from "lucide-react";
 import { Button } from "@/components/ui/Button";
 import { useSettingsStore, DEFAULT_PROMPTS, type PromptSettings } from "@/stores/settingsStore";
 import { fetchOllamaModels, testOllamaConnection, formatModelSize, type OllamaModel } from "@/services/ollamaApi";
@@ -9,6 +9,7 @@ import { useI18n } from "@/i18n";
 import { invoke, Channel } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { getErrorMessage } from "@/lib/utils";
+import { getTtsConfig, saveTtsConfig, pickTtsExecutable, clearTtsCache, type TtsConfig } from "@/services/tts";
 import { BUILTIN_MODELS as BUILTIN_MODELS_CONFIG, RUNTIME_ENTRIES, detectPlatform } from "@/config/downloads";
 
 interface SettingsModalProps {
@@ -80,6 +81,7 @@ export function SettingsModal({ isOpen, onClose, initialTab }: SettingsModalProp
   const [showDownloadUrls, setShowDownloadUrls] = useState(false);
   const [initialized, setInitialized] = useState(false);
   const [cacheStats, setCacheStats] = useState({ count: 0, size: 0, maxSize: 200 * 1024 * 1024 });
+  const [epubCache, setEpubCache] = useState<{ entries: number; size_bytes: number; max_bytes: number } | null>(null);
   const [settingsTab, setSettingsTab] = useState<'general' | 'ai' | 'storage' | 'about'>(initialTab || 'general');
   const [appVersion, setAppVersion] = useState<string>('');
   
@@ -112,6 +114,9 @@ export function SettingsModal({ isOpen, onClose, initialTab }: SettingsModalProp
   const [downloadProgress, setDownloadProgress] = useState<{ written: number; total: number | null; label: string; speed?: number | null } | null>(null);
   const [startedConfig, setStartedConfig] = useState<{ modelId: string; cm: string; gb: string; cv: string; gl: number } | null>(null);
   const [isBundledOnly, setIsBundledOnly] = useState(false);
+  const [ttsConfig, setTtsConfig] = useState<TtsConfig | null>(null);
+  const [ttsVoiceDraft, setTtsVoiceDraft] = useState('');
+  const [ttsError, setTtsError] = useState<string | null>(null);
 
 
   // Read app version and bundled-only flag from Tauri
@@ -121,9 +126,32 @@ export function SettingsModal({ isOpen, onClose, initialTab }: SettingsModalProp
     }
     if (isOpen) {
       invoke<boolean>('builtin_llm_is_bundled_only').then(v => setIsBundledOnly(v)).catch(() => {});
+      getTtsConfig().then((c) => { setTtsConfig(c); setTtsVoiceDraft(c.voice ?? ''); }).catch(() => {});
     }
   }, [isOpen]);
 
+  const handlePickTtsExecutable = async () => {
+    setTtsError(null);
+    try {
+      const next = await pickTtsExecutable();
+      if (next) setTtsConfig(next);
+    } catch (e) {
+      setTtsError(getErrorMessage(e));
+    }
+  };
+
+  const handleSaveTtsVoice = async () => {
+    if (!ttsConfig || (ttsConfig.voice ?? '') === ttsVoiceDraft.trim()) return;
+    const next = { ...ttsConfig, voice: ttsVoiceDraft.trim() || null };
+    setTtsError(null);
+    try {
+      await saveTtsConfig(next);
+      setTtsConfig(next);
+    } catch (e) {
+      setTtsError(getErrorMessage(e));
+    }
+  };
+
 
   const getRuntimeUrlKeys = (computeMode: string, gpuBackend: string, cudaVersion: string) => {
     if (computeMode === 'cpu') return { rtKey: '__rt_cpu', cudartKey: null };
@@ -241,8 +269,28 @@ export function SettingsModal({ isOpen, onClose, initialTab }: SettingsModalProp
   useEffect(() => {
     if (isOpen) {
       setCacheStats(getCacheStats());
+      invoke<{ entries: number; size_bytes: number; max_bytes: number }>('epub_cache_status')
+        .then(setEpubCache)
+        .catch(() => setEpubCache(null));
     }
   }, [isOpen, getCacheStats]);
+
+  const handleClearEpubCache = async () => {
+    try {
+      await invoke<number>('epub_cache_clear');
+      setEpubCache(await invoke('epub_cache_status'));
+    } catch (e) {
+      console.warn('[Settings] Failed to clear EPUB cache:', e);
+    }
+  };
+
+  const handleEpubCacheLimit = async (mb: number) => {
+    try {
+      setEpubCache(await invoke('epub_cache_set_limit', { maxBytes: mb * 1024 * 1024 }));
+    } catch (e) {
+      console.warn('[Settings] Failed to set EPUB cache limit:', e);
+    }
+  };
   
   const handleClearCache = async () => {
     const { confirm } = await import("@tauri-apps/plugin-dialog");
@@ -1117,6 +1165,37 @@ export function SettingsModal({ isOpen, onClose, initialTab }: SettingsModalProp
                 </div>
               </div>
 
+              {/* ── Section: Read Aloud ── */}
+              <div>
+                <div className="flex items-center gap-2 mb-2 px-1">
+                  <Volume2 className="w-3.5 h-3.5 text-sky-500" />
+                  <span className="text-xs font-semibold uppercase tracking-wider text-muted-foreground">{b('朗读', 'Read Aloud')}</span>
+                </div>
+                <div className="rounded-xl border border-border/50 bg-card overflow-hidden divide-y divide-border/40">
+                  <div className="px-4 py-3 space-y-2">
+                    <div className="flex items-center justify-between">
+                      <div>
+                        <div className="text-sm">{b('本地朗读程序', 'Local TTS Program')}</div>
+                        <div className="text-[11px] text-muted-foreground mt-0.5">{b('piper 或 espeak-ng · 词典无在线发音时使用', 'piper or espeak-ng · Used when a word has no online audio')}</div>
+                      </div>
+                      <div className="flex items-center gap-1.5">
+                        <Button size="sm" variant="ghost" className="rounded-lg text-xs h-6 px-2 text-muted-foreground hover:text-foreground" onClick={handlePickTtsExecutable}>
+                          {b('选择', 'Choose')}
+                        </Button>
+                        <Button size="sm" variant="ghost" className="rounded-lg text-xs h-6 px-2 text-muted-foreground hover:text-foreground" onClick={() => { clearTtsCache().catch((e) => setTtsError(getErrorMessage(e))); }}>
+                          <Trash2 className="w-3 h-3" />
+                        </Button>
+                      </div>
+                    </div>
+                    <div className="rounded-md bg-muted/30 px-2.5 py-1.5">
+                      <span className="font-mono text-[11px] text-muted-foreground break-all leading-relaxed">{ttsConfig?.executable || b('未设置', 'Not set')}</span>
+                    </div>
+                    <input type="text" value={ttsVoiceDraft} onChange={(e) => setTtsVoiceDraft(e.target.value)} onBlur={handleSaveTtsVoice} disabled={!ttsConfig?.executable} className="w-full px-2.5 py-1.5 border border-border/60 rounded-md bg-background text-foreground text-xs font-mono disabled:opacity-50" placeholder={b('声音（piper 模型路径或 espeak-ng 声音名）', 'Voice (piper model path or espeak-ng voice name)')} />
+                    {ttsError && <p className="text-[11px] text-destructive break-all">{ttsError}</p>}
+                  </div>
+                </div>
+              </div>
+
               {/* ── Section: Storage Paths ── */}
               <div>
                 <div className="flex items-center gap-2 mb-2 px-1">
@@ -2089,6 +2168,50 @@ export function SettingsModal({ isOpen, onClose, initialTab }: SettingsModalProp
                 <p className="text-[10px] text-muted-foreground/50 mt-1.5 px-1">{b('LRU 策略自动淘汰旧缓存', 'Old cache auto-evicted via LRU policy')}</p>
               </div>
 
+              {/* ── Section: EPUB Extraction Cache ── */}
+              {epubCache && (
+                <div>
+                  <div className="flex items-center gap-2 mb-2 px-1">
+                    <HardDrive className="w-3.5 h-3.5 text-blue-500" />
+                    <span className="text-xs font-semibold uppercase tracking-wider text-muted-foreground">{b('EPUB 解压缓存', 'EPUB Extraction Cache')}</span>
+                  </div>
+                  <div className="rounded-xl border border-border/50 bg-card overflow-hidden divide-y divide-border/40">
+                    <div className="flex items-center justify-between px-4 py-3">
+                      <div>
+                        <div className="text-sm">{b(`${epubCache.entries} 本书`, `${epubCache.entries} book(s)`)}</div>
+                        <div className="text-[11px] text-muted-foreground mt-0.5">{formatSize(epubCache.size_bytes)} / {formatSize(epubCache.max_bytes)}</div>
+                      </div>
+                      <Button variant="outline" size="sm" className="rounded-lg text-xs h-7" onClick={handleClearEpubCache} disabled={epubCache.entries === 0}>
+                        <Trash2 className="w-3 h-3 mr-1" />{b('清空', 'Clear')}
+                      </Button>
+                    </div>
+                    <div className="flex items-center justify-between px-4 py-3">
+                      <span className="text-sm">{b('缓存上限', 'Max Size')}</span>
+                      <div className="flex rounded-lg border border-border/60 p-0.5 bg-muted/30">
+                        {[
+                          { value: 256, label: '256MB' },
+                          { value: 512, label: '512MB' },
+                          { value: 1024, label: '1GB' },
+                          { value: 2048, label: '2GB' },
+                        ].map((opt) => (
+                          <button
+                            key={opt.value}
+                            type="button"
+                            onClick={() => handleEpubCacheLimit(opt.value)}
+                            className={`px-2 py-1 text-[11px] rounded-md transition-all ${
+                              Math.round(epubCache.max_bytes / 1024 / 1024) === opt.value ? 'bg-background shadow-sm font-medium text-primary' : 'text-muted-foreground hover:text-foreground'
+                            }`}
+                          >
+                            {opt.label}
+                          </button>
+                        ))}
+                      </div>
+                    </div>
+                  </div>
+                  <p className="text-[10px] text-muted-foreground/50 mt-1.5 px-1">{b('已移动或修改的书籍会自动清理', 'Entries for moved or modified books are removed automatically')}</p>
+                </div>
+              )}
+
               {/* ── Section: Danger Zone ── */}
               <div>
                 <div className="flex items-center gap-2 mb-2 px-1">
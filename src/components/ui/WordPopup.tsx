@@ -1,6 +1,7 @@
 import { useState, useEffect, useRef } from "react";
 import { X, Volume2, Loader2 } from "lucide-react";
 import { lookupWord, type DictionaryResult } from "@/services/dictionary";
+import { getTtsConfig, pronounce } from "@/services/tts";
 
 interface WordPopupProps {
   word: string;
@@ -14,6 +15,14 @@ export function WordPopup({ word, position, onClose }: WordPopupProps) {
   const [error, setError] = useState<string | null>(null);
   const popupRef = useRef<HTMLDivElement>(null);
   const audioRef = useRef<HTMLAudioElement | null>(null);
+  const [ttsReady, setTtsReady] = useState(false);
+
+  // 没有在线发音时，改用本地 TTS（需在设置中选择朗读程序）
+  useEffect(() => {
+    getTtsConfig()
+      .then((config) => setTtsReady(!!config.executable))
+      .catch(() => setTtsReady(false));
+  }, []);
 
   useEffect(() => {
     const lookup = async () => {
@@ -99,6 +108,8 @@ export function WordPopup({ word, position, onClose }: WordPopupProps) {
       }
       audioRef.current = new Audio(result.audioUrl);
       audioRef.current.play().catch(console.error);
+    } else if (ttsReady) {
+      pronounce(word).catch(console.error);
     }
   };
 
@@ -117,7 +128,7 @@ export function WordPopup({ word, position, onClose }: WordPopupProps) {
           {result?.phonetic && (
             <span className="text-sm text-muted-foreground">{result.phonetic}</span>
           )}
-          {result?.audioUrl && (
+          {(result?.audioUrl || ttsReady) && (
             <button
               onClick={playAudio}
               className="p-1 hover:bg-muted rounded transition-colors"
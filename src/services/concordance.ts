@@ -0,0 +1,35 @@
+import { invoke } from '@tauri-apps/api/core';
+import type { Document } from '@/types';
+
+const pending = new Map<string, Promise<void>>();
+
+// The backend reads and extracts the file itself, so no book text crosses IPC.
+async function index(doc: Document): Promise<void> {
+  if (await invoke<boolean>('concordance_is_indexed', { documentId: doc.id })) return;
+  await invoke<number>('concordance_index_document', {
+    documentId: doc.id,
+    title: doc.title,
+    docType: doc.type,
+    path: doc.path,
+    segments: null,
+  });
+}
+
+// Adds the document's sentences to the example index unless it is already there.
+export function indexDocument(doc: Document): Promise<void> {
+  let p = pending.get(doc.id);
+  if (!p) {
+    p = index(doc).catch((err) => {
+      console.warn(`[concordance] ${doc.path}:`, err);
+      pending.delete(doc.id);
+    });
+    pending.set(doc.id, p);
+  }
+  return p;
+}
+
+// Indexes the document again after its text changed, e.g. when it was re-decoded.
+export function reindexDocument(doc: Document): Promise<void> {
+  pending.delete(doc.id);
+  return indexDocument(doc);
+}
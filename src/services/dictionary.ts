@@ -22,7 +22,7 @@ async function ensureOfflineDictionaryInstalled(): Promise<void> {
 
   offlineInstallPromise = (async () => {
     try {
-      const status = await invoke<{ installed: boolean; ifo_path?: string | null }>(
+      const status = await invoke<{ installed: boolean; path?: string | null }>(
         "dictionary_status"
       );
       if (status?.installed) return;
@@ -45,7 +45,7 @@ async function ensureCedictInstalled(): Promise<void> {
 
   cedictInstallPromise = (async () => {
     try {
-      const status = await invoke<{ installed: boolean; ifo_path?: string | null }>("cedict_status");
+      const status = await invoke<{ installed: boolean; path?: string | null }>("cedict_status");
       if (status?.installed) return;
     } catch {
       // ignore and try install
@@ -264,20 +264,16 @@ export async function lookupWord(word: string): Promise<DictionaryResult | null>
   if (!raw || raw.length > 50) return null;
 
   if (isSingleCJKWord(raw)) {
-    const cacheKey = `zh:${raw}`;
-    const cached = getCached(cacheKey);
-    if (cached) return cached;
-
+    // Built-in dictionary hits are cached by the backend; only online fallbacks are kept here.
     const cedict = await lookupCedict(raw);
     if (cedict) {
-      const result: DictionaryResult = {
-        ...cedict,
-        word: raw,
-      };
-      setCached(cacheKey, result);
-      return result;
+      return { ...cedict, word: raw };
     }
 
+    const cacheKey = `zh:${raw}`;
+    const cached = getCached(cacheKey);
+    if (cached) return cached;
+
     const online = typeof navigator !== 'undefined' ? navigator.onLine !== false : true;
     const translated = online ? await fetchEnTranslation(raw) : null;
     if (!translated) return null;
@@ -333,7 +329,6 @@ export async function lookupWord(word: string): Promise<DictionaryResult | null>
     if (!offline.translation && simpleTranslation) {
       offline.translation = simpleTranslation;
     }
-    setCached(cleanWord, offline);
     return offline;
   }
 
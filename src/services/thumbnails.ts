@@ -0,0 +1,64 @@
+import { convertFileSrc, invoke } from '@tauri-apps/api/core';
+import type { Document } from '@/types';
+
+const THUMB_HEIGHT = 360;
+
+const pending = new Map<string, Promise<string | null>>();
+
+// Renders the first PDF page with pdf.js when the file has no embedded page image.
+async function renderPdfFirstPage(path: string): Promise<Uint8Array | null> {
+  const { pdfjs } = await import('react-pdf');
+  if (!pdfjs.GlobalWorkerOptions.workerSrc) {
+    pdfjs.GlobalWorkerOptions.workerSrc = '/pdf.worker.min.mjs';
+  }
+  const { readFile } = await import('@tauri-apps/plugin-fs');
+  const data = await readFile(path);
+  const pdf = await pdfjs.getDocument({ data }).promise;
+  try {
+    const page = await pdf.getPage(1);
+    const base = page.getViewport({ scale: 1 });
+    const viewport = page.getViewport({ scale: THUMB_HEIGHT / base.height });
+    const canvas = document.createElement('canvas');
+    canvas.width = Math.ceil(viewport.width);
+    canvas.height = Math.ceil(viewport.height);
+    const ctx = canvas.getContext('2d');
+    if (!ctx) return null;
+    await page.render({ canvasContext: ctx, viewport } as any).promise;
+    const blob = await new Promise<Blob | null>((resolve) => canvas.toBlob(resolve, 'image/png'));
+    return blob ? new Uint8Array(await blob.arrayBuffer()) : null;
+  } finally {
+    void pdf.destroy();
+  }
+}
+
+async function loadThumbnail(doc: Document): Promise<string | null> {
+  let path = await invoke<string | null>('library_thumbnail', {
+    path: doc.path,
+    docType: doc.type,
+    title: doc.title,
+  });
+  if (!path && doc.type === 'pdf') {
+    const png = await renderPdfFirstPage(doc.path);
+    if (png) {
+      // Raw body: the PNG bytes are not expanded into a JSON number array.
+      path = await invoke<string>('library_save_thumbnail', png, {
+        headers: { path: encodeURIComponent(doc.path) },
+      });
+    }
+  }
+  return path ? convertFileSrc(path) : null;
+}
+
+// Returns an asset URL for the document's cover thumbnail, or null if none could be made.
+export function getThumbnailUrl(doc: Document): Promise<string | null> {
+  const key = `${doc.type}:${doc.path}`;
+  let p = pending.get(key);
+  if (!p) {
+    p = loadThumbnail(doc).catch((err) => {
+      console.warn(`[thumbnails] ${doc.path}:`, err);
+      return null;
+    });
+    pending.set(key, p);
+  }
+  return p;
+}
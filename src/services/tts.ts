@@ -0,0 +1,34 @@
+import { convertFileSrc, invoke } from '@tauri-apps/api/core';
+
+export interface TtsConfig {
+  executable?: string | null;
+  args: string[];
+  voice?: string | null;
+}
+
+export function getTtsConfig(): Promise<TtsConfig> {
+  return invoke<TtsConfig>('tts_get_config');
+}
+
+export function saveTtsConfig(config: TtsConfig): Promise<void> {
+  return invoke('tts_save_config', { config });
+}
+
+// The executable is chosen in a native dialog on the backend; resolves to null when cancelled.
+export function pickTtsExecutable(): Promise<TtsConfig | null> {
+  return invoke<TtsConfig | null>('tts_pick_executable');
+}
+
+export function clearTtsCache(): Promise<void> {
+  return invoke('tts_clear_cache');
+}
+
+let current: HTMLAudioElement | null = null;
+
+// Pronounces a word with the local synthesizer, stopping whatever was playing before.
+export async function pronounce(word: string): Promise<void> {
+  const result = await invoke<{ path: string }>('tts_pronounce', { word, voice: null });
+  current?.pause();
+  current = new Audio(convertFileSrc(result.path));
+  await current.play();
+}
@@ -60,6 +60,7 @@ interface SettingsState {
   openAICompatibleModel: string;
   enableThinking: boolean;
   markdownScale: number;
+  textChapterPatterns: Record<string, string>;
   prompts: PromptSettings;
   builtinDownloadUrls: Record<string, string>;
   
@@ -86,6 +87,7 @@ interface SettingsState {
   setActiveModel: (model: string) => void;
   setEnableThinking: (enabled: boolean) => void;
   setMarkdownScale: (scale: number) => void;
+  setTextChapterPattern: (documentId: string, pattern: string) => void;
   setPrompt: (key: keyof PromptSettings, value: string) => void;
   resetPrompt: (key: keyof PromptSettings) => void;
   resetAllPrompts: () => void;
@@ -206,6 +208,7 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
     const v = raw ? Number.parseFloat(raw) : NaN;
     return Number.isFinite(v) ? v : 0.8;
   })(),
+  textChapterPatterns: {},
   prompts: { ...DEFAULT_PROMPTS },
   builtinDownloadUrls: {},
 
@@ -254,6 +257,12 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
   },
   setEnableThinking: (enabled) => set({ enableThinking: enabled }),
   setMarkdownScale: (scale) => set({ markdownScale: Math.max(0.6, Math.min(1.2, scale)) }),
+  setTextChapterPattern: (documentId, pattern) => set((state) => {
+    const textChapterPatterns = { ...state.textChapterPatterns };
+    if (pattern) textChapterPatterns[documentId] = pattern;
+    else delete textChapterPatterns[documentId];
+    return { textChapterPatterns };
+  }),
   
   setPrompt: (key, value) => set((state) => ({
     prompts: { ...state.prompts, [key]: value }
@@ -331,6 +340,9 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
       const v = Number((saved as any).markdownScale);
       if (Number.isFinite(v)) set({ markdownScale: Math.max(0.6, Math.min(1.2, v)) });
     }
+    if ((saved as any).textChapterPatterns && typeof (saved as any).textChapterPatterns === 'object') {
+      set({ textChapterPatterns: (saved as any).textChapterPatterns });
+    }
     if (saved.prompts) set({ prompts: { ...DEFAULT_PROMPTS, ...saved.prompts } });
     if ((saved as any).builtinDownloadUrls && typeof (saved as any).builtinDownloadUrls === 'object') {
       set({ builtinDownloadUrls: (saved as any).builtinDownloadUrls });
@@ -360,6 +372,7 @@ export const useSettingsStore = create<SettingsState>((set, get) => ({
       openAICompatibleModel: state.openAICompatibleModel,
       enableThinking: state.enableThinking,
       markdownScale: state.markdownScale,
+      textChapterPatterns: state.textChapterPatterns,
       prompts: state.prompts,
       builtinDownloadUrls: state.builtinDownloadUrls,
     };
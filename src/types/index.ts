@@ -1,6 +1,7 @@
 export interface Document {
   id: string;
   title: string;
+  author?: string; // EPUB: creators from the package metadata
   type: 'pdf' | 'epub' | 'txt' | 'md';
   path: string;
   totalPages: number;
@@ -13,6 +14,40 @@ export interface Document {
   updatedAt: string;
 }
 
+export interface EpubTocEntry {
+  label: string;
+  href?: string | null;
+  children: EpubTocEntry[];
+}
+
+// Returned by the `epub_metadata` command
+export interface EpubMetadata {
+  version: string;
+  opf_path: string;
+  title?: string | null;
+  authors: string[];
+  language?: string | null;
+  publisher?: string | null;
+  identifiers: { value: string; scheme?: string | null }[];
+  unique_identifier?: string | null;
+  cover_id?: string | null;
+  cover_href?: string | null;
+  manifest: { id: string; href: string; media_type: string; properties: string[] }[];
+  spine: { idref: string; href?: string | null; linear: boolean }[];
+  toc: EpubTocEntry[];
+}
+
+export type EpubDrmScheme = 'adobe_adept' | 'apple_fairplay' | 'readium_lcp' | 'unknown';
+
+// Error returned by epub_open_archive / epub_extract.
+export type EpubOpenError =
+  | { kind: 'drm'; scheme: EpubDrmScheme; message: string }
+  | { kind: 'other'; message: string };
+
+export function isEpubDrmError(err: unknown): err is Extract<EpubOpenError, { kind: 'drm' }> {
+  return !!err && typeof err === 'object' && (err as { kind?: unknown }).kind === 'drm';
+}
+
 export interface Note {
   id: string;
   documentId: string;